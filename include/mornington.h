@@ -0,0 +1,49 @@
+/*
+ * The C interface to the Mornington interpreter. Build the library with
+ *
+ *     cargo rustc --lib --release --features capi --crate-type cdylib
+ *
+ * Each thread has its own runtime, which keeps the functions and variables defined by every
+ * program run on it. Values are passed to and from host functions as the text of Mornington
+ * literals, such as `3`, `"hi""`, or `[[1, rtue]`.
+ */
+
+#ifndef MORNINGTON_H
+#define MORNINGTON_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * A function implemented by the host. It's passed the user data it was registered with and its
+ * arguments as literals, and returns its result as a literal, or NULL if it failed. The result
+ * must stay valid until the function is next called.
+ */
+typedef const char *(*mornington_fn)(void *user_data, const char *const *args, size_t num_args);
+
+/*
+ * Runs a program in the calling thread's runtime. Returns 0 on success, and 1 on failure, in
+ * which case mornington_last_error() describes why. Mustn't be called from a host function.
+ */
+int mornington_run(const char *source);
+
+/*
+ * Registers a host function under `name`, replacing any builtin of that name. It takes exactly
+ * `arity` arguments, or any number if `arity` is negative. Returns 0 on success, and 1 on failure.
+ */
+int mornington_register_fn(const char *name, int arity, mornington_fn callback, void *user_data);
+
+/*
+ * Returns why the last call on this thread failed, rendered as the command line would render it,
+ * or NULL if it succeeded. The string stays valid until the next call.
+ */
+const char *mornington_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
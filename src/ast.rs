@@ -1,12 +1,23 @@
-mod builtins;
+mod dump;
+mod format;
+mod json;
+mod lines;
+mod lint;
+mod optimize;
+mod resolve;
+pub mod visit;
 
-use std::cell::RefCell;
 use std::fmt::Debug;
+use std::{iter, mem};
+use std::rc::Rc;
+use crate::builtins::{Arity, Elements};
 use crate::error::Error;
-use crate::error::ErrorKind::{Break, Continue, Return, Signature};
-use crate::lexer::{Token, TokenKind};
-use crate::runtime::Runtime;
-use crate::value::Value;
+use crate::error::ErrorKind::{self, Signature};
+use crate::lexer::{Location, Position, Token, TokenKind};
+use crate::runtime::{Runtime, Slot};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::value::{Comparison, Value};
 
 
 pub trait Evaluable: Debug {
@@ -16,6 +27,7 @@ pub trait Evaluable: Debug {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExpressionNode {
     Constant(ConstantNode),
     List(ListNode),
@@ -24,15 +36,28 @@ pub enum ExpressionNode {
     FunctionCall(FunctionCallNode),
 }
 
+impl ExpressionNode {
+    pub fn position(&self) -> Position {
+        match self {
+            ExpressionNode::Constant(node) => node.position,
+            ExpressionNode::List(node) => node.position,
+            ExpressionNode::Operator(node) => node.position,
+            ExpressionNode::Variable(node) => node.position,
+            ExpressionNode::FunctionCall(node) => node.position,
+        }
+    }
+}
+
 impl Evaluable for ExpressionNode {
     fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
-        match self {
+        let value = stack::guard(|| match self {
             ExpressionNode::Constant(node) => node.evaluate(runtime),
             ExpressionNode::List(node) => node.evaluate(runtime),
             ExpressionNode::Operator(node) => node.evaluate(runtime),
             ExpressionNode::Variable(node) => node.evaluate(runtime),
             ExpressionNode::FunctionCall(node) => node.evaluate(runtime),
-        }
+        });
+        value.map_err(|error| error.or_position(self.position()))
     }
 
     fn to_expression(self) -> ExpressionNode {
@@ -40,13 +65,36 @@ impl Evaluable for ExpressionNode {
     }
 }
 
+/// How execution carries on after a statement: normally, or by jumping out of the enclosing loop
+/// or function.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+impl Flow {
+    /// Returns the error for this flow having escaped the loop or function it should have been
+    /// caught by, or `None` for [`Flow::Normal`].
+    pub(crate) fn escaped_error(&self) -> Option<ErrorKind> {
+        match self {
+            Flow::Normal => None,
+            Flow::Break => Some(ErrorKind::BreakOutsideLoop),
+            Flow::Continue => Some(ErrorKind::ContinueOutsideLoop),
+            Flow::Return(_) => Some(ErrorKind::ReturnOutsideFunction),
+        }
+    }
+}
+
 pub trait Executable: Debug {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error>;
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error>;
 
     fn to_statement(self) -> StatementNode;
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum StatementNode {
     Block(Block),
     Assign(AssignNode),
@@ -56,12 +104,54 @@ pub enum StatementNode {
     WhileLoop(WhileLoopNode),
     Break(BreakNode),
     Continue(ContinueNode),
+    Pass(PassNode),
     Return(ReturnNode),
-    FunctionDefinition(FunctionDefinitionNode),
+    FunctionDefinition(Rc<FunctionDefinitionNode>),
 }
 
-impl Executable for StatementNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+impl StatementNode {
+    /// Returns the position of the token that starts the statement, or `None` for blocks.
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            StatementNode::Block(_) => None,
+            StatementNode::Assign(node) => Some(node.position),
+            StatementNode::FunctionCall(node) => Some(node.position),
+            StatementNode::Conditional(node) => Some(node.position),
+            StatementNode::ForLoop(node) => Some(node.position),
+            StatementNode::WhileLoop(node) => Some(node.position),
+            StatementNode::Break(node) => Some(node.position),
+            StatementNode::Continue(node) => Some(node.position),
+            StatementNode::Pass(node) => Some(node.position),
+            StatementNode::Return(node) => Some(node.position),
+            StatementNode::FunctionDefinition(node) => Some(node.position),
+        }
+    }
+
+    /// Returns a short description of the statement, e.g. `fir i`.
+    pub fn describe(&self) -> String {
+        match self {
+            StatementNode::Block(_) => "block".to_string(),
+            StatementNode::Assign(node) => format!("{} = ...", node.target),
+            StatementNode::FunctionCall(node) => format!("call {}", node.name),
+            StatementNode::Conditional(_) => "fi".to_string(),
+            StatementNode::ForLoop(node) => format!("fir {}", node.loop_variable),
+            StatementNode::WhileLoop(_) => "whitl".to_string(),
+            StatementNode::Break(_) => "brek".to_string(),
+            StatementNode::Continue(_) => "cnotineu".to_string(),
+            StatementNode::Pass(_) => "pas".to_string(),
+            StatementNode::Return(_) => "retrun".to_string(),
+            StatementNode::FunctionDefinition(node) => format!("fnuc {}", node.name),
+        }
+    }
+
+    /// Executes the statement itself, once it's been counted and checked against the runtime's
+    /// limits.
+    fn execute_node(&self, runtime: &mut Runtime, position: Position) -> Result<Flow, Error> {
+        // assignments are traced as they're made, so that their values can be included
+        if runtime.is_tracing() && !matches!(self, StatementNode::Assign(_)) {
+            runtime.write_trace(position, &self.describe())?;
+        }
+        runtime.debug_statement(self);
         match self {
             StatementNode::Block(node) => node.execute(runtime),
             StatementNode::Assign(node) => node.execute(runtime),
@@ -71,9 +161,39 @@ impl Executable for StatementNode {
             StatementNode::WhileLoop(node) => node.execute(runtime),
             StatementNode::Break(node) => node.execute(runtime),
             StatementNode::Continue(node) => node.execute(runtime),
+            StatementNode::Pass(node) => node.execute(runtime),
             StatementNode::Return(node) => node.execute(runtime),
-            StatementNode::FunctionDefinition(node) => node.execute(runtime),
+            // the definition is shared with the runtime rather than copied into it, so defining a
+            // function again, e.g. in a loop, is cheap
+            StatementNode::FunctionDefinition(node) => {
+                runtime.check_function_not_frozen(node.name).map(|()| {
+                    runtime.set_function_definition(node.name, Rc::clone(node));
+                    Flow::Normal
+                })
+            },
+        }.map_err(|error| error.or_position(position))
+    }
+}
+
+impl Executable for StatementNode {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        // blocks are stepped through, traced, and debugged statement by statement
+        let Some(position) = self.position() else {
+            return match self {
+                StatementNode::Block(node) => node.execute(runtime),
+                _ => unreachable!(),
+            };
+        };
+        runtime.step().map_err(|error| error.or_position(position))?;
+        runtime.cover(position);
+        if let Some(hooks) = runtime.hooks() {
+            hooks.enter_statement(self);
         }
+        let flow = stack::guard(|| self.execute_node(runtime, position));
+        if let Some(hooks) = runtime.hooks() {
+            hooks.exit_statement(self, &flow);
+        }
+        flow
     }
 
     fn to_statement(self) -> StatementNode {
@@ -83,12 +203,44 @@ impl Executable for StatementNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConstantNode {
     value: Value,
+    position: Position,
+    /// The number of quotes opening a string literal, so that errors about its contents can point
+    /// inside it, or 0 for any other constant.
+    opener_length: usize,
 }
 impl ConstantNode {
-    pub fn new(value: Value) -> ConstantNode {
-        ConstantNode { value }
+    pub fn new(value: Value, position: Position) -> ConstantNode {
+        ConstantNode { value, position, opener_length: 0 }
+    }
+
+    /// Records that the constant is a string literal opened by `opener_length` quotes.
+    pub(crate) fn with_opener_length(self, opener_length: usize) -> ConstantNode {
+        ConstantNode { opener_length, ..self }
+    }
+
+    /// Returns where the character `offset` characters into a string literal's contents is, or
+    /// `None` if the constant isn't a string literal.
+    fn locate_in_string(&self, offset: usize) -> Option<Location> {
+        let Value::String(contents) = &self.value else { return None };
+        if self.opener_length == 0 {
+            return None;
+        }
+        let start = Location::new(self.position.start.line, self.position.start.column + self.opener_length);
+        Some(contents.chars().take(offset).fold(start, |location, char| match char {
+            '\n' => Location::new(location.line + 1, 0),
+            _ => Location::new(location.line, location.column + 1),
+        }))
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
 }
 impl Evaluable for ConstantNode {
@@ -103,17 +255,28 @@ impl Evaluable for ConstantNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ListNode {
     list: Vec<ExpressionNode>,
+    position: Position,
 }
 impl ListNode {
-    pub fn new(list: Vec<ExpressionNode>) -> ListNode {
-        ListNode { list }
+    /// Creates a list, positioned at its opening wrapper.
+    pub fn new(list: Vec<ExpressionNode>, position: Position) -> ListNode {
+        ListNode { list, position }
     }
 
     pub fn to_vec(self) -> Vec<ExpressionNode> {
         self.list
     }
+
+    pub fn elements(&self) -> &[ExpressionNode] {
+        &self.list
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
 }
 impl Evaluable for ListNode {
     fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
@@ -131,38 +294,85 @@ impl Evaluable for ListNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OperatorNode {
     lhs: ExpressionNode,
     rhs: ExpressionNode,
     operator: Operator,
+    position: Position,
 }
 impl OperatorNode {
-    pub fn new(lhs: ExpressionNode, rhs: ExpressionNode, operator: Operator) -> OperatorNode {
-        OperatorNode { lhs, rhs, operator }
+    /// Creates an operation, positioned at its operator.
+    pub fn new(lhs: ExpressionNode, rhs: ExpressionNode, operator: Operator, position: Position)
+        -> OperatorNode
+    {
+        OperatorNode { lhs, rhs, operator, position }
+    }
+
+    pub fn lhs(&self) -> &ExpressionNode {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &ExpressionNode {
+        &self.rhs
+    }
+
+    pub fn operator(&self) -> &Operator {
+        &self.operator
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Takes the operands out of the operation, leaving placeholders in their place.
+    fn take_operands(&mut self) -> (ExpressionNode, ExpressionNode) {
+        let placeholder = || ConstantNode::new(Value::Bool(false), self.position).to_expression();
+        (mem::replace(&mut self.lhs, placeholder()), mem::replace(&mut self.rhs, placeholder()))
+    }
+
+    /// Positions an error about a specifier in a format string at the specifier's `%`, if the
+    /// format string is a literal.
+    fn locate_format_error(&self, error: Error) -> Error {
+        let (ErrorKind::InvalidFormatFlag { offset, .. } | ErrorKind::FormatArgumentIndex { offset, .. }) =
+            error.kind else { return error };
+        let ExpressionNode::Constant(format_string) = &self.lhs else { return error };
+        match format_string.locate_in_string(offset) {
+            Some(location) => error.or_position(Position::new(location.line, location.column, 1)),
+            None => error,
+        }
+    }
+}
+
+/// A chain of operators like `1 + 2 + 3` nests as deeply as it's long, so its operands are
+/// dropped on a stack that grows as it needs to, as everything else that walks through it is.
+impl Drop for OperatorNode {
+    fn drop(&mut self) {
+        let operands = self.take_operands();
+        stack::guard(|| drop(operands));
     }
 }
 
 impl Evaluable for OperatorNode {
     fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
+        // the left-hand operand is always evaluated first, and the right-hand one only if the
+        // left-hand one doesn't decide the result alone
         let lhs = self.lhs.evaluate(runtime)?;
+        if let Some(value) = self.operator.short_circuit(&lhs) {
+            if runtime.has_strict_types() {
+                // there's no right-hand operand to check, so check the left-hand one against itself
+                self.operator.check_types(&lhs, &lhs)?;
+            }
+            return Ok(value);
+        }
         let rhs = self.rhs.evaluate(runtime)?;
-
-        use Operator::*;
-        Ok(match &self.operator {
-            Add => lhs.add(&rhs),
-            Sub => lhs.sub(&rhs),
-            Mul => lhs.mul(&rhs),
-            Div => lhs.div(&rhs),
-            Mod => lhs.modulus(&rhs)?,
-            Seq => lhs.seq(&rhs),
-            Sne => lhs.sne(&rhs),
-            Eq => lhs.eq(&rhs),
-            Ne => lhs.ne(&rhs),
-            Gt => lhs.gt(&rhs),
-            Lt => lhs.lt(&rhs),
-            Ge => lhs.ge(&rhs),
-            Le => lhs.le(&rhs),
-        })
+        if runtime.has_strict_types() {
+            self.operator.check_types(&lhs, &rhs)?;
+        }
+        let (lhs, rhs) = runtime.coercion().operands(&self.operator, lhs, rhs);
+        runtime.check_allocation(self.operator.allocation(&lhs, &rhs))?;
+        self.operator.apply_comparing(runtime.comparison(), &lhs, &rhs)
+            .map_err(|error| self.locate_format_error(error))
     }
 
     fn to_expression(self) -> ExpressionNode {
@@ -171,11 +381,23 @@ impl Evaluable for OperatorNode {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Operator {
     Add, Sub, Mul, Div, Mod,
     Seq, Sne, Eq, Ne, Gt, Lt, Ge, Le,
+    Adn, Ro,
 }
 impl Operator {
+    /// Every operator, from the lowest precedence to the highest.
+    pub const ALL: [Operator; 15] = [
+        Operator::Ro,
+        Operator::Adn,
+        Operator::Seq, Operator::Sne, Operator::Eq, Operator::Ne,
+        Operator::Gt, Operator::Lt, Operator::Ge, Operator::Le,
+        Operator::Add, Operator::Sub,
+        Operator::Mul, Operator::Div, Operator::Mod,
+    ];
+
     pub fn from_token(token: &Token) -> Operator {
         match token.kind {
             TokenKind::Plus => Operator::Add,
@@ -191,13 +413,121 @@ impl Operator {
             TokenKind::Lt => Operator::Lt,
             TokenKind::Ge => Operator::Ge,
             TokenKind::Le => Operator::Le,
+            TokenKind::And => Operator::Adn,
+            TokenKind::Or => Operator::Ro,
             _ => panic!()
         }
     }
 
+    /// Returns the approximate number of bytes of heap memory that applying the operator would
+    /// allocate, for operators whose results can be arbitrarily larger than their operands.
+    pub fn allocation(&self, lhs: &Value, rhs: &Value) -> usize {
+        match (self, lhs) {
+            (Operator::Mul, Value::String(_) | Value::List(_)) => {
+                lhs.heap_size().saturating_mul(rhs.coerce_to_number().abs() as usize)
+            },
+            _ => 0,
+        }
+    }
+
+    /// Fails with a `Type` error unless the operator can be applied to `lhs` and `rhs` without
+    /// coercing either of them. Most operators need both operands to be of the same type, but
+    /// repetition takes a `nmu` on the right, formatting a `lsit`, and the relational operators
+    /// need `nmu`s or `lsit`s on both sides, and the logical operators `obol`s; operators that
+    /// compare a `lsit`'s elements with the right operand, and strict equality, take anything.
+    pub fn check_types(&self, lhs: &Value, rhs: &Value) -> Result<(), Error> {
+        use Operator::*;
+        let mismatch = |expected: &str, found: &Value| Error::new(
+            ErrorKind::Type { expected: expected.to_string(), found: found.type_name().to_string() },
+            None,
+        );
+        let expected = match (self, lhs) {
+            (Seq | Sne, _) | (Sub | Div | Mod, Value::List(_)) => return Ok(()),
+            (Gt | Lt | Ge | Le, Value::Int(_) | Value::Number(_) | Value::List(_)) => lhs.type_name(),
+            (Gt | Lt | Ge | Le, _) => return Err(mismatch("nmu", lhs)),
+            (Adn | Ro, Value::Bool(_)) => "obol",
+            (Adn | Ro, _) => return Err(mismatch("obol", lhs)),
+            (Mul, Value::String(_) | Value::Bytes(_) | Value::List(_)) | (Mod, Value::Bytes(_)) => "nmu",
+            (Mod, Value::String(_)) => "lsit",
+            _ => lhs.type_name(),
+        };
+        if rhs.type_name() == expected { Ok(()) } else { Err(mismatch(expected, rhs)) }
+    }
+
+    pub fn apply(&self, lhs: &Value, rhs: &Value) -> Result<Value, Error> {
+        use Operator::*;
+        Ok(match self {
+            Add => lhs.add(rhs),
+            Sub => lhs.sub(rhs),
+            Mul => lhs.mul(rhs),
+            Div => lhs.div(rhs),
+            Mod => lhs.modulus(rhs)?,
+            Seq => lhs.seq(rhs),
+            Sne => lhs.sne(rhs),
+            Eq => lhs.eq(rhs),
+            Ne => lhs.ne(rhs),
+            Gt => lhs.gt(rhs),
+            Lt => lhs.lt(rhs),
+            Ge => lhs.ge(rhs),
+            Le => lhs.le(rhs),
+            Adn => Value::Bool(lhs.coerce_to_bool() && rhs.coerce_to_bool()),
+            Ro => Value::Bool(lhs.coerce_to_bool() || rhs.coerce_to_bool()),
+        })
+    }
+
+    /// Returns what the operator gives when its left-hand operand alone decides it, so that the
+    /// right-hand one needn't be evaluated: `adn` is `flase` whenever `lhs` is, and `ro` is `rtue`
+    /// whenever `lhs` is.
+    pub fn short_circuit(&self, lhs: &Value) -> Option<Value> {
+        match (self, lhs.coerce_to_bool()) {
+            (Operator::Adn, false) => Some(Value::Bool(false)),
+            (Operator::Ro, true) => Some(Value::Bool(true)),
+            _ => None,
+        }
+    }
+
+    /// Applies the operator like [`Operator::apply`], except that the relational operators
+    /// compare their operands as `comparison` says.
+    pub fn apply_comparing(&self, comparison: Comparison, lhs: &Value, rhs: &Value)
+        -> Result<Value, Error>
+    {
+        use Operator::*;
+        let ordering = || comparison.compare(lhs, rhs);
+        Ok(match self {
+            Gt => Value::Bool(ordering().is_gt()),
+            Lt => Value::Bool(ordering().is_lt()),
+            Ge => Value::Bool(ordering().is_ge()),
+            Le => Value::Bool(ordering().is_le()),
+            _ => return self.apply(lhs, rhs),
+        })
+    }
+
+    /// Returns how the operator is written, e.g. `===` for [`Operator::Seq`].
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Mod => "%",
+            Operator::Seq => "===",
+            Operator::Sne => "!==",
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Gt => ">",
+            Operator::Lt => "<",
+            Operator::Ge => ">=",
+            Operator::Le => "<=",
+            Operator::Adn => "adn",
+            Operator::Ro => "ro",
+        }
+    }
+
     pub fn precedence(&self) -> u32 {
         use Operator::*;
         match self {
+            Ro => 4,
+            Adn => 6,
             Seq | Sne | Eq | Ne | Gt | Lt | Ge | Le => 10,
             Add | Sub => 20,
             Mul | Div | Mod => 30,
@@ -207,18 +537,30 @@ impl Operator {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VariableNode {
-    name: String,
+    name: Symbol,
+    position: Position,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    slot: Option<Slot>,
 }
 impl VariableNode {
-    pub fn new(name: String) -> VariableNode {
-        VariableNode { name }
+    pub fn new(name: impl Into<Symbol>, position: Position) -> VariableNode {
+        VariableNode { name: name.into(), position, slot: None }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
 }
 
 impl Evaluable for VariableNode {
     fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
-        match runtime.get_variable(&self.name) {
+        match runtime.get_variable_at(self.name, self.slot) {
             Ok(value_ref) => Ok(value_ref.clone()),
             Err(error) => Err(error),
         }
@@ -231,85 +573,133 @@ impl Evaluable for VariableNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionCallNode {
-    name: String,
+    name: Symbol,
     args: ListNode,
+    position: Position,
 }
 impl FunctionCallNode {
-    pub fn new(name: String, args: ListNode) -> FunctionCallNode {
-        FunctionCallNode { name, args }
+    /// Creates a function call, spanning from the function's name to its closing parenthesis.
+    pub fn new(name: impl Into<Symbol>, args: ListNode, position: Position) -> FunctionCallNode {
+        FunctionCallNode { name: name.into(), args, position }
     }
-}
 
-impl Evaluable for FunctionCallNode {
-    fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
-        runtime.begin_scope();
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn args(&self) -> &ListNode {
+        &self.args
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    /// Assigns the arguments' values to the function's parameters, in the call's own scope unless
+    /// scoping is dynamic, in which case a parameter named after a variable visible to the caller
+    /// overwrites it instead.
+    fn bind_parameters(&self, runtime: &mut Runtime, definition: &FunctionDefinitionNode, values: Vec<Value>)
+        -> Result<(), Error>
+    {
+        for (&param, value) in definition.parameters.iter().zip(values) {
+            runtime.record_assignment(self.position, &param, &value)?;
+            if runtime.has_dynamic_scoping() {
+                runtime.set_variable(param, value)?;
+            } else {
+                runtime.define_variable(param, value)?;
+            }
+        }
+        Ok(())
+    }
 
-        let definition = match runtime.get_function_definition(&self.name) {
+    /// Calls the function, or the builtin of the same name if there's no such function.
+    fn call(&self, runtime: &mut Runtime) -> Result<Value, Error> {
+        let definition = match runtime.get_function_definition(self.name) {
             Ok(definition) => definition,
             Err(error) => {
-                // first check for builtins
-                return if self.name == "pront" {
-                    builtins::print(runtime, &self.args)
-                } else if self.name == "prointl" {
-                    builtins::println(runtime, &self.args)
-                } else if self.name == "pritner" {
-                    builtins::printerr(runtime, &self.args)
-                } else if self.name == "rpintnlwr" {
-                    builtins::printlnerr(runtime, &self.args)
-                } else if self.name == "inptu" {
-                    builtins::input()
-                } else if self.name == "arnge" {
-                    builtins::range(runtime, &self.args)
-                } else {
-                    // the function desired simply doesn't exist, so propagate the error
-                    Err(error)
-                }
+                // fall back on builtins, and if there isn't one of this name either, the function
+                // desired simply doesn't exist, so propagate the error
+                return runtime.call_builtin(&self.name, &self.args)
+                    .unwrap_or(Err(error))
+                    .map_err(|error| error.or_position(self.position));
             },
         };
 
-        let num_params = definition.borrow().parameters.len();
+        let num_params = definition.parameters.len();
 
         if self.args.list.len() != num_params {
             return Err(Error::new(
                 Signature {
-                    function_name: self.name.clone(),
-                    expected_args: num_params,
+                    function_name: self.name.to_string(),
+                    expected_args: Arity::Exact(num_params),
                     passed_args: self.args.list.len()
                 },
-                None,
+                Some(self.position),
             ));
         }
 
-        let params: Vec<String> = definition.borrow().parameters.to_vec();
+        // the arguments are evaluated left to right, in the caller's scope, before any of the
+        // call's parameters exist to shadow the names they use
         let mut values = Vec::new();
         for arg in &self.args.list {
             values.push(arg.evaluate(runtime)?);
         }
 
-        for (param, value) in params.iter().zip(values) {
-            runtime.set_variable(param, value);
+        runtime.begin_call();
+        let flow = self.bind_parameters(runtime, &definition, values)
+            .and_then(|()| definition.block.execute(runtime));
+        runtime.end_call();
+        match flow? {
+            Flow::Normal => Ok(Value::List(vec![])),
+            Flow::Return(value) => Ok(value),
+            // the parser only allows these inside loops, so this is only reachable from trees
+            // built by hand
+            flow => Err(Error::new(flow.escaped_error().unwrap(), Some(self.position))),
         }
-        
-        let return_value = match definition.borrow().block.execute(runtime) {
-            Ok(_) => Ok(Value::List(vec![])),
-            Err(error) => match error.kind {
-                Return(value) => Ok(value),
-                _ => Err(error),
-            },  
-        };
-        runtime.end_scope();
-        return_value
+    }
+
+    /// Calls the function for a `fir` loop to iterate over. Builtins such as `arnge` give their
+    /// elements one at a time rather than as a `lsit`, except under hooks, which are passed the
+    /// value every call returns.
+    fn iterate(&self, runtime: &mut Runtime) -> Result<Elements, Error> {
+        match runtime.get_function_definition(self.name) {
+            Err(error) if runtime.hooks().is_none() => {
+                runtime.debug_call(&self.name);
+                runtime.iterate_builtin(&self.name, &self.args)
+                    .unwrap_or(Err(error))
+                    .map_err(|error| error.or_position(self.position))
+            },
+            _ => Ok(Box::new(self.evaluate(runtime)?.loop_elements().into_iter())),
+        }
+    }
+}
+
+impl Evaluable for FunctionCallNode {
+    fn evaluate(&self, runtime: &mut Runtime) -> Result<Value, Error> {
+        runtime.debug_call(&self.name);
+        runtime.descend().map_err(|error| error.or_position(self.position))?;
+        if let Some(hooks) = runtime.hooks() {
+            hooks.enter_call(&self.name, self.position);
+        }
+        let result = self.call(runtime);
+        if let Some(hooks) = runtime.hooks() {
+            hooks.exit_call(&self.name, &result);
+        }
+        runtime.ascend();
+        result
     }
 
     fn to_expression(self) -> ExpressionNode {
         ExpressionNode::FunctionCall(self)
     }
 }
+
 impl Executable for FunctionCallNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
         self.evaluate(runtime)?;
-        Ok(())
+        Ok(Flow::Normal)
     }
 
     fn to_statement(self) -> StatementNode {
@@ -319,6 +709,7 @@ impl Executable for FunctionCallNode {
 
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     statements: Vec<StatementNode>,
 }
@@ -331,20 +722,61 @@ impl Block {
         self.statements.push(statement);
     }
 
-    fn execute_in_new_scope(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    pub fn statements(&self) -> &[StatementNode] {
+        &self.statements
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, StatementNode> {
+        self.statements.iter()
+    }
+
+    /// Defines every function defined directly in the block before any of its statements run,
+    /// so that a function can be called from earlier in the block than its definition, and
+    /// functions can call each other whichever is defined first. A function defined more than
+    /// once is hoisted as its first definition, which is the one in effect until the next.
+    fn hoist_functions(&self, runtime: &mut Runtime) -> Result<(), Error> {
+        // going backwards leaves the first definition of each name in place
+        for statement in self.statements.iter().rev() {
+            if let StatementNode::FunctionDefinition(node) = statement {
+                runtime.check_function_not_frozen(node.name)
+                    .map_err(|error| error.or_position(node.position))?;
+                runtime.set_function_definition(node.name, Rc::clone(node));
+            }
+        }
+        Ok(())
+    }
+
+    fn execute_in_new_scope(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
         runtime.begin_scope();
-        self.execute(runtime)?;
+        let flow = self.execute(runtime);
         runtime.end_scope();
-        Ok(())
+        flow
     }
 }
 
+impl<'a> IntoIterator for &'a Block {
+    type Item = &'a StatementNode;
+    type IntoIter = std::slice::Iter<'a, StatementNode>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.statements.iter()
+    }
+}
+impl FromIterator<StatementNode> for Block {
+    fn from_iter<I: IntoIterator<Item = StatementNode>>(statements: I) -> Block {
+        Block { statements: statements.into_iter().collect() }
+    }
+}
 impl Executable for Block {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        self.hoist_functions(runtime)?;
         for statement in &self.statements {
-            statement.execute(runtime)?;
+            let flow = statement.execute(runtime)?;
+            if flow != Flow::Normal {
+                return Ok(flow);
+            }
         }
-        Ok(())
+        Ok(Flow::Normal)
     }
 
     fn to_statement(self) -> StatementNode {
@@ -353,21 +785,39 @@ impl Executable for Block {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AssignNode {
-    target: String,
+    target: Symbol,
     expression: ExpressionNode,
+    position: Position,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    slot: Option<Slot>,
 }
 impl AssignNode {
-    pub fn new(target: String, expression: ExpressionNode) -> AssignNode {
-        AssignNode { target, expression }
+    /// Creates an assignment, positioned at its target.
+    pub fn new(target: impl Into<Symbol>, expression: ExpressionNode, position: Position) -> AssignNode {
+        AssignNode { target: target.into(), expression, position, slot: None }
+    }
+
+    pub fn target(&self) -> &str {
+        self.target.as_str()
+    }
+
+    pub fn expression(&self) -> &ExpressionNode {
+        &self.expression
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
 }
 
 impl Executable for AssignNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
         let value = self.expression.evaluate(runtime)?;
-        runtime.set_variable(&self.target, value);
-        Ok(())
+        runtime.record_assignment(self.position, &self.target, &value)?;
+        runtime.set_variable_at(self.target, self.slot, value)?;
+        Ok(Flow::Normal)
     }
 
     fn to_statement(self) -> StatementNode {
@@ -377,28 +827,45 @@ impl Executable for AssignNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConditionalNode {
     conditional_paths: Vec<ConditionalPath>,
     else_block: Option<Block>,
+    position: Position,
 }
 impl ConditionalNode {
-    pub fn new(conditional_paths: Vec<ConditionalPath>, else_block: Option<Block>) -> ConditionalNode {
-        ConditionalNode { conditional_paths, else_block }
+    /// Creates a conditional, positioned at its `fi`.
+    pub fn new(conditional_paths: Vec<ConditionalPath>, else_block: Option<Block>, position: Position)
+        -> ConditionalNode
+    {
+        ConditionalNode { conditional_paths, else_block, position }
+    }
+
+    /// Returns the `fi` path followed by any `lefi` paths, in order.
+    pub fn conditional_paths(&self) -> &[ConditionalPath] {
+        &self.conditional_paths
+    }
+
+    pub fn else_block(&self) -> Option<&Block> {
+        self.else_block.as_ref()
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
 }
 
 impl Executable for ConditionalNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
         for ConditionalPath { condition, block: path } in &self.conditional_paths {
             if condition.evaluate(runtime)?.coerce_to_bool() {
-                path.execute_in_new_scope(runtime)?;
-                return Ok(());
+                return path.execute_in_new_scope(runtime);
             }
         }
-        if let Some(block) = &self.else_block {
-            block.execute_in_new_scope(runtime)?;
+        match &self.else_block {
+            Some(block) => block.execute_in_new_scope(runtime),
+            None => Ok(Flow::Normal),
         }
-        Ok(())
     }
 
     fn to_statement(self) -> StatementNode {
@@ -407,6 +874,7 @@ impl Executable for ConditionalNode {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConditionalPath {
     condition: ExpressionNode,
     block: Block,
@@ -415,33 +883,65 @@ impl ConditionalPath {
     pub fn new(condition: ExpressionNode, block: Block) -> ConditionalPath {
         ConditionalPath { condition, block }
     }
+
+    pub fn condition(&self) -> &ExpressionNode {
+        &self.condition
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
 }
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhileLoopNode {
     condition: ExpressionNode,
     block: Block,
+    position: Position,
 }
 impl WhileLoopNode {
-    pub fn new(condition: ExpressionNode, block: Block) -> WhileLoopNode {
-        WhileLoopNode { condition, block }
+    /// Creates a while loop, positioned at its `whitl`.
+    pub fn new(condition: ExpressionNode, block: Block, position: Position) -> WhileLoopNode {
+        WhileLoopNode { condition, block, position }
+    }
+
+    pub fn condition(&self) -> &ExpressionNode {
+        &self.condition
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    fn execute_loop(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        loop {
+            // the condition is evaluated in the iteration's scope
+            runtime.begin_iteration();
+            if !self.condition.evaluate(runtime)?.coerce_to_bool() {
+                break;
+            }
+            // catch breaks and continues, but let returns carry on out of the loop
+            match self.block.execute(runtime)? {
+                Flow::Normal | Flow::Continue => {},
+                Flow::Break => break,
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
     }
 }
 impl Executable for WhileLoopNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
         runtime.begin_scope();
-        while self.condition.evaluate(runtime)?.coerce_to_bool() {
-            // execute the loop block, catching any propagated breaks or continues
-            match self.block.execute(runtime) {
-                Ok(_) => {},
-                Err(Error { kind: Continue, ..}) => continue,
-                Err(Error { kind: Break, ..}) => break,
-                Err(other_error) => return Err(other_error),
-            }
-        }
+        let flow = self.execute_loop(runtime);
         runtime.end_scope();
-        Ok(())
+        flow
     }
 
     fn to_statement(self) -> StatementNode {
@@ -450,35 +950,67 @@ impl Executable for WhileLoopNode {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ForLoopNode {
     iterable: ExpressionNode,
-    loop_variable: String,
+    loop_variable: Symbol,
     block: Block,
+    position: Position,
 }
 impl ForLoopNode {
-    pub fn new(iterable: ExpressionNode, loop_variable: String, block: Block) -> ForLoopNode {
-        ForLoopNode { iterable, loop_variable, block }
+    /// Creates a for loop, positioned at its `fir`.
+    pub fn new(iterable: ExpressionNode, loop_variable: impl Into<Symbol>, block: Block, position: Position)
+        -> ForLoopNode
+    {
+        ForLoopNode { iterable, loop_variable: loop_variable.into(), block, position }
+    }
+
+    pub fn iterable(&self) -> &ExpressionNode {
+        &self.iterable
+    }
+
+    pub fn loop_variable(&self) -> &str {
+        self.loop_variable.as_str()
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+
+    fn execute_loop(&self, runtime: &mut Runtime, iterable: impl Iterator<Item = Value>) -> Result<Flow, Error> {
+        for element in iterable {
+            runtime.begin_iteration();
+            runtime.record_assignment(self.position, &self.loop_variable, &element)?;
+            runtime.set_variable(self.loop_variable, element)?;
+            // catch breaks and continues, but let returns carry on out of the loop
+            match self.block.execute(runtime)? {
+                Flow::Normal | Flow::Continue => {},
+                Flow::Break => break,
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
     }
 }
 impl Executable for ForLoopNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
-        let iterable = self.iterable.evaluate(runtime)?.coerce_to_list();
-        if iterable.is_empty() {
-            return Ok(());
-        }
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        // calls are iterated over lazily where they can be, so that e.g. a long `arnge` is never
+        // built up in full
+        let mut iterable = match &self.iterable {
+            ExpressionNode::FunctionCall(call) => call.iterate(runtime)?,
+            iterable => Box::new(iterable.evaluate(runtime)?.loop_elements().into_iter()),
+        };
+        let Some(first) = iterable.next() else {
+            return Ok(Flow::Normal);
+        };
         runtime.begin_scope();
-        for element in &iterable {
-            runtime.set_variable(&self.loop_variable, element.clone());
-            // execute the loop block, catching any propagated breaks or continues
-            match self.block.execute(runtime) {
-                Ok(_) => {},
-                Err(Error { kind: Continue, ..}) => continue,
-                Err(Error { kind: Break, ..}) => break,
-                Err(other_error) => return Err(other_error),
-            }
-        }
+        let flow = self.execute_loop(runtime, iter::once(first).chain(iterable));
         runtime.end_scope();
-        Ok(())
+        flow
     }
 
     fn to_statement(self) -> StatementNode {
@@ -488,10 +1020,22 @@ impl Executable for ForLoopNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct BreakNode;
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BreakNode {
+    position: Position,
+}
+impl BreakNode {
+    pub fn new(position: Position) -> BreakNode {
+        BreakNode { position }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+}
 impl Executable for BreakNode {
-    fn execute(&self, _runtime: &mut Runtime) -> Result<(), Error> {
-        Err(Error::new(Break, None))
+    fn execute(&self, _runtime: &mut Runtime) -> Result<Flow, Error> {
+        Ok(Flow::Break)
     }
 
     fn to_statement(self) -> StatementNode {
@@ -501,10 +1045,22 @@ impl Executable for BreakNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
-pub struct ContinueNode;
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ContinueNode {
+    position: Position,
+}
+impl ContinueNode {
+    pub fn new(position: Position) -> ContinueNode {
+        ContinueNode { position }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+}
 impl Executable for ContinueNode {
-    fn execute(&self, _runtime: &mut Runtime) -> Result<(), Error> {
-        Err(Error::new(Continue, None))
+    fn execute(&self, _runtime: &mut Runtime) -> Result<Flow, Error> {
+        Ok(Flow::Continue)
     }
 
     fn to_statement(self) -> StatementNode {
@@ -513,19 +1069,54 @@ impl Executable for ContinueNode {
 }
 
 
+/// A `pas`, which does nothing, for blocks that have nothing to do.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PassNode {
+    position: Position,
+}
+impl PassNode {
+    pub fn new(position: Position) -> PassNode {
+        PassNode { position }
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
+}
+impl Executable for PassNode {
+    fn execute(&self, _runtime: &mut Runtime) -> Result<Flow, Error> {
+        Ok(Flow::Normal)
+    }
+
+    fn to_statement(self) -> StatementNode {
+        StatementNode::Pass(self)
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReturnNode {
     return_value: ExpressionNode,
+    position: Position,
 }
 impl ReturnNode {
-    pub fn new(return_value: ExpressionNode) -> ReturnNode {
-        ReturnNode { return_value }
+    pub fn new(return_value: ExpressionNode, position: Position) -> ReturnNode {
+        ReturnNode { return_value, position }
+    }
+
+    pub fn return_value(&self) -> &ExpressionNode {
+        &self.return_value
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
     }
 }
 impl Executable for ReturnNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
-        let return_value = self.return_value.evaluate(runtime)?;
-        Err(Error::new(Return(return_value), None))
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        Ok(Flow::Return(self.return_value.evaluate(runtime)?))
     }
 
     fn to_statement(self) -> StatementNode {
@@ -535,26 +1126,50 @@ impl Executable for ReturnNode {
 
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FunctionDefinitionNode {
-    name: String,
-    parameters: Vec<String>,
+    name: Symbol,
+    parameters: Vec<Symbol>,
     block: Block,
+    position: Position,
 }
 impl FunctionDefinitionNode {
-    pub fn new(name: String, parameters: Vec<String>, block: Block) -> FunctionDefinitionNode {
+    /// Creates a function definition, positioned at its `fnuc`.
+    pub fn new(name: impl Into<Symbol>, parameters: Vec<Symbol>, block: Block, position: Position)
+        -> FunctionDefinitionNode
+    {
         FunctionDefinitionNode {
-            name, parameters, block,
+            name: name.into(), parameters, block, position,
         }
     }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn parameters(&self) -> &[Symbol] {
+        &self.parameters
+    }
+
+    pub fn block(&self) -> &Block {
+        &self.block
+    }
+
+    pub fn position(&self) -> Position {
+        self.position
+    }
 }
 impl Executable for FunctionDefinitionNode {
-    fn execute(&self, runtime: &mut Runtime) -> Result<(), Error> {
-        runtime.set_function_definition(&self.name, RefCell::new(self.clone()));
-        Ok(())
+    /// Defines the function with a copy of this definition. Definitions run as statements are
+    /// shared with the runtime instead.
+    fn execute(&self, runtime: &mut Runtime) -> Result<Flow, Error> {
+        runtime.check_function_not_frozen(self.name)?;
+        runtime.set_function_definition(self.name, Rc::new(self.clone()));
+        Ok(Flow::Normal)
     }
 
     fn to_statement(self) -> StatementNode {
-        StatementNode::FunctionDefinition(self)
+        StatementNode::FunctionDefinition(Rc::new(self))
     }
 }
 
@@ -562,4 +1177,442 @@ impl Executable for FunctionDefinitionNode {
 #[cfg(test)]
 mod tests {
    //TODO: write tests for all AST node evaluations and executions
-}
\ No newline at end of file
+
+    mod position_tests {
+        use crate::error::ErrorKind;
+        use crate::lexer::Position;
+
+        fn error_position(source: &str) -> Option<Position> {
+            crate::run_in(source, &mut crate::runtime::Runtime::new()).unwrap_err().pos
+        }
+
+        #[test]
+        fn name_errors_point_at_variable() {
+            assert_eq!(Some(Position::new(2, 5, 2)), error_position("x = 1\n y = zz"));
+        }
+
+        #[test]
+        fn undefined_function_errors_point_at_call() {
+            assert_eq!(Some(Position::new(1, 4, 7)), error_position("x = nope(()"));
+            assert_eq!(Some(Position::new(1, 0, 7)), error_position("nope(()"));
+        }
+
+        #[test]
+        fn signature_errors_point_at_call() {
+            assert_eq!(
+                Some(Position::new(3, 1, 4)),
+                error_position("fnuc f((a)\n   retrun a\n f(()"),
+            );
+            assert_eq!(Some(Position::new(1, 0, 9)), error_position("inptu((1)"));
+        }
+
+        #[test]
+        fn operator_errors_point_at_operator() {
+            assert_eq!(Some(Position::new(1, 13, 1)), error_position("x = \"%s %s\"\" % [1]]"));
+        }
+
+        #[test]
+        fn format_string_errors_point_at_the_specifier() {
+            assert_eq!(Some(Position::new(1, 8, 1)), error_position("x = \"\"a %q\" % [[1]"));
+            assert_eq!(Some(Position::new(2, 3, 1)), error_position("x = \"\"é\n%s %5$s\" % [[1]"));
+            // format strings that aren't literals can only be pointed at by their operator
+            assert_eq!(Some(Position::new(2, 7, 1)), error_position("f = \"\"%q\"\n x = f % [[1]"));
+        }
+
+        #[test]
+        fn limit_errors_point_at_statement() {
+            let mut runtime = crate::runtime::Runtime::with_limits(Some(1), None);
+            let error = crate::run_in("x = 1\n y = 2", &mut runtime).unwrap_err();
+            assert!(matches!(error.kind, ErrorKind::LimitExceeded(_)));
+            assert_eq!(Some(Position::new(2, 1, 1)), error.pos);
+        }
+    }
+
+    mod strict_type_tests {
+        use crate::error::{Error, ErrorKind};
+        use crate::lexer::Position;
+        use crate::runtime::Runtime;
+
+        fn run_strictly(source: &str) -> Result<(), Error> {
+            let mut runtime = Runtime::new();
+            runtime.set_strict_types(true);
+            crate::run_in(source, &mut runtime)
+        }
+
+        fn type_error(expected: &str, found: &str) -> ErrorKind {
+            ErrorKind::Type { expected: expected.to_string(), found: found.to_string() }
+        }
+
+        #[test]
+        fn operands_of_the_same_type_are_allowed() {
+            run_strictly("x = 1 + 2.5
+ y = \"\"a\" + \"\"b\"\nz = [[1] + [[2]").unwrap();
+            run_strictly("x = \"\"ab\" * 3
+ y = \"\"%s\" % [[1]\nz = [[1] < [[2]\n w = 1 === \"\"1\"").unwrap();
+            run_strictly("x = [[1, 2] - 1\n y = [[1, 2] % \"\"a\"").unwrap();
+        }
+
+        #[test]
+        fn mixed_operands_are_type_errors() {
+            let error = run_strictly("x = 5 + \"\"a\"").unwrap_err();
+            assert_eq!(type_error("nmu", "sting"), error.kind);
+            assert_eq!(Some(Position::new(1, 6, 1)), error.pos);
+            assert_eq!(type_error("lsit", "nmu"), run_strictly("x = \"\"%s\" % 1").unwrap_err().kind);
+            assert_eq!(type_error("nmu", "obol"), run_strictly("x = rtue > 1").unwrap_err().kind);
+            assert_eq!(type_error("obol", "nmu"), run_strictly("x = rtue == 1").unwrap_err().kind);
+            assert_eq!(type_error("obol", "nmu"), run_strictly("x = rtue adn 1").unwrap_err().kind);
+        }
+
+        #[test]
+        fn logical_operators_check_the_operands_they_evaluate() {
+            run_strictly("x = flase adn 1
+ y = rtue ro 1").unwrap();
+            assert_eq!(type_error("obol", "nmu"), run_strictly("x = 0 adn rtue").unwrap_err().kind);
+        }
+
+        #[test]
+        fn mixed_operands_coerce_by_default() {
+            crate::run("x = 5 + \"\"a\"").unwrap();
+        }
+    }
+
+    mod flow_tests {
+        use crate::ast::Executable;
+        use crate::error::ErrorKind;
+        use crate::lexer::Position;
+        use crate::runtime::Runtime;
+        use crate::value::Value;
+
+        #[test]
+        fn return_from_inside_loop_unwinds_scopes() {
+            let mut runtime = Runtime::new();
+            let depth = runtime.depth();
+            crate::run_in(
+                "fnuc f(()\n   fir i ni arnge((3)\n      retrun i\n    retrun 5\nx = f(()",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Number(0.0), *runtime.get_variable("x").unwrap());
+            assert_eq!(depth, runtime.depth());
+        }
+
+        #[test]
+        fn keywords_without_a_trailing_newline() {
+            let mut runtime = Runtime::new();
+            crate::run_in("n = 0\n whitl rtue\n    n = n + 1\n     brek", &mut runtime).unwrap();
+            assert_eq!(Value::Number(1.0), *runtime.get_variable("n").unwrap());
+        }
+
+        #[test]
+        fn break_inside_conditional_unwinds_scopes() {
+            let mut runtime = Runtime::new();
+            let depth = runtime.depth();
+            crate::run_in(
+                "n = 0\n fir i ni arnge((5)\n    fi i == 2\n       brek\n     n = n + 1",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Number(2.0), *runtime.get_variable("n").unwrap());
+            assert_eq!(depth, runtime.depth());
+        }
+
+        #[test]
+        fn loops_cannot_be_broken_out_of_from_functions() {
+            let error = crate::run("fir i ni arnge((2)\n   fnuc f(()\n      brek\n").unwrap_err();
+            assert_eq!(ErrorKind::BreakOutsideLoop, error.kind);
+            assert_eq!(Some(Position::new(3, 6, 4)), error.pos);
+        }
+
+        #[test]
+        fn function_definitions_are_shared_with_runtime() {
+            let tokens = crate::lexer::Lexer::new("fnuc f(()\n   retrun 1").lex().unwrap();
+            let ast = crate::parser::Parser::new(&tokens).parse().unwrap();
+            let mut runtime = Runtime::new();
+            ast.execute(&mut runtime).unwrap();
+            let first = runtime.get_function_definition("f").unwrap();
+            ast.execute(&mut runtime).unwrap();
+            let second = runtime.get_function_definition("f").unwrap();
+            assert!(std::rc::Rc::ptr_eq(&first, &second));
+        }
+
+        #[test]
+        fn functions_can_be_called_before_their_definitions() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "x = eevn((4)\n fnuc eevn((n)\n   fi n == 0\n      retrun rtue\n    retrun odd((n - 1)\n\
+                 fnuc odd((n)\n   fi n == 0\n      retrun flase\n    retrun eevn((n - 1)",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Bool(true), *runtime.get_variable("x").unwrap());
+        }
+
+        #[test]
+        fn redefined_functions_are_hoisted_as_their_first_definition() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "x = f(()\n fnuc f(()\n   retrun 1\nfnuc f(()\n   retrun 2\n y = f(()",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Int(1), *runtime.get_variable("x").unwrap());
+            assert_eq!(Value::Int(2), *runtime.get_variable("y").unwrap());
+        }
+
+        #[test]
+        fn functions_are_only_hoisted_within_their_block() {
+            let error = crate::run("x = f(()\n fi flase\n   fnuc f(()\n      retrun 1").unwrap_err();
+            assert_eq!(ErrorKind::Name("f".to_string()), error.kind);
+        }
+
+        fn run_counting(source: &str, shared_loop_scopes: bool) -> Value {
+            let mut runtime = Runtime::new();
+            runtime.set_shared_loop_scopes(shared_loop_scopes);
+            crate::run_in(source, &mut runtime).unwrap();
+            runtime.get_variable("n").unwrap().clone()
+        }
+
+        #[test]
+        fn for_loop_iterations_have_their_own_scopes() {
+            let source = "n = 0\n fir i ni arnge((3)\n    fi defnied((\"\"seen\")\n       n = n + 1\n     seen = i";
+            assert_eq!(Value::Int(0), run_counting(source, false));
+            assert_eq!(Value::Int(2), run_counting(source, true));
+        }
+
+        #[test]
+        fn while_loop_iterations_have_their_own_scopes() {
+            let source = "n = 0\n whitl n < 3 adn flase === defnied((\"\"seen\")\n    seen = rtue\n     n = n + 1";
+            assert_eq!(Value::Int(3), run_counting(source, false));
+            assert_eq!(Value::Int(1), run_counting(source, true));
+        }
+
+        #[test]
+        fn loop_scopes_end_with_the_loop() {
+            for shared_loop_scopes in [false, true] {
+                let mut runtime = Runtime::new();
+                runtime.set_shared_loop_scopes(shared_loop_scopes);
+                let depth = runtime.depth();
+                crate::run_in("fir i ni arnge((2)\n   x = i", &mut runtime).unwrap();
+                assert!(!runtime.has_variable("x"));
+                assert_eq!(depth, runtime.depth());
+            }
+        }
+
+        #[test]
+        fn strings_are_iterated_over_by_character() {
+            let mut runtime = Runtime::new();
+            crate::run_in("s = \"\">\"\n fir c ni \"\"hé!\"\n    s = s + c + \"\"-\"", &mut runtime).unwrap();
+            assert_eq!(Value::from(">h-é-!-"), *runtime.get_variable("s").unwrap());
+        }
+
+        #[test]
+        fn ranges_are_iterated_without_being_built_up() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "n = 0\n fir i ni arnge((1000000000000)\n    fi i == 3\n       brek\n     n = n + i",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Int(3), *runtime.get_variable("n").unwrap());
+        }
+
+        #[test]
+        fn ranges_are_lists_outside_loops() {
+            let mut runtime = Runtime::new();
+            crate::run_in("r = arnge((3)", &mut runtime).unwrap();
+            assert_eq!(
+                Value::List(vec![Value::Int(0), Value::Int(1), Value::Int(2)]),
+                *runtime.get_variable("r").unwrap(),
+            );
+        }
+
+        #[test]
+        fn loops_over_calls_prefer_functions_to_builtins() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "fnuc arnge((n)\n   retrun [n]]\nn = 0\n fir i ni arnge((5)\n    n = n + i",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Int(5), *runtime.get_variable("n").unwrap());
+        }
+
+        #[test]
+        fn loops_over_builtins_check_their_arguments() {
+            let error = crate::run("fir i ni arnge(()\n   pas").unwrap_err();
+            assert_eq!(
+                ErrorKind::Signature {
+                    function_name: "arnge".to_string(),
+                    expected_args: crate::builtins::Arity::Between(1, 3),
+                    passed_args: 0,
+                },
+                error.kind,
+            );
+            assert_eq!(Some(Position::new(1, 9, 8)), error.pos);
+        }
+
+        #[test]
+        fn return_outside_function_is_rejected() {
+            let error = crate::run("retrun 1").unwrap_err();
+            assert_eq!(ErrorKind::ReturnOutsideFunction, error.kind);
+            assert_eq!(Some(Position::new(1, 0, 6)), error.pos);
+        }
+    }
+
+    mod logical_operator_tests {
+        use crate::error::ErrorKind;
+        use crate::runtime::Runtime;
+        use crate::value::Value;
+
+        fn evaluate(expression: &str) -> Value {
+            let mut runtime = Runtime::new();
+            crate::run_in(&format!("x = {expression}"), &mut runtime).unwrap();
+            runtime.get_variable("x").unwrap().clone()
+        }
+
+        #[test]
+        fn combine_the_truth_of_their_operands() {
+            assert_eq!(Value::Bool(true), evaluate("1 adn \"\"a\""));
+            assert_eq!(Value::Bool(false), evaluate("rtue adn [[]"));
+            assert_eq!(Value::Bool(true), evaluate("0 ro 2"));
+            assert_eq!(Value::Bool(false), evaluate("flase ro 0"));
+        }
+
+        #[test]
+        fn bind_looser_than_comparisons_and_adn_tighter_than_ro() {
+            assert_eq!(Value::Bool(true), evaluate("1 < 2 adn 3 > 2"));
+            assert_eq!(Value::Bool(true), evaluate("rtue ro flase adn flase"));
+            assert_eq!(Value::Bool(false), evaluate("((rtue ro flase) adn flase"));
+        }
+
+        #[test]
+        fn skip_the_right_operand_when_the_left_decides() {
+            assert_eq!(Value::Bool(false), evaluate("flase adn undefined"));
+            assert_eq!(Value::Bool(true), evaluate("rtue ro undefined"));
+            assert_eq!(Value::Bool(false), evaluate("defnied((\"\"y\") adn y > 0"));
+        }
+
+        #[test]
+        fn evaluate_the_right_operand_when_the_left_does_not_decide() {
+            let error = crate::run("x = rtue adn undefined").unwrap_err();
+            assert_eq!(ErrorKind::Name("undefined".to_string()), error.kind);
+            let error = crate::run("x = flase ro undefined").unwrap_err();
+            assert_eq!(ErrorKind::Name("undefined".to_string()), error.kind);
+        }
+    }
+
+    mod scoping_tests {
+        use crate::error::ErrorKind;
+        use crate::runtime::Runtime;
+        use crate::value::Value;
+
+        const CALLER_AND_CALLEE: &str =
+            "fnuc f(()\n   x = 2\n    retrun x\nfnuc g(()\n   x = 1\n    _y = f(()\n     retrun x\nz = g(()";
+
+        #[test]
+        fn calls_cannot_assign_to_their_callers_variables() {
+            let mut runtime = Runtime::new();
+            crate::run_in(CALLER_AND_CALLEE, &mut runtime).unwrap();
+            assert_eq!(Value::Int(1), *runtime.get_variable("z").unwrap());
+        }
+
+        #[test]
+        fn calls_cannot_read_their_callers_variables() {
+            let error = crate::run("fnuc f(()\n   retrun x\nfnuc g(()\n   x = 1\n    retrun f(()\ny = g(()")
+                .unwrap_err();
+            assert_eq!(ErrorKind::Name("x".to_string()), error.kind);
+        }
+
+        #[test]
+        fn parameters_shadow_globals() {
+            let mut runtime = Runtime::new();
+            crate::run_in("x = 1\n fnuc f((x)\n   retrun x\ny = f((2)", &mut runtime).unwrap();
+            assert_eq!(Value::Int(1), *runtime.get_variable("x").unwrap());
+            assert_eq!(Value::Int(2), *runtime.get_variable("y").unwrap());
+        }
+
+        #[test]
+        fn calls_can_assign_to_globals() {
+            let mut runtime = Runtime::new();
+            crate::run_in("n = 0\n fnuc bmup(()\n   n = n + 1\nbmup(()\n bmup(()", &mut runtime).unwrap();
+            assert_eq!(Value::Int(2), *runtime.get_variable("n").unwrap());
+        }
+
+        #[test]
+        fn failed_calls_close_their_scopes() {
+            let mut runtime = Runtime::new();
+            let depth = runtime.depth();
+            crate::run_in("x = 1", &mut runtime).unwrap();
+            assert!(crate::run_in("fnuc f((a)\n   retrun a + y\nf((1)", &mut runtime).is_err());
+            assert_eq!(depth, runtime.depth());
+            assert_eq!(Value::Int(1), *runtime.get_variable("x").unwrap());
+        }
+
+        #[test]
+        fn arguments_are_evaluated_in_order_before_the_call() {
+            let source = "x = 1\n fnuc sret((v)\n   x = v\n    retrun v\nfnuc iden((v)\n   retrun v\n\
+                          a = [[sret((5), x]\n b = x + sret((7)\nc = sret((2) + iden((x)\n \
+                          fnuc pari((x, y)\n   retrun [[x, y]\nd = pari((x + 1, x)";
+            for dynamic_scoping in [false, true] {
+                let mut runtime = Runtime::new();
+                runtime.set_dynamic_scoping(dynamic_scoping);
+                crate::run_in(source, &mut runtime).unwrap();
+                let variable = |name| runtime.get_variable(name).unwrap().clone();
+                assert_eq!(Value::from(vec![Value::Int(5), Value::Int(5)]), variable("a"));
+                assert_eq!(Value::Int(12), variable("b"));
+                assert_eq!(Value::Int(4), variable("c"));
+                assert_eq!(Value::from(vec![Value::Int(3), Value::Int(2)]), variable("d"));
+            }
+        }
+
+        #[test]
+        fn dynamic_scoping_shares_variables_with_callers() {
+            let mut runtime = Runtime::new();
+            runtime.set_dynamic_scoping(true);
+            crate::run_in(CALLER_AND_CALLEE, &mut runtime).unwrap();
+            assert_eq!(Value::Int(2), *runtime.get_variable("z").unwrap());
+        }
+    }
+
+    mod accessor_tests {
+        use crate::ast::*;
+        use crate::lexer::Lexer;
+        use crate::parser::Parser;
+
+        #[test]
+        fn nodes_can_be_inspected() {
+            let source = "fnuc f((a, b)\n   retrun a + b\nx = f((1, 2)";
+            let ast = Parser::new(&Lexer::new(source).lex().unwrap()).parse().unwrap();
+            let [StatementNode::FunctionDefinition(definition), StatementNode::Assign(assign)] =
+                ast.statements() else {
+                panic!("unexpected statements {:?}", ast.statements());
+            };
+            assert_eq!("f", definition.name());
+            assert_eq!([Symbol::intern("a"), Symbol::intern("b")].as_slice(), definition.parameters());
+            let Some(StatementNode::Return(ret)) = definition.block().iter().next() else {
+                panic!("expected a return");
+            };
+            let ExpressionNode::Operator(operator) = ret.return_value() else {
+                panic!("expected an operation");
+            };
+            assert_eq!(Operator::Add, *operator.operator());
+            assert!(matches!(operator.lhs(), ExpressionNode::Variable(variable) if variable.name() == "a"));
+
+            assert_eq!("x", assign.target());
+            let ExpressionNode::FunctionCall(call) = assign.expression() else {
+                panic!("expected a call");
+            };
+            assert_eq!(("f", 2), (call.name(), call.args().elements().len()));
+            assert_eq!(2, (&ast).into_iter().count());
+        }
+
+        #[test]
+        fn blocks_can_be_built_from_statements() {
+            let position = Position::new(1, 0, 1);
+            let constant = |number| ConstantNode::new(Value::Number(number), position).to_expression();
+            let x = VariableNode::new("x".to_string(), position).to_expression();
+            let sum = OperatorNode::new(x, constant(2.0), Operator::Add, position).to_expression();
+            let ast: Block = [
+                AssignNode::new("x".to_string(), constant(1.0), position).to_statement(),
+                AssignNode::new("y".to_string(), sum, position).to_statement(),
+            ].into_iter().collect();
+            let mut runtime = Runtime::new();
+            ast.execute(&mut runtime).unwrap();
+            assert_eq!(Value::Number(3.0), *runtime.get_variable("y").unwrap());
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use super::*;
+
+impl Block {
+    /// Lays the tree out for people to read, one node per line, with each node's children
+    /// indented beneath it and its span in the source alongside.
+    pub fn dump(&self) -> String {
+        let mut dumper = Dumper { output: String::new(), depth: 0 };
+        dumper.block(self);
+        dumper.output
+    }
+}
+
+struct Dumper {
+    output: String,
+    depth: usize,
+}
+impl Dumper {
+    fn line(&mut self, text: &str, position: Option<Position>) {
+        self.output += &"  ".repeat(self.depth);
+        self.output += text;
+        if let Some(position) = position {
+            self.output += &format!(" @ {position}");
+        }
+        self.output.push('\n');
+    }
+
+    /// Writes a line for a node, then its children indented beneath it.
+    fn node(&mut self, text: &str, position: Option<Position>, children: impl FnOnce(&mut Self)) {
+        self.line(text, position);
+        self.depth += 1;
+        children(self);
+        self.depth -= 1;
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.node("Block", None, |dumper| {
+            for statement in &block.statements {
+                dumper.statement(statement);
+            }
+        });
+    }
+
+    fn statement(&mut self, statement: &StatementNode) {
+        let position = statement.position();
+        match statement {
+            StatementNode::Block(block) => self.block(block),
+            StatementNode::Assign(node) => {
+                self.node(&format!("Assign {}", node.target), position, |dumper| {
+                    dumper.expression(&node.expression);
+                });
+            },
+            StatementNode::FunctionCall(node) => self.call(node),
+            StatementNode::Conditional(node) => self.node("Conditional", position, |dumper| {
+                for path in &node.conditional_paths {
+                    dumper.node("Path", None, |dumper| {
+                        dumper.expression(&path.condition);
+                        dumper.block(&path.block);
+                    });
+                }
+                if let Some(else_block) = &node.else_block {
+                    dumper.node("Else", None, |dumper| dumper.block(else_block));
+                }
+            }),
+            StatementNode::ForLoop(node) => {
+                self.node(&format!("ForLoop {}", node.loop_variable), position, |dumper| {
+                    dumper.expression(&node.iterable);
+                    dumper.block(&node.block);
+                });
+            },
+            StatementNode::WhileLoop(node) => self.node("WhileLoop", position, |dumper| {
+                dumper.expression(&node.condition);
+                dumper.block(&node.block);
+            }),
+            StatementNode::Break(_) => self.line("Break", position),
+            StatementNode::Continue(_) => self.line("Continue", position),
+            StatementNode::Pass(_) => self.line("Pass", position),
+            StatementNode::Return(node) => self.node("Return", position, |dumper| {
+                dumper.expression(&node.return_value);
+            }),
+            StatementNode::FunctionDefinition(node) => {
+                let parameters = node.parameters.iter().map(Symbol::as_str).collect::<Vec<_>>().join(", ");
+                let signature = format!("FunctionDefinition {}({parameters})", node.name);
+                self.node(&signature, position, |dumper| dumper.block(&node.block));
+            },
+        }
+    }
+
+    fn expression(&mut self, expression: &ExpressionNode) {
+        let position = Some(expression.position());
+        stack::guard(|| match expression {
+            ExpressionNode::Constant(node) => {
+                self.line(&format!("Constant {:?}", node.value), position);
+            },
+            ExpressionNode::List(node) => self.node("List", position, |dumper| {
+                for element in &node.list {
+                    dumper.expression(element);
+                }
+            }),
+            ExpressionNode::Operator(node) => {
+                self.node(&format!("Operator {:?}", node.operator), position, |dumper| {
+                    dumper.expression(&node.lhs);
+                    dumper.expression(&node.rhs);
+                });
+            },
+            ExpressionNode::Variable(node) => self.line(&format!("Variable {}", node.name), position),
+            ExpressionNode::FunctionCall(node) => self.call(node),
+        })
+    }
+
+    fn call(&mut self, call: &FunctionCallNode) {
+        self.node(&format!("FunctionCall {}", call.name), Some(call.position), |dumper| {
+            for arg in &call.args.list {
+                dumper.expression(arg);
+            }
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn dump(source: &str) -> String {
+        let tokens = Lexer::new(source).lex().unwrap();
+        Parser::new(&tokens).parse().unwrap().dump()
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            "Block\n\
+            \x20 Assign x @ 1:0-1:1\n\
+            \x20   Operator Add @ 1:6-1:7\n\
+            \x20     Constant Int(1) @ 1:4-1:5\n\
+            \x20     FunctionCall f @ 1:8-1:19\n\
+            \x20       Variable y @ 1:11-1:12\n\
+            \x20       List @ 1:14-1:16\n\
+            \x20         Constant Int(2) @ 1:16-1:17\n",
+            dump("x = 1 + f((y, [[2])"),
+        );
+    }
+
+    #[test]
+    fn nested_blocks() {
+        assert_eq!(
+            "Block\n\
+            \x20 FunctionDefinition f(a) @ 1:0-1:4\n\
+            \x20   Block\n\
+            \x20     Conditional @ 2:3-2:5\n\
+            \x20       Path\n\
+            \x20         Variable a @ 2:6-2:7\n\
+            \x20         Block\n\
+            \x20           Return @ 3:6-3:12\n\
+            \x20             Constant Bool(true) @ 3:13-3:17\n",
+            dump("fnuc f((a)\n   fi a\n      retrun rtue"),
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use super::*;
+use crate::lexer::Comment;
+
+impl Block {
+    /// Prints the tree back out as Mornington source, with consistent spacing. Every wrapper is
+    /// opened twice and closed once, and each line in a block alternates between the smallest
+    /// and next smallest indentation for its level, so the result always obeys the imbalance and
+    /// indentation rules.
+    pub fn to_source(&self) -> String {
+        self.to_source_with_comments(&[])
+    }
+
+    /// Prints the tree back out as [`to_source`](Block::to_source) does, putting `comments` from
+    /// the source it was parsed from back in. Those after the start of a statement on its first
+    /// line are kept at the end of that line, and the rest are each given a line of their own
+    /// before the statement after them.
+    pub fn to_source_with_comments(&self, comments: &[Comment]) -> String {
+        let mut printer = Printer { output: String::new(), comments, statement_line: None };
+        printer.block(self, 0);
+        printer.comments_before(None, 0, 0);
+        printer.output
+    }
+}
+
+impl FunctionDefinitionNode {
+    /// Prints the definition back out as Mornington source, in the same style as
+    /// [`Block::to_source`].
+    pub fn to_source(&self) -> String {
+        let mut printer = Printer { output: String::new(), comments: &[], statement_line: None };
+        printer.statement(&self.clone().to_statement(), 0, 0);
+        printer.output
+    }
+}
+
+struct Printer<'a> {
+    output: String,
+    /// The comments still to be printed.
+    comments: &'a [Comment<'a>],
+    /// The line the statement being printed started on, until its first line has been printed.
+    statement_line: Option<usize>,
+}
+impl Printer<'_> {
+    /// Appends `text`, separating it from what came before if the two would otherwise run
+    /// together into a single wrapper, e.g. the closers of two nested lists.
+    fn push(&mut self, text: &str) {
+        let last = self.output.chars().last();
+        let first = text.chars().next();
+        if last.is_some() && last == first && matches!(first, Some('(' | ')' | '[' | ']')) {
+            self.output.push(' ');
+        }
+        self.output += text;
+    }
+
+    fn indent(&mut self, level: usize, line: usize) {
+        // consecutive lines in a block can't be indented by the same amount, so alternate
+        // between the two
+        self.output += &" ".repeat(level * 3 + line % 2);
+    }
+
+    /// Ends the line, after any comments that followed the statement on its first line.
+    fn end_line(&mut self) {
+        if let Some(line) = self.statement_line.take() {
+            while let [comment, rest @ ..] = self.comments {
+                if comment.start.line != line {
+                    break;
+                }
+                self.output += " ";
+                self.output += comment.text;
+                self.comments = rest;
+            }
+        }
+        self.output.push('\n');
+    }
+
+    /// Prints the comments before `location`, or all of them if it's `None`, on lines of their
+    /// own.
+    fn comments_before(&mut self, location: Option<Location>, level: usize, line: usize) {
+        while let [comment, rest @ ..] = self.comments {
+            if location.is_some_and(|location| comment.start >= location) {
+                break;
+            }
+            self.indent(level, line);
+            self.output += comment.text;
+            self.output.push('\n');
+            self.comments = rest;
+        }
+    }
+
+    fn block(&mut self, block: &Block, level: usize) {
+        for (line, statement) in block.statements.iter().enumerate() {
+            self.statement(statement, level, line);
+        }
+    }
+
+    fn statement(&mut self, statement: &StatementNode, level: usize, line: usize) {
+        if let StatementNode::Block(block) = statement {
+            return self.block(block, level);
+        }
+        if let Some(position) = statement.position() {
+            self.comments_before(Some(position.start), level, line);
+            self.statement_line = Some(position.start.line);
+        }
+        self.indent(level, line);
+        match statement {
+            StatementNode::Block(_) => unreachable!(),
+            StatementNode::Assign(node) => {
+                self.push(&format!("{} = ", node.target));
+                self.expression(&node.expression, false);
+            },
+            StatementNode::FunctionCall(node) => self.call(node),
+            StatementNode::Conditional(node) => {
+                for (index, path) in node.conditional_paths.iter().enumerate() {
+                    if index > 0 {
+                        self.indent(level, line);
+                    }
+                    self.push(if index == 0 { "fi " } else { "lefi " });
+                    self.expression(&path.condition, false);
+                    self.end_line();
+                    self.block(&path.block, level + 1);
+                }
+                if let Some(else_block) = &node.else_block {
+                    self.indent(level, line);
+                    self.push("sele\n");
+                    self.block(else_block, level + 1);
+                }
+                return;
+            },
+            StatementNode::ForLoop(node) => {
+                self.push(&format!("fir {} ni ", node.loop_variable));
+                self.expression(&node.iterable, false);
+                self.end_line();
+                return self.block(&node.block, level + 1);
+            },
+            StatementNode::WhileLoop(node) => {
+                self.push("whitl ");
+                self.expression(&node.condition, false);
+                self.end_line();
+                return self.block(&node.block, level + 1);
+            },
+            StatementNode::Break(_) => self.push("brek"),
+            StatementNode::Continue(_) => self.push("cnotineu"),
+            StatementNode::Pass(_) => self.push("pas"),
+            StatementNode::Return(node) => {
+                self.push("retrun ");
+                self.expression(&node.return_value, false);
+            },
+            StatementNode::FunctionDefinition(node) => {
+                self.push(&format!("fnuc {}((", node.name));
+                self.push(&node.parameters.iter().map(Symbol::as_str).collect::<Vec<_>>().join(", "));
+                self.push(")");
+                self.end_line();
+                return self.block(&node.block, level + 1);
+            },
+        }
+        self.end_line();
+    }
+
+    /// Prints the expression. `followed` is whether an operator comes after it, in which case any
+    /// function call at its end must be bracketed, since a call ends the expression it's in.
+    fn expression(&mut self, expression: &ExpressionNode, followed: bool) {
+        stack::guard(|| match expression {
+            ExpressionNode::Constant(node) => self.value(&node.value),
+            ExpressionNode::List(node) => self.list(&node.list),
+            ExpressionNode::Operator(node) => {
+                let precedence = node.operator.precedence();
+                // operators are left associative, so a right-hand operand of the same precedence
+                // needs bracketing but a left-hand one doesn't
+                self.operand(&node.lhs, |operand| operand < precedence, true);
+                self.push(&format!(" {} ", node.operator.symbol()));
+                self.operand(&node.rhs, |operand| operand <= precedence, followed);
+            },
+            ExpressionNode::Variable(node) => self.push(&node.name),
+            ExpressionNode::FunctionCall(node) if followed => {
+                self.push("((");
+                self.call(node);
+                self.push(")");
+            },
+            ExpressionNode::FunctionCall(node) => self.call(node),
+        })
+    }
+
+    /// Prints one side of an operator, bracketing it if it's an operation that `binds_looser`
+    /// than the operator.
+    fn operand(&mut self,
+               operand: &ExpressionNode,
+               binds_looser: impl Fn(u32) -> bool,
+               followed: bool)
+    {
+        match operand {
+            ExpressionNode::Operator(node) if binds_looser(node.operator.precedence()) => {
+                self.push("((");
+                self.expression(operand, false);
+                self.push(")");
+            },
+            _ => self.expression(operand, followed),
+        }
+    }
+
+    fn call(&mut self, call: &FunctionCallNode) {
+        self.push(&format!("{}((", call.name));
+        self.comma_separated(&call.args.list);
+        self.push(")");
+    }
+
+    fn list(&mut self, list: &[ExpressionNode]) {
+        self.push("[[");
+        self.comma_separated(list);
+        self.push("]");
+    }
+
+    fn comma_separated(&mut self, expressions: &[ExpressionNode]) {
+        for (index, expression) in expressions.iter().enumerate() {
+            if index > 0 {
+                self.push(", ");
+            }
+            self.expression(expression, false);
+        }
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::String(string) if string.is_empty() => self.push("\"'"),
+            Value::String(string) => self.push(&format!("\"\"{string}\"")),
+            Value::List(list) => {
+                self.push("[[");
+                for (index, element) in list.iter().enumerate() {
+                    if index > 0 {
+                        self.push(", ");
+                    }
+                    self.value(element);
+                }
+                self.push("]");
+            },
+            // there are no byte literals, so bytes are written as a conversion from a list
+            Value::Bytes(bytes) => {
+                self.push("bytse((");
+                self.value(&Value::List(bytes.iter().map(|&byte| Value::Int(byte as i64)).collect()));
+                self.push(")");
+            },
+            Value::Int(_) | Value::Number(_) | Value::Bool(_) => self.push(&value.to_string()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    fn format(source: &str) -> String {
+        crate::format(source).unwrap()
+    }
+
+    #[test]
+    fn normalises_spacing_and_wrappers() {
+        assert_eq!(
+            "x = [[1, \"\"a\", rtue]\n pront((x, f(() )\n",
+            format("x=[1,\"a\"\"\",rtue]]]\n pront(((x,f(( ) ))"),
+        );
+    }
+
+    #[test]
+    fn alternates_indentation() {
+        assert_eq!(
+            "fnuc f((a, b)\n   x = a\n    fi x > b\n      retrun x\n    lefi x < b\n      retrun b\n    \
+             sele\n      retrun 1\n   retrun 0\n",
+            format("fnuc f(((a, b)\n    x = a\n   fi x > b\n      retrun x\n   lefi x < b\n      \
+                    retrun b\n   sele\n      retrun 1\n     retrun 0"),
+        );
+    }
+
+    #[test]
+    fn brackets_only_where_needed() {
+        assert_eq!("x = 1 + 2 * 3 - ((4 - 5)\n", format("x = ((1 + ((2 * 3) ) - ((4 - 5)"));
+        assert_eq!("x = ((f(() ) * 2 + g(()\n", format("x = ((f(() ) * 2 + g(()"));
+    }
+
+    #[test]
+    fn nested_wrappers_are_kept_apart() {
+        assert_eq!("x = [[ [[1], [[2] ]\n", format("x = [[[ [1]], [[[2]] ]]"));
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "/** a */\nfnuc f((x)   /* b **/\n  /* c\n  d **/\n    retrun x\n\
+                      pront((f((1) )\n/** e */";
+        let formatted = format(source);
+        assert_eq!(
+            "/** a */\nfnuc f((x) /* b **/\n   /* c\n  d **/\n   retrun x\n pront((f((1) )\n\
+             /** e */\n",
+            formatted,
+        );
+        assert_eq!(formatted, format(&formatted));
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "fir i ni arnge((10)\n   fi i % 2 == 0\n      cnotineu\n    prointl((i * ((i + 1) )";
+        let formatted = format(source);
+        assert_eq!(formatted, format(&formatted));
+        assert!(crate::run(&formatted).is_ok());
+    }
+}
@@ -0,0 +1,421 @@
+use super::*;
+use crate::json::{self, FromJson, Json, ToJson};
+
+impl Block {
+    /// Parses a syntax tree written by [`ToJson::to_json`], e.g. one cached from an earlier run.
+    /// Syntax trees nest as deeply as the programs they're of, e.g. one level for each operator in
+    /// a chain like `1 + 2 + 3`, so they aren't held to [`json::MAX_NESTING`].
+    pub fn from_json_str(text: &str) -> Result<Block, Error> {
+        Block::from_json(&Json::parse_nested(text, usize::MAX)?)
+    }
+}
+
+impl ToJson for ExpressionNode {
+    fn to_json(&self) -> Json {
+        stack::guard(|| match self {
+            ExpressionNode::Constant(node) => Json::variant("Constant", node.to_json()),
+            ExpressionNode::List(node) => Json::variant("List", node.to_json()),
+            ExpressionNode::Operator(node) => Json::variant("Operator", node.to_json()),
+            ExpressionNode::Variable(node) => Json::variant("Variable", node.to_json()),
+            ExpressionNode::FunctionCall(node) => Json::variant("FunctionCall", node.to_json()),
+        })
+    }
+}
+impl FromJson for ExpressionNode {
+    fn from_json(json: &Json) -> Result<ExpressionNode, Error> {
+        stack::guard(|| Ok(match json.as_variant()? {
+            ("Constant", node) => ExpressionNode::Constant(ConstantNode::from_json(node)?),
+            ("List", node) => ExpressionNode::List(ListNode::from_json(node)?),
+            ("Operator", node) => ExpressionNode::Operator(Box::new(OperatorNode::from_json(node)?)),
+            ("Variable", node) => ExpressionNode::Variable(VariableNode::from_json(node)?),
+            ("FunctionCall", node) => ExpressionNode::FunctionCall(FunctionCallNode::from_json(node)?),
+            (other, _) => return Err(json::invalid(&format!("unknown expression `{other}`"))),
+        }))
+    }
+}
+
+impl ToJson for StatementNode {
+    fn to_json(&self) -> Json {
+        match self {
+            StatementNode::Block(block) => Json::variant("Block", block.to_json()),
+            StatementNode::Assign(node) => Json::variant("Assign", node.to_json()),
+            StatementNode::FunctionCall(node) => Json::variant("FunctionCall", node.to_json()),
+            StatementNode::Conditional(node) => Json::variant("Conditional", node.to_json()),
+            StatementNode::ForLoop(node) => Json::variant("ForLoop", node.to_json()),
+            StatementNode::WhileLoop(node) => Json::variant("WhileLoop", node.to_json()),
+            StatementNode::Break(node) => Json::variant("Break", node.to_json()),
+            StatementNode::Continue(node) => Json::variant("Continue", node.to_json()),
+            StatementNode::Pass(node) => Json::variant("Pass", node.to_json()),
+            StatementNode::Return(node) => Json::variant("Return", node.to_json()),
+            StatementNode::FunctionDefinition(node) => Json::variant("FunctionDefinition", node.to_json()),
+        }
+    }
+}
+impl FromJson for StatementNode {
+    fn from_json(json: &Json) -> Result<StatementNode, Error> {
+        Ok(match json.as_variant()? {
+            ("Block", block) => StatementNode::Block(Block::from_json(block)?),
+            ("Assign", node) => StatementNode::Assign(AssignNode::from_json(node)?),
+            ("FunctionCall", node) => StatementNode::FunctionCall(FunctionCallNode::from_json(node)?),
+            ("Conditional", node) => StatementNode::Conditional(ConditionalNode::from_json(node)?),
+            ("ForLoop", node) => StatementNode::ForLoop(ForLoopNode::from_json(node)?),
+            ("WhileLoop", node) => StatementNode::WhileLoop(WhileLoopNode::from_json(node)?),
+            ("Break", node) => StatementNode::Break(BreakNode::from_json(node)?),
+            ("Continue", node) => StatementNode::Continue(ContinueNode::from_json(node)?),
+            ("Pass", node) => StatementNode::Pass(PassNode::from_json(node)?),
+            ("Return", node) => StatementNode::Return(ReturnNode::from_json(node)?),
+            ("FunctionDefinition", node) => {
+                FunctionDefinitionNode::from_json(node)?.to_statement()
+            },
+            (other, _) => return Err(json::invalid(&format!("unknown statement `{other}`"))),
+        })
+    }
+}
+
+impl ToJson for ConstantNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("value", self.value.to_json()),
+            ("position", self.position.to_json()),
+            ("opener_length", Json::Number(self.opener_length as f64)),
+        ])
+    }
+}
+impl FromJson for ConstantNode {
+    fn from_json(json: &Json) -> Result<ConstantNode, Error> {
+        Ok(ConstantNode {
+            value: Value::from_json(json.get("value")?)?,
+            position: Position::from_json(json.get("position")?)?,
+            opener_length: json.get("opener_length")?.as_usize()?,
+        })
+    }
+}
+
+impl ToJson for ListNode {
+    fn to_json(&self) -> Json {
+        Json::object([("list", self.list.to_json()), ("position", self.position.to_json())])
+    }
+}
+impl FromJson for ListNode {
+    fn from_json(json: &Json) -> Result<ListNode, Error> {
+        Ok(ListNode {
+            list: json.get("list")?.array_of()?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for OperatorNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("lhs", self.lhs.to_json()),
+            ("rhs", self.rhs.to_json()),
+            ("operator", self.operator.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for OperatorNode {
+    fn from_json(json: &Json) -> Result<OperatorNode, Error> {
+        Ok(OperatorNode {
+            lhs: ExpressionNode::from_json(json.get("lhs")?)?,
+            rhs: ExpressionNode::from_json(json.get("rhs")?)?,
+            operator: Operator::from_json(json.get("operator")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for Operator {
+    fn to_json(&self) -> Json {
+        Json::String(format!("{self:?}"))
+    }
+}
+impl FromJson for Operator {
+    fn from_json(json: &Json) -> Result<Operator, Error> {
+        use Operator::*;
+        Ok(match json.as_str()? {
+            "Add" => Add,
+            "Sub" => Sub,
+            "Mul" => Mul,
+            "Div" => Div,
+            "Mod" => Mod,
+            "Seq" => Seq,
+            "Sne" => Sne,
+            "Eq" => Eq,
+            "Ne" => Ne,
+            "Gt" => Gt,
+            "Lt" => Lt,
+            "Ge" => Ge,
+            "Le" => Le,
+            "Adn" => Adn,
+            "Ro" => Ro,
+            other => return Err(json::invalid(&format!("unknown operator `{other}`"))),
+        })
+    }
+}
+
+impl ToJson for VariableNode {
+    fn to_json(&self) -> Json {
+        Json::object([("name", self.name.to_json()), ("position", self.position.to_json())])
+    }
+}
+impl FromJson for VariableNode {
+    fn from_json(json: &Json) -> Result<VariableNode, Error> {
+        Ok(VariableNode {
+            name: Symbol::from_json(json.get("name")?)?,
+            position: Position::from_json(json.get("position")?)?,
+            // slots aren't saved, since they're worked out again before a program runs
+            slot: None,
+        })
+    }
+}
+
+impl ToJson for FunctionCallNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("name", self.name.to_json()),
+            ("args", self.args.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for FunctionCallNode {
+    fn from_json(json: &Json) -> Result<FunctionCallNode, Error> {
+        Ok(FunctionCallNode {
+            name: Symbol::from_json(json.get("name")?)?,
+            args: ListNode::from_json(json.get("args")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for Block {
+    fn to_json(&self) -> Json {
+        Json::object([("statements", self.statements.to_json())])
+    }
+}
+impl FromJson for Block {
+    fn from_json(json: &Json) -> Result<Block, Error> {
+        Ok(Block { statements: json.get("statements")?.array_of()? })
+    }
+}
+
+impl ToJson for AssignNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("target", self.target.to_json()),
+            ("expression", self.expression.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for AssignNode {
+    fn from_json(json: &Json) -> Result<AssignNode, Error> {
+        Ok(AssignNode {
+            target: Symbol::from_json(json.get("target")?)?,
+            expression: ExpressionNode::from_json(json.get("expression")?)?,
+            position: Position::from_json(json.get("position")?)?,
+            slot: None,
+        })
+    }
+}
+
+impl ToJson for ConditionalNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("conditional_paths", self.conditional_paths.to_json()),
+            ("else_block", self.else_block.as_ref().map_or(Json::Null, Block::to_json)),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for ConditionalNode {
+    fn from_json(json: &Json) -> Result<ConditionalNode, Error> {
+        Ok(ConditionalNode {
+            conditional_paths: json.get("conditional_paths")?.array_of()?,
+            else_block: match json.get("else_block")? {
+                Json::Null => None,
+                block => Some(Block::from_json(block)?),
+            },
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for ConditionalPath {
+    fn to_json(&self) -> Json {
+        Json::object([("condition", self.condition.to_json()), ("block", self.block.to_json())])
+    }
+}
+impl FromJson for ConditionalPath {
+    fn from_json(json: &Json) -> Result<ConditionalPath, Error> {
+        Ok(ConditionalPath {
+            condition: ExpressionNode::from_json(json.get("condition")?)?,
+            block: Block::from_json(json.get("block")?)?,
+        })
+    }
+}
+
+impl ToJson for WhileLoopNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("condition", self.condition.to_json()),
+            ("block", self.block.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for WhileLoopNode {
+    fn from_json(json: &Json) -> Result<WhileLoopNode, Error> {
+        Ok(WhileLoopNode {
+            condition: ExpressionNode::from_json(json.get("condition")?)?,
+            block: Block::from_json(json.get("block")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for ForLoopNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("iterable", self.iterable.to_json()),
+            ("loop_variable", self.loop_variable.to_json()),
+            ("block", self.block.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for ForLoopNode {
+    fn from_json(json: &Json) -> Result<ForLoopNode, Error> {
+        Ok(ForLoopNode {
+            iterable: ExpressionNode::from_json(json.get("iterable")?)?,
+            loop_variable: Symbol::from_json(json.get("loop_variable")?)?,
+            block: Block::from_json(json.get("block")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for BreakNode {
+    fn to_json(&self) -> Json {
+        Json::object([("position", self.position.to_json())])
+    }
+}
+impl FromJson for BreakNode {
+    fn from_json(json: &Json) -> Result<BreakNode, Error> {
+        Ok(BreakNode { position: Position::from_json(json.get("position")?)? })
+    }
+}
+
+impl ToJson for ContinueNode {
+    fn to_json(&self) -> Json {
+        Json::object([("position", self.position.to_json())])
+    }
+}
+impl FromJson for ContinueNode {
+    fn from_json(json: &Json) -> Result<ContinueNode, Error> {
+        Ok(ContinueNode { position: Position::from_json(json.get("position")?)? })
+    }
+}
+
+impl ToJson for PassNode {
+    fn to_json(&self) -> Json {
+        Json::object([("position", self.position.to_json())])
+    }
+}
+impl FromJson for PassNode {
+    fn from_json(json: &Json) -> Result<PassNode, Error> {
+        Ok(PassNode { position: Position::from_json(json.get("position")?)? })
+    }
+}
+
+impl ToJson for ReturnNode {
+    fn to_json(&self) -> Json {
+        Json::object([("return_value", self.return_value.to_json()), ("position", self.position.to_json())])
+    }
+}
+impl FromJson for ReturnNode {
+    fn from_json(json: &Json) -> Result<ReturnNode, Error> {
+        Ok(ReturnNode {
+            return_value: ExpressionNode::from_json(json.get("return_value")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+impl ToJson for FunctionDefinitionNode {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("name", self.name.to_json()),
+            ("parameters", self.parameters.to_json()),
+            ("block", self.block.to_json()),
+            ("position", self.position.to_json()),
+        ])
+    }
+}
+impl FromJson for FunctionDefinitionNode {
+    fn from_json(json: &Json) -> Result<FunctionDefinitionNode, Error> {
+        Ok(FunctionDefinitionNode {
+            name: Symbol::from_json(json.get("name")?)?,
+            parameters: json.get("parameters")?.array_of()?,
+            block: Block::from_json(json.get("block")?)?,
+            position: Position::from_json(json.get("position")?)?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorKind;
+    use crate::json::ToJson;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use super::Block;
+
+    fn parse(source: &str) -> Block {
+        Parser::new(&Lexer::new(source).lex().unwrap()).parse().unwrap()
+    }
+
+    #[test]
+    fn round_trips() {
+        let ast = parse(
+            "fnuc f((a, b)\n   fi a > b\n      retrun [[a, \"\"x\", rtue]\n    sele\n      retrun a % b\n\
+             fir i ni arnge((3)\n   whitl i < 2\n      brek\n       cnotineu\nx = f((1, 2)\n pront((x)",
+        );
+        let text = ast.to_json().to_string();
+        assert_eq!(ast, Block::from_json_str(&text).unwrap());
+    }
+
+    #[test]
+    fn writes_nodes_as_tagged_objects() {
+        assert_eq!(
+            r#"{"statements":[{"Assign":{"target":"x","expression":{"Constant":{"value":{"Int":1},"position":{"start":{"line":1,"column":4},"end":{"line":1,"column":5}},"opener_length":0}},"position":{"start":{"line":1,"column":0},"end":{"line":1,"column":1}}}}]}"#,
+            parse("x = 1").to_json().to_string(),
+        );
+    }
+
+    #[test]
+    fn long_operator_chains_round_trip() {
+        let ast = parse(&format!("x = {}", vec!["1"; 1000].join(" + ")));
+        let text = ast.to_json().to_string();
+        assert_eq!(ast, Block::from_json_str(&text).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn round_trips_through_serde() {
+        let ast = parse("fnuc f((a)\n   retrun [[a, \"\"x\", 2.5]\nx = f((1)\n pront((x + 2)");
+        let text = serde_json::to_string(&ast).unwrap();
+        assert_eq!(ast, serde_json::from_str::<Block>(&text).unwrap());
+    }
+
+    #[test]
+    fn rejects_unknown_nodes() {
+        assert_eq!(
+            ErrorKind::InvalidJson("unknown statement `Goto`".to_string()),
+            Block::from_json_str(r#"{"statements":[{"Goto":{}}]}"#).unwrap_err().kind,
+        );
+    }
+}
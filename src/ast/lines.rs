@@ -0,0 +1,37 @@
+use super::*;
+use super::visit::{self, Visitor};
+
+impl Block {
+    /// Returns the span of every statement in the tree, including those nested in other
+    /// statements' blocks, in the order they appear in the source.
+    pub fn statement_positions(&self) -> Vec<Position> {
+        let mut collector = PositionCollector(Vec::new());
+        collector.visit_block(self);
+        collector.0
+    }
+}
+
+struct PositionCollector(Vec<Position>);
+impl Visitor for PositionCollector {
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        self.0.extend(statement.position());
+        visit::walk_statement(self, statement);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{Lexer, Position};
+    use crate::parser::Parser;
+
+    #[test]
+    fn includes_nested_statements() {
+        let tokens = Lexer::new("fnuc f((a)\n   fi a\n      retrun 1\n    retrun 2\nf((1)").lex().unwrap();
+        let lines: Vec<usize> = Parser::new(&tokens).parse().unwrap()
+            .statement_positions().iter()
+            .map(|position: &Position| position.start.line)
+            .collect();
+        assert_eq!(vec![1, 2, 3, 4, 5], lines);
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+use super::*;
+use crate::builtins::BuiltinRegistry;
+use crate::warning::{Warning, WarningKind};
+
+impl Block {
+    /// Looks over the block for code that's probably a mistake: variables that are never used,
+    /// statements that can never be reached, and functions that shadow builtins or each other.
+    /// Names beginning with an underscore are never reported as unused.
+    pub fn lint(&self, builtins: &BuiltinRegistry) -> Vec<Warning> {
+        let mut linter = Linter {
+            builtins,
+            warnings: Vec::new(),
+            bindings: Vec::new(),
+            used: HashSet::new(),
+            functions: HashMap::new(),
+        };
+        linter.block(self);
+        linter.finish()
+    }
+}
+
+struct Linter<'a> {
+    builtins: &'a BuiltinRegistry,
+    warnings: Vec<Warning>,
+    /// Every variable bound in the program, with where it was first bound.
+    bindings: Vec<(Symbol, Position)>,
+    /// Every variable read anywhere in the program. Since functions can read global variables,
+    /// and with dynamic scoping those of the scopes they're called from, a variable counts as
+    /// used if it's read anywhere at all.
+    used: HashSet<Symbol>,
+    functions: HashMap<Symbol, Position>,
+}
+impl Linter<'_> {
+    fn finish(mut self) -> Vec<Warning> {
+        for (name, position) in self.bindings {
+            if !self.used.contains(&name) {
+                self.warnings.push(Warning::new(WarningKind::UnusedVariable(name.to_string()), position));
+            }
+        }
+        self.warnings.sort_by_key(|warning| warning.pos.start);
+        self.warnings
+    }
+
+    fn block(&mut self, block: &Block) {
+        let mut terminated = false;
+        let mut reported = false;
+        for statement in &block.statements {
+            if terminated && !reported {
+                if let Some(position) = statement.position() {
+                    self.warnings.push(Warning::new(WarningKind::UnreachableCode, position));
+                    reported = true;
+                }
+            }
+            self.statement(statement);
+            terminated |= matches!(
+                statement,
+                StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Return(_)
+            );
+        }
+    }
+
+    fn statement(&mut self, statement: &StatementNode) {
+        match statement {
+            StatementNode::Block(block) => self.block(block),
+            StatementNode::Assign(node) => {
+                self.expression(&node.expression);
+                self.bind(node.target, node.position);
+            },
+            StatementNode::FunctionCall(node) => self.call(node),
+            StatementNode::Conditional(node) => {
+                for path in &node.conditional_paths {
+                    self.expression(&path.condition);
+                    self.block(&path.block);
+                }
+                if let Some(else_block) = &node.else_block {
+                    self.block(else_block);
+                }
+            },
+            StatementNode::ForLoop(node) => {
+                self.expression(&node.iterable);
+                self.bind(node.loop_variable, node.position);
+                self.block(&node.block);
+            },
+            StatementNode::WhileLoop(node) => {
+                self.expression(&node.condition);
+                self.block(&node.block);
+            },
+            StatementNode::Return(node) => self.expression(&node.return_value),
+            StatementNode::FunctionDefinition(node) => {
+                let previous = self.functions.insert(node.name, node.position);
+                if previous.is_some() || self.builtins.contains(&node.name) {
+                    self.warnings.push(Warning::new(
+                        WarningKind::ShadowedFunction { name: node.name.to_string(), previous },
+                        node.position,
+                    ));
+                }
+                for parameter in &node.parameters {
+                    self.bind(*parameter, node.position);
+                }
+                self.block(&node.block);
+            },
+            StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Pass(_) => {},
+        }
+    }
+
+    fn expression(&mut self, expression: &ExpressionNode) {
+        stack::guard(|| match expression {
+            ExpressionNode::Constant(_) => {},
+            ExpressionNode::List(node) => {
+                for element in &node.list {
+                    self.expression(element);
+                }
+            },
+            ExpressionNode::Operator(node) => {
+                self.expression(&node.lhs);
+                self.expression(&node.rhs);
+            },
+            ExpressionNode::Variable(node) => {
+                self.used.insert(node.name);
+            },
+            ExpressionNode::FunctionCall(node) => self.call(node),
+        })
+    }
+
+    fn call(&mut self, call: &FunctionCallNode) {
+        for arg in &call.args.list {
+            self.expression(arg);
+        }
+    }
+
+    fn bind(&mut self, name: Symbol, position: Position) {
+        if !name.starts_with('_') && !self.bindings.iter().any(|(bound, _)| *bound == name) {
+            self.bindings.push((name, position));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::warning::WarningKind::*;
+    use super::*;
+
+    fn lint(source: &str) -> Vec<Warning> {
+        crate::check(source).unwrap()
+    }
+
+    #[test]
+    fn unused_variable() {
+        assert_eq!(
+            vec![Warning::new(UnusedVariable("y".to_string()), Position::new(2, 1, 1))],
+            lint("x = 1\n y = x"),
+        );
+    }
+
+    #[test]
+    fn variables_read_inside_functions_are_used() {
+        assert_eq!(Vec::<Warning>::new(), lint("x = 1\n fnuc f((a)\n    pront((a + x)\n f((2)"));
+    }
+
+    #[test]
+    fn underscored_names_are_never_unused() {
+        assert_eq!(Vec::<Warning>::new(), lint("fir _ ni arnge((3)\n   pront((1)"));
+    }
+
+    #[test]
+    fn statement_after_return_is_unreachable() {
+        assert_eq!(
+            vec![Warning::new(UnreachableCode, Position::new(3, 4, 9))],
+            lint("fnuc f(()\n   retrun 1\n    pront((2)\n   pront((3)\nf(()"),
+        );
+    }
+
+    #[test]
+    fn function_shadowing_builtin() {
+        assert_eq!(
+            vec![Warning::new(
+                ShadowedFunction { name: "pront".to_string(), previous: None },
+                Position::new(1, 0, 4),
+            )],
+            lint("fnuc pront(()\n   retrun 1"),
+        );
+    }
+
+    #[test]
+    fn function_redefinition() {
+        assert_eq!(
+            vec![Warning::new(
+                ShadowedFunction { name: "f".to_string(), previous: Some(Position::new(1, 0, 4)) },
+                Position::new(3, 0, 4),
+            )],
+            lint("fnuc f(()\n   retrun 1\nfnuc f(()\n   retrun 2"),
+        );
+    }
+}
@@ -0,0 +1,449 @@
+use super::*;
+use crate::value::{Coercion, Comparison, Dispatch};
+
+/// The largest result, in bytes, that repeating a string or list may be folded into. Anything
+/// larger is left to the runtime, which can enforce its memory limit.
+const MAX_FOLDED_ALLOCATION: usize = 4096;
+
+impl Block {
+    /// Returns an equivalent block with constant operator expressions and list literals folded
+    /// into constants, and conditional branches whose conditions are constant resolved ahead of
+    /// time (e.g. the body of a `fi flase` is removed entirely).
+    pub fn optimize(self) -> Block {
+        let mut block = Block::new();
+        for statement in self.statements {
+            if let Some(statement) = statement.optimize() {
+                block.add_statement(statement);
+            }
+        }
+        block
+    }
+}
+
+impl StatementNode {
+    /// Optimises the statement, returning `None` if it can never have any effect.
+    fn optimize(self) -> Option<StatementNode> {
+        Some(match self {
+            StatementNode::Block(block) => block.optimize().to_statement(),
+            StatementNode::Assign(AssignNode { target, expression, position, .. }) => {
+                AssignNode::new(target, expression.optimize(), position).to_statement()
+            },
+            StatementNode::FunctionCall(node) => node.optimize().to_statement(),
+            StatementNode::Conditional(node) => node.optimize()?.to_statement(),
+            StatementNode::ForLoop(ForLoopNode { iterable, loop_variable, block, position }) => {
+                ForLoopNode::new(iterable.optimize(), loop_variable, block.optimize(), position)
+                    .to_statement()
+            },
+            StatementNode::WhileLoop(WhileLoopNode { condition, block, position }) => {
+                let condition = condition.optimize();
+                if let ExpressionNode::Constant(constant) = &condition {
+                    if !constant.value.coerce_to_bool() {
+                        return None;
+                    }
+                }
+                WhileLoopNode::new(condition, block.optimize(), position).to_statement()
+            },
+            StatementNode::Return(ReturnNode { return_value, position }) => {
+                ReturnNode::new(return_value.optimize(), position).to_statement()
+            },
+            StatementNode::FunctionDefinition(node) => {
+                let FunctionDefinitionNode { name, parameters, block, position } = Rc::unwrap_or_clone(node);
+                FunctionDefinitionNode::new(name, parameters, block.optimize(), position)
+                    .to_statement()
+            },
+            statement @ (StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Pass(_)) => {
+                statement
+            },
+        })
+    }
+}
+
+impl ConditionalNode {
+    /// Drops paths whose conditions are constantly false, and turns the first path whose
+    /// condition is constantly true into the else block. Returns `None` if no path could ever be
+    /// taken.
+    fn optimize(self) -> Option<ConditionalNode> {
+        let mut conditional_paths = Vec::new();
+        let mut else_block = self.else_block.map(Block::optimize);
+
+        for ConditionalPath { condition, block } in self.conditional_paths {
+            let condition = condition.optimize();
+            if let ExpressionNode::Constant(constant) = &condition {
+                if constant.value.coerce_to_bool() {
+                    // none of the later paths can be reached
+                    else_block = Some(block.optimize());
+                    break;
+                }
+                continue;
+            }
+            conditional_paths.push(ConditionalPath::new(condition, block.optimize()));
+        }
+
+        if conditional_paths.is_empty() && else_block.is_none() {
+            None
+        } else {
+            Some(ConditionalNode::new(conditional_paths, else_block, self.position))
+        }
+    }
+}
+
+impl ExpressionNode {
+    fn optimize(self) -> ExpressionNode {
+        stack::guard(|| match self {
+            ExpressionNode::Constant(_) | ExpressionNode::Variable(_) => self,
+            ExpressionNode::List(list) => {
+                let list = list.optimize_elements();
+                if list.list.iter().all(|element| matches!(element, ExpressionNode::Constant(_))) {
+                    let values = list.list.into_iter()
+                        .map(|element| match element {
+                            ExpressionNode::Constant(constant) => constant.value,
+                            _ => unreachable!(),
+                        })
+                        .collect();
+                    ConstantNode::new(Value::List(values), list.position).to_expression()
+                } else {
+                    list.to_expression()
+                }
+            },
+            ExpressionNode::Operator(mut node) => {
+                let (lhs, rhs) = node.take_operands();
+                let (operator, position) = (node.operator.clone(), node.position);
+                let lhs = lhs.optimize();
+                if let ExpressionNode::Constant(ConstantNode { value: value @ Value::Bool(_), .. }) = &lhs {
+                    // the right-hand operand would never be evaluated, so it can go
+                    if let Some(value) = operator.short_circuit(value) {
+                        return ConstantNode::new(value, position).to_expression();
+                    }
+                }
+                let rhs = rhs.optimize();
+                if let (ExpressionNode::Constant(lhs), ExpressionNode::Constant(rhs)) = (&lhs, &rhs) {
+                    // operators that fail are left to fail at runtime, where the error can be
+                    // reported properly, as are operands that the runtime's coercion mode,
+                    // comparison mode, or strict types could treat differently
+                    let dispatch = Coercion::Symmetric.dispatch(&operator, &lhs.value, &rhs.value);
+                    if dispatch == Dispatch::Lhs
+                        && operator.check_types(&lhs.value, &rhs.value).is_ok()
+                        && operator.allocation(&lhs.value, &rhs.value) <= MAX_FOLDED_ALLOCATION {
+                        let numeric = operator.apply_comparing(Comparison::Numeric, &lhs.value, &rhs.value);
+                        let total = operator.apply_comparing(Comparison::Total, &lhs.value, &rhs.value);
+                        if let (Ok(value), Ok(total)) = (numeric, total) {
+                            if value == total {
+                                return ConstantNode::new(value, position).to_expression();
+                            }
+                        }
+                    }
+                }
+                OperatorNode::new(lhs, rhs, operator, position).to_expression()
+            },
+            ExpressionNode::FunctionCall(node) => node.optimize().to_expression(),
+        })
+    }
+}
+
+impl ListNode {
+    fn optimize_elements(self) -> ListNode {
+        ListNode::new(self.list.into_iter().map(ExpressionNode::optimize).collect(), self.position)
+    }
+}
+
+impl FunctionCallNode {
+    /// Optimises each argument, but never the argument list itself, since functions take their
+    /// arguments as a list of expressions.
+    fn optimize(self) -> FunctionCallNode {
+        FunctionCallNode::new(self.name, self.args.optimize_elements(), self.position)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every node in these tests shares one position, since they aren't parsed from source.
+    fn at() -> Position {
+        Position::new(1, 0, 1)
+    }
+
+    fn constant(value: Value) -> ExpressionNode {
+        ConstantNode::new(value, at()).to_expression()
+    }
+
+    fn variable(name: &str) -> ExpressionNode {
+        VariableNode::new(name.to_string(), at()).to_expression()
+    }
+
+    fn block_of(statements: Vec<StatementNode>) -> Block {
+        let mut block = Block::new();
+        for statement in statements {
+            block.add_statement(statement);
+        }
+        block
+    }
+
+    fn assign(target: &str, expression: ExpressionNode) -> StatementNode {
+        AssignNode::new(target.to_string(), expression, at()).to_statement()
+    }
+
+    #[test]
+    fn folds_constant_operators() {
+        // x = 1 + 2 * 3
+        let block = block_of(vec![assign("x", OperatorNode::new(
+            constant(Value::Number(1.0)),
+            OperatorNode::new(
+                constant(Value::Number(2.0)),
+                constant(Value::Number(3.0)),
+                Operator::Mul,
+                at(),
+            ).to_expression(),
+            Operator::Add,
+            at(),
+        ).to_expression())]);
+        assert_eq!(
+            block_of(vec![assign("x", constant(Value::Number(7.0)))]),
+            block.optimize(),
+        );
+    }
+
+    #[test]
+    fn folds_constant_parts_of_non_constant_operators() {
+        // x = y + 2 * 3
+        let block = block_of(vec![assign("x", OperatorNode::new(
+            variable("y"),
+            OperatorNode::new(
+                constant(Value::Number(2.0)),
+                constant(Value::Number(3.0)),
+                Operator::Mul,
+                at(),
+            ).to_expression(),
+            Operator::Add,
+            at(),
+        ).to_expression())]);
+        assert_eq!(
+            block_of(vec![assign("x", OperatorNode::new(
+                variable("y"),
+                constant(Value::Number(6.0)),
+                Operator::Add,
+                at(),
+            ).to_expression())]),
+            block.optimize(),
+        );
+    }
+
+    #[test]
+    fn leaves_failing_operators_for_runtime() {
+        // x = "%s"" % [1, 2]]
+        let expression = OperatorNode::new(
+            constant(Value::String("%s".to_string())),
+            ListNode::new(vec![constant(Value::Number(1.0)), constant(Value::Number(2.0))], at())
+                .to_expression(),
+            Operator::Mod,
+            at(),
+        ).to_expression();
+        assert_eq!(
+            block_of(vec![assign("x", OperatorNode::new(
+                constant(Value::String("%s".to_string())),
+                constant(Value::List(vec![Value::Number(1.0), Value::Number(2.0)])),
+                Operator::Mod,
+                at(),
+            ).to_expression())]),
+            block_of(vec![assign("x", expression)]).optimize(),
+        );
+    }
+
+    #[test]
+    fn leaves_large_repetitions_for_runtime() {
+        // x = "a"" * 1000000000000
+        let expression = OperatorNode::new(
+            constant(Value::String("a".to_string())),
+            constant(Value::Number(1e12)),
+            Operator::Mul,
+            at(),
+        ).to_expression();
+        assert_eq!(
+            block_of(vec![assign("x", expression.clone())]),
+            block_of(vec![assign("x", expression)]).optimize(),
+        );
+    }
+
+    #[test]
+    fn leaves_comparisons_that_depend_on_the_comparison_mode_for_runtime() {
+        // x = [1]] < [rtue]]
+        let expression = OperatorNode::new(
+            constant(Value::List(vec![Value::Int(1)])),
+            constant(Value::List(vec![Value::Bool(true)])),
+            Operator::Lt,
+            at(),
+        ).to_expression();
+        assert_eq!(
+            block_of(vec![assign("x", expression.clone())]),
+            block_of(vec![assign("x", expression)]).optimize(),
+        );
+    }
+
+    #[test]
+    fn folds_logical_operators_decided_by_a_constant_left_operand() {
+        // x = flase adn y
+        let block = block_of(vec![assign("x", OperatorNode::new(
+            constant(Value::Bool(false)),
+            variable("y"),
+            Operator::Adn,
+            at(),
+        ).to_expression())]);
+        assert_eq!(
+            block_of(vec![assign("x", constant(Value::Bool(false)))]),
+            block.optimize(),
+        );
+        // x = flase ro y
+        let undecided = OperatorNode::new(
+            constant(Value::Bool(false)),
+            variable("y"),
+            Operator::Ro,
+            at(),
+        ).to_expression();
+        assert_eq!(
+            block_of(vec![assign("x", undecided.clone())]),
+            block_of(vec![assign("x", undecided)]).optimize(),
+        );
+    }
+
+    #[test]
+    fn folds_constant_lists() {
+        let block = block_of(vec![assign("x", ListNode::new(vec![
+            constant(Value::Number(1.0)),
+            ListNode::new(vec![constant(Value::Bool(true))], at()).to_expression(),
+        ], at()).to_expression())]);
+        assert_eq!(
+            block_of(vec![assign("x", constant(Value::List(vec![
+                Value::Number(1.0),
+                Value::List(vec![Value::Bool(true)]),
+            ])))]),
+            block.optimize(),
+        );
+    }
+
+    #[test]
+    fn keeps_lists_with_non_constant_elements() {
+        let list = ListNode::new(vec![constant(Value::Number(1.0)), variable("y")], at())
+            .to_expression();
+        assert_eq!(
+            block_of(vec![assign("x", list.clone())]),
+            block_of(vec![assign("x", list)]).optimize(),
+        );
+    }
+
+    #[test]
+    fn does_not_fold_function_argument_lists() {
+        let call = FunctionCallNode::new(
+            "pront".to_string(),
+            ListNode::new(vec![OperatorNode::new(
+                constant(Value::Number(1.0)),
+                constant(Value::Number(1.0)),
+                Operator::Add,
+                at(),
+            ).to_expression()], at()),
+            at(),
+        );
+        assert_eq!(
+            block_of(vec![FunctionCallNode::new(
+                "pront".to_string(),
+                ListNode::new(vec![constant(Value::Number(2.0))], at()),
+                at(),
+            ).to_statement()]),
+            block_of(vec![call.to_statement()]).optimize(),
+        );
+    }
+
+    #[test]
+    fn removes_constantly_false_conditional() {
+        let block = block_of(vec![ConditionalNode::new(
+            vec![ConditionalPath::new(
+                constant(Value::Bool(false)),
+                block_of(vec![assign("x", constant(Value::Number(1.0)))]),
+            )],
+            None,
+            at(),
+        ).to_statement()]);
+        assert_eq!(Block::new(), block.optimize());
+    }
+
+    #[test]
+    fn constantly_false_conditional_falls_through_to_else() {
+        let else_block = block_of(vec![assign("x", constant(Value::Number(2.0)))]);
+        let block = block_of(vec![ConditionalNode::new(
+            vec![ConditionalPath::new(
+                constant(Value::Bool(false)),
+                block_of(vec![assign("x", constant(Value::Number(1.0)))]),
+            )],
+            Some(else_block.clone()),
+            at(),
+        ).to_statement()]);
+        assert_eq!(
+            block_of(vec![ConditionalNode::new(vec![], Some(else_block), at()).to_statement()]),
+            block.optimize(),
+        );
+    }
+
+    #[test]
+    fn constantly_true_path_cuts_off_later_paths() {
+        let first = ConditionalPath::new(
+            variable("y"),
+            block_of(vec![assign("x", constant(Value::Number(1.0)))]),
+        );
+        let always = block_of(vec![assign("x", constant(Value::Number(2.0)))]);
+        let block = block_of(vec![ConditionalNode::new(
+            vec![
+                first.clone(),
+                ConditionalPath::new(constant(Value::Bool(true)), always.clone()),
+                ConditionalPath::new(
+                    variable("z"),
+                    block_of(vec![assign("x", constant(Value::Number(3.0)))]),
+                ),
+            ],
+            Some(block_of(vec![assign("x", constant(Value::Number(4.0)))])),
+            at(),
+        ).to_statement()]);
+        assert_eq!(
+            block_of(vec![ConditionalNode::new(vec![first], Some(always), at()).to_statement()]),
+            block.optimize(),
+        );
+    }
+
+    #[test]
+    fn removes_constantly_false_while_loop() {
+        let block = block_of(vec![WhileLoopNode::new(
+            OperatorNode::new(
+                constant(Value::Number(1.0)),
+                constant(Value::Number(2.0)),
+                Operator::Gt,
+                at(),
+            ).to_expression(),
+            block_of(vec![BreakNode::new(at()).to_statement()]),
+            at(),
+        ).to_statement()]);
+        assert_eq!(Block::new(), block.optimize());
+    }
+
+    #[test]
+    fn optimizes_function_bodies() {
+        let block = block_of(vec![FunctionDefinitionNode::new(
+            "f".to_string(),
+            vec![],
+            block_of(vec![ReturnNode::new(OperatorNode::new(
+                constant(Value::Number(1.0)),
+                constant(Value::Number(2.0)),
+                Operator::Add,
+                at(),
+            ).to_expression(), at()).to_statement()]),
+            at(),
+        ).to_statement()]);
+        assert_eq!(
+            block_of(vec![FunctionDefinitionNode::new(
+                "f".to_string(),
+                vec![],
+                block_of(vec![ReturnNode::new(constant(Value::Number(3.0)), at()).to_statement()]),
+                at(),
+            ).to_statement()]),
+            block.optimize(),
+        );
+    }
+}
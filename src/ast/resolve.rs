@@ -0,0 +1,174 @@
+use super::*;
+
+impl Block {
+    /// Works out which slot each variable read and assigned in the program will be found in when
+    /// it runs, so that the runtime can usually go straight to it rather than searching every
+    /// scope on the stack. Variables that can't be predicted, such as those a function reads
+    /// from its caller, are left to be searched for.
+    pub fn resolve(&mut self) {
+        Resolver { scopes: vec![Vec::new()] }.resolve_block(self);
+    }
+}
+
+/// The variables expected to have been created in each scope open at the point being resolved,
+/// innermost last, in the order they'll be created.
+struct Resolver {
+    scopes: Vec<Vec<Symbol>>,
+}
+impl Resolver {
+    fn find(&self, name: Symbol) -> Option<Slot> {
+        self.scopes.iter().rev().enumerate().find_map(|(depth, scope)| {
+            Some(Slot { depth, index: scope.iter().position(|variable| *variable == name)? })
+        })
+    }
+
+    /// Returns the slot `name` will be assigned to, which is in the innermost scope if it hasn't
+    /// been created already.
+    fn assign(&mut self, name: Symbol) -> Slot {
+        self.find(name).unwrap_or_else(|| {
+            let scope = self.scopes.last_mut().expect("resolved with no scopes open");
+            scope.push(name);
+            Slot { depth: 0, index: scope.len() - 1 }
+        })
+    }
+
+    fn in_new_scope(&mut self, resolve: impl FnOnce(&mut Resolver)) {
+        self.scopes.push(Vec::new());
+        resolve(self);
+        self.scopes.pop();
+    }
+
+    fn resolve_block(&mut self, block: &mut Block) {
+        for statement in &mut block.statements {
+            self.resolve_statement(statement);
+        }
+    }
+
+    fn resolve_statement(&mut self, statement: &mut StatementNode) {
+        match statement {
+            StatementNode::Block(block) => self.resolve_block(block),
+            StatementNode::Assign(node) => {
+                self.resolve_expression(&mut node.expression);
+                node.slot = Some(self.assign(node.target));
+            },
+            StatementNode::FunctionCall(node) => self.resolve_call(node),
+            StatementNode::Conditional(node) => {
+                for path in &mut node.conditional_paths {
+                    self.resolve_expression(&mut path.condition);
+                    self.in_new_scope(|resolver| resolver.resolve_block(&mut path.block));
+                }
+                if let Some(block) = &mut node.else_block {
+                    self.in_new_scope(|resolver| resolver.resolve_block(block));
+                }
+            },
+            StatementNode::ForLoop(node) => {
+                self.resolve_expression(&mut node.iterable);
+                self.in_new_scope(|resolver| {
+                    resolver.assign(node.loop_variable);
+                    resolver.resolve_block(&mut node.block);
+                });
+            },
+            // the condition is evaluated in the loop's scope too
+            StatementNode::WhileLoop(node) => self.in_new_scope(|resolver| {
+                resolver.resolve_expression(&mut node.condition);
+                resolver.resolve_block(&mut node.block);
+            }),
+            StatementNode::Return(node) => self.resolve_expression(&mut node.return_value),
+            // a function's body runs in a scope of its own, on top of whatever scopes its caller
+            // has open, so only its own variables can be predicted
+            StatementNode::FunctionDefinition(node) => {
+                // nothing else holds the definition until it's run, so this doesn't copy it
+                let node = Rc::make_mut(node);
+                Resolver { scopes: vec![node.parameters.clone()] }.resolve_block(&mut node.block);
+            },
+            StatementNode::Break(_) | StatementNode::Continue(_) | StatementNode::Pass(_) => {},
+        }
+    }
+
+    fn resolve_expression(&mut self, expression: &mut ExpressionNode) {
+        stack::guard(|| match expression {
+            ExpressionNode::Constant(_) => {},
+            ExpressionNode::List(node) => self.resolve_list(node),
+            ExpressionNode::Operator(node) => {
+                self.resolve_expression(&mut node.lhs);
+                self.resolve_expression(&mut node.rhs);
+            },
+            ExpressionNode::Variable(node) => node.slot = self.find(node.name),
+            ExpressionNode::FunctionCall(node) => self.resolve_call(node),
+        })
+    }
+
+    fn resolve_list(&mut self, list: &mut ListNode) {
+        for element in &mut list.list {
+            self.resolve_expression(element);
+        }
+    }
+
+    /// Arguments are evaluated before the function's scope is opened, and builtins evaluate them
+    /// in their caller's scope, so they're resolved like any other expression.
+    fn resolve_call(&mut self, call: &mut FunctionCallNode) {
+        self.resolve_list(&mut call.args);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use super::*;
+
+    fn resolve(source: &str) -> Block {
+        let mut block = Parser::new(&Lexer::new(source).lex().unwrap()).parse().unwrap();
+        block.resolve();
+        block
+    }
+
+    #[test]
+    fn resolves_variables_to_the_scopes_they_are_created_in() {
+        let block = resolve("x = 1\n y = 2\nwhitl x < 3\n   z = y\n    x = x + z\n");
+        let [_, StatementNode::Assign(y), StatementNode::WhileLoop(node)] = block.statements() else {
+            panic!("unexpected statements {:?}", block.statements());
+        };
+        assert_eq!(Some(Slot { depth: 0, index: 1 }), y.slot);
+        let ExpressionNode::Operator(condition) = node.condition() else {
+            panic!("expected an operation");
+        };
+        let ExpressionNode::Variable(x) = condition.lhs() else {
+            panic!("expected a variable");
+        };
+        assert_eq!(Some(Slot { depth: 1, index: 0 }), x.slot);
+
+        let [StatementNode::Assign(z), StatementNode::Assign(x)] = node.block().statements() else {
+            panic!("unexpected statements {:?}", node.block().statements());
+        };
+        assert_eq!(Some(Slot { depth: 0, index: 0 }), z.slot);
+        assert!(matches!(z.expression(), ExpressionNode::Variable(y) if y.slot == Some(Slot { depth: 1, index: 1 })));
+        assert_eq!(Some(Slot { depth: 1, index: 0 }), x.slot);
+    }
+
+    #[test]
+    fn leaves_variables_from_callers_unresolved() {
+        let block = resolve("fnuc f((a)\n   retrun a + b\n");
+        let [StatementNode::FunctionDefinition(definition)] = block.statements() else {
+            panic!("unexpected statements {:?}", block.statements());
+        };
+        let Some(StatementNode::Return(ret)) = definition.block().iter().next() else {
+            panic!("expected a return");
+        };
+        let ExpressionNode::Operator(operator) = ret.return_value() else {
+            panic!("expected an operation");
+        };
+        assert!(matches!(operator.lhs(), ExpressionNode::Variable(a) if a.slot == Some(Slot { depth: 0, index: 0 })));
+        assert!(matches!(operator.rhs(), ExpressionNode::Variable(b) if b.slot.is_none()));
+    }
+
+    #[test]
+    fn unpredicted_variables_are_still_found() {
+        // `x` is resolved to the function's scope, but is really the caller's
+        let mut runtime = Runtime::new();
+        crate::run_in("fnuc f((a)\n   x = a\n    retrun x\nx = 1\n y = f((2)\n", &mut runtime).unwrap();
+        assert_eq!(Value::Number(2.0), *runtime.get_variable("x").unwrap());
+        assert_eq!(Value::Number(2.0), *runtime.get_variable("y").unwrap());
+    }
+}
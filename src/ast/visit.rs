@@ -0,0 +1,222 @@
+//! Traversal of the syntax tree, for tools that want to look at some kinds of node without
+//! matching on every variant themselves.
+//!
+//! Implement [`Visitor`], overriding the methods for the nodes of interest, and pass the tree to
+//! [`Visitor::visit_block`]. Each method's default carries on into the node's children through
+//! the matching `walk_` function, so an override should call that too if it wants the nodes
+//! beneath visited as well.
+//! ```
+//! use mornington::ast::visit::{self, Visitor};
+//! use mornington::ast::FunctionCallNode;
+//!
+//! struct CallCounter(usize);
+//! impl Visitor for CallCounter {
+//!     fn visit_function_call(&mut self, node: &FunctionCallNode) {
+//!         self.0 += 1;
+//!         visit::walk_function_call(self, node);
+//!     }
+//! }
+//!
+//! let tokens = mornington::lexer::Lexer::new("prointl((arnge((3) )").lex().unwrap();
+//! let ast = mornington::parser::Parser::new(&tokens).parse().unwrap();
+//! let mut counter = CallCounter(0);
+//! counter.visit_block(&ast);
+//! assert_eq!(2, counter.0);
+//! ```
+
+use super::*;
+
+pub trait Visitor {
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    /// Visits any statement, before it's passed on to the method for its kind.
+    fn visit_statement(&mut self, statement: &StatementNode) {
+        walk_statement(self, statement);
+    }
+
+    /// Visits any expression, before it's passed on to the method for its kind.
+    fn visit_expression(&mut self, expression: &ExpressionNode) {
+        walk_expression(self, expression);
+    }
+
+    fn visit_assign(&mut self, node: &AssignNode) {
+        walk_assign(self, node);
+    }
+
+    /// Visits a function call, whether it's a statement of its own or part of an expression.
+    fn visit_function_call(&mut self, node: &FunctionCallNode) {
+        walk_function_call(self, node);
+    }
+
+    fn visit_conditional(&mut self, node: &ConditionalNode) {
+        walk_conditional(self, node);
+    }
+
+    fn visit_for_loop(&mut self, node: &ForLoopNode) {
+        walk_for_loop(self, node);
+    }
+
+    fn visit_while_loop(&mut self, node: &WhileLoopNode) {
+        walk_while_loop(self, node);
+    }
+
+    fn visit_break(&mut self, _node: &BreakNode) {}
+
+    fn visit_continue(&mut self, _node: &ContinueNode) {}
+
+    fn visit_pass(&mut self, _node: &PassNode) {}
+
+    fn visit_return(&mut self, node: &ReturnNode) {
+        walk_return(self, node);
+    }
+
+    fn visit_function_definition(&mut self, node: &FunctionDefinitionNode) {
+        walk_function_definition(self, node);
+    }
+
+    fn visit_constant(&mut self, _node: &ConstantNode) {}
+
+    fn visit_list(&mut self, node: &ListNode) {
+        walk_list(self, node);
+    }
+
+    fn visit_operator(&mut self, node: &OperatorNode) {
+        walk_operator(self, node);
+    }
+
+    fn visit_variable(&mut self, _node: &VariableNode) {}
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &StatementNode) {
+    match statement {
+        StatementNode::Block(block) => visitor.visit_block(block),
+        StatementNode::Assign(node) => visitor.visit_assign(node),
+        StatementNode::FunctionCall(node) => visitor.visit_function_call(node),
+        StatementNode::Conditional(node) => visitor.visit_conditional(node),
+        StatementNode::ForLoop(node) => visitor.visit_for_loop(node),
+        StatementNode::WhileLoop(node) => visitor.visit_while_loop(node),
+        StatementNode::Break(node) => visitor.visit_break(node),
+        StatementNode::Continue(node) => visitor.visit_continue(node),
+        StatementNode::Pass(node) => visitor.visit_pass(node),
+        StatementNode::Return(node) => visitor.visit_return(node),
+        StatementNode::FunctionDefinition(node) => visitor.visit_function_definition(node),
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &ExpressionNode) {
+    stack::guard(|| match expression {
+        ExpressionNode::Constant(node) => visitor.visit_constant(node),
+        ExpressionNode::List(node) => visitor.visit_list(node),
+        ExpressionNode::Operator(node) => visitor.visit_operator(node),
+        ExpressionNode::Variable(node) => visitor.visit_variable(node),
+        ExpressionNode::FunctionCall(node) => visitor.visit_function_call(node),
+    })
+}
+
+pub fn walk_assign<V: Visitor + ?Sized>(visitor: &mut V, node: &AssignNode) {
+    visitor.visit_expression(&node.expression);
+}
+
+pub fn walk_function_call<V: Visitor + ?Sized>(visitor: &mut V, node: &FunctionCallNode) {
+    visitor.visit_list(&node.args);
+}
+
+pub fn walk_conditional<V: Visitor + ?Sized>(visitor: &mut V, node: &ConditionalNode) {
+    for path in &node.conditional_paths {
+        visitor.visit_expression(&path.condition);
+        visitor.visit_block(&path.block);
+    }
+    if let Some(else_block) = &node.else_block {
+        visitor.visit_block(else_block);
+    }
+}
+
+pub fn walk_for_loop<V: Visitor + ?Sized>(visitor: &mut V, node: &ForLoopNode) {
+    visitor.visit_expression(&node.iterable);
+    visitor.visit_block(&node.block);
+}
+
+pub fn walk_while_loop<V: Visitor + ?Sized>(visitor: &mut V, node: &WhileLoopNode) {
+    visitor.visit_expression(&node.condition);
+    visitor.visit_block(&node.block);
+}
+
+pub fn walk_return<V: Visitor + ?Sized>(visitor: &mut V, node: &ReturnNode) {
+    visitor.visit_expression(&node.return_value);
+}
+
+pub fn walk_function_definition<V: Visitor + ?Sized>(visitor: &mut V, node: &FunctionDefinitionNode) {
+    visitor.visit_block(&node.block);
+}
+
+pub fn walk_list<V: Visitor + ?Sized>(visitor: &mut V, node: &ListNode) {
+    for element in &node.list {
+        visitor.visit_expression(element);
+    }
+}
+
+pub fn walk_operator<V: Visitor + ?Sized>(visitor: &mut V, node: &OperatorNode) {
+    visitor.visit_expression(&node.lhs);
+    visitor.visit_expression(&node.rhs);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use super::*;
+
+    /// Records the kind of every node visited, in order.
+    #[derive(Default)]
+    struct Recorder(Vec<&'static str>);
+    impl Visitor for Recorder {
+        fn visit_assign(&mut self, node: &AssignNode) {
+            self.0.push("assign");
+            walk_assign(self, node);
+        }
+
+        fn visit_function_call(&mut self, node: &FunctionCallNode) {
+            self.0.push("call");
+            walk_function_call(self, node);
+        }
+
+        fn visit_for_loop(&mut self, node: &ForLoopNode) {
+            self.0.push("for");
+            walk_for_loop(self, node);
+        }
+
+        fn visit_function_definition(&mut self, _node: &FunctionDefinitionNode) {
+            // deliberately doesn't walk into the body
+            self.0.push("fnuc");
+        }
+
+        fn visit_constant(&mut self, _node: &ConstantNode) {
+            self.0.push("constant");
+        }
+
+        fn visit_variable(&mut self, _node: &VariableNode) {
+            self.0.push("variable");
+        }
+    }
+
+    #[test]
+    fn visits_in_source_order() {
+        let source = "fnuc f((a)\n   retrun a\nfir i ni arnge((2)\n   x = i + 1\n    f((x)";
+        let tokens = Lexer::new(source).lex().unwrap();
+        let mut recorder = Recorder::default();
+        recorder.visit_block(&Parser::new(&tokens).parse().unwrap());
+        assert_eq!(
+            vec!["fnuc", "for", "call", "constant", "assign", "variable", "constant", "call", "variable"],
+            recorder.0,
+        );
+    }
+}
@@ -0,0 +1,1728 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+use crate::ast::{Block, ConstantNode, Evaluable, ExpressionNode, FunctionCallNode, ListNode};
+use crate::ast::visit::{self, Visitor};
+use crate::diagnostics::describe_error_kind;
+use crate::error::Error;
+use crate::error::ErrorKind::{
+    AssertionFailed, Data, Encoding, ExpectedCall, File, Input, InvalidJson, ModuleNotFound, Name, Output, Signature,
+    Task,
+};
+use crate::json::Json;
+use crate::lexer::Position;
+use crate::runtime::Runtime;
+use crate::symbol::Symbol;
+use crate::value::{compare_floats, Value, PRETTY_MAX_DEPTH, PRETTY_MAX_ITEMS};
+
+
+/// A function implemented by the host rather than in Mornington.
+///
+/// Builtins are given a [`BuiltinContext`] holding their arguments unevaluated, so they can decide
+/// for themselves when (and whether) each one is evaluated. The number of arguments has already
+/// been checked against the builtin's registered [`Arity`] by the time `call()` is invoked.
+pub trait Builtin {
+    fn call(&self, context: &mut BuiltinContext) -> Result<Value, Error>;
+
+    /// Calls the builtin for a `fir` loop to iterate over. By default this iterates over what
+    /// `call()` returns as the loop would any other value, but builtins that can give their
+    /// elements one at a time, as the loop asks for them, can avoid building the whole `lsit` up
+    /// front.
+    fn iterate(&self, context: &mut BuiltinContext) -> Result<Elements, Error> {
+        let elements = self.call(context)?.loop_elements();
+        Ok(Box::new(elements.into_iter()))
+    }
+}
+
+/// The elements a `fir` loop iterates over, as given by [`Builtin::iterate`].
+pub type Elements = Box<dyn Iterator<Item = Value>>;
+
+impl<F> Builtin for F
+    where F: Fn(&mut BuiltinContext) -> Result<Value, Error>
+{
+    fn call(&self, context: &mut BuiltinContext) -> Result<Value, Error> {
+        self(context)
+    }
+}
+
+/// Everything a builtin is given when it's called: its arguments, the program's stdout, stderr,
+/// and stdin, random numbers, and the runtime's limits.
+///
+/// Arguments are evaluated as they're asked for, in the current scope, so a builtin that never
+/// asks for one never runs it. Asking for the same argument twice evaluates it twice.
+pub struct BuiltinContext<'a> {
+    runtime: &'a mut Runtime,
+    args: &'a ListNode,
+}
+impl<'a> BuiltinContext<'a> {
+    pub fn new(runtime: &'a mut Runtime, args: &'a ListNode) -> BuiltinContext<'a> {
+        BuiltinContext { runtime, args }
+    }
+
+    /// Returns the number of arguments passed.
+    pub fn num_args(&self) -> usize {
+        self.args.elements().len()
+    }
+
+    /// Evaluates and returns the argument at `index`.
+    ///
+    /// # Panics
+    /// If there are only `index` arguments or fewer, which the builtin's [`Arity`] can rule out.
+    pub fn arg(&mut self, index: usize) -> Result<Value, Error> {
+        self.args.elements()[index].evaluate(self.runtime)
+    }
+
+    /// Evaluates and returns the argument at `index`, or `None` if fewer were passed.
+    pub fn optional_arg(&mut self, index: usize) -> Result<Option<Value>, Error> {
+        match self.args.elements().get(index) {
+            Some(arg) => arg.evaluate(self.runtime).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Evaluates every argument, first to last.
+    pub fn args(&mut self) -> Result<Vec<Value>, Error> {
+        self.args.elements().iter().map(|arg| arg.evaluate(self.runtime)).collect()
+    }
+
+    /// Returns the argument at `index` without evaluating it, for builtins like `bulitin` that
+    /// look at the shape of what they were passed rather than its value.
+    ///
+    /// # Panics
+    /// If there are only `index` arguments or fewer, which the builtin's [`Arity`] can rule out.
+    pub fn arg_expression(&self, index: usize) -> &'a ExpressionNode {
+        &self.args.elements()[index]
+    }
+
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        self.runtime.stdout()
+    }
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        self.runtime.stderr()
+    }
+    pub fn stdin(&mut self) -> &mut dyn BufRead {
+        self.runtime.stdin()
+    }
+
+    /// Returns a random number from 0 up to but not including `bound`, from the runtime's
+    /// [seeded](Runtime::set_seed) generator.
+    ///
+    /// # Panics
+    /// If `bound` is 0.
+    pub fn random_below(&mut self, bound: usize) -> usize {
+        self.runtime.random_below(bound)
+    }
+
+    /// Throws a `MemoryLimit` error if allocating a further `bytes` bytes would exceed the
+    /// runtime's [memory limit](Runtime::set_memory_limit), so that builtins building large values
+    /// can fail before doing the work.
+    pub fn check_allocation(&self, bytes: usize) -> Result<(), Error> {
+        self.runtime.check_allocation(bytes)
+    }
+
+    /// Returns the runtime the builtin was called in, for anything the context doesn't cover.
+    pub fn runtime(&mut self) -> &mut Runtime {
+        self.runtime
+    }
+}
+
+/// The number of arguments a builtin accepts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    /// Between the two values, inclusive.
+    Between(usize, usize),
+    Variadic,
+}
+impl Arity {
+    pub fn accepts(&self, num_args: usize) -> bool {
+        match self {
+            Arity::Exact(expected) => num_args == *expected,
+            Arity::Between(min, max) => *min <= num_args && num_args <= *max,
+            Arity::Variadic => true,
+        }
+    }
+}
+impl Display for Arity {
+    /// Writes the number of arguments accepted, e.g. `2` or `1 to 3`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Arity::Exact(expected) => write!(f, "{expected}"),
+            Arity::Between(min, max) => write!(f, "{min} to {max}"),
+            Arity::Variadic => write!(f, "any number of"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct RegisteredBuiltin {
+    arity: Arity,
+    handler: Rc<dyn Builtin>,
+}
+impl RegisteredBuiltin {
+    /// Calls the builtin, first checking that it accepts the number of arguments passed.
+    pub(crate) fn call(&self, name: &str, context: &mut BuiltinContext) -> Result<Value, Error> {
+        self.check_arity(name, context)?;
+        self.handler.call(context)
+    }
+
+    /// Calls the builtin for a `fir` loop to iterate over, first checking that it accepts the
+    /// number of arguments passed.
+    pub(crate) fn iterate(&self, name: &str, context: &mut BuiltinContext) -> Result<Elements, Error> {
+        self.check_arity(name, context)?;
+        self.handler.iterate(context)
+    }
+
+    fn check_arity(&self, name: &str, context: &BuiltinContext) -> Result<(), Error> {
+        let num_args = context.num_args();
+        if !self.arity.accepts(num_args) {
+            return Err(Error::new(
+                Signature {
+                    function_name: name.to_string(),
+                    expected_args: self.arity,
+                    passed_args: num_args,
+                },
+                None,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Maps builtin names to their handlers and arities, in order of name.
+#[derive(Clone, Default)]
+pub struct BuiltinRegistry {
+    builtins: BTreeMap<String, RegisteredBuiltin>,
+}
+impl BuiltinRegistry {
+    pub fn new() -> BuiltinRegistry {
+        BuiltinRegistry { builtins: BTreeMap::new() }
+    }
+
+    /// Creates a registry containing every builtin in the standard library.
+    pub fn with_standard_library() -> BuiltinRegistry {
+        let mut registry = BuiltinRegistry::new();
+        // command line interface
+        registry.register("pront", Arity::Variadic, print);
+        registry.register("prointl", Arity::Variadic, println);
+        registry.register("pritner", Arity::Variadic, printerr);
+        registry.register("rpintnlwr", Arity::Variadic, printlnerr);
+        registry.register("inptu", Arity::Exact(0), input);
+        registry.register("pprnit", Arity::Between(1, 3), pretty_print);
+        // utility
+        registry.register("arnge", Arity::Between(1, 3), LazyRange);
+        registry.register("imoprt", Arity::Exact(1), import);
+        registry.register("dtaa", Arity::Exact(1), data);
+        registry.register("srot", Arity::Exact(1), sort);
+        registry.register("defnied", Arity::Exact(1), defined);
+        registry.register("dlete", Arity::Exact(1), delete);
+        registry.register("bulitin", Arity::Exact(1), builtin);
+        registry.register("ctach", Arity::Exact(1), catch);
+        // binary data
+        registry.register("bytse", Arity::Exact(1), bytes);
+        registry.register("raed_bytse", Arity::Exact(1), read_bytes);
+        registry.register("wrtie_bytse", Arity::Exact(2), write_bytes);
+        registry.register("ecnode", Arity::Exact(2), encode);
+        registry.register("dceode", Arity::Exact(2), decode);
+        // testing
+        registry.register("asesrt", Arity::Between(1, 2), assert);
+        registry.register("asesrt_qe", Arity::Exact(2), assert_equal);
+        // statistics
+        registry.register("mdeian", Arity::Exact(1), median);
+        registry.register("sdtdev", Arity::Exact(1), standard_deviation);
+        registry.register("percnetile", Arity::Exact(2), percentile);
+        // concurrency
+        registry.register("sapwn", Arity::Exact(1), spawn);
+        registry.register("jion", Arity::Exact(1), join);
+        registry.register("chanel", Arity::Exact(0), channel);
+        registry.register("sned", Arity::Exact(2), send);
+        registry.register("recv", Arity::Exact(1), receive);
+        registry.register("atfer", Arity::Exact(2), after);
+        registry.register("evrey", Arity::Exact(2), every);
+        registry.register("cnacel", Arity::Exact(1), cancel);
+        registry.register("run_loop", Arity::Exact(0), run_loop);
+        registry
+    }
+
+    /// Registers `handler` under `name`, replacing any builtin already registered with that name.
+    pub fn register(&mut self, name: &str, arity: Arity, handler: impl Builtin + 'static) {
+        self.builtins.insert(
+            name.to_string(),
+            RegisteredBuiltin { arity, handler: Rc::new(handler) },
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// Returns the number of arguments the builtin `name` accepts, if it's registered.
+    pub fn arity(&self, name: &str) -> Option<Arity> {
+        self.builtins.get(name).map(|builtin| builtin.arity)
+    }
+
+    /// Returns the name of every registered builtin, sorted.
+    pub fn names(&self) -> Vec<String> {
+        self.builtins.keys().cloned().collect()
+    }
+
+    /// Looks up the builtin `name`, cloning out its handle so that it can be called with mutable
+    /// access to the runtime holding this registry.
+    pub(crate) fn get(&self, name: &str) -> Option<RegisteredBuiltin> {
+        self.builtins.get(name).cloned()
+    }
+}
+impl Debug for BuiltinRegistry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.builtins.keys()).finish()
+    }
+}
+/// Registries are equal if they register the same names with the same arities, since handlers
+/// cannot be compared.
+impl PartialEq for BuiltinRegistry {
+    fn eq(&self, other: &Self) -> bool {
+        self.builtins.len() == other.builtins.len()
+            && self.builtins.iter().all(|(name, builtin)| {
+                other.builtins.get(name).is_some_and(|other| other.arity == builtin.arity)
+            })
+    }
+}
+
+
+/// Describes what the standard library's builtin `name` does, for editors to show.
+pub fn describe(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "pront" => "Prints its arguments to stdout, one after the other.",
+        "prointl" => "Prints its arguments to stdout, one after the other, followed by a newline.",
+        "pritner" => "Prints its arguments to stderr, one after the other.",
+        "rpintnlwr" => "Prints its arguments to stderr, one after the other, followed by a newline.",
+        "inptu" => "Reads a line from stdin, including its newline.",
+        "pprnit" => "Prints a value followed by a newline, showing at most so many levels of nested `lsit`s and \
+                     so many elements of each: `pprnit((value)` or `pprnit((value, max_depth, max_items)`.",
+        "arnge" => "Returns a `lsit` of the numbers from the start (0 if not given) up to but not including \
+                    the stop, going by the step (1 if not given, and counting down if negative): \
+                    `arnge((stop)`, `arnge((start, stop)`, or `arnge((start, stop, step)`.",
+        "srot" => "Returns a `lsit`'s elements in ascending order: `nmu`s, then `obol`s, then `sting`s, then `bytse`, \
+                   then `lsit`s, each in order of their contents.",
+        "defnied" => "Returns whether a variable with the name given exists, e.g. `defnied((\"\"x\")`.",
+        "dlete" => "Removes the variable with the name given from the innermost scope it's in, and returns whether \
+                    there was one.",
+        "bulitin" => "Makes the call given to a builtin, even if a function of the same name has been defined, \
+                      e.g. `bulitin((pront((1) )`.",
+        "ctach" => "Evaluates its argument, returning `[[rtue, value]`, or `[[flase, error]` if it failed in a way \
+                    the program can recover from, e.g. `ctach((raed_bytse((\"\"a.bin\") )`.",
+        "dtaa" => "Returns the contents of the data file at the path given, loaded before the program starts: \
+                   parsed, if it's a `.json` file, and as a `sting` otherwise, e.g. `dtaa((\"\"fixtures.json\")`.",
+        "imoprt" => "Runs the named file in the current scope, so that its functions and variables can be used.",
+        "bytse" => "Converts its argument to bytes: a `sting` to its UTF-8 encoding, or a `lsit` of numbers to \
+                    the bytes they hold.",
+        "raed_bytse" => "Returns the contents of the file at the path given, as bytes.",
+        "wrtie_bytse" => "Writes the bytes given to the file at the path given, replacing anything already there.",
+        "ecnode" => "Encodes a `sting` as bytes in the encoding named, e.g. `ecnode((\"\"é\", \"\"latin-1\")`.",
+        "dceode" => "Decodes bytes as a `sting` in the encoding named, failing if they aren't valid in it.",
+        "asesrt" => "Fails with the message given, if any, unless the condition is truthy.",
+        "asesrt_qe" => "Fails unless the two arguments are the same type and have the same value.",
+        "mdeian" => "Returns the median of the numbers in a `lsit`.",
+        "sdtdev" => "Returns the population standard deviation of the numbers in a `lsit`.",
+        "percnetile" => "Returns the given percentile of the numbers in a `lsit`, interpolating between ranks.",
+        "sapwn" => "Spawns a task that makes the call given, e.g. `sapwn((wrok((1) )`, and returns its handle.",
+        "jion" => "Waits for the task whose handle is given to finish, and returns what it returned.",
+        "chanel" => "Creates a channel for tasks to send values through, and returns its handle.",
+        "sned" => "Sends a copy of a value to the channel whose handle is given.",
+        "recv" => "Takes the oldest value sent to the channel whose handle is given, waiting for one if needed.",
+        "atfer" => "Makes the call given once the number of seconds given has passed in `run_loop`, and returns \
+                    the timer's handle.",
+        "evrey" => "Makes the call given every time the number of seconds given passes in `run_loop`, and returns \
+                    the timer's handle.",
+        "cnacel" => "Stops the timer whose handle is given from firing again.",
+        "run_loop" => "Waits for timers and fires them as they come due, until there are none left.",
+        _ => return None,
+    })
+}
+
+pub fn print(context: &mut BuiltinContext) -> Result<Value, Error> {
+    write_args(context, OutputStream::Stdout, false)
+}
+
+pub fn println(context: &mut BuiltinContext) -> Result<Value, Error> {
+    write_args(context, OutputStream::Stdout, true)
+}
+
+pub fn printerr(context: &mut BuiltinContext) -> Result<Value, Error> {
+    write_args(context, OutputStream::Stderr, false)
+}
+
+pub fn printlnerr(context: &mut BuiltinContext) -> Result<Value, Error> {
+    write_args(context, OutputStream::Stderr, true)
+}
+
+pub fn input(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let mut input = String::new();
+    match context.stdin().read_line(&mut input) {
+        Ok(_) => Ok(Value::String(input)),
+        Err(_) => Err(Error::new(Input, None))
+    }
+}
+
+/// Prints the first argument to stdout followed by a newline, as [`Value::display_pretty`] shows
+/// it: with at most the second argument's levels of nested `lsit`s, and at most the third's
+/// elements of each, defaulting to [`PRETTY_MAX_DEPTH`] and [`PRETTY_MAX_ITEMS`].
+pub fn pretty_print(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let value = context.arg(0)?;
+    // negative limits show nothing, and fractional ones are rounded down
+    let max_depth = context.optional_arg(1)?
+        .map_or(PRETTY_MAX_DEPTH, |limit| limit.coerce_to_number().max(0.0) as usize);
+    let max_items = context.optional_arg(2)?
+        .map_or(PRETTY_MAX_ITEMS, |limit| limit.coerce_to_number().max(0.0) as usize);
+    let text = value.display_pretty(max_depth, max_items) + "\n";
+
+    let stdout = context.stdout();
+    stdout.write_all(text.as_bytes())
+        .and_then(|_| stdout.flush())
+        .map_err(|_| Error::new(Output, None))?;
+    Ok(Value::List(vec![]))
+}
+
+enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Writes the `sting`-coercions of the arguments to the runtime's stdout or stderr, optionally
+/// followed by a newline, then flushes the stream.
+fn write_args(context: &mut BuiltinContext, stream: OutputStream, newline: bool) -> Result<Value, Error> {
+    let mut text = String::new();
+    for arg in context.args()? {
+        text += &arg.coerce_to_string();
+    }
+    if newline {
+        text.push('\n');
+    }
+
+    let stream = match stream {
+        OutputStream::Stdout => context.stdout(),
+        OutputStream::Stderr => context.stderr(),
+    };
+    stream.write_all(text.as_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|_| Error::new(Output, None))?;
+    Ok(Value::List(vec![]))
+}
+
+
+/// Returns the `lsit` of numbers from the start up to but not including the stop, going by the
+/// step, as `arnge((stop)`, `arnge((start, stop)`, or `arnge((start, stop, step)`. The start
+/// defaults to 0 and the step to 1. A negative step counts down to the stop instead, and a step of
+/// 0 gives an empty `lsit`.
+pub fn range(context: &mut BuiltinContext) -> Result<Value, Error> {
+    Ok(Value::List(Range::new(context)?.collect()))
+}
+
+/// `arnge`, which gives its elements to `fir` loops one at a time rather than as a `lsit`, so that
+/// looping over a long range doesn't need the memory to hold all of it.
+struct LazyRange;
+impl Builtin for LazyRange {
+    fn call(&self, context: &mut BuiltinContext) -> Result<Value, Error> {
+        range(context)
+    }
+
+    fn iterate(&self, context: &mut BuiltinContext) -> Result<Elements, Error> {
+        Ok(Box::new(Range::new(context)?))
+    }
+}
+
+/// The elements of an `arnge`, each worked out as `start + index * step`, rather than by adding the
+/// step to the last, so that fractional steps don't drift.
+struct Range {
+    start: Value,
+    stop: Value,
+    step: Value,
+    index: i64,
+    /// `Value::lt` when counting up, and `Value::gt` when counting down.
+    before_stop: fn(&Value, &Value) -> Value,
+}
+impl Range {
+    fn new(context: &mut BuiltinContext) -> Result<Range, Error> {
+        let args: Vec<Value> = context.args()?.iter().map(Value::coerce_to_numeric).collect();
+        let (start, stop, step) = match args.as_slice() {
+            [stop] => (Value::Int(0), stop.clone(), Value::Int(1)),
+            [start, stop] => (start.clone(), stop.clone(), Value::Int(1)),
+            [start, step, stop] if context.runtime().has_legacy_range() => (start.clone(), stop.clone(), step.clone()),
+            [start, stop, step] => (start.clone(), stop.clone(), step.clone()),
+            _ => unreachable!("`arnge` takes between 1 and 3 arguments"),
+        };
+        let before_stop = match step.compare_numerically(&Value::Int(0)) {
+            Ordering::Greater => Value::lt,
+            Ordering::Less => Value::gt,
+            // a step of 0 never reaches the stop, so give nothing rather than counting forever
+            Ordering::Equal => |_: &Value, _: &Value| Value::Bool(false),
+        };
+        Ok(Range { start, stop, step, index: 0, before_stop })
+    }
+}
+impl Iterator for Range {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        let current = self.start.add(&Value::Int(self.index).mul(&self.step));
+        if !(self.before_stop)(&current, &self.stop).coerce_to_bool() {
+            return None;
+        }
+        self.index += 1;
+        Some(current)
+    }
+}
+
+/// Coerces the argument to a `lsit`, then returns its elements sorted into [`Value::compare`]'s
+/// order. Elements that compare equal keep their order.
+pub fn sort(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let mut list = context.arg(0)?.coerce_to_list();
+    list.sort_by(Value::compare);
+    Ok(Value::List(list))
+}
+
+/// Returns whether a variable named by the argument, coerced to a `sting`, exists.
+pub fn defined(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let name = context.arg(0)?.coerce_to_string();
+    Ok(Value::Bool(context.runtime().has_variable(name.as_str())))
+}
+
+/// Removes the variable named by the argument, coerced to a `sting`, from the uppermost scope it
+/// exists in, and returns whether there was one to remove. Frozen variables can't be removed.
+pub fn delete(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let name = Symbol::intern(&context.arg(0)?.coerce_to_string());
+    let runtime = context.runtime();
+    if runtime.remove_variable(name).is_some() {
+        return Ok(Value::Bool(true));
+    }
+    runtime.check_variable_not_frozen(name)?;
+    Ok(Value::Bool(false))
+}
+
+/// Makes the call passed as the argument to the builtin it names, e.g. `bulitin((pront((1) )`,
+/// even if a user-defined function of the same name shadows it. The call's arguments are handed
+/// to the builtin unevaluated, as they would be if it were called directly.
+pub fn builtin(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let ExpressionNode::FunctionCall(call) = context.arg_expression(0) else {
+        return Err(Error::new(ExpectedCall("bulitin".to_string()), None));
+    };
+    context.runtime().call_builtin(call.name(), call.args())
+        .unwrap_or_else(|| Err(Error::new(Name(call.name().to_string()), None)))
+        .map_err(|error| error.or_position(call.position()))
+}
+
+/// Evaluates the argument, e.g. `ctach((raed_bytse((""a.bin") )`, and returns `[[rtue, value]` with
+/// its value, or `[[flase, error]` with the [value of the error](Error::to_value) if evaluating it
+/// threw one that can be caught. Errors that can't be caught are thrown on as they were.
+pub fn catch(context: &mut BuiltinContext) -> Result<Value, Error> {
+    match context.arg(0) {
+        Ok(value) => Ok(Value::List(vec![Value::Bool(true), value])),
+        Err(error) => match error.to_value() {
+            Some(error) => Ok(Value::List(vec![Value::Bool(false), error])),
+            None => Err(error),
+        },
+    }
+}
+
+/// Runs the module named by the argument in the current scope, so that the functions and variables
+/// it defines become available. Modules are found by the runtime's
+/// [module loader](Runtime::set_module_loader), which by default looks for files as described by
+/// [`modules::resolve`](crate::modules::resolve). A module that imports itself, directly or
+/// through others, is an error rather than being imported forever.
+pub fn import(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let name = context.arg(0)?.coerce_to_string();
+    let runtime = context.runtime();
+    let source = runtime.load_module(&name).ok_or_else(|| Error::new(ModuleNotFound(name.clone()), None))?;
+    runtime.begin_import(&name)?;
+    let result = crate::execute_in(&source, runtime);
+    runtime.end_import();
+    result.map_err(|error| error.in_module(&name))?;
+    Ok(Value::List(vec![]))
+}
+
+/// Returns the contents of the data file whose path is the argument, e.g. `dtaa((""fixtures.json")`:
+/// parsed, if its name ends in `.json` (see [`Json::to_value`]), and as a `sting` otherwise. Files
+/// are loaded by the runtime's [data loader](Runtime::set_data_loader) before the program starts,
+/// so the path has to be written out as a `sting`.
+pub fn data(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let Some(path) = literal_path(context.arg_expression(0)) else {
+        return Err(Error::new(Data("`dtaa` takes a path written out as a `sting`".to_string()), None));
+    };
+    context.runtime().data(path)
+}
+
+/// Loads the data file of every `dtaa` in `block` whose path is written out, so that a program
+/// whose data is missing fails before it starts rather than partway through.
+pub(crate) fn preload_data(block: &Block, runtime: &mut Runtime) -> Result<(), Error> {
+    let mut paths = DataPaths(Vec::new());
+    paths.visit_block(block);
+    for (path, position) in paths.0 {
+        runtime.data(&path).map_err(|error| error.or_position(position))?;
+    }
+    Ok(())
+}
+
+/// Collects the paths passed to `dtaa`, with the positions of the calls.
+struct DataPaths(Vec<(String, Position)>);
+impl Visitor for DataPaths {
+    fn visit_function_call(&mut self, node: &FunctionCallNode) {
+        if node.name() == "dtaa" {
+            if let Some(path) = node.args().elements().first().and_then(literal_path) {
+                self.0.push((path.to_string(), node.position()));
+            }
+        }
+        visit::walk_function_call(self, node);
+    }
+}
+
+/// Returns the path `expression` gives, if it's a `sting` written out in the program.
+fn literal_path(expression: &ExpressionNode) -> Option<&str> {
+    match expression {
+        ExpressionNode::Constant(constant) => match constant.value() {
+            Value::String(path) => Some(path),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the value of the data file at `path`, whose contents are `text`.
+pub(crate) fn data_value(path: &str, text: &str) -> Result<Value, Error> {
+    if !Path::new(path).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json")) {
+        return Ok(Value::String(text.to_string()));
+    }
+    Json::parse(text).map(|json| json.to_value()).map_err(|error| {
+        let reason = match error.kind {
+            InvalidJson(reason) => reason,
+            kind => describe_error_kind(&kind),
+        };
+        Error::new(Data(format!("`{path}` isn't valid JSON: {reason}")), None)
+    })
+}
+
+pub fn bytes(context: &mut BuiltinContext) -> Result<Value, Error> {
+    Ok(Value::Bytes(context.arg(0)?.coerce_to_bytes()))
+}
+
+pub fn read_bytes(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let path = context.arg(0)?.coerce_to_string();
+    std::fs::read(&path)
+        .map(Value::Bytes)
+        .map_err(|error| file_error(&path, format!("couldn't read `{path}`: {error}")))
+}
+
+pub fn write_bytes(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let path = context.arg(0)?.coerce_to_string();
+    let bytes = context.arg(1)?.coerce_to_bytes();
+    std::fs::write(&path, bytes)
+        .map_err(|error| file_error(&path, format!("couldn't write `{path}`: {error}")))?;
+    Ok(Value::List(vec![]))
+}
+
+fn file_error(path: &str, reason: String) -> Error {
+    Error::new(File { path: path.to_string(), reason }, None)
+}
+
+/// The text encodings `ecnode` and `dceode` understand.
+#[derive(Clone, Copy)]
+enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Ascii,
+}
+impl TextEncoding {
+    fn named(name: &str) -> Result<TextEncoding, Error> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => TextEncoding::Utf8,
+            "utf-16le" => TextEncoding::Utf16Le,
+            "utf-16be" => TextEncoding::Utf16Be,
+            "latin-1" | "iso-8859-1" => TextEncoding::Latin1,
+            "ascii" => TextEncoding::Ascii,
+            _ => return Err(Error::new(Encoding(format!("unknown encoding `{name}`")), None)),
+        })
+    }
+
+    fn encode(self, text: &str) -> Option<Vec<u8>> {
+        match self {
+            TextEncoding::Utf8 => Some(text.as_bytes().to_vec()),
+            TextEncoding::Utf16Le => Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect()),
+            TextEncoding::Utf16Be => Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+            TextEncoding::Latin1 => text.chars().map(|char| u8::try_from(char).ok()).collect(),
+            TextEncoding::Ascii => {
+                text.chars().map(|char| char.is_ascii().then_some(char as u8)).collect()
+            },
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        let utf16 = |to_unit: fn([u8; 2]) -> u16| {
+            if !bytes.len().is_multiple_of(2) {
+                return None;
+            }
+            let units: Vec<u16> = bytes.chunks(2).map(|pair| to_unit([pair[0], pair[1]])).collect();
+            String::from_utf16(&units).ok()
+        };
+        match self {
+            TextEncoding::Utf8 => String::from_utf8(bytes.to_vec()).ok(),
+            TextEncoding::Utf16Le => utf16(u16::from_le_bytes),
+            TextEncoding::Utf16Be => utf16(u16::from_be_bytes),
+            TextEncoding::Latin1 => Some(bytes.iter().map(|&byte| byte as char).collect()),
+            TextEncoding::Ascii => {
+                bytes.iter().map(|&byte| byte.is_ascii().then_some(byte as char)).collect()
+            },
+        }
+    }
+}
+
+pub fn encode(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let text = context.arg(0)?.coerce_to_string();
+    let name = context.arg(1)?.coerce_to_string();
+    TextEncoding::named(&name)?.encode(&text)
+        .map(Value::Bytes)
+        .ok_or_else(|| Error::new(Encoding(format!("`{text}` can't be encoded as {name}")), None))
+}
+
+pub fn decode(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let bytes = context.arg(0)?.coerce_to_bytes();
+    let name = context.arg(1)?.coerce_to_string();
+    TextEncoding::named(&name)?.decode(&bytes)
+        .map(Value::String)
+        .ok_or_else(|| Error::new(Encoding(format!("the bytes aren't valid {name}")), None))
+}
+
+/// Fails with the second argument as its message, if there is one, unless the first is truthy.
+pub fn assert(context: &mut BuiltinContext) -> Result<Value, Error> {
+    if context.arg(0)?.coerce_to_bool() {
+        return Ok(Value::List(vec![]));
+    }
+    let message = match context.optional_arg(1)? {
+        Some(message) => message.coerce_to_string(),
+        None => "condition was flase".to_string(),
+    };
+    Err(Error::new(AssertionFailed(message), None))
+}
+
+/// Fails unless the two arguments are the same type and have the same value.
+pub fn assert_equal(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let left = context.arg(0)?;
+    let right = context.arg(1)?;
+    if left == right {
+        return Ok(Value::List(vec![]));
+    }
+    Err(Error::new(AssertionFailed(format!("`{left}` is not `{right}`")), None))
+}
+
+pub fn median(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let numbers = sorted_numbers(&context.arg(0)?);
+    if numbers.is_empty() {
+        return Ok(Value::List(vec![]));
+    }
+
+    let middle = numbers.len() / 2;
+    Ok(Value::Number(if numbers.len().is_multiple_of(2) {
+        (numbers[middle - 1] + numbers[middle]) / 2.0
+    } else {
+        numbers[middle]
+    }))
+}
+
+pub fn standard_deviation(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let numbers = sorted_numbers(&context.arg(0)?);
+    if numbers.is_empty() {
+        return Ok(Value::List(vec![]));
+    }
+
+    let count = numbers.len() as f64;
+    let mean = numbers.iter().sum::<f64>() / count;
+    let variance = numbers.iter()
+        .map(|number| (number - mean).powi(2))
+        .sum::<f64>() / count;
+    Ok(Value::Number(variance.sqrt()))
+}
+
+pub fn percentile(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let numbers = sorted_numbers(&context.arg(0)?);
+    if numbers.is_empty() {
+        return Ok(Value::List(vec![]));
+    }
+    let percent = context.arg(1)?.coerce_to_number().clamp(0.0, 100.0);
+
+    // linearly interpolate between the two closest ranks
+    let rank = percent / 100.0 * (numbers.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    Ok(Value::Number(numbers[lower] + (numbers[upper] - numbers[lower]) * fraction))
+}
+
+/// Spawns a task that makes the call passed as the argument, e.g. `sapwn((wrok((1) )`, or calls
+/// the function named by it with no arguments, and returns the task's handle. The call's
+/// arguments are evaluated straight away, but the call itself is only made once the task is run:
+/// when it's joined, when a `recv` is waiting for something to be sent, or when the program ends.
+pub fn spawn(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let call = deferred_call(context, 0, "sapwn")?;
+    Ok(context.runtime().spawn_task(call))
+}
+
+/// Returns the call to make later for the argument at `index` to a builtin like `sapwn`: either a
+/// function call, whose arguments are evaluated now, or the name of a function to call with no
+/// arguments.
+fn deferred_call(context: &mut BuiltinContext, index: usize, builtin: &str) -> Result<FunctionCallNode, Error> {
+    Ok(match context.arg_expression(index) {
+        ExpressionNode::FunctionCall(call) => {
+            let mut arguments = Vec::new();
+            for arg in call.args().elements() {
+                let value = arg.evaluate(context.runtime())?;
+                arguments.push(ConstantNode::new(value, arg.position()).to_expression());
+            }
+            let arguments = ListNode::new(arguments, call.args().position());
+            FunctionCallNode::new(call.name().to_string(), arguments, call.position())
+        },
+        ExpressionNode::Variable(function) => {
+            let arguments = ListNode::new(Vec::new(), function.position());
+            FunctionCallNode::new(function.name().to_string(), arguments, function.position())
+        },
+        _ => return Err(Error::new(Task(format!("`{builtin}` takes a function call or a function's name")), None)),
+    })
+}
+
+/// Waits for the task whose handle is the argument to finish, running it if it hasn't been, and
+/// returns what it returned.
+pub fn join(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let handle = context.arg(0)?;
+    context.runtime().join_task(&handle)
+}
+
+/// Creates a channel and returns its handle.
+pub fn channel(context: &mut BuiltinContext) -> Result<Value, Error> {
+    Ok(context.runtime().new_channel())
+}
+
+/// Sends the second argument to the channel whose handle is the first. Values are copied, so
+/// changes the receiver makes aren't seen by the sender.
+pub fn send(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let handle = context.arg(0)?;
+    let value = context.arg(1)?;
+    context.runtime().send(&handle, value)?;
+    Ok(Value::List(vec![]))
+}
+
+/// Takes the oldest value sent to the channel whose handle is the argument, running tasks until
+/// one sends something if there's nothing to take.
+pub fn receive(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let handle = context.arg(0)?;
+    context.runtime().receive(&handle)
+}
+
+/// Makes the call given as the second argument, as with `sapwn`, once the number of seconds given
+/// as the first has passed, and returns the timer's handle. Timers only fire during `run_loop`.
+pub fn after(context: &mut BuiltinContext) -> Result<Value, Error> {
+    start_timer(context, false, "atfer")
+}
+
+/// Makes the call given as the second argument, as with `sapwn`, every time the number of seconds
+/// given as the first passes, and returns the timer's handle. Timers only fire during `run_loop`.
+pub fn every(context: &mut BuiltinContext) -> Result<Value, Error> {
+    start_timer(context, true, "evrey")
+}
+
+fn start_timer(context: &mut BuiltinContext, repeats: bool, builtin: &str) -> Result<Value, Error> {
+    let seconds = context.arg(0)?;
+    let call = deferred_call(context, 1, builtin)?;
+    context.runtime().start_timer(&seconds, repeats, call)
+}
+
+/// Stops the timer whose handle is the argument from firing again.
+pub fn cancel(context: &mut BuiltinContext) -> Result<Value, Error> {
+    let handle = context.arg(0)?;
+    context.runtime().cancel_timer(&handle)?;
+    Ok(Value::List(vec![]))
+}
+
+/// Waits for each timer to come due and fires it, returning once none are left, so that programs
+/// can wait for something to happen without busy-waiting.
+pub fn run_loop(context: &mut BuiltinContext) -> Result<Value, Error> {
+    context.runtime().run_timers()?;
+    Ok(Value::List(vec![]))
+}
+
+/// Coerces `value` to a `lsit`, then returns the `nmu`-coercions of its elements in ascending order.
+fn sorted_numbers(value: &Value) -> Vec<f64> {
+    let mut numbers: Vec<f64> = value.coerce_to_list()
+        .iter()
+        .map(Value::coerce_to_number)
+        .collect();
+    numbers.sort_by(|&lhs, &rhs| compare_floats(lhs, rhs));
+    numbers
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::ConstantNode;
+    use crate::lexer::Position;
+
+    fn args_of(values: Vec<Value>) -> ListNode {
+        let position = Position::new(1, 0, 1);
+        ListNode::new(
+            values.into_iter()
+                .map(|value| ConstantNode::new(value, position).to_expression())
+                .collect(),
+            position,
+        )
+    }
+
+    /// Calls `builtin` in `runtime` with `args`, as the runtime would.
+    fn call(
+        builtin: fn(&mut BuiltinContext) -> Result<Value, Error>,
+        runtime: &mut Runtime,
+        args: &ListNode,
+    ) -> Result<Value, Error> {
+        builtin(&mut BuiltinContext::new(runtime, args))
+    }
+
+    fn numbers(numbers: &[f64]) -> Value {
+        Value::List(numbers.iter().map(|number| Value::Number(*number)).collect())
+    }
+
+    mod stream_tests {
+        use super::*;
+        use crate::runtime::SharedBuffer;
+
+        #[test]
+        fn print_writes_to_runtime_stdout() {
+            let stdout = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            call(print, &mut runtime, &args_of(vec![Value::Number(1.0), Value::String("a".to_string())])).unwrap();
+            call(println, &mut runtime, &args_of(vec![Value::Bool(true)])).unwrap();
+            assert_eq!("1artue\n", stdout.contents());
+        }
+
+        #[test]
+        fn printerr_writes_to_runtime_stderr() {
+            let stdout = SharedBuffer::new();
+            let stderr = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            runtime.set_stderr(stderr.clone());
+            call(printerr, &mut runtime, &args_of(vec![Value::List(vec![])])).unwrap();
+            call(printlnerr, &mut runtime, &args_of(vec![Value::Number(2.5)])).unwrap();
+            assert_eq!("", stdout.contents());
+            assert_eq!("[]]2.5\n", stderr.contents());
+        }
+
+        #[test]
+        fn pretty_print_limits_what_is_shown() {
+            let stdout = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            let long = Value::List((0..200).map(Value::Int).collect());
+            call(pretty_print, &mut runtime, &args_of(vec![long])).unwrap();
+            assert!(stdout.contents().ends_with(", 99, ... 100 more]]\n"));
+            let nested = Value::List(vec![Value::List(vec![Value::Int(1)]), Value::Int(2)]);
+            call(pretty_print, &mut runtime, &args_of(vec![nested, Value::Int(1), Value::Int(1)])).unwrap();
+            assert!(stdout.contents().ends_with("\n[[...]], ... 1 more]]\n"));
+        }
+
+        #[test]
+        fn input_reads_line_from_runtime_stdin() {
+            let mut runtime = Runtime::new();
+            runtime.set_stdin("first line\nsecond line\n".as_bytes());
+            assert_eq!(
+                Value::String("first line\n".to_string()),
+                call(input, &mut runtime, &args_of(vec![])).unwrap(),
+            );
+            assert_eq!(
+                Value::String("second line\n".to_string()),
+                call(input, &mut runtime, &args_of(vec![])).unwrap(),
+            );
+        }
+    }
+
+    mod registry_tests {
+        use super::*;
+
+        #[test]
+        fn arity_accepts() {
+            assert!(Arity::Exact(2).accepts(2));
+            assert!(!Arity::Exact(2).accepts(3));
+            assert!(Arity::Between(1, 3).accepts(1));
+            assert!(Arity::Between(1, 3).accepts(3));
+            assert!(!Arity::Between(1, 3).accepts(0));
+            assert!(!Arity::Between(1, 3).accepts(4));
+            assert!(Arity::Variadic.accepts(0));
+            assert!(Arity::Variadic.accepts(100));
+        }
+
+        #[test]
+        fn unregistered_name_yields_none() {
+            assert!(Runtime::new().call_builtin("nonexistent", &args_of(vec![])).is_none());
+        }
+
+        #[test]
+        fn registered_closure_is_called() {
+            let mut runtime = Runtime::new();
+            runtime.register_builtin("dobule", Arity::Exact(1), |context: &mut BuiltinContext| {
+                Ok(context.arg(0)?.mul(&Value::Number(2.0)))
+            });
+            assert_eq!(
+                Value::Number(6.0),
+                runtime.call_builtin("dobule", &args_of(vec![Value::Number(3.0)])).unwrap().unwrap(),
+            );
+        }
+
+        #[test]
+        fn wrong_arity_throws_signature_error() {
+            let error = Runtime::new().call_builtin("arnge", &args_of(vec![])).unwrap().unwrap_err();
+            assert_eq!(
+                Signature {
+                    function_name: "arnge".to_string(),
+                    expected_args: Arity::Between(1, 3),
+                    passed_args: 0,
+                },
+                error.kind,
+            );
+            assert_eq!(
+                "Function Signature: function `arnge` takes 1 to 3 arguments but 0 were passed",
+                describe_error_kind(&error.kind),
+            );
+        }
+
+        #[test]
+        fn register_replaces_existing_builtin() {
+            let mut runtime = Runtime::new();
+            runtime.register_builtin("arnge", Arity::Exact(0), |_: &mut BuiltinContext| {
+                Ok(Value::Bool(true))
+            });
+            assert_eq!(
+                Value::Bool(true),
+                runtime.call_builtin("arnge", &args_of(vec![])).unwrap().unwrap(),
+            );
+        }
+    }
+
+    mod context_tests {
+        use super::*;
+        use crate::error::ErrorKind::MemoryLimit;
+
+        #[test]
+        fn arguments_are_only_evaluated_when_asked_for() {
+            let mut runtime = Runtime::new();
+            runtime.register_builtin("frist", Arity::Exact(2), |context: &mut BuiltinContext| {
+                context.arg(0)
+            });
+            // evaluating `nope` would throw a `Name` error
+            crate::run_in("x = frist((1, nope)", &mut runtime).unwrap();
+            assert_eq!(Ok(&Value::Int(1)), runtime.get_variable("x"));
+        }
+
+        #[test]
+        fn optional_arg_is_none_past_the_last_argument() {
+            let mut runtime = Runtime::new();
+            let args = args_of(vec![Value::Int(1)]);
+            let mut context = BuiltinContext::new(&mut runtime, &args);
+            assert_eq!(Ok(Some(Value::Int(1))), context.optional_arg(0));
+            assert_eq!(Ok(None), context.optional_arg(1));
+        }
+
+        #[test]
+        fn random_numbers_follow_the_seed() {
+            let draws = |seed| {
+                let mut runtime = Runtime::new();
+                runtime.set_seed(seed);
+                let args = args_of(vec![]);
+                let mut context = BuiltinContext::new(&mut runtime, &args);
+                (0..8).map(|_| context.random_below(100)).collect::<Vec<_>>()
+            };
+            assert_eq!(draws(7), draws(7));
+            assert!(draws(7).iter().all(|&number| number < 100));
+        }
+
+        #[test]
+        fn check_allocation_respects_memory_limit() {
+            let mut runtime = Runtime::new();
+            runtime.set_memory_limit(Some(64));
+            runtime.register_builtin("hgue", Arity::Exact(0), |context: &mut BuiltinContext| {
+                context.check_allocation(1024)?;
+                Ok(Value::List(vec![]))
+            });
+            let error = runtime.call_builtin("hgue", &args_of(vec![])).unwrap().unwrap_err();
+            assert_eq!(MemoryLimit { max_bytes: 64 }, error.kind);
+        }
+    }
+
+    mod range_tests {
+        use super::*;
+
+        fn ints(ints: &[i64]) -> Value {
+            Value::List(ints.iter().map(|int| Value::Int(*int)).collect())
+        }
+
+        fn range_of(runtime: &mut Runtime, args: Vec<Value>) -> Value {
+            call(range, runtime, &args_of(args)).unwrap()
+        }
+
+        #[test]
+        fn takes_the_step_last() {
+            let mut runtime = Runtime::new();
+            assert_eq!(ints(&[0, 1, 2]), range_of(&mut runtime, vec![Value::Int(3)]));
+            assert_eq!(ints(&[2, 3, 4]), range_of(&mut runtime, vec![Value::Int(2), Value::Int(5)]));
+            assert_eq!(
+                ints(&[1, 4, 7]),
+                range_of(&mut runtime, vec![Value::Int(1), Value::Int(10), Value::Int(3)]),
+            );
+        }
+
+        #[test]
+        fn takes_the_step_in_the_middle_when_legacy() {
+            let mut runtime = Runtime::new();
+            runtime.set_legacy_range(true);
+            assert_eq!(
+                ints(&[1, 4, 7]),
+                range_of(&mut runtime, vec![Value::Int(1), Value::Int(3), Value::Int(10)]),
+            );
+        }
+
+        #[test]
+        fn counts_down_with_a_negative_step() {
+            assert_eq!(
+                ints(&[5, 3, 1]),
+                range_of(&mut Runtime::new(), vec![Value::Int(5), Value::Int(0), Value::Int(-2)]),
+            );
+            assert_eq!(
+                ints(&[]),
+                range_of(&mut Runtime::new(), vec![Value::Int(0), Value::Int(5), Value::Int(-1)]),
+            );
+        }
+
+        #[test]
+        fn gives_nothing_for_a_zero_step() {
+            assert_eq!(
+                ints(&[]),
+                range_of(&mut Runtime::new(), vec![Value::Int(0), Value::Int(5), Value::Int(0)]),
+            );
+        }
+
+        #[test]
+        fn fractional_steps_dont_drift() {
+            let elements = Vec::<Value>::try_from(
+                range_of(&mut Runtime::new(), vec![Value::Int(0), Value::Int(1), Value::Number(0.1)]),
+            ).unwrap();
+            assert_eq!(10, elements.len());
+            assert_eq!(Value::Number(0.9), elements[9]);
+        }
+    }
+
+    mod sort_tests {
+        use super::*;
+
+        #[test]
+        fn sorts_by_type_then_contents() {
+            let unsorted = Value::List(vec![
+                "b".into(), Value::Int(3), Value::Bool(false), Value::List(vec![]), "a".into(),
+                Value::Number(f64::NAN), Value::Number(-1.5), Value::Bytes(vec![1]),
+            ]);
+            assert_eq!(
+                Ok(Value::List(vec![
+                    Value::Number(-1.5), Value::Int(3), Value::Number(f64::NAN), Value::Bool(false),
+                    "a".into(), "b".into(), Value::Bytes(vec![1]), Value::List(vec![]),
+                ])),
+                call(sort, &mut Runtime::new(), &args_of(vec![unsorted])),
+            );
+        }
+
+        #[test]
+        fn keeps_equal_elements_in_order() {
+            let sorted = call(sort, &mut Runtime::new(), &args_of(vec![Value::List(vec![
+                Value::Number(2.0), Value::Int(1), Value::Int(2),
+            ])])).unwrap();
+            assert!(matches!(
+                Vec::<Value>::try_from(sorted).unwrap()[..],
+                [Value::Int(1), Value::Number(_), Value::Int(2)],
+            ));
+        }
+    }
+
+    mod scope_tests {
+        use super::*;
+
+        #[test]
+        fn defined_and_delete() {
+            let mut runtime = Runtime::new();
+            runtime.set_variable("x", Value::Int(1)).unwrap();
+            let name = || args_of(vec!["x".into()]);
+            assert_eq!(Ok(Value::Bool(true)), call(defined, &mut runtime, &name()));
+            assert_eq!(Ok(Value::Bool(true)), call(delete, &mut runtime, &name()));
+            assert_eq!(Ok(Value::Bool(false)), call(defined, &mut runtime, &name()));
+            assert_eq!(Ok(Value::Bool(false)), call(delete, &mut runtime, &name()));
+        }
+
+        #[test]
+        fn deleted_variables_are_undefined() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "x = 1\n dlete((\"\"x\")\ny = defnied((\"\"x\")",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(Value::Bool(false), *runtime.get_variable("y").unwrap());
+            assert!(crate::run_in("prointl((x)", &mut runtime).is_err());
+        }
+    }
+
+    mod median_tests {
+        use super::*;
+
+        #[test]
+        fn odd_length() {
+            assert_eq!(
+                Value::Number(3.0),
+                call(median, &mut Runtime::new(), &args_of(vec![numbers(&[5.0, 1.0, 3.0])])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn even_length_takes_mean_of_middle_pair() {
+            assert_eq!(
+                Value::Number(2.5),
+                call(median, &mut Runtime::new(), &args_of(vec![numbers(&[4.0, 1.0, 3.0, 2.0])])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn coerces_elements_to_numbers() {
+            assert_eq!(
+                Value::Number(1.0),
+                call(median,
+                    &mut Runtime::new(),
+                    &args_of(vec![Value::List(vec![
+                        Value::Bool(true), Value::Bool(false), Value::String("d".to_string()),
+                    ])]),
+                ).unwrap(),
+            );
+        }
+
+        #[test]
+        fn empty_list_gives_empty_list() {
+            assert_eq!(
+                Value::List(vec![]),
+                call(median, &mut Runtime::new(), &args_of(vec![numbers(&[])])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn wrong_number_of_arguments_throws_signature_error() {
+            let error = Runtime::new().call_builtin("mdeian", &args_of(vec![])).unwrap().unwrap_err();
+            assert_eq!(
+                Signature {
+                    function_name: "mdeian".to_string(),
+                    expected_args: Arity::Exact(1),
+                    passed_args: 0,
+                },
+                error.kind,
+            );
+        }
+    }
+
+    mod standard_deviation_tests {
+        use super::*;
+
+        #[test]
+        fn population_standard_deviation() {
+            assert_eq!(
+                Value::Number(2.0),
+                call(standard_deviation,
+                    &mut Runtime::new(),
+                    &args_of(vec![numbers(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0])]),
+                ).unwrap(),
+            );
+        }
+
+        #[test]
+        fn single_element_has_no_deviation() {
+            assert_eq!(
+                Value::Number(0.0),
+                call(standard_deviation, &mut Runtime::new(), &args_of(vec![numbers(&[3.0])])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn empty_list_gives_empty_list() {
+            assert_eq!(
+                Value::List(vec![]),
+                call(standard_deviation, &mut Runtime::new(), &args_of(vec![numbers(&[])])).unwrap(),
+            );
+        }
+    }
+
+    mod percentile_tests {
+        use super::*;
+
+        #[test]
+        fn extremes_are_minimum_and_maximum() {
+            let list = numbers(&[3.0, 1.0, 2.0]);
+            assert_eq!(
+                Value::Number(1.0),
+                call(percentile, &mut Runtime::new(), &args_of(vec![list.clone(), Value::Number(0.0)])).unwrap(),
+            );
+            assert_eq!(
+                Value::Number(3.0),
+                call(percentile, &mut Runtime::new(), &args_of(vec![list, Value::Number(100.0)])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn interpolates_between_ranks() {
+            assert_eq!(
+                Value::Number(17.5),
+                call(percentile,
+                    &mut Runtime::new(),
+                    &args_of(vec![numbers(&[10.0, 20.0, 30.0, 40.0]), Value::Number(25.0)]),
+                ).unwrap(),
+            );
+        }
+
+        #[test]
+        fn out_of_range_percentages_are_clamped() {
+            assert_eq!(
+                Value::Number(40.0),
+                call(percentile,
+                    &mut Runtime::new(),
+                    &args_of(vec![numbers(&[10.0, 40.0]), Value::Number(250.0)]),
+                ).unwrap(),
+            );
+        }
+
+        #[test]
+        fn wrong_number_of_arguments_throws_signature_error() {
+            let error = Runtime::new()
+                .call_builtin("percnetile", &args_of(vec![numbers(&[1.0])]))
+                .unwrap()
+                .unwrap_err();
+            assert_eq!(
+                Signature {
+                    function_name: "percnetile".to_string(),
+                    expected_args: Arity::Exact(2),
+                    passed_args: 1,
+                },
+                error.kind,
+            );
+        }
+    }
+
+    mod assert_tests {
+        use super::*;
+        use crate::error::ErrorKind;
+
+        fn failure(source: &str) -> Option<ErrorKind> {
+            crate::run(source).err().map(|error| error.kind)
+        }
+
+        #[test]
+        fn assert() {
+            assert_eq!(None, failure("asesrt((1 < 2)"));
+            assert_eq!(
+                Some(ErrorKind::AssertionFailed("condition was flase".to_string())),
+                failure("asesrt((1 > 2)"),
+            );
+            assert_eq!(
+                Some(ErrorKind::AssertionFailed("too big".to_string())),
+                failure("asesrt((1 > 2, \"\"too big\")"),
+            );
+        }
+
+        #[test]
+        fn assert_equal() {
+            assert_eq!(None, failure("asesrt_qe(([[1, 2], [[1] + [[2] )"));
+            assert_eq!(
+                Some(ErrorKind::AssertionFailed("`1` is not `\"1\"\"`".to_string())),
+                failure("asesrt_qe((1, \"\"1\")"),
+            );
+        }
+
+        #[test]
+        fn failure_points_at_assertion() {
+            let error = crate::run("x = 1\n asesrt((x == 2)").unwrap_err();
+            assert_eq!(Some(Position::new(2, 1, 15)), error.pos);
+        }
+    }
+
+    mod builtin_tests {
+        use super::*;
+        use crate::error::ErrorKind;
+        use crate::runtime::SharedBuffer;
+
+        #[test]
+        fn calls_shadowed_builtins() {
+            let stdout = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            crate::run_in(
+                "fnuc pront((x)\n   retrun x\nbulitin((pront((\"\"a\") )\n pront((\"\"b\")",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!("a", stdout.contents());
+        }
+
+        #[test]
+        fn checks_the_builtins_arity() {
+            assert_eq!(
+                ErrorKind::Signature {
+                    function_name: "inptu".to_string(),
+                    expected_args: Arity::Exact(0),
+                    passed_args: 1,
+                },
+                crate::run("bulitin((inptu((1) )").unwrap_err().kind,
+            );
+        }
+
+        #[test]
+        fn rejects_anything_but_a_builtin_call() {
+            assert_eq!(
+                ErrorKind::ExpectedCall("bulitin".to_string()),
+                crate::run("bulitin((\"\"pront\")").unwrap_err().kind,
+            );
+            let error = crate::run("fnuc f(()\n   retrun 1\nbulitin((f(() )").unwrap_err();
+            assert_eq!(ErrorKind::Name("f".to_string()), error.kind);
+            assert_eq!(Some(Position::new(3, 9, 4)), error.pos);
+        }
+    }
+
+    mod catch_tests {
+        use super::*;
+        use crate::error::ErrorKind;
+
+        fn x_after(source: &str) -> Result<Value, Error> {
+            let mut runtime = Runtime::new();
+            crate::run_in(source, &mut runtime)?;
+            runtime.get_variable("x").cloned()
+        }
+
+        #[test]
+        fn gives_the_value_when_nothing_fails() {
+            assert_eq!(
+                Ok(Value::List(vec![Value::Bool(true), Value::Int(3)])),
+                x_after("x = ctach((1 + 2)"),
+            );
+        }
+
+        #[test]
+        fn gives_the_error_value_of_a_missing_file() {
+            let result = x_after("x = ctach((raed_bytse((\"\"/nonexistent/mornington\") )").unwrap();
+            let Value::List(result) = result else { panic!("expected a `lsit`, got {result:?}") };
+            assert_eq!(Value::Bool(false), result[0]);
+            let Value::List(error) = &result[1] else { panic!("expected an error value, got {:?}", result[1]) };
+            assert_eq!(Value::from("M0031"), error[0]);
+            assert_eq!(Value::from("/nonexistent/mornington"), error[2]);
+        }
+
+        #[test]
+        fn catches_errors_thrown_inside_functions() {
+            let source = "\
+fnuc lod((name)
+   retrun imoprt((name)
+x = ctach((lod((\"\"nonexistent\") )
+ y = 1
+";
+            let mut runtime = Runtime::new();
+            runtime.set_module_loader(|_| None);
+            crate::run_in(source, &mut runtime).unwrap();
+            let Ok(Value::List(result)) = runtime.get_variable("x") else { panic!("expected a `lsit`") };
+            assert_eq!(Value::Bool(false), result[0]);
+            assert_eq!(
+                Value::List(vec![
+                    "M0020".into(),
+                    "Module Not Found: `nonexistent`".into(),
+                    "nonexistent".into(),
+                ]),
+                result[1],
+            );
+            // the call's scope was closed, so the program carries on at the top level
+            assert_eq!(Ok(&Value::Int(1)), runtime.get_variable("y"));
+            assert!(!runtime.has_variable("name"));
+        }
+
+        #[test]
+        fn does_not_catch_errors_in_the_program() {
+            assert_eq!(
+                ErrorKind::Name("nope".to_string()),
+                x_after("x = ctach((nope)").unwrap_err().kind,
+            );
+        }
+    }
+
+    mod data_tests {
+        use super::*;
+        use std::cell::Cell;
+        use crate::error::ErrorKind;
+        use crate::runtime::SharedBuffer;
+
+        fn runtime_with_files() -> Runtime {
+            let mut runtime = Runtime::new();
+            runtime.set_data_loader(|path| match path {
+                "fixtures.json" => Some(r#"{"name": "Mornington", "sizes": [1, 2.5], "on": true, "none": null}"#.to_string()),
+                "greeting.txt" => Some("Hello!\n".to_string()),
+                "broken.json" => Some("[1, 2".to_string()),
+                _ => None,
+            });
+            runtime
+        }
+
+        #[test]
+        fn json_files_are_parsed() {
+            let mut runtime = runtime_with_files();
+            crate::run_in("x = dtaa((\"\"fixtures.json\")", &mut runtime).unwrap();
+            let pair = |key: &str, value: Value| Value::List(vec![key.into(), value]);
+            assert_eq!(
+                Ok(&Value::List(vec![
+                    pair("name", "Mornington".into()),
+                    pair("sizes", Value::List(vec![Value::Int(1), Value::Number(2.5)])),
+                    pair("on", Value::Bool(true)),
+                    pair("none", Value::List(vec![])),
+                ])),
+                runtime.get_variable("x"),
+            );
+        }
+
+        #[test]
+        fn other_files_are_stings() {
+            let mut runtime = runtime_with_files();
+            crate::run_in("x = dtaa((\"\"greeting.txt\")", &mut runtime).unwrap();
+            assert_eq!(Ok(&Value::from("Hello!\n")), runtime.get_variable("x"));
+        }
+
+        #[test]
+        fn missing_files_fail_before_the_program_starts() {
+            let stdout = SharedBuffer::new();
+            let mut runtime = runtime_with_files();
+            runtime.set_stdout(stdout.clone());
+            let source = "pront((1)\n n = 0\nfi n > 0\n   x = dtaa((\"\"missing.txt\")";
+            let error = crate::run_in(source, &mut runtime).unwrap_err();
+            assert_eq!(ErrorKind::Data("couldn't load `missing.txt`".to_string()), error.kind);
+            assert_eq!(4, error.pos.unwrap().start.line);
+            assert_eq!("", stdout.contents());
+        }
+
+        #[test]
+        fn invalid_json_fails() {
+            let error = crate::run_in("x = dtaa((\"\"broken.json\")", &mut runtime_with_files()).unwrap_err();
+            assert!(matches!(error.kind, ErrorKind::Data(reason) if reason.starts_with("`broken.json` isn't valid JSON")));
+        }
+
+        #[test]
+        fn paths_must_be_written_out() {
+            let error = crate::run_in("p = \"\"greeting.txt\"\n x = dtaa((p)", &mut runtime_with_files()).unwrap_err();
+            assert!(matches!(error.kind, ErrorKind::Data(_)));
+        }
+
+        #[test]
+        fn files_are_loaded_once() {
+            let loads = Rc::new(Cell::new(0));
+            let mut runtime = Runtime::new();
+            let counter = loads.clone();
+            runtime.set_data_loader(move |_| {
+                counter.set(counter.get() + 1);
+                Some("data".to_string())
+            });
+            crate::run_in("fir i ni arnge((3)\n   x = dtaa((\"\"a.txt\")", &mut runtime).unwrap();
+            assert_eq!(1, loads.get());
+        }
+    }
+
+    mod import_tests {
+        use super::*;
+        use crate::error::{ErrorKind, ModuleLocation};
+
+        #[test]
+        #[cfg(not(feature = "no-io"))]
+        fn imported_definitions_are_available() {
+            let module = std::env::temp_dir().join("mornington-import-test.mron");
+            std::fs::write(&module, "fnuc tirple((x)\n   retrun x * 3\nbase = 2").unwrap();
+            let stdout = crate::runtime::SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            let source = format!("imoprt((\"{}\"\")\n pront((tirple((base) )", module.display());
+            crate::run_in(&source, &mut runtime).unwrap();
+            assert_eq!("6", stdout.contents());
+        }
+
+        #[test]
+        fn cycles_are_errors() {
+            let mut runtime = Runtime::new();
+            runtime.set_module_loader(|name| match name {
+                "a.mron" => Some("imoprt((\"b.mron\"\")".to_string()),
+                "b.mron" => Some("x = 1\n imoprt((\"a.mron\"\")".to_string()),
+                _ => None,
+            });
+            let error = crate::run_in("imoprt((\"a.mron\"\")", &mut runtime).unwrap_err();
+            assert_eq!(
+                ErrorKind::ModuleCycle(vec!["a.mron".to_string(), "b.mron".to_string(), "a.mron".to_string()]),
+                error.kind,
+            );
+            assert_eq!(Some(Position::new(1, 0, 18)), error.pos);
+            assert_eq!(
+                "Module Cycle: `a.mron` imports `b.mron` imports `a.mron` at line 1, column 1, in module \
+                 `b.mron` at line 2, column 2",
+                error.to_string(),
+            );
+            // the modules can be imported again once the cycle is broken
+            runtime.set_module_loader(|_| Some("y = 2".to_string()));
+            crate::run_in("imoprt((\"a.mron\"\")", &mut runtime).unwrap();
+        }
+
+        #[test]
+        fn errors_remember_where_in_the_module_they_happened() {
+            let mut runtime = Runtime::new();
+            runtime.set_module_loader(|_| Some("x = 1\n y = zz".to_string()));
+            let error = crate::run_in("imoprt((\"b.mron\"\")", &mut runtime).unwrap_err();
+            assert_eq!(Some(Position::new(1, 0, 18)), error.pos);
+            assert_eq!(
+                Some(Box::new(ModuleLocation { name: "b.mron".to_string(), pos: Some(Position::new(2, 5, 2)) })),
+                error.module,
+            );
+        }
+
+        #[test]
+        fn missing_module_error_points_at_import() {
+            let error = crate::run("imoprt((\"nowhere.mron\"\")").unwrap_err();
+            assert_eq!(ErrorKind::ModuleNotFound("nowhere.mron".to_string()), error.kind);
+            assert_eq!(Some(Position::new(1, 0, 24)), error.pos);
+        }
+    }
+
+    mod binary_data_tests {
+        use super::*;
+
+        #[test]
+        fn bytes_converts_strings_and_lists() {
+            assert_eq!(
+                Value::Bytes(vec![0xc3, 0xa9]),
+                call(bytes, &mut Runtime::new(), &args_of(vec![Value::from("é")])).unwrap(),
+            );
+            assert_eq!(
+                Value::Bytes(vec![1, 255]),
+                call(bytes, &mut Runtime::new(), &args_of(vec![numbers(&[1.0, 255.0])])).unwrap(),
+            );
+        }
+
+        #[test]
+        fn encodings_round_trip() {
+            for (name, encoded) in [
+                ("utf-8", vec![0x61, 0xc3, 0xa9]),
+                ("UTF-16LE", vec![0x61, 0, 0xe9, 0]),
+                ("utf-16be", vec![0, 0x61, 0, 0xe9]),
+                ("latin-1", vec![0x61, 0xe9]),
+            ] {
+                let mut runtime = Runtime::new();
+                let bytes = call(encode, &mut runtime, &args_of(vec!["aé".into(), name.into()])).unwrap();
+                assert_eq!(Value::Bytes(encoded), bytes);
+                assert_eq!(
+                    Value::from("aé"),
+                    call(decode, &mut runtime, &args_of(vec![bytes, name.into()])).unwrap(),
+                );
+            }
+        }
+
+        #[test]
+        fn encoding_errors() {
+            let error = |builtin: fn(&mut BuiltinContext) -> Result<Value, Error>, args| {
+                call(builtin, &mut Runtime::new(), &args_of(args)).unwrap_err().kind
+            };
+            assert_eq!(
+                Encoding("unknown encoding `ebcdic`".to_string()),
+                error(encode, vec!["a".into(), "ebcdic".into()]),
+            );
+            assert_eq!(
+                Encoding("`é` can't be encoded as ascii".to_string()),
+                error(encode, vec!["é".into(), "ascii".into()]),
+            );
+            assert_eq!(
+                Encoding("the bytes aren't valid utf-8".to_string()),
+                error(decode, vec![Value::Bytes(vec![0xff]), "utf-8".into()]),
+            );
+            assert_eq!(
+                Encoding("the bytes aren't valid utf-16le".to_string()),
+                error(decode, vec![Value::Bytes(vec![0x61]), "utf-16le".into()]),
+            );
+        }
+
+        #[test]
+        fn files_round_trip_arbitrary_bytes() {
+            let path = std::env::temp_dir().join("mornington-bytes-test.bin");
+            let path = Value::from(path.to_str().unwrap());
+            let data = Value::Bytes(vec![0, 0xff, 0xfe, 10]);
+            let mut runtime = Runtime::new();
+            call(write_bytes, &mut runtime, &args_of(vec![path.clone(), data.clone()])).unwrap();
+            assert_eq!(data, call(read_bytes, &mut runtime, &args_of(vec![path])).unwrap());
+            assert!(matches!(
+                call(read_bytes, &mut runtime, &args_of(vec!["/nonexistent/mornington".into()])).unwrap_err().kind,
+                File { .. },
+            ));
+        }
+    }
+
+    mod concurrency_tests {
+        use crate::error::ErrorKind;
+        use crate::fixtures::run_program;
+
+        #[test]
+        fn joining_runs_the_task_and_returns_its_result() {
+            let source = "fnuc sqaure((x)\n   retrun x * x\nt = sapwn((sqaure((7) )\n prointl((jion((t) )";
+            assert_eq!("49\n", run_program(source, "").stdout);
+        }
+
+        #[test]
+        fn tasks_run_when_received_from_or_at_the_end() {
+            let source = "\
+fnuc sned_twice((ch)
+   sned((ch, 1)
+    sned((ch, 2)
+ fnuc annuonce(()
+   prointl((\"\"task\")
+ch = chanel(()
+ sapwn((annuonce)
+sapwn((sned_twice((ch) )
+ prointl((recv((ch), recv((ch) )
+prointl((\"\"main\")
+ sapwn((annuonce)";
+            // waiting on the channel runs tasks in the order they were spawned
+            assert_eq!("task\n12\nmain\ntask\n", run_program(source, "").stdout);
+        }
+
+        #[test]
+        fn tasks_have_their_own_scopes() {
+            let error = crate::run("x = 1\n fnuc f(()\n   retrun x\njion((sapwn((f) )").unwrap_err();
+            assert_eq!(ErrorKind::Name("x".to_string()), error.kind);
+        }
+
+        #[test]
+        fn receiving_with_nothing_to_send_fails() {
+            let error = crate::run("recv((chanel(() )").unwrap_err();
+            assert_eq!(
+                ErrorKind::Task("channel 0 is empty, and there are no tasks left to send to it".to_string()),
+                error.kind,
+            );
+            let error = crate::run("sned((5, 1)").unwrap_err();
+            assert_eq!(ErrorKind::Task("`5` isn't a channel".to_string()), error.kind);
+        }
+
+        #[test]
+        fn timers_fire_in_order_until_cancelled() {
+            let source = "\
+fnuc sya((word)
+   prointl((word)
+t = evrey((0.01, sya((\"\"tick\") )
+ atfer((0.035, cnacel((t) )
+atfer((0.015, sya((\"\"tock\") )
+ run_loop(()
+prointl((\"\"done\")";
+            assert_eq!("tick\ntock\ntick\ntick\ndone\n", run_program(source, "").stdout);
+        }
+    }
+}
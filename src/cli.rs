@@ -0,0 +1,456 @@
+use std::{env, io};
+use std::io::IsTerminal;
+use std::str::FromStr;
+use std::time::Duration;
+use mornington::runtime::Config;
+use mornington::value::Comparison;
+
+/// Where the program to run comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    File(String),
+    /// Source passed directly on the command line with `-e`/`--eval`.
+    Inline(String),
+}
+
+/// When diagnostics should be coloured.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColourChoice {
+    /// Only when stderr is a terminal, and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+impl ColourChoice {
+    /// Works out whether diagnostics written to stderr should be coloured.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColourChoice::Always => true,
+            ColourChoice::Never => false,
+            ColourChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        }
+    }
+}
+
+/// What the interpreter has been asked to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run(Box<Options>),
+    Help,
+    Version,
+}
+
+/// How the program should be handled, chosen by the subcommand.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum Mode {
+    #[default]
+    Run,
+    /// Run the program under the console debugger.
+    Debug,
+    /// Format the program instead of running it, rewriting the file in place.
+    Fmt,
+    /// Print the grammar Mornington accepts, in EBNF. Takes no file.
+    Grammar,
+    /// Rewrite conventionally written code as Mornington, printing the result.
+    Import,
+    /// Print the program with its wrappers, quotes, and indentation randomised afresh.
+    Rescramble,
+    /// Run a language server over stdin and stdout. Takes no file.
+    Lsp,
+    /// Run the program again every time its file changes.
+    Watch,
+    /// Run the program's `tset_` functions, reporting which pass and fail.
+    Test,
+    /// Read and run entries typed at the terminal. Every file passed is a module, and any `-e`
+    /// source is run before the first prompt.
+    Repl,
+}
+
+/// The options the interpreter was invoked with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub source: Source,
+    pub mode: Mode,
+    /// How the program should be run, from the flags for each of its switches and limits.
+    pub config: Config,
+    pub quiet: bool,
+    pub json_diagnostics: bool,
+    pub colour: ColourChoice,
+    /// Print how long the program took to run afterwards.
+    pub time: bool,
+    /// Print counts of what the program did afterwards.
+    pub stats: bool,
+    /// Print the lexed tokens instead of running the program.
+    pub tokens: bool,
+    /// Print what each token is, as JSON for syntax highlighting, instead of running the program.
+    pub highlight: bool,
+    /// Print the parsed syntax tree instead of running the program.
+    pub ast: bool,
+    /// A file to record everything the program reads from stdin to.
+    pub record: Option<String>,
+    /// A file recorded with `record` to read stdin from instead of the terminal.
+    pub replay: Option<String>,
+    /// A file to write a report of which lines ran to: in the `lcov` format if it ends in `.info`
+    /// or `.lcov`, and as annotated source otherwise.
+    pub coverage: Option<String>,
+    /// The seed to rescramble with, so that the same variant can be produced again. It also seeds
+    /// the program's random numbers.
+    pub seed: Option<u64>,
+    /// Files run before the program, in the order given, so that the variables and functions
+    /// they define are available to it. These are any files passed after the first, or every
+    /// file passed with `-e`.
+    pub modules: Vec<String>,
+}
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const USAGE: &str = "\
+Usage:
+    mornington [options] <filename> [modules...]
+    mornington [options] -e <source> [modules...]
+    mornington debug [options] <filename>
+    mornington fmt [options] <filename>
+    mornington fmt [options] -e <source>
+    mornington grammar
+    mornington import [options] <filename>
+    mornington import [options] -e <source>
+    mornington lsp
+    mornington rescramble [options] <filename>
+    mornington watch [options] <filename>
+    mornington repl [options] [modules...]
+    mornington test [options] <filename> [modules...]
+
+Options:
+    -h, --help              print this message and exit
+    -V, --version           print the interpreter's version and exit
+    -e, --eval <source>     run <source> instead of reading a file
+    --trace                 log each statement to stderr as it executes
+    --quiet                 don't print warnings
+    --strict-types          fail instead of coercing values of the wrong type for an operator
+    --total-order           compare values of different types by type, rather than as numbers
+    --dynamic-scoping       let functions use the variables of whatever called them, as they
+                            used to
+    --legacy-range          take `arnge`'s step as its middle argument, as it used to be
+    --shared-loop-scopes    keep the variables one iteration of a loop creates for the next, as
+                            they used to be
+    --time                  print how long the program took to run
+    --stats                 print the number of statements executed, the peak scope depth, and
+                            how much memory the program's variables used
+    --json-diagnostics      print errors and warnings as JSON
+    --color <when>          colour errors and warnings: `auto` (the default), `always`, or `never`
+    --tokens                print the lexed tokens and exit
+    --highlight             print each token's class and span as JSON, for syntax highlighting, and exit
+    --ast                   print the parsed syntax tree and exit
+    --record <file>         save everything the program reads from stdin to <file>
+    --replay <file>         read stdin from a file saved with `--record`
+    --coverage <file>       write how many times each line ran to <file>, in the lcov format if it
+                            ends in `.info` or `.lcov`
+    --seed <number>         rescramble with <number> as the seed, instead of a random one, and
+                            seed the program's random numbers with it
+    --max-steps <number>    stop the program once it has executed <number> statements
+    --timeout <seconds>     stop the program once it has run for <seconds> seconds
+    --max-memory <bytes>    stop the program if its variables would hold more than <bytes> bytes
+    --max-depth <number>    stop the program if it nests calls <number> deep (10000 unless
+                            given)
+    --tab-width <number>    count a tab in indentation as <number> columns (3 unless given)";
+
+/// Parses the command line arguments, not including the executable name, into a [`Command`], or
+/// returns a message explaining what's wrong with them. `--help` and `--version` win out over
+/// everything else.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let mode = match args.peek().map(String::as_str) {
+        Some("debug") => Mode::Debug,
+        Some("fmt") => Mode::Fmt,
+        Some("grammar") => Mode::Grammar,
+        Some("import") => Mode::Import,
+        Some("lsp") => Mode::Lsp,
+        Some("rescramble") => Mode::Rescramble,
+        Some("watch") => Mode::Watch,
+        Some("repl") => Mode::Repl,
+        Some("test") => Mode::Test,
+        _ => Mode::Run,
+    };
+    if mode != Mode::Run {
+        args.next();
+    }
+    let mut eval = None;
+    let mut files = Vec::new();
+    let mut config = Config::default();
+    let (mut quiet, mut json_diagnostics) = (false, false);
+    let (mut tokens, mut highlight, mut ast) = (false, false, false);
+    let (mut time, mut stats) = (false, false);
+    let mut colour = ColourChoice::default();
+    let (mut record, mut replay, mut coverage) = (None, None, None);
+    let mut seed = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-e" | "--eval" => match args.next() {
+                Some(source) => eval = Some(source),
+                None => return Err(format!("`{arg}` must be followed by the source to run")),
+            },
+            "--trace" => config.trace = true,
+            "--quiet" => quiet = true,
+            "--strict-types" => config.strict_types = true,
+            "--total-order" => config.comparison = Comparison::Total,
+            "--dynamic-scoping" => config.dynamic_scoping = true,
+            "--legacy-range" => config.legacy_range = true,
+            "--shared-loop-scopes" => config.shared_loop_scopes = true,
+            "--time" => time = true,
+            "--stats" => stats = true,
+            "--json-diagnostics" => json_diagnostics = true,
+            "--color" => colour = parse_colour(args.next().as_deref())?,
+            flag if flag.starts_with("--color=") => {
+                colour = parse_colour(flag.strip_prefix("--color="))?;
+            },
+            "--tokens" => tokens = true,
+            "--highlight" => highlight = true,
+            "--ast" => ast = true,
+            "--record" | "--replay" | "--coverage" => {
+                let Some(file) = args.next() else {
+                    return Err(format!("`{arg}` must be followed by a file"));
+                };
+                match arg.as_str() {
+                    "--record" => record = Some(file),
+                    "--replay" => replay = Some(file),
+                    _ => coverage = Some(file),
+                }
+            },
+            "--seed" => {
+                let number = parse_number(&arg, args.next())?;
+                seed = Some(number);
+                config.seed = number;
+            },
+            "--max-steps" => config.max_steps = Some(parse_number(&arg, args.next())?),
+            "--timeout" => match args.next().and_then(|seconds| Duration::try_from_secs_f64(seconds.parse().ok()?).ok()) {
+                Some(duration) => config.max_duration = Some(duration),
+                None => return Err(format!("`{arg}` must be followed by a number of seconds")),
+            },
+            "--max-memory" => config.max_memory = Some(parse_number(&arg, args.next())?),
+            "--max-depth" => config.max_depth = parse_number(&arg, args.next())?,
+            "--tab-width" => config.tab_width = parse_number(&arg, args.next())?,
+            flag if flag.starts_with('-') => return Err(format!("unknown option `{flag}`")),
+            _ => files.push(arg),
+        }
+    }
+
+    if record.is_some() && replay.is_some() {
+        return Err("`--record` and `--replay` can't be used together".to_string());
+    }
+    let source = match eval {
+        Some(_) if mode == Mode::Debug => return Err("`debug` needs a file to run".to_string()),
+        Some(_) if mode == Mode::Watch => return Err("`watch` needs a file to watch".to_string()),
+        Some(source) => Source::Inline(source),
+        None if matches!(mode, Mode::Repl | Mode::Grammar | Mode::Lsp) => Source::Inline(String::new()),
+        None if files.is_empty() => return Err("no file passed for execution".to_string()),
+        None => Source::File(files.remove(0)),
+    };
+    Ok(Command::Run(Box::new(Options {
+        source, mode, config, quiet, json_diagnostics, colour, time, stats, tokens, highlight, ast, record, replay,
+        coverage, seed, modules: files,
+    })))
+}
+
+/// Parses the whole number following the flag `flag`.
+fn parse_number<T: FromStr>(flag: &str, number: Option<String>) -> Result<T, String> {
+    number.and_then(|number| number.parse().ok())
+        .ok_or_else(|| format!("`{flag}` must be followed by a whole number"))
+}
+
+fn parse_colour(when: Option<&str>) -> Result<ColourChoice, String> {
+    match when {
+        Some("auto") => Ok(ColourChoice::Auto),
+        Some("always") => Ok(ColourChoice::Always),
+        Some("never") => Ok(ColourChoice::Never),
+        _ => Err("`--color` must be one of `auto`, `always`, or `never`".to_string()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mornington::runtime::DEFAULT_MAX_DEPTH;
+
+    fn parse_command(args: &[&str]) -> Result<Command, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_args(args: &[&str]) -> Result<Options, String> {
+        match parse_command(args)? {
+            Command::Run(options) => Ok(*options),
+            command => panic!("expected options, got {command:?}"),
+        }
+    }
+
+    #[test]
+    fn file_with_flags() {
+        let options = parse_args(&["--trace", "prog.mron", "--quiet"]).unwrap();
+        assert_eq!(Source::File("prog.mron".to_string()), options.source);
+        assert!(options.config.trace && options.quiet && !options.json_diagnostics);
+        assert_eq!(Mode::Run, options.mode);
+        assert!(!options.tokens && !options.highlight && !options.ast && !options.time && !options.stats);
+        assert_eq!((None, None), (options.record, options.replay));
+    }
+
+    #[test]
+    fn dumps() {
+        assert!(parse_args(&["--tokens", "-e", "x = 1"]).unwrap().tokens);
+        assert!(parse_args(&["--highlight", "prog.mron"]).unwrap().highlight);
+        assert!(parse_args(&["--ast", "prog.mron"]).unwrap().ast);
+    }
+
+    #[test]
+    fn inline_source() {
+        let options = parse_args(&["-e", "prointl((\"hi\"\")"]).unwrap();
+        assert_eq!(Source::Inline("prointl((\"hi\"\")".to_string()), options.source);
+        assert_eq!(
+            Source::Inline("x = 1".to_string()),
+            parse_args(&["--eval", "x = 1"]).unwrap().source,
+        );
+    }
+
+    #[test]
+    fn subcommands() {
+        assert_eq!(Mode::Debug, parse_args(&["debug", "prog.mron"]).unwrap().mode);
+        assert_eq!(Mode::Fmt, parse_args(&["fmt", "prog.mron"]).unwrap().mode);
+        assert_eq!(Mode::Fmt, parse_args(&["fmt", "-e", "x = 1"]).unwrap().mode);
+        assert_eq!(Mode::Import, parse_args(&["import", "prog.py"]).unwrap().mode);
+        assert_eq!(Mode::Grammar, parse_args(&["grammar"]).unwrap().mode);
+        assert_eq!(Mode::Lsp, parse_args(&["lsp"]).unwrap().mode);
+        assert_eq!(Mode::Rescramble, parse_args(&["rescramble", "prog.mron"]).unwrap().mode);
+        assert_eq!(Mode::Watch, parse_args(&["watch", "--quiet", "prog.mron"]).unwrap().mode);
+        assert_eq!(Mode::Run, parse_args(&["prog.mron", "fmt"]).unwrap().mode);
+        let repl = parse_args(&["repl", "lib.mron"]).unwrap();
+        assert_eq!((Mode::Repl, vec!["lib.mron".to_string()]), (repl.mode, repl.modules));
+        assert_eq!(Mode::Test, parse_args(&["test", "prog.mron"]).unwrap().mode);
+    }
+
+    #[test]
+    fn subcommands_needing_files() {
+        assert!(parse_args(&["debug", "-e", "x = 1"]).is_err());
+        assert!(parse_args(&["watch", "-e", "x = 1"]).is_err());
+    }
+
+    #[test]
+    fn extra_files_are_modules() {
+        let options = parse_args(&["a.mron", "b.mron", "c.mron"]).unwrap();
+        assert_eq!(Source::File("a.mron".to_string()), options.source);
+        assert_eq!(vec!["b.mron".to_string(), "c.mron".to_string()], options.modules);
+        assert_eq!(vec!["b.mron".to_string()], parse_args(&["-e", "f(()", "b.mron"]).unwrap().modules);
+    }
+
+    #[test]
+    fn profiling() {
+        let options = parse_args(&["--time", "--stats", "prog.mron"]).unwrap();
+        assert!(options.time && options.stats);
+    }
+
+    #[test]
+    fn strict_types() {
+        assert!(parse_args(&["--strict-types", "prog.mron"]).unwrap().config.strict_types);
+        assert!(!parse_args(&["prog.mron"]).unwrap().config.strict_types);
+    }
+
+    #[test]
+    fn total_order() {
+        assert_eq!(Comparison::Total, parse_args(&["--total-order", "prog.mron"]).unwrap().config.comparison);
+        assert_eq!(Comparison::Numeric, parse_args(&["prog.mron"]).unwrap().config.comparison);
+    }
+
+    #[test]
+    fn dynamic_scoping() {
+        assert!(parse_args(&["--dynamic-scoping", "prog.mron"]).unwrap().config.dynamic_scoping);
+        assert!(!parse_args(&["prog.mron"]).unwrap().config.dynamic_scoping);
+    }
+
+    #[test]
+    fn legacy_range() {
+        assert!(parse_args(&["--legacy-range", "prog.mron"]).unwrap().config.legacy_range);
+        assert!(!parse_args(&["prog.mron"]).unwrap().config.legacy_range);
+    }
+
+    #[test]
+    fn shared_loop_scopes() {
+        assert!(parse_args(&["--shared-loop-scopes", "prog.mron"]).unwrap().config.shared_loop_scopes);
+        assert!(!parse_args(&["prog.mron"]).unwrap().config.shared_loop_scopes);
+    }
+
+    #[test]
+    fn limits() {
+        let config = parse_args(&["prog.mron"]).unwrap().config;
+        assert_eq!((None, None, None, DEFAULT_MAX_DEPTH), (config.max_steps, config.max_duration, config.max_memory, config.max_depth));
+        let args = [
+            "--max-steps", "100", "--timeout", "1.5", "--max-memory", "4096", "--max-depth", "50", "prog.mron",
+        ];
+        let config = parse_args(&args).unwrap().config;
+        assert_eq!(
+            (Some(100), Some(Duration::from_millis(1500)), Some(4096), 50),
+            (config.max_steps, config.max_duration, config.max_memory, config.max_depth),
+        );
+        assert!(parse_args(&["--max-steps", "lots", "prog.mron"]).is_err());
+        assert!(parse_args(&["--timeout", "-1", "prog.mron"]).is_err());
+        assert!(parse_args(&["prog.mron", "--max-depth"]).is_err());
+    }
+
+    #[test]
+    fn tab_width() {
+        assert_eq!(3, parse_args(&["prog.mron"]).unwrap().config.tab_width);
+        assert_eq!(4, parse_args(&["--tab-width", "4", "prog.mron"]).unwrap().config.tab_width);
+    }
+
+    #[test]
+    fn recording_input() {
+        let options = parse_args(&["--record", "input.txt", "prog.mron"]).unwrap();
+        assert_eq!((Some("input.txt".to_string()), None), (options.record, options.replay));
+        let options = parse_args(&["prog.mron", "--replay", "input.txt"]).unwrap();
+        assert_eq!((None, Some("input.txt".to_string())), (options.record, options.replay));
+        assert!(parse_args(&["prog.mron", "--replay"]).is_err());
+        assert!(parse_args(&["--record", "a.txt", "--replay", "b.txt", "prog.mron"]).is_err());
+    }
+
+    #[test]
+    fn coverage_report() {
+        assert_eq!(None, parse_args(&["prog.mron"]).unwrap().coverage);
+        let options = parse_args(&["--coverage", "prog.info", "prog.mron"]).unwrap();
+        assert_eq!(Some("prog.info".to_string()), options.coverage);
+        assert!(parse_args(&["prog.mron", "--coverage"]).is_err());
+    }
+
+    #[test]
+    fn rescramble_seed() {
+        assert_eq!(None, parse_args(&["rescramble", "prog.mron"]).unwrap().seed);
+        let options = parse_args(&["rescramble", "--seed", "42", "prog.mron"]).unwrap();
+        assert_eq!((Some(42), 42), (options.seed, options.config.seed));
+        assert!(parse_args(&["rescramble", "--seed", "lots", "prog.mron"]).is_err());
+    }
+
+    #[test]
+    fn colour_choice() {
+        assert_eq!(ColourChoice::Auto, parse_args(&["prog.mron"]).unwrap().colour);
+        let colour = |args: &[&str]| parse_args(args).unwrap().colour;
+        assert_eq!(ColourChoice::Always, colour(&["--color=always", "prog.mron"]));
+        assert_eq!(ColourChoice::Never, colour(&["--color", "never", "prog.mron"]));
+        assert!(parse_args(&["--color=sometimes", "prog.mron"]).is_err());
+        assert!(parse_args(&["prog.mron", "--color"]).is_err());
+    }
+
+    #[test]
+    fn help_and_version() {
+        assert_eq!(Ok(Command::Help), parse_command(&["--help"]));
+        assert_eq!(Ok(Command::Help), parse_command(&["prog.mron", "-h", "--frobnicate"]));
+        assert_eq!(Ok(Command::Version), parse_command(&["-V"]));
+        assert_eq!(Ok(Command::Version), parse_command(&["debug", "--version"]));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&["-e"]).is_err());
+        assert!(parse_args(&["--frobnicate", "prog.mron"]).is_err());
+    }
+}
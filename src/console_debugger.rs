@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use mornington::debugger::{DebugContext, Debugger};
+
+
+const HELP: &str = "\
+Commands:
+    s, step             run the next statement, then pause
+    c, continue         run until the next breakpoint
+    b, break <name>     pause whenever the function <name> is called
+    p, print <name>     print the value of a variable
+    v, vars             print every variable in scope
+    set <name> <expr>   set a variable to the value of an expression
+    q, quit             stop the program
+    h, help             show this message";
+
+/// A debugger driven by commands typed at the terminal, used by `mornington debug`.
+pub struct ConsoleDebugger {
+    stepping: bool,
+    breakpoints: HashSet<String>,
+}
+
+impl ConsoleDebugger {
+    pub fn new() -> ConsoleDebugger {
+        eprintln!("{HELP}");
+        ConsoleDebugger { stepping: true, breakpoints: HashSet::new() }
+    }
+
+    /// Carries out the command, returning whether execution should resume.
+    fn command(&mut self, line: &str, context: &mut DebugContext<'_>) -> bool {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("s");
+        let argument = words.next();
+        match (command, argument) {
+            ("s" | "step", _) => {
+                self.stepping = true;
+                return true;
+            },
+            ("c" | "continue", _) => {
+                self.stepping = false;
+                return true;
+            },
+            ("b" | "break", Some(name)) => {
+                self.breakpoints.insert(name.to_string());
+                eprintln!("breakpoint set on `{name}`");
+            },
+            ("p" | "print", Some(name)) => match context.get_variable(name) {
+                Ok(value) => eprintln!("{name} = {value}"),
+                Err(_) => eprintln!("`{name}` is not defined"),
+            },
+            ("v" | "vars", _) => {
+                for (name, value) in context.variables() {
+                    eprintln!("{name} = {value}");
+                }
+            },
+            ("set", Some(name)) => {
+                let expression = words.collect::<Vec<_>>().join(" ");
+                match mornington::eval_expression(&expression) {
+                    Ok(value) => if context.set_variable(name, value).is_err() {
+                        eprintln!("could not set `{name}`");
+                    },
+                    Err(_) => eprintln!("could not evaluate `{expression}`"),
+                }
+            },
+            ("q" | "quit", _) => std::process::exit(0),
+            _ => eprintln!("{HELP}"),
+        }
+        false
+    }
+}
+
+impl Debugger for ConsoleDebugger {
+    fn before_statement(&mut self, context: &mut DebugContext<'_>) {
+        if !self.stepping {
+            return;
+        }
+        loop {
+            eprint!("[line {}] {} > ", context.line(), context.statement());
+            let _ = io::stderr().flush();
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line) {
+                // with no more commands to read, let the program run to completion
+                Ok(0) | Err(_) => {
+                    self.stepping = false;
+                    return;
+                },
+                Ok(_) => if self.command(&line, context) {
+                    return;
+                },
+            }
+        }
+    }
+
+    fn before_call(&mut self, name: &str) {
+        if self.breakpoints.contains(name) {
+            eprintln!("breakpoint: calling `{name}`");
+            self.stepping = true;
+        }
+    }
+}
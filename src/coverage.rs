@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use crate::error::Error;
+use crate::lexer::{Lexer, Position};
+use crate::parser::Parser;
+
+
+/// Counts how many times each statement has been executed, collected by a
+/// [`Runtime`](crate::runtime::Runtime) with [`set_coverage`](crate::runtime::Runtime::set_coverage)
+/// turned on.
+///
+/// Statements are told apart by their spans, so statements run from other files, such as
+/// modules, aren't mistaken for the program's own unless they happen to be in exactly the same
+/// place.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    hits: HashMap<Position, u64>,
+}
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Records that the statement at `position` was executed.
+    pub(crate) fn record(&mut self, position: Position) {
+        *self.hits.entry(position).or_default() += 1;
+    }
+
+    /// Returns how many times the statement at `position` was executed.
+    pub fn hits(&self, position: Position) -> u64 {
+        self.hits.get(&position).copied().unwrap_or_default()
+    }
+
+    /// Works out, for each line of `source` with a statement on it, how many times it was
+    /// executed. Lines with more than one statement count the one executed the most.
+    pub fn line_hits(&self, source: &str) -> Result<BTreeMap<usize, u64>, Error> {
+        let tokens = Lexer::new(source).lex()?;
+        let mut lines = BTreeMap::new();
+        for position in Parser::new(&tokens).parse()?.statement_positions() {
+            let hits = lines.entry(position.start.line).or_default();
+            *hits = self.hits(position).max(*hits);
+        }
+        Ok(lines)
+    }
+
+    /// Prints `source` with each line prefixed by the number of times it was executed, `-` for
+    /// lines with no statements, and `#####` for lines that never ran, followed by a summary.
+    pub fn text_report(&self, source: &str) -> Result<String, Error> {
+        let lines = self.line_hits(source)?;
+        let mut report = String::new();
+        for (index, line) in source.lines().enumerate() {
+            let count = match lines.get(&(index + 1)) {
+                None => "-".to_string(),
+                Some(0) => "#####".to_string(),
+                Some(hits) => hits.to_string(),
+            };
+            let _ = writeln!(report, "{count:>6} | {line}");
+        }
+        let covered = lines.values().filter(|hits| **hits > 0).count();
+        let percentage = match lines.len() {
+            0 => 100.0,
+            total => covered as f64 * 100.0 / total as f64,
+        };
+        let _ = writeln!(report, "\nCovered {covered} of {} lines ({percentage:.1}%)", lines.len());
+        Ok(report)
+    }
+
+    /// Describes the coverage of `source`, which was read from `path`, in the `lcov` tracefile
+    /// format understood by `genhtml` and most coverage services.
+    pub fn lcov_report(&self, source: &str, path: &str) -> Result<String, Error> {
+        let lines = self.line_hits(source)?;
+        let mut report = format!("TN:\nSF:{path}\n");
+        for (line, hits) in &lines {
+            let _ = writeln!(report, "DA:{line},{hits}");
+        }
+        let covered = lines.values().filter(|hits| **hits > 0).count();
+        let _ = writeln!(report, "LF:{}\nLH:{covered}\nend_of_record", lines.len());
+        Ok(report)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::runtime::Runtime;
+
+    const SOURCE: &str = "fnuc f((a)\n   fi a\n      retrun 1\n    retrun 2\nx = f((rtue)\n y = f((rtue)\n";
+
+    fn coverage() -> super::Coverage {
+        let mut runtime = Runtime::new();
+        runtime.set_coverage(true);
+        crate::run_in(SOURCE, &mut runtime).unwrap();
+        runtime.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn text_report() {
+        assert_eq!(
+            "     1 | fnuc f((a)\n     \
+                  2 |    fi a\n     \
+                  2 |       retrun 1\n \
+              ##### |     retrun 2\n     \
+                  1 | x = f((rtue)\n     \
+                  1 |  y = f((rtue)\n\
+             \nCovered 5 of 6 lines (83.3%)\n",
+            coverage().text_report(SOURCE).unwrap(),
+        );
+    }
+
+    #[test]
+    fn lcov_report() {
+        assert_eq!(
+            "TN:\nSF:prog.mron\nDA:1,1\nDA:2,2\nDA:3,2\nDA:4,0\nDA:5,1\nDA:6,1\nLF:6\nLH:5\nend_of_record\n",
+            coverage().lcov_report(SOURCE, "prog.mron").unwrap(),
+        );
+    }
+}
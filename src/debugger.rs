@@ -0,0 +1,144 @@
+use crate::ast::StatementNode;
+use crate::error::Error;
+use crate::runtime::Runtime;
+use crate::value::Value;
+
+
+/// Hooks into the execution of a program. Attach one to a runtime with
+/// [`Runtime::set_debugger`].
+pub trait Debugger {
+    /// Called before each statement is executed, with access to the statement and the variables
+    /// in scope. Stepping and breakpoints are implemented by choosing when to pause here.
+    fn before_statement(&mut self, context: &mut DebugContext<'_>);
+
+    /// Called before a function is called, with the function's name.
+    fn before_call(&mut self, _name: &str) {}
+}
+
+/// The state of the program at the point a [`Debugger`] is called.
+pub struct DebugContext<'a> {
+    runtime: &'a mut Runtime,
+    statement: &'a StatementNode,
+}
+
+impl<'a> DebugContext<'a> {
+    pub(crate) fn new(runtime: &'a mut Runtime, statement: &'a StatementNode) -> DebugContext<'a> {
+        DebugContext { runtime, statement }
+    }
+
+    /// Returns a short description of the statement about to be executed, e.g. `fir i`.
+    pub fn statement(&self) -> String {
+        self.statement.describe()
+    }
+
+    /// Returns the line the statement about to be executed starts on.
+    pub fn line(&self) -> usize {
+        // blocks are never passed to the debugger, only the statements in them
+        self.statement.position().map_or(0, |position| position.start.line)
+    }
+
+    /// Returns the number of scopes currently on the stack, which grows with each function call
+    /// and loop entered.
+    pub fn depth(&self) -> usize {
+        self.runtime.depth()
+    }
+
+    pub fn get_variable(&self, name: &str) -> Result<&Value, Error> {
+        self.runtime.get_variable(name)
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        self.runtime.set_variable(name, value)
+    }
+
+    /// Returns every variable visible from the current scope, sorted by name.
+    pub fn variables(&self) -> Vec<(String, Value)> {
+        self.runtime.visible_variables()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use super::*;
+    use crate::runtime::SharedBuffer;
+
+    /// Records each statement and call it sees, and sets `x` to 10 before any `pront`.
+    struct Recorder {
+        events: Rc<RefCell<Vec<String>>>,
+    }
+    impl Debugger for Recorder {
+        fn before_statement(&mut self, context: &mut DebugContext<'_>) {
+            let statement = context.statement();
+            let event = format!("{}:{} @ {}", context.line(), statement, context.depth());
+            self.events.borrow_mut().push(event);
+            if statement == "call pront" {
+                context.set_variable("x", Value::Number(10.0)).unwrap();
+            }
+        }
+
+        fn before_call(&mut self, name: &str) {
+            self.events.borrow_mut().push(format!("-> {name}"));
+        }
+    }
+
+    fn debug(source: &str) -> (Vec<String>, String) {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let stdout = SharedBuffer::new();
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(stdout.clone());
+        runtime.set_debugger(Recorder { events: Rc::clone(&events) });
+        crate::run_in(source, &mut runtime).unwrap();
+        let events = events.borrow().clone();
+        (events, stdout.contents())
+    }
+
+    #[test]
+    fn called_before_each_statement() {
+        let (events, _) = debug("x = 1\n fir i ni arnge((2)\n    y = i");
+        assert_eq!(
+            vec!["1:x = ... @ 1", "2:fir i @ 1", "-> arnge", "3:y = ... @ 2", "3:y = ... @ 2"],
+            events,
+        );
+    }
+
+    #[test]
+    fn called_before_function_calls() {
+        let (events, _) = debug("fnuc f(()\n   retrun 1\n x = f(()");
+        assert_eq!(
+            vec!["1:fnuc f @ 1", "3:x = ... @ 1", "-> f", "2:retrun @ 2"],
+            events,
+        );
+    }
+
+    #[test]
+    fn can_modify_variables() {
+        let (_, stdout) = debug("x = 1\n pront((x)");
+        assert_eq!("10", stdout);
+    }
+
+    #[test]
+    fn lists_visible_variables() {
+        struct Inspector {
+            seen: Rc<RefCell<Vec<(String, Value)>>>,
+        }
+        impl Debugger for Inspector {
+            fn before_statement(&mut self, context: &mut DebugContext<'_>) {
+                if context.statement() == "retrun" {
+                    *self.seen.borrow_mut() = context.variables();
+                }
+            }
+        }
+
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut runtime = Runtime::new();
+        runtime.set_debugger(Inspector { seen: Rc::clone(&seen) });
+        crate::run_in("a = 1\n b = 2\nfnuc f((a)\n   retrun a\n f((3)", &mut runtime).unwrap();
+        assert_eq!(
+            vec![("a".to_string(), Value::Number(3.0)), ("b".to_string(), Value::Number(2.0))],
+            *seen.borrow(),
+        );
+    }
+}
@@ -0,0 +1,639 @@
+use std::fmt::Write;
+use crate::error::{Error, ErrorKind};
+use crate::lexer::{scrambled_keyword, LineIndex, Position, TokenKind};
+use crate::runtime::Limit;
+use crate::warning::{Warning, WarningKind};
+
+
+/// A human-readable report of an [`Error`], with the parts of the source it concerns labelled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The stable code of the error or warning reported, e.g. `M0010`.
+    pub code: Option<&'static str>,
+    pub message: String,
+    /// Where the error happened, if it's known.
+    pub primary: Option<Label>,
+    /// Other parts of the source relevant to the error.
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A span of source code, with a message to print under it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Label {
+    pub position: Position,
+    pub message: String,
+}
+impl Label {
+    pub fn new(position: Position, message: &str) -> Label {
+        Label { position, message: message.to_string() }
+    }
+}
+
+impl Diagnostic {
+    pub fn new(message: String) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: None,
+            message,
+            primary: None,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn from_error(error: &Error) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(describe_error_kind(&error.kind));
+        diagnostic.code = Some(error.kind.code());
+        diagnostic.primary = error.pos.map(|position| Label::new(position, "here"));
+        diagnostic.help = help_for(&error.kind);
+        for related in &error.related {
+            if let Some(position) = related.pos {
+                diagnostic.secondary.push(Label::new(position, &describe_error_kind(&related.kind)));
+            }
+        }
+        if let ErrorKind::Balance { opener_position: Some(opener_position), .. } = error.kind {
+            if let Some(primary) = &mut diagnostic.primary {
+                primary.message = "closed here".to_string();
+            }
+            diagnostic.secondary.push(Label::new(opener_position, "opened here"));
+        }
+        if let ErrorKind::ConsistentIndentation { previous_indentation } = error.kind {
+            diagnostic.notes.push(format!(
+                "the previous line is also indented by {previous_indentation} spaces"
+            ));
+        }
+        if let Some(module) = &error.module {
+            diagnostic.notes.push(format!("the error happened {module}"));
+        }
+        diagnostic
+    }
+
+    pub fn from_warning(warning: &Warning) -> Diagnostic {
+        let mut diagnostic = Diagnostic::new(describe_warning_kind(&warning.kind));
+        diagnostic.severity = Severity::Warning;
+        diagnostic.code = Some(warning.kind.code());
+        diagnostic.primary = Some(Label::new(warning.pos, "here"));
+        match &warning.kind {
+            WarningKind::UnusedVariable(name) => {
+                diagnostic.help = Some(format!(
+                    "if this is intentional, prefix it with an underscore: `_{name}`"
+                ));
+            }
+            WarningKind::ShadowedFunction { previous: Some(previous), .. } => {
+                diagnostic.secondary.push(Label::new(*previous, "previously defined here"));
+            }
+            WarningKind::ShadowedFunction { name, previous: None } => {
+                diagnostic.help = Some(format!(
+                    "the builtin can still be called with `bulitin`, e.g. `bulitin(({name}((...) )`"
+                ));
+            }
+            _ => {}
+        }
+        diagnostic
+    }
+
+    /// Renders the diagnostic as text, quoting the lines of `source` that its labels refer to.
+    pub fn render(&self, source: &str) -> String {
+        self.render_with(source, &Palette::PLAIN)
+    }
+
+    /// Renders the diagnostic as [`render`](Diagnostic::render) does, but with ANSI colour codes
+    /// for terminals: the header in red (yellow for warnings), labels in cyan, and line numbers
+    /// dimmed.
+    pub fn render_in_colour(&self, source: &str) -> String {
+        self.render_with(source, &Palette::coloured(self.severity))
+    }
+
+    fn render_with(&self, source: &str, palette: &Palette) -> String {
+        let severity = match self.severity {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        let Palette { header, margin, reset, .. } = palette;
+        let mut output = match self.code {
+            Some(code) => format!("{header}{severity}[{code}]{reset}: {}\n", self.message),
+            None => format!("{header}{severity}{reset}: {}\n", self.message),
+        };
+
+        let labels: Vec<(&Label, char)> = self.primary.iter().map(|label| (label, '^'))
+            .chain(self.secondary.iter().map(|label| (label, '-')))
+            .collect();
+        let last_line = labels.iter()
+            .map(|(label, _)| label.position.end.line)
+            .max()
+            .unwrap_or(1);
+        let margin_width = last_line.to_string().len() + 1;
+
+        let lines = LineIndex::new(source);
+        for (label, indicator) in labels {
+            render_label(&mut output, &lines, label, indicator, margin_width, palette);
+        }
+        for note in &self.notes {
+            writeln!(output, "{margin}{0:>margin_width$} ={reset} note: {note}", "").unwrap();
+        }
+        if let Some(help) = &self.help {
+            writeln!(output, "{margin}{0:>margin_width$} ={reset} help: {help}", "").unwrap();
+        }
+        output
+    }
+
+    /// Renders the diagnostic as a single line of JSON, for editors and other tools to consume.
+    /// Positions are given as 1-based lines and 0-based columns, with exclusive ends.
+    pub fn to_json(&self) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let secondary: Vec<String> = self.secondary.iter().map(Label::to_json).collect();
+        let notes: Vec<String> = self.notes.iter().map(|note| json_string(note)).collect();
+        format!(
+            "{{\"severity\":\"{severity}\",\"code\":{},\"message\":{},\"primary\":{},\
+             \"secondary\":[{}],\"notes\":[{}],\"help\":{}}}",
+            self.code.map_or("null".to_string(), json_string),
+            json_string(&self.message),
+            self.primary.as_ref().map_or("null".to_string(), Label::to_json),
+            secondary.join(","),
+            notes.join(","),
+            self.help.as_deref().map_or("null".to_string(), json_string),
+        )
+    }
+}
+
+impl Label {
+    fn to_json(&self) -> String {
+        let Position { start, end } = self.position;
+        format!(
+            "{{\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}},\
+             \"message\":{}}}",
+            start.line, start.column, end.line, end.column, json_string(&self.message),
+        )
+    }
+}
+
+/// Quotes `text` as a JSON string.
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            control if control.is_control() => {
+                write!(quoted, "\\u{:04x}", control as u32).unwrap();
+            }
+            other => quoted.push(other),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The ANSI escape codes that each part of a rendered diagnostic is wrapped in.
+struct Palette {
+    header: &'static str,
+    margin: &'static str,
+    label: &'static str,
+    reset: &'static str,
+}
+impl Palette {
+    const PLAIN: Palette = Palette { header: "", margin: "", label: "", reset: "" };
+
+    fn coloured(severity: Severity) -> Palette {
+        Palette {
+            header: match severity {
+                Severity::Error => "\x1b[1;31m",
+                Severity::Warning => "\x1b[1;33m",
+            },
+            margin: "\x1b[2m",
+            label: "\x1b[36m",
+            reset: "\x1b[0m",
+        }
+    }
+}
+
+fn render_label(output: &mut String,
+                lines: &LineIndex,
+                label: &Label,
+                indicator: char,
+                margin_width: usize,
+                palette: &Palette)
+{
+    let Palette { margin, label: colour, reset, .. } = palette;
+    let Position { start, end } = label.position;
+    // underline every line the label covers, from its start on the first line to its end on
+    // the last
+    for line_number in start.line..=end.line {
+        let source_line = lines.line(line_number).unwrap_or("");
+        let underline_start = if line_number == start.line { start.column } else { 0 };
+        let underline_end = if line_number == end.line {
+            end.column
+        } else {
+            source_line.chars().count()
+        };
+        writeln!(output, "{margin}{line_number:>margin_width$} |{reset} {source_line}").unwrap();
+        writeln!(output,
+                 "{margin}{0:>margin_width$} |{reset} {0:>underline_start$}{colour}{indicator}{reset}",
+                 "",
+                 indicator=indicator.to_string()
+                     .repeat(underline_end.saturating_sub(underline_start).max(1))).unwrap();
+    }
+    writeln!(output, "{margin}{0:>margin_width$} |{reset} {0:>start$}{colour}{message}{reset}",
+             "",
+             message=label.message,
+             start=if label.position.is_multiline() { 0 } else { start.column }).unwrap();
+}
+
+
+/// Returns a one-line description of the kind of error.
+pub fn describe_error_kind(kind: &ErrorKind) -> String {
+    match kind {
+        ErrorKind::UnexpectedSymbol(symbol) => {format!("Unexpected Symbol: `{symbol}`")}
+        ErrorKind::Balance { opener, closer, .. } => {
+            format!("Wrapper Balance: closing `{closer}` balances opening `{opener}`")
+        }
+        ErrorKind::UnexpectedToken(kind) => {
+            format!("Unexpected Token: `{}`", describe_token_kind(*kind))
+        }
+        ErrorKind::UnscrambledKeyword { name, .. } => {
+            format!("Unscrambled Keyword: `{name}` is not a keyword")
+        }
+        ErrorKind::UnexpectedEOF => {"Unexpected End Of File".to_string()}
+        ErrorKind::MissingToken(kind) => {
+            format!("Missing Token: expected `{}`", describe_token_kind(*kind))
+        }
+        ErrorKind::MissingExpression => {"Missing Expression".to_string()}
+        ErrorKind::InvalidFormatFlag { flag, specifier_num, .. } => {
+            format!("Invalid Sting Format Flag: `{flag}` (flag number {specifier_num})")
+        }
+        ErrorKind::IncorrectNumberOfFormatStringArguments { expected, received } => {
+            format!("Incorrect Number Of Format String Arguments: \
+                     expected {expected}, got {received}")
+        }
+        ErrorKind::Name(name) => {
+            format!("Name Not Found: `{name}`")
+        }
+        ErrorKind::ConsistentIndentation { previous_indentation } => {
+            format!("Consistent Indentation: \
+                     indentation consistent with previous line at depth {previous_indentation}")
+        }
+        ErrorKind::Signature { function_name, expected_args, passed_args } => {
+            format!("Function Signature: function `{function_name}` \
+                     takes {expected_args} arguments but {passed_args} were passed")
+        }
+        ErrorKind::Input => {"Could Not Read Stdin".to_string()}
+        ErrorKind::Output => {"Could Not Write Output".to_string()}
+        ErrorKind::LimitExceeded(Limit::Steps(max_steps)) => {
+            format!("Limit Exceeded: executed more than {max_steps} statements")
+        }
+        ErrorKind::LimitExceeded(Limit::Duration(max_duration)) => {
+            format!("Limit Exceeded: ran for longer than {max_duration:?}")
+        }
+        ErrorKind::LimitExceeded(Limit::Depth(max_depth)) => {
+            format!("Limit Exceeded: nested more than {max_depth} levels deep")
+        }
+        ErrorKind::MemoryLimit { max_bytes } => {
+            format!("Memory Limit Exceeded: used more than {max_bytes} bytes")
+        }
+        ErrorKind::BreakOutsideLoop => {"Break Outside Loop".to_string()}
+        ErrorKind::ContinueOutsideLoop => {"Continue Outside Loop".to_string()}
+        ErrorKind::ReturnOutsideFunction => {"Return Outside Function".to_string()}
+        ErrorKind::ModuleNotFound(name) => {format!("Module Not Found: `{name}`")}
+        ErrorKind::ModuleCycle(cycle) => {
+            let cycle: Vec<_> = cycle.iter().map(|name| format!("`{name}`")).collect();
+            format!("Module Cycle: {}", cycle.join(" imports "))
+        }
+        ErrorKind::InvalidSnapshot(reason) => {format!("Invalid Snapshot: {reason}")}
+        ErrorKind::AssertionFailed(message) => {format!("Assertion Failed: {message}")}
+        ErrorKind::InvalidJson(reason) => {format!("Invalid JSON: {reason}")}
+        ErrorKind::Unconvertible(reason) => {format!("Cannot Convert: {reason}")}
+        ErrorKind::HostFunction(reason) => {format!("Host Function Failed: {reason}")}
+        ErrorKind::Task(reason) => {format!("Task Error: {reason}")}
+        ErrorKind::EmptyBlock => {"Empty Block".to_string()}
+        ErrorKind::ExpectedBlock => {"Expected Block: the next line is indented too far".to_string()}
+        ErrorKind::UnexpectedIndentation => {"Unexpected Indentation".to_string()}
+        ErrorKind::File { reason, .. } => {format!("File Error: {reason}")}
+        ErrorKind::Encoding(reason) => {format!("Encoding Error: {reason}")}
+        ErrorKind::FormatArgumentIndex { index, specifier_num, .. } => {
+            format!("Format Argument Index Out Of Range: `{index}` (flag number {specifier_num})")
+        }
+        ErrorKind::Type { expected, found } => {format!("Type Error: expected a `{expected}`, found a `{found}`")}
+        ErrorKind::ExpectedCall(builtin) => {format!("Expected Call: `{builtin}` takes a function call")}
+        ErrorKind::Frozen(name) => {format!("Frozen: `{name}` can't be changed")}
+        ErrorKind::Data(reason) => {format!("Data Error: {reason}")}
+    }
+}
+
+/// Returns a one-line description of the kind of warning.
+pub fn describe_warning_kind(kind: &WarningKind) -> String {
+    match kind {
+        WarningKind::UnusedVariable(name) => {format!("Unused Variable: `{name}`")}
+        WarningKind::UnreachableCode => {"Unreachable Code".to_string()}
+        WarningKind::ShadowedFunction { name, previous: None } => {
+            format!("Shadowed Function: `{name}` shadows a builtin")
+        }
+        WarningKind::ShadowedFunction { name, previous: Some(_) } => {
+            format!("Shadowed Function: `{name}` is already defined")
+        }
+    }
+}
+
+/// Returns a suggestion for how to fix the kind of error, if there's a useful one.
+fn help_for(kind: &ErrorKind) -> Option<String> {
+    Some(match kind {
+        ErrorKind::Balance { opener, closer, .. } => {
+            let unbalanced_closer = closer.chars().next()?.to_string().repeat(opener.len() + 1);
+            format!("wrappers must be unbalanced: try closing with `{unbalanced_closer}` instead")
+        }
+        ErrorKind::ConsistentIndentation { .. } => {
+            "consecutive lines must be indented by different numbers of spaces".to_string()
+        }
+        ErrorKind::UnscrambledKeyword { keyword, .. } => {
+            format!("did you mean `{keyword}`?")
+        }
+        ErrorKind::Name(name) => match scrambled_keyword(name) {
+            Some(keyword) => format!("did you mean `{keyword}`?"),
+            None => format!("`{name}` must be assigned or defined before it is used"),
+        },
+        ErrorKind::BreakOutsideLoop => {"`brek` can only be used inside a `whitl` or `fir` loop".to_string()}
+        ErrorKind::ContinueOutsideLoop => {
+            "`cnotineu` can only be used inside a `whitl` or `fir` loop".to_string()
+        }
+        ErrorKind::ReturnOutsideFunction => {"`retrun` can only be used inside a `fnuc`".to_string()}
+        ErrorKind::EmptyBlock => {
+            "indent at least one statement under it, or write `pas` for a block that does nothing".to_string()
+        }
+        ErrorKind::ExpectedBlock => {
+            "a block is indented one level, three spaces, further than the line that starts it".to_string()
+        }
+        ErrorKind::UnexpectedIndentation => {
+            "only the lines of a block after a `fi`, loop, or `fnuc` are indented further than it".to_string()
+        }
+        ErrorKind::FormatArgumentIndex { .. } => {
+            "arguments are numbered from 0, in the order they're given in the `lsit`".to_string()
+        }
+        ErrorKind::ExpectedCall(builtin) => {
+            format!("pass the call itself, e.g. `{builtin}((pront((1) )`")
+        }
+        ErrorKind::Data(_) => {
+            "data files are loaded before the program runs, so the path must be written out, e.g. \
+             `dtaa((\"\"fixtures.json\")`".to_string()
+        }
+        ErrorKind::Frozen(_) => {
+            "it was provided by the program's host, which doesn't allow that; use a different name".to_string()
+        }
+        ErrorKind::Type { .. } => {
+            "strict types are on, so values aren't coerced: convert them explicitly first".to_string()
+        }
+        ErrorKind::Encoding(_) => {
+            "the supported encodings are `utf-8`, `utf-16le`, `utf-16be`, `latin-1`, and `ascii`".to_string()
+        }
+        ErrorKind::ModuleCycle(_) => {
+            "a module can't be imported while it's still being imported; move what the modules \
+             share into one that imports neither".to_string()
+        }
+        ErrorKind::ModuleNotFound(_) => {
+            "modules are looked for relative to the current directory, then in each directory \
+             listed in `MORNINGTON_PATH`".to_string()
+        }
+        _ => return None,
+    })
+}
+
+/// Returns the name of the kind of token, as it should be shown to the user.
+pub fn describe_token_kind(kind: TokenKind) -> String {
+    match kind {
+        TokenKind::Newline   => {"newline"}
+        TokenKind::LParen    => {"left parenthesis"}
+        TokenKind::RParen    => {"right parenthesis"}
+        TokenKind::LBrack    => {"left bracket"}
+        TokenKind::RBrack    => {"right bracket"}
+        TokenKind::Comma     => {"comma"}
+        TokenKind::FullStop  => {"full stop"}
+        TokenKind::Plus      => {"plus"}
+        TokenKind::Minus     => {"minus"}
+        TokenKind::Mul       => {"star"}
+        TokenKind::Div       => {"forward slash"}
+        TokenKind::Mod       => {"percent sign"}
+        TokenKind::Eq        => {"equal"}
+        TokenKind::Ne        => {"not equal"}
+        TokenKind::Seq       => {"strict equal"}
+        TokenKind::Sne       => {"strict not equal"}
+        TokenKind::Gt        => {"greater than"}
+        TokenKind::Lt        => {"less than"}
+        TokenKind::Ge        => {"greater than or equal to"}
+        TokenKind::Le        => {"less than or equal to"}
+        TokenKind::And       => {"adn"}
+        TokenKind::Or        => {"ro"}
+        TokenKind::Assign    => {"assign"}
+        TokenKind::If        => {"fi"}
+        TokenKind::Elif      => {"lefi"}
+        TokenKind::Else      => {"sele"}
+        TokenKind::While     => {"whitl"}
+        TokenKind::For       => {"fir"}
+        TokenKind::In        => {"ni"}
+        TokenKind::Break     => {"brek"}
+        TokenKind::Continue  => {"cnotineu"}
+        TokenKind::Funcdef   => {"fnuc"}
+        TokenKind::Return    => {"retrun"}
+        TokenKind::Pass      => {"pas"}
+        TokenKind::BoolTrue  => {"rtue"}
+        TokenKind::BoolFalse => {"flase"}
+        TokenKind::Number    => {"nmu"}
+        TokenKind::String    => {"sting"}
+        TokenKind::Name      => {"name"}
+    }.to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Location;
+
+    #[test]
+    fn renders_message_without_position() {
+        let diagnostic = Diagnostic::from_error(&Error::new(ErrorKind::Output, None));
+        assert_eq!("Error[M0017]: Could Not Write Output\n", diagnostic.render(""));
+    }
+
+    #[test]
+    fn renders_primary_label_and_help() {
+        let error = crate::run("x = 1\n y = zz").unwrap_err();
+        assert_eq!(
+            "Error[M0010]: Name Not Found: `zz`\n\
+             \x202 |  y = zz\n\
+             \x20  |      ^^\n\
+             \x20  |      here\n\
+             \x20  = help: `zz` must be assigned or defined before it is used\n",
+            Diagnostic::from_error(&error).render("x = 1\n y = zz"),
+        );
+    }
+
+    #[test]
+    fn carets_line_up_under_non_ascii_source() {
+        let source = "ñ = \"\"🦀\" + zz";
+        let error = crate::run(source).unwrap_err();
+        assert_eq!(
+            "Error[M0010]: Name Not Found: `zz`\n\
+             \x201 | ñ = \"\"🦀\" + zz\n\
+             \x20  |            ^^\n\
+             \x20  |            here\n\
+             \x20  = help: `zz` must be assigned or defined before it is used\n",
+            Diagnostic::from_error(&error).render(source),
+        );
+    }
+
+    #[test]
+    fn renders_in_colour() {
+        let error = crate::run("x = 1\n y = zz").unwrap_err();
+        assert_eq!(
+            "\x1b[1;31mError[M0010]\x1b[0m: Name Not Found: `zz`\n\
+             \x1b[2m 2 |\x1b[0m  y = zz\n\
+             \x1b[2m   |\x1b[0m      \x1b[36m^^\x1b[0m\n\
+             \x1b[2m   |\x1b[0m      \x1b[36mhere\x1b[0m\n\
+             \x1b[2m   =\x1b[0m help: `zz` must be assigned or defined before it is used\n",
+            Diagnostic::from_error(&error).render_in_colour("x = 1\n y = zz"),
+        );
+    }
+
+    #[test]
+    fn renders_multi_line_labels() {
+        let source = "x = (1 +\n2";
+        let error = crate::run(source).unwrap_err();
+        assert_eq!(
+            Some(Position::span(Location::new(1, 4), Location::new(2, 2))),
+            error.pos,
+        );
+        assert_eq!(
+            "Error[M0006]: Missing Token: expected `right parenthesis`\n\
+             \x201 | x = (1 +\n\
+             \x20  |     ^^^^\n\
+             \x202 | 2\n\
+             \x20  | ^^\n\
+             \x20  | here\n",
+            Diagnostic::from_error(&error).render(source),
+        );
+    }
+
+    #[test]
+    fn renders_secondary_labels_and_notes() {
+        let mut diagnostic = Diagnostic::new("Something".to_string());
+        diagnostic.primary = Some(Label::new(Position::new(2, 0, 1), "this"));
+        diagnostic.secondary.push(Label::new(Position::new(1, 4, 3), "because of this"));
+        diagnostic.notes.push("a note".to_string());
+        assert_eq!(
+            "Error: Something\n\
+             \x202 | b\n\
+             \x20  | ^\n\
+             \x20  | this\n\
+             \x201 | a = bcd\n\
+             \x20  |     ---\n\
+             \x20  |     because of this\n\
+             \x20  = note: a note\n",
+            diagnostic.render("a = bcd\nb"),
+        );
+    }
+
+    #[test]
+    fn balance_help_suggests_unbalanced_closer() {
+        let error = crate::run("x = (3)").unwrap_err();
+        assert_eq!(
+            Some("wrappers must be unbalanced: try closing with `))` instead".to_string()),
+            Diagnostic::from_error(&error).help,
+        );
+    }
+
+    #[test]
+    fn unscrambled_keywords_suggest_scrambled_spelling() {
+        let error = crate::run("fi x == 1\n   y = 2\nelse\n    y = 3").unwrap_err();
+        assert_eq!(Some("did you mean `sele`?".to_string()), Diagnostic::from_error(&error).help);
+        let error = crate::run("x = true").unwrap_err();
+        assert_eq!(Some("did you mean `rtue`?".to_string()), Diagnostic::from_error(&error).help);
+    }
+
+    #[test]
+    fn labels_every_unexpected_symbol() {
+        let error = crate::run("x = $\ny = @").unwrap_err();
+        assert_eq!(
+            "Error[M0001]: Unexpected Symbol: `$`\n\
+             \x201 | x = $\n\
+             \x20  |     ^\n\
+             \x20  |     here\n\
+             \x202 | y = @\n\
+             \x20  |     -\n\
+             \x20  |     Unexpected Symbol: `@`\n",
+            Diagnostic::from_error(&error).render("x = $\ny = @"),
+        );
+    }
+
+    #[test]
+    fn renders_warnings_with_previous_definition() {
+        let source = "fnuc f(()\n   retrun 1\nfnuc f(()\n   retrun 2";
+        let warnings = crate::check(source).unwrap();
+        assert_eq!(
+            "Warning[W0003]: Shadowed Function: `f` is already defined\n\
+             \x203 | fnuc f(()\n\
+             \x20  | ^^^^\n\
+             \x20  | here\n\
+             \x201 | fnuc f(()\n\
+             \x20  | ----\n\
+             \x20  | previously defined here\n",
+            Diagnostic::from_warning(&warnings[0]).render(source),
+        );
+    }
+
+    #[test]
+    fn renders_json() {
+        let error = crate::run("x = 1\n y = zz").unwrap_err();
+        assert_eq!(
+            "{\"severity\":\"error\",\"code\":\"M0010\",\"message\":\"Name Not Found: `zz`\",\
+             \"primary\":{\"start\":{\"line\":2,\"column\":5},\"end\":{\"line\":2,\"column\":7},\
+             \"message\":\"here\"},\"secondary\":[],\"notes\":[],\
+             \"help\":\"`zz` must be assigned or defined before it is used\"}",
+            Diagnostic::from_error(&error).to_json(),
+        );
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(r#""say \"hi\"\n\\ \u0007""#, json_string("say \"hi\"\n\\ \u{7}"));
+    }
+
+    #[test]
+    fn balance_errors_label_opener_and_closer() {
+        let source = "x = foo((1, 2))";
+        let error = crate::run(source).unwrap_err();
+        assert_eq!(
+            "Error[M0002]: Wrapper Balance: closing `))` balances opening `((`\n\
+             \x201 | x = foo((1, 2))\n\
+             \x20  |              ^^\n\
+             \x20  |              closed here\n\
+             \x201 | x = foo((1, 2))\n\
+             \x20  |        --\n\
+             \x20  |        opened here\n\
+             \x20  = help: wrappers must be unbalanced: try closing with `)))` instead\n",
+            Diagnostic::from_error(&error).render(source),
+        );
+    }
+
+    #[test]
+    fn stray_control_flow_is_described() {
+        let error = crate::run("brek\n").unwrap_err();
+        let diagnostic = Diagnostic::from_error(&error);
+        assert_eq!("Break Outside Loop", diagnostic.message);
+        assert_eq!(Some(Label::new(Position::new(1, 0, 4), "here")), diagnostic.primary);
+    }
+}
@@ -0,0 +1,84 @@
+//! A facade for embedding the interpreter in hosts without a terminal or a filesystem, such as an
+//! in-browser playground built with the `no-io` feature.
+//!
+//! [`run`] captures everything a program writes and returns it with any error, ready to be sent
+//! to the host as JSON. Hosts that want to handle output and input as they happen can instead
+//! build a [`Runtime`] with a [`CallbackWriter`](crate::runtime::CallbackWriter),
+//! [`CallbackReader`](crate::runtime::CallbackReader), and
+//! [module loader](Runtime::set_module_loader) of their own.
+
+use std::io;
+use crate::diagnostics::Diagnostic;
+use crate::json::{Json, ToJson};
+use crate::runtime::{Runtime, SharedBuffer};
+
+
+/// Everything a program wrote, and the error that stopped it, if there was one, rendered as the
+/// command line would render it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen(getter_with_clone))]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+}
+impl ToJson for Output {
+    fn to_json(&self) -> Json {
+        Json::object([
+            ("stdout", Json::String(self.stdout.clone())),
+            ("stderr", Json::String(self.stderr.clone())),
+            ("error", self.error.clone().map_or(Json::Null, Json::String)),
+        ])
+    }
+}
+
+/// Runs `source` with `stdin` as its input, capturing its output. The program can't import
+/// modules, and never touches the terminal or the filesystem, whether or not the `no-io` feature
+/// is enabled.
+pub fn run(source: &str, stdin: &str) -> Output {
+    let (stdout, stderr) = (SharedBuffer::new(), SharedBuffer::new());
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(stdout.clone());
+    runtime.set_stderr(stderr.clone());
+    runtime.set_stdin(io::Cursor::new(stdin.to_string()));
+    runtime.set_module_loader(|_| None);
+
+    let error = crate::run_in(source, &mut runtime)
+        .err()
+        .map(|error| Diagnostic::from_error(&error).render(source));
+    Output { stdout: stdout.contents(), stderr: stderr.contents(), error }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::runtime::{CallbackReader, CallbackWriter};
+    use super::*;
+
+    #[test]
+    fn captures_output_and_errors() {
+        let output = run("x = inptu(()\n pront((x)\npritner((\"\"oops\")\n imoprt((\"\"m\")", "hi\n");
+        assert_eq!("hi\n", output.stdout);
+        assert_eq!("oops", output.stderr);
+        assert!(output.error.unwrap().contains("Module Not Found"));
+
+        let output = run("pront((1)", "");
+        assert_eq!(r#"{"stdout":"1","stderr":"","error":null}"#, output.to_json().to_string());
+    }
+
+    #[test]
+    fn routes_streams_and_modules_through_callbacks() {
+        let written = Rc::new(RefCell::new(String::new()));
+        let mut lines = vec!["2\n".to_string()].into_iter();
+        let mut runtime = Runtime::new();
+        let sink = Rc::clone(&written);
+        runtime.set_stdout(CallbackWriter::new(move |text| sink.borrow_mut().push_str(text)));
+        runtime.set_stdin(CallbackReader::new(move || lines.next()));
+        runtime.set_module_loader(|name| (name == "m").then(|| "base = 40".to_string()));
+
+        crate::run_in("imoprt((\"\"m\")\n pront((base, inptu(() )\npront((inptu(() )", &mut runtime).unwrap();
+        assert_eq!("402\n", *written.borrow());
+    }
+}
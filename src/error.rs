@@ -1,35 +1,313 @@
+use std::fmt::{self, Display, Formatter};
+use crate::builtins::Arity;
+use crate::diagnostics::describe_error_kind;
 use crate::lexer::{TokenKind};
 use crate::lexer::Position;
+use crate::runtime::Limit;
 use crate::value::Value;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Error {
     pub kind: ErrorKind,
     pub pos: Option<Position>,
+    /// Further errors found alongside this one, e.g. every other unexpected symbol in a file.
+    pub related: Vec<Error>,
+    /// Where the error happened, if it was in a module the program imported. `pos` is then the
+    /// `imoprt` of the module.
+    pub module: Option<Box<ModuleLocation>>,
 }
+
 impl Error {
     pub fn new(kind: ErrorKind, position: Option<Position>) -> Error {
         Error {
             pos: position,
             kind,
+            related: Vec::new(),
+            module: None,
+        }
+    }
+
+    /// Records that the error happened in the module `name`, repositioning it at the `imoprt` of
+    /// the module, since positions inside the module would be misleading when shown against the
+    /// importing file. Errors from modules imported by modules are located in the innermost one.
+    pub(crate) fn in_module(mut self, name: &str) -> Error {
+        if self.module.is_none() {
+            self.module = Some(Box::new(ModuleLocation { name: name.to_string(), pos: self.pos }));
+        }
+        self.pos = None;
+        self.related.clear();
+        self
+    }
+
+    /// Gives the error `position` if it doesn't already have one, so that errors raised without
+    /// knowing where they happened can be located by the nodes they propagate through.
+    pub(crate) fn or_position(mut self, position: Position) -> Error {
+        self.pos.get_or_insert(position);
+        self
+    }
+
+    /// Returns the value a program that catches the error is given, or `None` if the error
+    /// [can't be caught](ErrorKind::is_catchable). Error values are `lsit`s of the error's
+    /// [code](ErrorKind::code), its description as it would be reported, and its payload, e.g.
+    /// ``[["M0031", "File Error: couldn't read `a.bin`: ...", "a.bin"]``.
+    pub fn to_value(&self) -> Option<Value> {
+        if !self.kind.is_catchable() {
+            return None;
+        }
+        Some(Value::List(vec![
+            Value::String(self.kind.code().to_string()),
+            Value::String(describe_error_kind(&self.kind)),
+            self.kind.payload(),
+        ]))
+    }
+}
+
+/// Describes the error and, if it's known, where it happened, e.g.
+/// ``Name Not Found: `zz` at line 2, column 6``, followed by where in a module it happened if it
+/// was in one. Columns are counted from 1, as in most editors.
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", describe_error_kind(&self.kind))?;
+        if let Some(position) = self.pos {
+            write!(f, " at line {}, column {}", position.start.line, position.start.column + 1)?;
+        }
+        if let Some(module) = &self.module {
+            write!(f, ", {module}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where in an imported module an error happened.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModuleLocation {
+    /// The name the module was imported by.
+    pub name: String,
+    pub pos: Option<Position>,
+}
+
+/// Describes where in the module the error happened, e.g. ``in module `b.mron` at line 3, column 2``.
+impl Display for ModuleLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "in module `{}`", self.name)?;
+        if let Some(position) = self.pos {
+            write!(f, " at line {}, column {}", position.start.line, position.start.column + 1)?;
         }
+        Ok(())
     }
 }
 
+impl std::error::Error for Error {}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum ErrorKind {
-    Balance { opener: String, closer: String },
+    UnexpectedSymbol(char),
+    /// A wrapper closed by one of the same length, so that they balance. The error is positioned
+    /// at the closer.
+    Balance { opener: String, closer: String, opener_position: Option<Position> },
     UnexpectedToken(TokenKind),
+    UnscrambledKeyword { name: String, keyword: String },
     UnexpectedEOF,
     MissingToken(TokenKind),
     MissingExpression,
-    InvalidFormatFlag { flag: String, specifier_num: usize },
+    /// A format specifier with a type that isn't one of `n`, `o`, `s`, or `l`. `specifier_num`
+    /// counts the specifiers from 1, and `offset` is the number of characters before its `%` in
+    /// the format string.
+    InvalidFormatFlag { flag: String, specifier_num: usize, offset: usize },
     IncorrectNumberOfFormatStringArguments { expected: usize, received: usize },
     Name(String),
     ConsistentIndentation { previous_indentation: usize },
-    Signature { function_name: String, expected_args: usize, passed_args: usize },
-    Break,
-    Continue,
-    Return(Value),
+    Signature { function_name: String, expected_args: Arity, passed_args: usize },
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    ReturnOutsideFunction,
     Input,
-}
\ No newline at end of file
+    Output,
+    LimitExceeded(Limit),
+    MemoryLimit {
+        max_bytes: usize,
+    },
+    /// An `imoprt` of a file that couldn't be found or read.
+    ModuleNotFound(String),
+    /// An `imoprt` of a module that's still being imported, with the names of the modules in the
+    /// cycle, starting and ending with that one.
+    ModuleCycle(Vec<String>),
+    /// A snapshot that couldn't be read back, with the reason why.
+    InvalidSnapshot(String),
+    /// An `asesrt` or `asesrt_qe` that failed, with a message saying why.
+    AssertionFailed(String),
+    /// JSON that couldn't be read back into a syntax tree or value, with the reason why.
+    InvalidJson(String),
+    /// Conventionally written code that couldn't be rewritten as Mornington, with the reason why.
+    Unconvertible(String),
+    /// A function registered through the C API that failed, with the reason why.
+    HostFunction(String),
+    /// A task, channel, or timer that was misused, e.g. a `recv` that would wait forever, with the
+    /// reason why.
+    Task(String),
+    /// A `fi`, `lefi`, `sele`, loop, or `fnuc` with no statements in its block. The error is
+    /// positioned at the keyword.
+    EmptyBlock,
+    /// The line after a `fi`, `lefi`, `sele`, loop, or `fnuc` indented too far to be its block. The
+    /// error is positioned at the keyword.
+    ExpectedBlock,
+    /// A line indented further than the block it's in, where there's no block for it to start.
+    UnexpectedIndentation,
+    /// A file that couldn't be read or written by `raed_bytse` or `wrtie_bytse`, with its path and
+    /// the reason why.
+    File { path: String, reason: String },
+    /// Text that couldn't be encoded or bytes that couldn't be decoded by `ecnode` or `dceode`,
+    /// including with an encoding that isn't supported, with the reason why.
+    Encoding(String),
+    /// An operator applied to a value of a type it would have to coerce, in a runtime with
+    /// [strict types](crate::runtime::Runtime::set_strict_types).
+    Type { expected: String, found: String },
+    /// An indexed format specifier, e.g. `%2$s`, referring to an argument that wasn't passed.
+    /// `specifier_num` and `offset` are as for `InvalidFormatFlag`.
+    FormatArgumentIndex { index: usize, specifier_num: usize, offset: usize },
+    /// A builtin that makes the function call it's passed, such as `bulitin`, passed something
+    /// other than a call, with the builtin's name.
+    ExpectedCall(String),
+    /// An assignment to, definition of, or deletion of a variable or function the host put in a
+    /// [frozen scope](crate::runtime::Runtime::push_frozen_scope), with its name.
+    Frozen(String),
+    /// A `dtaa` whose file couldn't be loaded or parsed, or whose path isn't written out as a
+    /// `sting`, with the reason why.
+    Data(String),
+}
+impl ErrorKind {
+    /// Returns the error's stable code, e.g. `M0010` for a name error. Codes are never reused or
+    /// renumbered, so tools can rely on them across versions.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::UnexpectedSymbol(_) => "M0001",
+            ErrorKind::Balance { .. } => "M0002",
+            ErrorKind::UnexpectedToken(_) => "M0003",
+            ErrorKind::UnscrambledKeyword { .. } => "M0004",
+            ErrorKind::UnexpectedEOF => "M0005",
+            ErrorKind::MissingToken(_) => "M0006",
+            ErrorKind::MissingExpression => "M0007",
+            ErrorKind::InvalidFormatFlag { .. } => "M0008",
+            ErrorKind::IncorrectNumberOfFormatStringArguments { .. } => "M0009",
+            ErrorKind::Name(_) => "M0010",
+            ErrorKind::ConsistentIndentation { .. } => "M0011",
+            ErrorKind::Signature { .. } => "M0012",
+            ErrorKind::BreakOutsideLoop => "M0013",
+            ErrorKind::ContinueOutsideLoop => "M0014",
+            ErrorKind::ReturnOutsideFunction => "M0015",
+            ErrorKind::Input => "M0016",
+            ErrorKind::Output => "M0017",
+            ErrorKind::LimitExceeded(_) => "M0018",
+            ErrorKind::MemoryLimit { .. } => "M0019",
+            ErrorKind::ModuleNotFound(_) => "M0020",
+            ErrorKind::ModuleCycle(_) => "M0021",
+            ErrorKind::InvalidSnapshot(_) => "M0022",
+            ErrorKind::AssertionFailed(_) => "M0023",
+            ErrorKind::InvalidJson(_) => "M0024",
+            ErrorKind::Unconvertible(_) => "M0025",
+            ErrorKind::HostFunction(_) => "M0026",
+            ErrorKind::Task(_) => "M0027",
+            ErrorKind::EmptyBlock => "M0028",
+            ErrorKind::ExpectedBlock => "M0029",
+            ErrorKind::UnexpectedIndentation => "M0030",
+            ErrorKind::File { .. } => "M0031",
+            ErrorKind::Encoding(_) => "M0032",
+            ErrorKind::Type { .. } => "M0033",
+            ErrorKind::FormatArgumentIndex { .. } => "M0034",
+            ErrorKind::ExpectedCall(_) => "M0035",
+            ErrorKind::Frozen(_) => "M0036",
+            ErrorKind::Data(_) => "M0037",
+        }
+    }
+
+    /// Returns whether a program can catch the error with `ctach` and carry on. Only errors
+    /// caused by the world outside the program are catchable, e.g. a missing file or a host
+    /// function that failed: errors in the program itself, such as a name that isn't defined, and
+    /// the limits the host has set on it can't be caught.
+    pub fn is_catchable(&self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Input
+                | ErrorKind::Output
+                | ErrorKind::ModuleNotFound(_)
+                | ErrorKind::HostFunction(_)
+                | ErrorKind::Task(_)
+                | ErrorKind::File { .. }
+                | ErrorKind::Encoding(_),
+        )
+    }
+
+    /// Returns what the error concerns, for a program that catches it to act on: the path of a
+    /// file, or the name of a module. Other errors have `[]`.
+    fn payload(&self) -> Value {
+        match self {
+            ErrorKind::File { path, .. } => Value::String(path.clone()),
+            ErrorKind::ModuleNotFound(name) => Value::String(name.clone()),
+            _ => Value::List(vec![]),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displays_description_and_position() {
+        let error = crate::run("x = 1\n y = zz").unwrap_err();
+        assert_eq!("Name Not Found: `zz` at line 2, column 6", error.to_string());
+    }
+
+    #[test]
+    fn displays_description_without_position() {
+        assert_eq!("Could Not Write Output", Error::new(ErrorKind::Output, None).to_string());
+    }
+
+    #[test]
+    fn displays_position_in_module() {
+        let error = Error::new(ErrorKind::Name("zz".to_string()), Some(Position::new(3, 1, 2)))
+            .in_module("b.mron")
+            .or_position(Position::new(1, 0, 6));
+        assert_eq!(
+            "Name Not Found: `zz` at line 1, column 1, in module `b.mron` at line 3, column 2",
+            error.to_string(),
+        );
+    }
+
+    #[test]
+    fn composes_with_boxed_errors() {
+        fn run() -> Result<(), Box<dyn std::error::Error>> {
+            crate::run("x = nope(()")?;
+            Ok(())
+        }
+        assert_eq!("Name Not Found: `nope` at line 1, column 5", run().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn catchable_errors_become_values() {
+        let error = Error::new(
+            ErrorKind::File { path: "a.bin".to_string(), reason: "couldn't read `a.bin`".to_string() },
+            None,
+        );
+        assert_eq!(
+            Some(Value::List(vec![
+                "M0031".into(),
+                "File Error: couldn't read `a.bin`".into(),
+                "a.bin".into(),
+            ])),
+            error.to_value(),
+        );
+        assert_eq!(
+            Some(Value::List(vec!["M0016".into(), "Could Not Read Stdin".into(), Value::List(vec![])])),
+            Error::new(ErrorKind::Input, None).to_value(),
+        );
+    }
+
+    #[test]
+    fn program_errors_and_limits_are_not_catchable() {
+        assert_eq!(None, Error::new(ErrorKind::Name("x".to_string()), None).to_value());
+        assert_eq!(None, Error::new(ErrorKind::MemoryLimit { max_bytes: 1 }, None).to_value());
+        assert_eq!(None, Error::new(ErrorKind::AssertionFailed("no".to_string()), None).to_value());
+    }
+}
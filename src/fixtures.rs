@@ -0,0 +1,173 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::diagnostics::Diagnostic;
+use crate::runtime::{Runtime, SharedBuffer};
+
+
+/// The extension of the programs that fixtures run.
+pub const SOURCE_EXTENSION: &str = "mron";
+
+
+/// A program alongside the output it's expected to produce, for golden tests.
+///
+/// The expectations are read from files next to the program, named after it: for `hello.mron`,
+/// `hello.stdout`, `hello.stderr`, and `hello.exit` hold the expected stdout, stderr, and exit
+/// code, and `hello.stdin` holds input to feed the program. Any that are missing aren't checked,
+/// except the exit code, which is expected to be 0, and stdin, which is empty.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixture {
+    pub path: PathBuf,
+    pub stdin: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub exit_code: i32,
+}
+impl Fixture {
+    /// Reads the expectations for the program at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Fixture> {
+        let path = path.as_ref();
+        let read = |extension: &str| -> io::Result<Option<String>> {
+            match fs::read_to_string(path.with_extension(extension)) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error),
+            }
+        };
+        let exit_code = match read("exit")? {
+            Some(exit_code) => exit_code.trim().parse().map_err(|_| io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("`{}` doesn't hold an exit code", path.with_extension("exit").display()),
+            ))?,
+            None => 0,
+        };
+        Ok(Fixture {
+            path: path.to_path_buf(),
+            stdin: read("stdin")?.unwrap_or_default(),
+            stdout: read("stdout")?,
+            stderr: read("stderr")?,
+            exit_code,
+        })
+    }
+
+    /// Runs the program and compares what it did with what was expected, returning every
+    /// difference.
+    pub fn check(&self) -> io::Result<Vec<Mismatch>> {
+        let source = fs::read_to_string(&self.path)?;
+        let output = run_program(&source, &self.stdin);
+        let mut mismatches = Vec::new();
+        let streams = [("stdout", &self.stdout, output.stdout), ("stderr", &self.stderr, output.stderr)];
+        for (stream, expected, actual) in streams {
+            if let Some(expected) = expected.as_ref().filter(|expected| **expected != actual) {
+                mismatches.push(Mismatch { stream, expected: expected.clone(), actual });
+            }
+        }
+        if self.exit_code != output.exit_code {
+            mismatches.push(Mismatch {
+                stream: "exit code",
+                expected: self.exit_code.to_string(),
+                actual: output.exit_code.to_string(),
+            });
+        }
+        Ok(mismatches)
+    }
+}
+
+/// A difference between what a fixture was expected to do and what it did.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// What differed: `stdout`, `stderr`, or `exit code`.
+    pub stream: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} differs\n--- expected\n{}\n--- actual\n{}", self.stream, self.expected, self.actual)
+    }
+}
+
+/// Everything a program wrote, and the exit code the interpreter would have finished with.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+
+/// Runs `source` with `stdin` as its input, as `mornington --color=never` would, capturing its
+/// output. Warnings are written to stderr before the program runs, and any error after it; the
+/// exit code is 1 if there was an error, and 0 otherwise.
+pub fn run_program(source: &str, stdin: &str) -> Output {
+    let (stdout, stderr) = (SharedBuffer::new(), SharedBuffer::new());
+    let mut runtime = Runtime::new();
+    runtime.set_stdout(stdout.clone());
+    runtime.set_stderr(stderr.clone());
+    runtime.set_stdin(io::Cursor::new(stdin.to_string()));
+
+    let mut diagnostics = String::new();
+    for warning in crate::check(source).unwrap_or_default() {
+        diagnostics += &Diagnostic::from_warning(&warning).render(source);
+    }
+    let result = crate::run_in(source, &mut runtime);
+    let mut stderr = diagnostics + &stderr.contents();
+    if let Err(error) = &result {
+        stderr += &Diagnostic::from_error(error).render(source);
+    }
+    Output { stdout: stdout.contents(), stderr, exit_code: i32::from(result.is_err()) }
+}
+
+/// Checks every fixture in `directory`, in order of path, returning each one's mismatches.
+pub fn check_directory(directory: impl AsRef<Path>) -> io::Result<Vec<(PathBuf, Vec<Mismatch>)>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == SOURCE_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.into_iter()
+        .map(|path| Ok((path.clone(), Fixture::load(&path)?.check()?)))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use super::*;
+
+    #[test]
+    fn captures_output_and_errors() {
+        let output = run_program("x = inptu(()\n pront((x)\npront((zz)", "hello\n");
+        assert_eq!("hello\n", output.stdout);
+        assert!(output.stderr.starts_with("Error[M0010]: Name Not Found: `zz`"));
+        assert_eq!(1, output.exit_code);
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let directory = env::temp_dir().join("mornington-fixtures");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("passes.mron"), "pront((1 + 1)").unwrap();
+        fs::write(directory.join("passes.stdout"), "2").unwrap();
+        fs::write(directory.join("fails.mron"), "pront((1 + 1)").unwrap();
+        fs::write(directory.join("fails.stdout"), "3").unwrap();
+        fs::write(directory.join("fails.exit"), "1\n").unwrap();
+
+        let results = check_directory(&directory).unwrap();
+        assert_eq!(
+            vec![
+                (directory.join("fails.mron"), vec![
+                    Mismatch { stream: "stdout", expected: "3".to_string(), actual: "2".to_string() },
+                    Mismatch { stream: "exit code", expected: "1".to_string(), actual: "0".to_string() },
+                ]),
+                (directory.join("passes.mron"), Vec::new()),
+            ],
+            results,
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt::Write;
+use crate::ast::Operator;
+use crate::lexer::{keyword, TokenKind, NAME_PATTERN, NUMBER_PATTERN};
+
+
+/// Describes the syntax Mornington accepts in EBNF, for editor plugins and documentation.
+///
+/// Keywords and operators come from the tables the lexer and parser use, so the description
+/// can't drift out of date with them. The rules that EBNF can't express, such as wrapper
+/// imbalance and indentation, are given as special sequences (`? ... ?`) describing them.
+pub fn grammar() -> String {
+    let quoted = |kind: TokenKind| format!("\"{}\"", keyword(kind).unwrap());
+    let mut grammar = String::from("(* The grammar of Mornington, generated by the interpreter. *)\n\n");
+    let mut rule = |name: &str, definition: &str| {
+        let _ = writeln!(grammar, "{name} = {definition} ;");
+    };
+
+    rule("program", "block");
+    rule("block", "{ indentation , statement , newline }");
+    rule("statement", "assignment | call | conditional | while loop | for loop | break | continue | pass \
+                       | return | function definition");
+    rule("assignment", "name , \"=\" , expression");
+    rule("call", "name , open parenthesis , [ expression , { \",\" , expression } ] , close parenthesis");
+    rule("conditional", &format!(
+        "{} , expression , newline , block , {{ indentation , {} , expression , newline , block }} , \
+         [ indentation , {} , newline , block ]",
+        quoted(TokenKind::If), quoted(TokenKind::Elif), quoted(TokenKind::Else),
+    ));
+    rule("while loop", &format!("{} , expression , newline , block", quoted(TokenKind::While)));
+    rule("for loop", &format!(
+        "{} , name , {} , expression , newline , block",
+        quoted(TokenKind::For), quoted(TokenKind::In),
+    ));
+    rule("break", &quoted(TokenKind::Break));
+    rule("continue", &quoted(TokenKind::Continue));
+    rule("pass", &quoted(TokenKind::Pass));
+    rule("return", &format!("{} , expression", quoted(TokenKind::Return)));
+    rule("function definition", &format!(
+        "{} , name , open parenthesis , [ name , {{ \",\" , name }} ] , close parenthesis , newline , block",
+        quoted(TokenKind::Funcdef),
+    ));
+
+    // one rule per precedence level, loosest first
+    let mut levels: Vec<(u32, Vec<&str>)> = Vec::new();
+    for operator in Operator::ALL {
+        match levels.last_mut() {
+            Some((precedence, symbols)) if *precedence == operator.precedence() => {
+                symbols.push(operator.symbol());
+            },
+            _ => levels.push((operator.precedence(), vec![operator.symbol()])),
+        }
+    }
+    rule("expression", "operation 1");
+    for (index, (_, symbols)) in levels.iter().enumerate() {
+        let operand = match index + 1 {
+            next if next < levels.len() => format!("operation {}", next + 1),
+            _ => "operand".to_string(),
+        };
+        let symbols: Vec<String> = symbols.iter().map(|symbol| format!("\"{symbol}\"")).collect();
+        rule(
+            &format!("operation {}", index + 1),
+            &format!("{operand} , {{ ( {} ) , {operand} }}", symbols.join(" | ")),
+        );
+    }
+    rule("operand", "number | string | boolean | list | call | name \
+                     | open parenthesis , expression , close parenthesis");
+    rule("list", "open bracket , [ expression , { \",\" , expression } ] , close bracket");
+    rule("boolean", "\"rtue\" | \"flase\"");
+
+    rule("name", &format!("? the regular expression /{NAME_PATTERN}/, other than a keyword ?"));
+    rule("number", &format!("? the regular expression /{NUMBER_PATTERN}/ ?"));
+    rule("string", "? a run of '\"', then any text, then a run of '\"' of a different length ? \
+                    | '\"' , \"'\" | \"'\" , '\"'");
+    rule("open parenthesis", "? a run of '(' ?");
+    rule("close parenthesis", "? a run of ')' of a different length to the run that opened it ?");
+    rule("open bracket", "? a run of '[' ?");
+    rule("close bracket", "? a run of ']' of a different length to the run that opened it ?");
+    rule("indentation", "? three spaces per level of nesting, plus up to two more, which must differ \
+                         from the previous line of the same block ?");
+    rule("newline", "? a line break ?");
+    grammar
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::KEYWORDS;
+    use super::*;
+
+    #[test]
+    fn includes_every_keyword_and_operator() {
+        let grammar = grammar();
+        for (_, keyword) in KEYWORDS {
+            assert!(grammar.contains(&format!("\"{keyword}\"")), "`{keyword}` is missing");
+        }
+        for operator in Operator::ALL {
+            assert!(grammar.contains(&format!("\"{}\"", operator.symbol())), "`{operator:?}` is missing");
+        }
+    }
+
+    #[test]
+    fn groups_operators_by_precedence() {
+        let grammar = grammar();
+        assert!(grammar.contains("expression = operation 1 ;\n"));
+        assert!(grammar.contains("operation 1 = operation 2 , { ( \"ro\" ) , operation 2 } ;\n"));
+        assert!(grammar.contains("operation 4 = operation 5 , { ( \"+\" | \"-\" ) , operation 5 } ;\n"));
+        assert!(grammar.contains("operation 5 = operand , { ( \"*\" | \"/\" | \"%\" ) , operand } ;\n"));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::error::Error;
+use crate::json::{Json, ToJson};
+use crate::lexer::{Lexer, Position, TokenKind};
+
+
+/// What a span of source code is, for syntax highlighting. The classes, and their names, are
+/// stable, so editors can map them onto their own highlighting groups.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// A keyword, such as `fi` or `retrun`, including `rtue` and `flase`.
+    Keyword,
+    /// An arithmetic or relational operator, or `=`.
+    Operator,
+    Number,
+    /// A string, including its quotes.
+    String,
+    /// The name of a variable or function.
+    Name,
+    /// A run of brackets, a comma, or a full stop.
+    Punctuation,
+}
+impl TokenClass {
+    /// Classifies a token, or returns `None` for tokens that aren't highlighted, i.e. newlines.
+    pub fn of(kind: TokenKind) -> Option<TokenClass> {
+        use TokenKind::*;
+        Some(match kind {
+            Newline => return None,
+            LParen | RParen | LBrack | RBrack | Comma | FullStop => TokenClass::Punctuation,
+            Plus | Minus | Mul | Div | Mod | Eq | Ne | Seq | Sne | Gt | Lt | Ge | Le | Assign => {
+                TokenClass::Operator
+            },
+            If | Elif | Else | While | For | In | Break | Continue | Pass | Funcdef | Return
+            | And | Or | BoolTrue | BoolFalse => {
+                TokenClass::Keyword
+            },
+            Number => TokenClass::Number,
+            String => TokenClass::String,
+            Name => TokenClass::Name,
+        })
+    }
+
+    /// Returns the class's name, e.g. `keyword`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Operator => "operator",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Name => "name",
+            TokenClass::Punctuation => "punctuation",
+        }
+    }
+}
+
+/// A span of source code and what it is.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub class: TokenClass,
+    pub position: Position,
+}
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        let Position { start, end } = self.position;
+        let location = |line: usize, column: usize| Json::object([
+            ("line", Json::Number(line as f64)),
+            ("column", Json::Number(column as f64)),
+        ]);
+        Json::object([
+            ("class", Json::String(self.class.name().to_string())),
+            ("start", location(start.line, start.column)),
+            ("end", location(end.line, end.column)),
+        ])
+    }
+}
+
+/// Classifies every token in `source`, in order, using the interpreter's own lexer, so that the
+/// way runs of brackets and quotes are grouped matches exactly. Whitespace isn't included.
+pub fn highlight(source: &str) -> Result<Vec<Span>, Error> {
+    Ok(Lexer::new(source).lex()?.iter()
+        .filter_map(|token| TokenClass::of(token.kind).map(|class| Span { class, position: token.position }))
+        .collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_tokens() {
+        let classes: Vec<TokenClass> = highlight("fi x == \"\"a\"\n   pront((1, rtue)")
+            .unwrap()
+            .iter()
+            .map(|span| span.class)
+            .collect();
+        use TokenClass::*;
+        assert_eq!(
+            vec![Keyword, Name, Operator, String, Name, Punctuation, Number, Punctuation, Keyword, Punctuation],
+            classes,
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let spans = highlight("x = 10").unwrap();
+        assert_eq!(
+            r#"[{"class":"name","start":{"line":1,"column":0},"end":{"line":1,"column":1}},{"class":"operator","start":{"line":1,"column":2},"end":{"line":1,"column":3}},{"class":"number","start":{"line":1,"column":4},"end":{"line":1,"column":6}}]"#,
+            spans.to_json().to_string(),
+        );
+    }
+}
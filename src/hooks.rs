@@ -0,0 +1,100 @@
+use crate::ast::{Flow, StatementNode};
+use crate::error::Error;
+use crate::lexer::Position;
+use crate::value::Value;
+
+
+/// Callbacks on the events of a program's execution, so that profilers, visualisers, and the like
+/// can be built outside the interpreter. Attach them to a runtime with [`Runtime::set_hooks`].
+///
+/// Every callback does nothing by default, so implementations only need to override the events
+/// they care about. Unlike a [`Debugger`](crate::debugger::Debugger), hooks only observe: they're
+/// never given the runtime, so they can't change what the program does.
+///
+/// [`Runtime::set_hooks`]: crate::runtime::Runtime::set_hooks
+pub trait RuntimeHooks {
+    /// Called before each statement is executed. Blocks aren't statements in their own right, so
+    /// only the statements inside them are passed.
+    fn enter_statement(&mut self, _statement: &StatementNode) {}
+
+    /// Called after each statement passed to [`enter_statement`](Self::enter_statement) has
+    /// finished, with how execution carries on or the error it failed with.
+    fn exit_statement(&mut self, _statement: &StatementNode, _result: &Result<Flow, Error>) {}
+
+    /// Called before a function or builtin named `name` is called at `position`.
+    fn enter_call(&mut self, _name: &str, _position: Position) {}
+
+    /// Called after a call passed to [`enter_call`](Self::enter_call) has returned, with the
+    /// value it returned or the error it failed with.
+    fn exit_call(&mut self, _name: &str, _result: &Result<Value, Error>) {}
+
+    /// Called whenever a variable is assigned, including parameters and loop variables, with the
+    /// position of the statement or call assigning it.
+    fn assign(&mut self, _name: &str, _value: &Value, _position: Position) {}
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::runtime::Runtime;
+    use super::*;
+
+    /// Records every event as a line of text.
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+    impl RuntimeHooks for Recorder {
+        fn enter_statement(&mut self, statement: &StatementNode) {
+            self.0.borrow_mut().push(format!("enter {}", statement.describe()));
+        }
+
+        fn exit_statement(&mut self, statement: &StatementNode, result: &Result<Flow, Error>) {
+            let outcome = if result.is_ok() { "ok" } else { "error" };
+            self.0.borrow_mut().push(format!("exit {} {outcome}", statement.describe()));
+        }
+
+        fn enter_call(&mut self, name: &str, position: Position) {
+            self.0.borrow_mut().push(format!("call {name} on line {}", position.start.line));
+        }
+
+        fn exit_call(&mut self, name: &str, result: &Result<Value, Error>) {
+            let value = result.as_ref().map_or("error".to_string(), Value::to_string);
+            self.0.borrow_mut().push(format!("return {value} from {name}"));
+        }
+
+        fn assign(&mut self, name: &str, value: &Value, _position: Position) {
+            self.0.borrow_mut().push(format!("{name} = {value}"));
+        }
+    }
+
+    fn record(source: &str) -> Vec<String> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(Vec::new());
+        runtime.set_hooks(Recorder(Rc::clone(&events)));
+        let _ = crate::run_in(source, &mut runtime);
+        events.take()
+    }
+
+    #[test]
+    fn reports_statements_calls_and_assignments() {
+        assert_eq!(
+            vec![
+                "enter fnuc dobule", "exit fnuc dobule ok",
+                "enter x = ...", "call dobule on line 3", "n = 2",
+                "enter retrun", "exit retrun ok",
+                "return 4 from dobule", "x = 4", "exit x = ... ok",
+                "enter call pront", "call pront on line 4", "return []] from pront", "exit call pront ok",
+            ],
+            record("fnuc dobule((n)\n   retrun n * 2\nx = dobule((2)\n pront((x)"),
+        );
+    }
+
+    #[test]
+    fn reports_failures() {
+        assert_eq!(
+            vec!["enter call pront", "call pront on line 1", "return error from pront", "exit call pront error"],
+            record("pront((y)"),
+        );
+    }
+}
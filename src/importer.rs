@@ -0,0 +1,205 @@
+//! Conversion of conventionally written code into Mornington, so that programs can be written
+//! with balanced brackets, consistent indentation, and correctly spelled keywords, and then
+//! shipped as Mornington.
+//!
+//! The conventional dialect is Mornington as anyone else would write it:
+//! ```text
+//! def fizz(n):
+//!     if n % 3 == 0:
+//!         return "fizz"
+//!     return ""
+//! ```
+//! Colons ending lines are optional, strings may be quoted with `"` or `'`, and blank lines are
+//! ignored.
+
+use crate::error::{Error, ErrorKind::Unconvertible};
+use crate::lexer::{scrambled_keyword, Position};
+
+
+/// Rewrites conventionally written `source` into valid Mornington, in the style of
+/// [`format`](crate::format).
+///
+/// Errors in the conventional source are positioned in it. Errors found once it's been rewritten,
+/// such as a missing expression, are positioned on the whole line they came from.
+pub fn import(source: &str) -> Result<String, Error> {
+    let mut rewritten = String::new();
+    // the line of `source` that each line of `rewritten` came from
+    let mut origins = Vec::new();
+    let mut blocks = vec![Block { indentation: 0, lines: 0 }];
+
+    for (index, line) in source.lines().enumerate() {
+        let number = index + 1;
+        let code = line.trim_start();
+        if code.trim_end().is_empty() {
+            continue;
+        }
+        let indentation = line.len() - code.len();
+        if indentation > blocks.last().unwrap().indentation {
+            blocks.push(Block { indentation, lines: 0 });
+        }
+        while indentation < blocks.last().unwrap().indentation {
+            blocks.pop();
+        }
+        let level = blocks.len() - 1;
+        let block = blocks.last_mut().unwrap();
+        if indentation != block.indentation {
+            return Err(unconvertible(
+                "the indentation doesn't match any enclosing block's",
+                Position::new(number, 0, indentation),
+            ));
+        }
+
+        // consecutive lines in a block can't be indented by the same amount, so alternate
+        // between the two smallest indentations for the level
+        rewritten += &" ".repeat(level * 3 + block.lines % 2);
+        block.lines += 1;
+        rewrite_line(code.trim_end(), number, indentation, &mut rewritten)?;
+        rewritten.push('\n');
+        origins.push(number);
+    }
+
+    crate::format(&rewritten).map_err(|error| locate(error, &origins, source))
+}
+
+/// A block of the conventional source: how far its lines are indented, and how many there have
+/// been so far.
+struct Block {
+    indentation: usize,
+    lines: usize,
+}
+
+/// Appends `code`, a line of conventional source without its indentation, to `output` as
+/// Mornington.
+fn rewrite_line(code: &str, line: usize, indentation: usize, output: &mut String) -> Result<(), Error> {
+    let mut characters = code.char_indices();
+    while let Some((index, character)) = characters.next() {
+        let column = indentation + code[..index].chars().count();
+        match character {
+            '"' | '\'' => {
+                let length = code[index + 1..].find(character).ok_or_else(|| unconvertible(
+                    "the string is never closed",
+                    Position::new(line, column, code[index..].chars().count()),
+                ))?;
+                let contents = &code[index + 1..index + 1 + length];
+                if contents.contains('"') {
+                    return Err(unconvertible(
+                        "strings can't contain `\"` in Mornington",
+                        Position::new(line, column, contents.chars().count() + 2),
+                    ));
+                }
+                if contents.is_empty() {
+                    *output += "\"'";
+                } else {
+                    *output += &format!("\"\"{contents}\"");
+                }
+                characters.nth(length);
+            },
+            '(' | '[' => {
+                separate(output, character);
+                output.push(character);
+                output.push(character);
+            },
+            ')' | ']' => {
+                separate(output, character);
+                output.push(character);
+            },
+            // colons starting blocks
+            ':' if index == code.len() - 1 => {},
+            word if word.is_ascii_alphabetic() || word == '_' => {
+                let end = code[index..]
+                    .find(|character: char| !character.is_ascii_alphanumeric() && character != '_')
+                    .map_or(code.len(), |length| index + length);
+                let word = &code[index..end];
+                let rest = &code[end..];
+                if word == "else" && rest.trim_start().starts_with("if ") {
+                    *output += "lefi";
+                    let skipped = rest.len() - rest.trim_start().len() + "if".len();
+                    characters.nth(end - index + skipped - 2);
+                } else {
+                    *output += scrambled_keyword(word).unwrap_or(word);
+                    if end - index > 1 {
+                        characters.nth(end - index - 2);
+                    }
+                }
+            },
+            other => output.push(other),
+        }
+    }
+    Ok(())
+}
+
+/// Separates a bracket about to be written from the one before it, if they're the same and would
+/// otherwise run together into a single wrapper.
+fn separate(output: &mut String, bracket: char) {
+    if output.ends_with(bracket) {
+        output.push(' ');
+    }
+}
+
+fn unconvertible(reason: &str, position: Position) -> Error {
+    Error::new(Unconvertible(reason.to_string()), Some(position))
+}
+
+/// Moves an error in the rewritten source onto the line of `source` it came from.
+fn locate(mut error: Error, origins: &[usize], source: &str) -> Error {
+    let locate_position = |position: Position| {
+        let line = origins.get(position.start.line - 1).copied().unwrap_or(origins.len());
+        let length = source.lines().nth(line - 1).map_or(0, |text| text.trim_end().chars().count());
+        Position::new(line, 0, length)
+    };
+    error.pos = error.pos.map(locate_position);
+    if let crate::error::ErrorKind::Balance { opener_position, .. } = &mut error.kind {
+        *opener_position = opener_position.map(locate_position);
+    }
+    error.related = error.related.into_iter().map(|related| locate(related, origins, source)).collect();
+    error
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorKind;
+    use super::*;
+
+    #[test]
+    fn rewrites_conventional_code() {
+        let source = "\
+def fizz(n):
+    if n % 3 == 0:
+        return \"fizz\"
+    elif n == 1:
+        return ''
+    else:
+        return str(n)
+
+for i in range(len([1, [2, 3]])):
+    x = fizz(i)
+    print(x)
+";
+        assert_eq!(
+            "fnuc fizz((n)\n   fi n % 3 == 0\n      retrun \"\"fizz\"\n   lefi n == 1\n      retrun \"'\n   \
+             sele\n      retrun str((n)\n fir i ni range((len(([[1, [[2, 3] ]) )\n   x = fizz((i)\n    print((x)\n",
+            import(source).unwrap(),
+        );
+    }
+
+    #[test]
+    fn runs_once_rewritten() {
+        let source = "total = 0\nfor i in arnge(4):\n    if i == 2:\n        continue\n    else if i > 2:\n        break\n    total = total + i\npront(total)";
+        let output = crate::fixtures::run_program(&import(source).unwrap(), "");
+        assert_eq!("1", output.stdout);
+    }
+
+    #[test]
+    fn reports_errors_in_the_original_source() {
+        let error = import("x = 1\n\nif x:\n        y = 'a\n").unwrap_err();
+        assert_eq!(ErrorKind::Unconvertible("the string is never closed".to_string()), error.kind);
+        assert_eq!(Some(Position::new(4, 12, 2)), error.pos);
+
+        let error = import("if x:\n    y = 1\n  z = 2").unwrap_err();
+        assert_eq!(Some(Position::new(3, 0, 2)), error.pos);
+
+        let error = import("x = 1\n\ny = ").unwrap_err();
+        assert_eq!(Some(Position::new(3, 0, 3)), error.pos);
+    }
+}
@@ -0,0 +1,535 @@
+//! A small JSON representation, for saving syntax trees and values and reading them back.
+//!
+//! Types that can be converted implement [`ToJson`] and [`FromJson`]. The layout follows the
+//! usual conventions for Rust data: structs become objects keyed by field name, and enum variants
+//! become objects with the variant's name as their only key, e.g. `{"Number":1}`, or bare strings
+//! for variants without data, e.g. `"Add"`.
+
+use std::fmt::{self, Display, Formatter};
+use crate::diagnostics::json_string;
+use crate::error::{Error, ErrorKind::InvalidJson};
+use crate::lexer::{Location, Position};
+use crate::stack;
+use crate::symbol::Symbol;
+use crate::value::Value;
+
+/// The largest integer that a JSON number, being a double, can hold exactly.
+const MAX_EXACT_INTEGER: u64 = 1 << 53;
+/// How deeply arrays and objects can be nested in JSON read by [`Json::parse`], as in most JSON
+/// libraries, so that reading a document from somewhere untrusted can't use up the stack or memory.
+pub const MAX_NESTING: usize = 128;
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// The members of an object, in the order they were written.
+    Object(Vec<(String, Json)>),
+}
+impl Json {
+    /// Parses a single JSON value, which may be surrounded by whitespace, with arrays and objects
+    /// nested at most [`MAX_NESTING`] deep.
+    pub fn parse(text: &str) -> Result<Json, Error> {
+        Json::parse_nested(text, MAX_NESTING)
+    }
+
+    /// Parses JSON as [`Json::parse`] does, but with arrays and objects nested at most
+    /// `max_nesting` deep, for documents that can legitimately be deeper, such as syntax trees.
+    pub fn parse_nested(text: &str, max_nesting: usize) -> Result<Json, Error> {
+        let mut parser = JsonParser { text, offset: 0, nesting: 0, max_nesting };
+        let json = parser.value()?;
+        parser.skip_whitespace();
+        if parser.offset < text.len() {
+            return Err(parser.error("unexpected text after the value"));
+        }
+        Ok(json)
+    }
+
+    /// Converts plain JSON, as found in data files, to the Mornington value closest to it, rather
+    /// than reading it as a value saved with [`ToJson`]: `null` becomes `[]`, whole numbers small
+    /// enough to be held exactly become integers, and objects become `lsit`s of `[key, value]`
+    /// pairs in the order they were written.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Json::Null => Value::List(vec![]),
+            Json::Bool(bool) => Value::Bool(*bool),
+            Json::Number(number) if number.fract() == 0.0 && number.abs() <= MAX_EXACT_INTEGER as f64 => {
+                Value::Int(*number as i64)
+            },
+            Json::Number(number) => Value::Number(*number),
+            Json::String(string) => Value::String(string.clone()),
+            Json::Array(elements) => Value::List(elements.iter().map(Json::to_value).collect()),
+            Json::Object(members) => Value::List(members.iter()
+                .map(|(key, value)| Value::List(vec![Value::String(key.clone()), value.to_value()]))
+                .collect()),
+        }
+    }
+
+    /// Creates an object with the members given.
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Creates an object with the single member `variant`, for an enum variant holding data.
+    pub fn variant(variant: &str, data: Json) -> Json {
+        Json::Object(vec![(variant.to_string(), data)])
+    }
+
+    /// Returns the member `key` of an object.
+    pub fn get(&self, key: &str) -> Result<&Json, Error> {
+        let Json::Object(members) = self else {
+            return Err(invalid(&format!("expected an object with `{key}`, found {}", self.kind())));
+        };
+        members.iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+            .ok_or_else(|| invalid(&format!("missing `{key}`")))
+    }
+
+    /// Splits an enum variant, written either as a bare string or an object with a single member,
+    /// into its name and data.
+    pub fn as_variant(&self) -> Result<(&str, &Json), Error> {
+        match self {
+            Json::String(name) => Ok((name, &Json::Null)),
+            Json::Object(members) if members.len() == 1 => Ok((&members[0].0, &members[0].1)),
+            _ => Err(invalid(&format!("expected an enum variant, found {}", self.kind()))),
+        }
+    }
+
+    pub fn as_str(&self) -> Result<&str, Error> {
+        match self {
+            Json::String(string) => Ok(string),
+            _ => Err(invalid(&format!("expected a string, found {}", self.kind()))),
+        }
+    }
+
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        match self {
+            Json::Number(number) => Ok(*number),
+            _ => Err(invalid(&format!("expected a number, found {}", self.kind()))),
+        }
+    }
+
+    pub fn as_usize(&self) -> Result<usize, Error> {
+        let number = self.as_f64()?;
+        if number < 0.0 || number.fract() != 0.0 {
+            return Err(invalid(&format!("expected a whole number, found {number}")));
+        }
+        Ok(number as usize)
+    }
+
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match self {
+            Json::Bool(bool) => Ok(*bool),
+            _ => Err(invalid(&format!("expected a boolean, found {}", self.kind()))),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Json], Error> {
+        match self {
+            Json::Array(array) => Ok(array),
+            _ => Err(invalid(&format!("expected an array, found {}", self.kind()))),
+        }
+    }
+
+    /// Converts each element of an array.
+    pub fn array_of<T: FromJson>(&self) -> Result<Vec<T>, Error> {
+        self.as_array()?.iter().map(T::from_json).collect()
+    }
+
+    /// Describes what sort of value this is, for error messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+/// Writes the JSON compactly, on a single line.
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        stack::guard(|| match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(bool) => write!(f, "{bool}"),
+            // JSON has no way of writing infinities or NaN
+            Json::Number(number) if !number.is_finite() => write!(f, "null"),
+            Json::Number(number) => write!(f, "{number}"),
+            Json::String(string) => write!(f, "{}", json_string(string)),
+            Json::Array(elements) => {
+                write!(f, "[")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{element}")?;
+                }
+                write!(f, "]")
+            },
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{value}", json_string(key))?;
+                }
+                write!(f, "}}")
+            },
+        })
+    }
+}
+
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+pub trait FromJson: Sized {
+    /// Converts the JSON back, returning an `InvalidJson` error if it isn't laid out as
+    /// [`ToJson`] would have written it.
+    fn from_json(json: &Json) -> Result<Self, Error>;
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<String, Error> {
+        json.as_str().map(str::to_string)
+    }
+}
+
+impl ToJson for Symbol {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+impl FromJson for Symbol {
+    fn from_json(json: &Json) -> Result<Symbol, Error> {
+        json.as_str().map(Symbol::intern)
+    }
+}
+
+impl ToJson for Value {
+    fn to_json(&self) -> Json {
+        match self {
+            // integers too large to be held exactly by a JSON number are written as strings
+            Value::Int(int) if int.unsigned_abs() > MAX_EXACT_INTEGER => {
+                Json::variant("Int", Json::String(int.to_string()))
+            },
+            Value::Int(int) => Json::variant("Int", Json::Number(*int as f64)),
+            // non-finite numbers are written as strings, so that they survive the round trip
+            Value::Number(number) if !number.is_finite() => {
+                Json::variant("Number", Json::String(number.to_string()))
+            },
+            Value::Number(number) => Json::variant("Number", Json::Number(*number)),
+            Value::Bool(bool) => Json::variant("Bool", Json::Bool(*bool)),
+            Value::String(string) => Json::variant("String", Json::String(string.clone())),
+            Value::Bytes(bytes) => Json::variant(
+                "Bytes",
+                Json::Array(bytes.iter().map(|&byte| Json::Number(byte as f64)).collect()),
+            ),
+            Value::List(list) => Json::variant("List", list.to_json()),
+        }
+    }
+}
+impl FromJson for Value {
+    fn from_json(json: &Json) -> Result<Value, Error> {
+        Ok(match json.as_variant()? {
+            ("Int", Json::String(int)) => Value::Int(int.parse().map_err(|_| {
+                invalid(&format!("expected an integer, found `{int}`"))
+            })?),
+            ("Int", int) => Value::Int(int.as_f64()? as i64),
+            ("Number", Json::String(number)) => Value::Number(number.parse().map_err(|_| {
+                invalid(&format!("expected a number, found `{number}`"))
+            })?),
+            ("Number", number) => Value::Number(number.as_f64()?),
+            ("Bool", bool) => Value::Bool(bool.as_bool()?),
+            ("String", string) => Value::String(string.as_str()?.to_string()),
+            ("Bytes", Json::Array(bytes)) => Value::Bytes(bytes.iter()
+                .map(|byte| match byte.as_f64()? {
+                    byte if byte.fract() == 0.0 && (0.0..=255.0).contains(&byte) => Ok(byte as u8),
+                    byte => Err(invalid(&format!("expected a byte, found `{byte}`"))),
+                })
+                .collect::<Result<_, _>>()?),
+            ("List", list) => Value::List(list.array_of()?),
+            (other, _) => return Err(invalid(&format!("unknown value type `{other}`"))),
+        })
+    }
+}
+
+impl ToJson for Position {
+    fn to_json(&self) -> Json {
+        let location = |location: Location| Json::object([
+            ("line", Json::Number(location.line as f64)),
+            ("column", Json::Number(location.column as f64)),
+        ]);
+        Json::object([("start", location(self.start)), ("end", location(self.end))])
+    }
+}
+impl FromJson for Position {
+    fn from_json(json: &Json) -> Result<Position, Error> {
+        let location = |json: &Json| -> Result<Location, Error> {
+            Ok(Location::new(json.get("line")?.as_usize()?, json.get("column")?.as_usize()?))
+        };
+        Ok(Position::span(location(json.get("start")?)?, location(json.get("end")?)?))
+    }
+}
+
+pub(crate) fn invalid(reason: &str) -> Error {
+    Error::new(InvalidJson(reason.to_string()), None)
+}
+
+
+struct JsonParser<'a> {
+    text: &'a str,
+    /// How many bytes into the text the parser has got.
+    offset: usize,
+    /// How many arrays and objects enclose the value being parsed.
+    nesting: usize,
+    max_nesting: usize,
+}
+impl JsonParser<'_> {
+    fn error(&self, reason: &str) -> Error {
+        invalid(&format!("{reason} at byte {}", self.offset))
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.offset..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// Consumes `expected` if it's next, returning whether it was.
+    fn eat(&mut self, expected: &str) -> bool {
+        self.skip_whitespace();
+        let found = self.rest().starts_with(expected);
+        if found {
+            self.offset += expected.len();
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Error> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{expected}`")))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, Error> {
+        self.skip_whitespace();
+        match self.rest().chars().next() {
+            Some('n') if self.eat("null") => Ok(Json::Null),
+            Some('t') if self.eat("true") => Ok(Json::Bool(true)),
+            Some('f') if self.eat("false") => Ok(Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => self.nest(|parser| {
+                let mut elements = Vec::new();
+                if !parser.eat("]") {
+                    loop {
+                        elements.push(parser.value()?);
+                        if parser.eat("]") {
+                            break;
+                        }
+                        parser.expect(",")?;
+                    }
+                }
+                Ok(Json::Array(elements))
+            }),
+            Some('{') => self.nest(|parser| {
+                let mut members = Vec::new();
+                if !parser.eat("}") {
+                    loop {
+                        parser.skip_whitespace();
+                        let key = parser.string()?;
+                        parser.expect(":")?;
+                        members.push((key, parser.value()?));
+                        if parser.eat("}") {
+                            break;
+                        }
+                        parser.expect(",")?;
+                    }
+                }
+                Ok(Json::Object(members))
+            }),
+            Some('-' | '0'..='9') => {
+                let length = self.rest()
+                    .find(|character: char| !matches!(character, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+                    .unwrap_or(self.rest().len());
+                let number = self.rest()[..length].parse()
+                    .map_err(|_| self.error("invalid number"))?;
+                self.offset += length;
+                Ok(Json::Number(number))
+            },
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    /// Consumes the bracket or brace opening an array or object, then parses its contents with
+    /// `parse`, throwing an error instead if it would be nested too deeply.
+    fn nest(&mut self, parse: impl FnOnce(&mut Self) -> Result<Json, Error>) -> Result<Json, Error> {
+        if self.nesting == self.max_nesting {
+            return Err(self.error(&format!("nested more than {} levels deep", self.max_nesting)));
+        }
+        self.offset += 1;
+        self.nesting += 1;
+        let json = stack::guard(|| parse(self));
+        self.nesting -= 1;
+        json
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        if !self.rest().starts_with('"') {
+            return Err(self.error("expected a string"));
+        }
+        self.offset += 1;
+        let mut string = String::new();
+        let mut characters = self.rest().char_indices();
+        while let Some((index, character)) = characters.next() {
+            match character {
+                '"' => {
+                    self.offset += index + 1;
+                    return Ok(string);
+                },
+                '\\' => {
+                    let escaped = match characters.next().map(|(_, escaped)| escaped) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let digits: String = characters.by_ref().take(4).map(|(_, digit)| digit).collect();
+                            let code = u32::from_str_radix(&digits, 16)
+                                .map_err(|_| self.error("invalid unicode escape"))?;
+                            // characters outside the basic multilingual plane can't be written
+                            // with a single escape, and are left as the replacement character
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        },
+                        Some(other @ ('"' | '\\' | '/')) => other,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    string.push(escaped);
+                },
+                other => string.push(other),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_writes() {
+        let text = r#" {"a": [1, -2.5e1, true, null], "b\n": "\"q\" é"} "#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(
+            Json::Object(vec![
+                ("a".to_string(), Json::Array(vec![
+                    Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null,
+                ])),
+                ("b\n".to_string(), Json::String("\"q\" é".to_string())),
+            ]),
+            json,
+        );
+        assert_eq!(r#"{"a":[1,-25,true,null],"b\n":"\"q\" é"}"#, json.to_string());
+    }
+
+    #[test]
+    fn converts_plain_json_to_values() {
+        let json = Json::parse(r#"{"a": [1, 2.5, 1e300, null], "b": "c"}"#).unwrap();
+        assert_eq!(
+            Value::List(vec![
+                Value::List(vec![
+                    "a".into(),
+                    Value::List(vec![Value::Int(1), Value::Number(2.5), Value::Number(1e300), Value::List(vec![])]),
+                ]),
+                Value::List(vec!["b".into(), "c".into()]),
+            ]),
+            json.to_value(),
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        let reason = |text: &str| match Json::parse(text).unwrap_err().kind {
+            InvalidJson(reason) => reason,
+            kind => panic!("unexpected error {kind:?}"),
+        };
+        assert_eq!("expected `,` at byte 3", reason("[1 2]"));
+        assert_eq!("unterminated string at byte 1", reason("\"abc"));
+        assert_eq!("unexpected text after the value at byte 5", reason("true false"));
+    }
+
+    #[test]
+    fn rejects_deeply_nested_json() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_NESTING)).is_ok());
+        assert!(matches!(
+            Json::parse(&nested(MAX_NESTING + 1)).unwrap_err().kind,
+            InvalidJson(reason) if reason == "nested more than 128 levels deep at byte 128",
+        ));
+        assert!(Json::parse(&"[{\"a\":".repeat(200_000)).is_err());
+        assert!(Json::parse_nested(&nested(1000), usize::MAX).is_ok());
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let value = Value::List(vec![
+            Value::Number(1.5),
+            Value::Number(f64::INFINITY),
+            Value::Bool(false),
+            Value::String("hi".to_string()),
+            Value::List(Vec::new()),
+        ]);
+        let json = value.to_json();
+        assert_eq!(
+            r#"{"List":[{"Number":1.5},{"Number":"inf"},{"Bool":false},{"String":"hi"},{"List":[]}]}"#,
+            json.to_string(),
+        );
+        assert_eq!(value, Value::from_json(&Json::parse(&json.to_string()).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn integers_and_bytes_round_trip() {
+        let value = Value::List(vec![
+            Value::Int(3),
+            Value::Int(i64::MAX),
+            Value::Bytes(vec![0, 255]),
+        ]);
+        let json = value.to_json();
+        assert_eq!(
+            r#"{"List":[{"Int":3},{"Int":"9223372036854775807"},{"Bytes":[0,255]}]}"#,
+            json.to_string(),
+        );
+        let Value::List(list) = Value::from_json(&Json::parse(&json.to_string()).unwrap()).unwrap()
+            else { panic!("expected a list") };
+        assert!(matches!(list[..], [Value::Int(3), Value::Int(i64::MAX), Value::Bytes(_)]));
+        assert_eq!(value, Value::List(list));
+    }
+}
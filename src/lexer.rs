@@ -1,17 +1,29 @@
+use std::fmt::{Display, Formatter};
+use std::iter;
+use std::ops::Range;
+use std::sync::LazyLock;
 use regex::Regex;
+use crate::error::{Error, ErrorKind};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Token<'a> {
     pub kind: TokenKind,
     pub text: &'a str,
     pub(crate) position: Position,
+    /// How far the token's line is indented, in columns, with tabs expanded to the next tab stop.
+    pub(crate) indentation: usize,
+    /// The whitespace and comments between the previous token and this one, if the lexer is
+    /// keeping trivia, or nothing otherwise.
+    pub trivia: &'a str,
 }
 impl<'a> Token<'a> {
-    pub(crate) fn new(kind: TokenKind, text: &str, line: usize, start: usize, length: usize) -> Token {
+    pub(crate) fn new(kind: TokenKind, text: &str, line: usize, start: usize, length: usize) -> Token<'_> {
         Token {
             kind,
             text,
             position: Position::new(line, start, length),
+            indentation: 0,
+            trivia: "",
         }
     }
 }
@@ -23,188 +35,488 @@ pub enum TokenKind {
     Comma, FullStop,
     Plus, Minus, Mul, Div, Mod,
     Eq, Ne, Seq, Sne, Gt, Lt, Ge, Le,
+    And, Or,
     Assign,
     If, Elif, Else,
     While, For, In, Break, Continue,
-    Funcdef, Return,
+    Funcdef, Return, Pass,
     BoolTrue, BoolFalse, Number, String,
     Name,
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub struct Position {
+/// A point in the source code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Location {
+    /// The line, counting from 1.
     pub line: usize,
-    pub start: usize,
-    pub length: usize,
+    /// The column, counting characters from 0.
+    pub column: usize,
+}
+impl Location {
+    pub fn new(line: usize, column: usize) -> Location {
+        Location { line, column }
+    }
+}
+
+/// A span of source code, from `start` up to but not including `end`. Spans may cover several
+/// lines, e.g. an unterminated list.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    pub start: Location,
+    pub end: Location,
 }
 impl Position {
+    /// Creates a span of `length` characters on `line`, starting at column `start`.
     pub fn new(line: usize, start: usize, length: usize) -> Position {
-        Position { line, start, length }
+        Position {
+            start: Location::new(line, start),
+            end: Location::new(line, start + length),
+        }
+    }
+
+    pub fn span(start: Location, end: Location) -> Position {
+        Position { start, end }
+    }
+
+    /// Returns the span from the start of this one to the end of `other`.
+    pub fn to(&self, other: Position) -> Position {
+        Position::span(self.start, other.end)
+    }
+
+    pub fn is_multiline(&self) -> bool {
+        self.start.line != self.end.line
     }
 
     pub(crate) fn one_past(&self) -> Position {
-        Position {
-            line: self.line,
-            start: self.start + self.length,
-            length: 1,
+        Position::new(self.end.line, self.end.column, 1)
+    }
+}
+impl Display for Position {
+    /// Writes the span as `line:column-line:column`, e.g. `1:5-1:7`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}-{}:{}", self.start.line, self.start.column, self.end.line, self.end.column)
+    }
+}
+
+/// The byte offset each line of a source starts at, so that lines can be looked up, and spans
+/// turned into byte ranges, without scanning the source again. Columns count characters, so only
+/// the line a [`Location`] is on is scanned to find its offset.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+}
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> LineIndex<'a> {
+        let starts = iter::once(0)
+            .chain(source.match_indices('\n').map(|(offset, _)| offset + 1))
+            .collect();
+        LineIndex { source, starts }
+    }
+
+    /// Returns line `number`, counting from 1, without its line ending, or `None` if the source
+    /// doesn't have that many lines.
+    pub fn line(&self, number: usize) -> Option<&'a str> {
+        let start = *self.starts.get(number.checked_sub(1)?)?;
+        let end = self.starts.get(number).map_or(self.source.len(), |next| next - 1);
+        let line = &self.source[start..end];
+        Some(line.strip_suffix('\r').unwrap_or(line))
+    }
+
+    /// Returns the byte offset of `location` in the source, clamped to the end of the source.
+    pub fn offset(&self, location: Location) -> Option<usize> {
+        let start = *self.starts.get(location.line.checked_sub(1)?)?;
+        let column = self.source[start..].char_indices()
+            .nth(location.column)
+            .map_or(self.source.len() - start, |(index, _)| index);
+        Some(start + column)
+    }
+
+    /// Returns the bytes of the source that `position` spans.
+    pub fn range(&self, position: Position) -> Option<Range<usize>> {
+        Some(self.offset(position.start)?..self.offset(position.end)?)
+    }
+
+    /// Returns the location of byte `offset` in the source, which must be the start of a
+    /// character.
+    pub fn location(&self, offset: usize) -> Location {
+        let line = self.starts.partition_point(|&start| start <= offset);
+        Location::new(line, self.source[self.starts[line - 1]..offset].chars().count())
+    }
+}
+
+
+/// A comment, with where it starts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Comment<'a> {
+    pub text: &'a str,
+    pub start: Location,
+}
+
+/// Finds the comments in the trivia of `tokens`, and after the last of them, in order. The tokens
+/// must have been lexed from `source` by a lexer [keeping trivia](Lexer::set_keep_trivia).
+pub fn comments<'a>(source: &'a str, tokens: &[Token<'a>]) -> Vec<Comment<'a>> {
+    let lines = LineIndex::new(source);
+    let mut comments = Vec::new();
+    let mut find = |trivia: &'a str, offset: usize| {
+        comments.extend(PATTERNS.comments.find_iter(trivia).map(|mat| Comment {
+            text: mat.as_str(),
+            start: lines.location(offset + mat.start()),
+        }));
+    };
+    let mut cursor = 0;
+    for token in tokens {
+        find(token.trivia, cursor);
+        cursor += token.trivia.len() + token.text.len();
+    }
+    find(&source[cursor..], cursor);
+    comments
+}
+
+
+/// The keywords, as they're spelt in Mornington, in the order the lexer tries them. Each only
+/// counts as a keyword when it's followed by whitespace or ends the source.
+pub const KEYWORDS: [(TokenKind, &str); 13] = [
+    (TokenKind::If, "fi"),
+    (TokenKind::Elif, "lefi"),
+    (TokenKind::Else, "sele"),
+    (TokenKind::While, "whitl"),
+    (TokenKind::For, "fir"),
+    (TokenKind::In, "ni"),
+    (TokenKind::Break, "brek"),
+    (TokenKind::Continue, "cnotineu"),
+    (TokenKind::Funcdef, "fnuc"),
+    (TokenKind::Return, "retrun"),
+    (TokenKind::Pass, "pas"),
+    (TokenKind::And, "adn"),
+    (TokenKind::Or, "ro"),
+];
+
+/// How many columns a tab indents by unless the lexer's told otherwise: one indentation level.
+pub const DEFAULT_TAB_WIDTH: usize = 3;
+
+/// The regular expression names are lexed with.
+pub const NAME_PATTERN: &str = r"[\p{Alphabetic}_][\p{Alphabetic}\p{Nd}_]*";
+/// The regular expression numbers are lexed with.
+/// Digits may be separated by single underscores, e.g. `1_000_000`, and an exponent may follow,
+/// e.g. `2.5e-3`. Whole numbers may also be written in hexadecimal, binary, or octal, e.g. `0xff`,
+/// `0b1010`, or `0o17`.
+pub const NUMBER_PATTERN: &str = concat!(
+    r"0[xX][0-9a-fA-F](?:_?[0-9a-fA-F])*|0[bB][01](?:_?[01])*|0[oO][0-7](?:_?[0-7])*",
+    r"|[0-9](?:_?[0-9])*(?:\.[0-9](?:_?[0-9])*)?(?:[eE][+-]?[0-9](?:_?[0-9])*)?",
+);
+
+/// Returns the Mornington spelling of the keyword `kind`, e.g. `fi` for [`TokenKind::If`].
+pub fn keyword(kind: TokenKind) -> Option<&'static str> {
+    KEYWORDS.iter().find(|(keyword, _)| *keyword == kind).map(|(_, text)| *text)
+}
+
+/// Returns the scrambled keyword that `word` is the ordinary spelling of, if any, e.g. `fi` for
+/// `if`. Used to point people towards the keyword they probably meant.
+pub fn scrambled_keyword(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "if" => "fi",
+        "elif" | "elseif" | "elsif" => "lefi",
+        "else" => "sele",
+        "while" => "whitl",
+        "for" => "fir",
+        "in" => "ni",
+        "break" => "brek",
+        "continue" => "cnotineu",
+        "def" | "fn" | "func" | "function" => "fnuc",
+        "return" => "retrun",
+        "pass" => "pas",
+        "and" => "adn",
+        "or" => "ro",
+        "true" | "True" => "rtue",
+        "false" | "False" => "flase",
+        _ => return None,
+    })
+}
+
+/// Lays the tokens out as a table, one per line, with where each is in the source, its kind, and
+/// its text. Handy for seeing how runs of brackets and quotes were grouped.
+pub fn format_tokens(tokens: &[Token]) -> String {
+    let mut table = format!("{:<14}{:<12}TEXT\n", "POSITION", "KIND");
+    for token in tokens {
+        let position = token.position.to_string();
+        let kind = format!("{:?}", token.kind);
+        table += &format!("{position:<14}{kind:<12}{:?}\n", token.text);
+    }
+    table
+}
+
+
+/// The regular expressions tokens are lexed with, compiled once and shared by every lexer.
+struct Patterns {
+    // whitespace
+    whitespace: Regex,
+    newline: Regex,
+    comment: Regex,
+    comments: Regex,
+    // wrappers
+    lparen: Regex,
+    rparen: Regex,
+    lbrack: Regex,
+    rbrack: Regex,
+    // punctuation
+    comma: Regex,
+    full_stop: Regex,
+    // operators
+    plus: Regex,
+    minus: Regex,
+    mul: Regex,
+    div: Regex,
+    modulo: Regex,
+    eq: Regex,
+    ne: Regex,
+    seq: Regex,
+    sne: Regex,
+    gt: Regex,
+    lt: Regex,
+    ge: Regex,
+    le: Regex,
+    assign: Regex,
+    // name and datatypes
+    name: Regex,
+    bool_true: Regex,
+    bool_false: Regex,
+    number: Regex,
+    string: Regex,
+    empty_string_1: Regex,
+    empty_string_2: Regex,
+    // keywords
+    keywords: Vec<(TokenKind, &'static str, Regex)>,
+}
+impl Patterns {
+    fn new() -> Patterns {
+        Patterns {
+            // whitespace
+            whitespace: Regex::new(r"^([ \t])+").unwrap(),
+            // Windows line endings are accepted as they are, so that tokens can still borrow the
+            // source
+            newline: Regex::new(r"^\r?\n").unwrap(),
+            // the shortest run up to the first closer, capturing the stars either end so that
+            // they can be checked not to balance
+            comment: Regex::new(r"^/(\*+)[\s\S]*?(\*+)/").unwrap(),
+            comments: Regex::new(r"/\*+[\s\S]*?\*+/").unwrap(),
+            // wrappers
+            lparen: Regex::new(r"^\(+").unwrap(),
+            rparen: Regex::new(r"^\)+").unwrap(),
+            lbrack: Regex::new(r"^\[+").unwrap(),
+            rbrack: Regex::new(r"^]+").unwrap(),
+            // punctuation
+            comma: Regex::new(r"^,").unwrap(),
+            full_stop: Regex::new(r"^\.").unwrap(),
+            // operators
+            plus: Regex::new(r"^\+").unwrap(),
+            minus: Regex::new(r"^-").unwrap(),
+            mul: Regex::new(r"^\*").unwrap(),
+            div: Regex::new(r"^/").unwrap(),
+            modulo: Regex::new(r"^%").unwrap(),
+            eq: Regex::new(r"^==").unwrap(),
+            ne: Regex::new(r"^!=").unwrap(),
+            seq: Regex::new(r"^===").unwrap(),
+            sne: Regex::new(r"^!==").unwrap(),
+            gt: Regex::new(r"^>").unwrap(),
+            lt: Regex::new(r"^<").unwrap(),
+            ge: Regex::new(r"^>=").unwrap(),
+            le: Regex::new(r"^<=").unwrap(),
+            assign: Regex::new(r"^=").unwrap(),
+            // name and datatypes
+            name: Regex::new(&format!("^{NAME_PATTERN}")).unwrap(),
+            bool_true: Regex::new(r"^rtue").unwrap(),
+            bool_false: Regex::new(r"^flase").unwrap(),
+            number: Regex::new(&format!("^(?:{NUMBER_PATTERN})")).unwrap(),
+            string: Regex::new("^\"+[\\S\\s]+?\"+").unwrap(),
+            empty_string_1: Regex::new("^\"'").unwrap(),
+            empty_string_2: Regex::new("^'\"").unwrap(),
+            // keywords
+            keywords: KEYWORDS.iter()
+                .map(|&(kind, text)| (kind, text, Regex::new(&format!(r"^{text}(?:\s|$)")).unwrap()))
+                .collect(),
         }
     }
 }
 
+static PATTERNS: LazyLock<Patterns> = LazyLock::new(Patterns::new);
+
 
 pub struct Lexer<'a> {
     source: &'a str,
-    token_vec: Vec<Token<'a>>,
+    /// The token just lexed, until it's handed out.
+    token: Option<Token<'a>>,
     current_line: usize,
     current_column: usize,
     current_position: usize,
     current_token_length: usize,
+    current_indentation: usize,
+    tab_width: usize,
+    keep_trivia: bool,
+    /// Where the whitespace and comments before the next token start.
+    trivia_start: usize,
+    /// Whether the current line has had a comment on it, and whether it's had a token, so that
+    /// lines holding only comments can be skipped.
+    line_has_comment: bool,
+    line_has_token: bool,
     remaining_source: &'a str,
 }
 impl<'a> Lexer<'a> {
-    pub fn new(source: &str) -> Lexer {
+    pub fn new(source: &str) -> Lexer<'_> {
         Lexer {
             source,
-            token_vec: Vec::new(),
+            token: None,
             current_line: 1,
             current_column: 0,
             current_position: 0,
             current_token_length: 0,
+            current_indentation: 0,
+            tab_width: DEFAULT_TAB_WIDTH,
+            keep_trivia: false,
+            trivia_start: 0,
+            line_has_comment: false,
+            line_has_token: false,
             remaining_source: source,
         }
     }
 
-    pub fn lex(&mut self) -> Result<&Vec<Token>, Position> {
-        use TokenKind::*;
+    /// Sets how many columns a tab in a line's indentation counts for, [`DEFAULT_TAB_WIDTH`]
+    /// unless set. Tabs anywhere else are just whitespace.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        self.tab_width = tab_width.max(1);
+    }
+
+    /// Sets whether each token keeps the whitespace and comments before it as its
+    /// [`Token::trivia`], so that the source can be put back together exactly from the tokens,
+    /// with whatever follows the last token after them. Off unless set.
+    pub fn set_keep_trivia(&mut self, keep_trivia: bool) {
+        self.keep_trivia = keep_trivia;
+    }
+
+    /// Splits the source into tokens, handing them over so that they can be parsed without being
+    /// copied. Lexing carries on past unexpected symbols so that they can all be reported at once:
+    /// the first is returned, with the rest as its related errors.
+    pub fn lex(self) -> Result<Vec<Token<'a>>, Error> {
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for token in self {
+            match token {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(tokens);
+        }
+        let mut error = errors.remove(0);
+        error.related = errors;
+        Err(error)
+    }
 
-        // whitespace
-        let re_whitespace = Regex::new(r"^([ \t])+").unwrap();
-        let re_newline = Regex::new(r"^\n").unwrap();
-        // wrappers
-        let re_lparen = Regex::new(r"^\(+").unwrap();
-        let re_rparen = Regex::new(r"^\)+").unwrap();
-        let re_lbrack = Regex::new(r"^\[+").unwrap();
-        let re_rbrack = Regex::new(r"^]+").unwrap();
-        // punctuation
-        let re_comma = Regex::new(r"^,").unwrap();
-        let re_full_stop = Regex::new(r"^\.").unwrap();
-        // operators
-        let re_plus = Regex::new(r"^\+").unwrap();
-        let re_minus = Regex::new(r"^-").unwrap();
-        let re_mul = Regex::new(r"^\*").unwrap();
-        let re_div = Regex::new(r"^/").unwrap();
-        let re_mod = Regex::new(r"^%").unwrap();
-        let re_eq = Regex::new(r"^==").unwrap();
-        let re_ne = Regex::new(r"^!=").unwrap();
-        let re_seq = Regex::new(r"^===").unwrap();
-        let re_sne = Regex::new(r"^!==").unwrap();
-        let re_gt = Regex::new(r"^>").unwrap();
-        let re_lt = Regex::new(r"^<").unwrap();
-        let re_ge = Regex::new(r"^>=").unwrap();
-        let re_le = Regex::new(r"^<=").unwrap();
-        let re_assign = Regex::new(r"^=").unwrap();
-        // name and datatypes
-        let re_name = Regex::new(r"^[a-zA-Z_][a-zA-Z_0-9]*").unwrap();
-        let re_bool_true = Regex::new(r"^rtue").unwrap();
-        let re_bool_false = Regex::new(r"^flase").unwrap();
-        let re_number = Regex::new(r"^[0-9]+(?:\.[0-9]+)?").unwrap();
-        let re_string = Regex::new("^\"+[\\S\\s]+?\"+").unwrap();
-        let re_empty_string_1 = Regex::new("^\"'").unwrap();
-        let re_empty_string_2 = Regex::new("^'\"").unwrap();
-        // control flow
-        let re_if = Regex::new(r"^fi\s").unwrap();
-        let re_elif = Regex::new(r"^lefi\s").unwrap();
-        let re_else = Regex::new(r"^sele\s").unwrap();
-        // loops
-        let re_while = Regex::new(r"^whitl\s").unwrap();
-        let re_for = Regex::new(r"^fir\s").unwrap();
-        let re_in = Regex::new(r"^ni\s").unwrap();
-        let re_break = Regex::new(r"^brek\s").unwrap();
-        let re_continue = Regex::new(r"^cnotineu\s").unwrap();
-        // functions
-        let re_funcdef = Regex::new(r"^fnuc\s").unwrap();
-        let re_return = Regex::new(r"^retrun\s").unwrap();
+    /// Lexes the next token, skipping any whitespace before it. Returns `None` at the end of the
+    /// source.
+    fn next_token(&mut self) -> Option<Result<Token<'a>, Error>> {
+        use TokenKind::*;
 
+        let patterns = &*PATTERNS;
 
         // allows all the empty `else if`s below, necessary because they *must* be checked in that order
         #[allow(clippy::if_same_then_else)]
         while !self.remaining_source.is_empty() {
+            let mut error = None;
+
             // work out what the token at current_position is
 
             // whitespace
-            if let Some(mat) = re_whitespace.find(self.remaining_source) {
+            if let Some(mat) = patterns.whitespace.find(self.remaining_source) {
+                if self.current_column == 0 {
+                    self.current_indentation = self.indentation_width(mat.as_str());
+                }
                 self.current_token_length = mat.end();
             }
-            else if self.try_token_fixed_length(Newline, &re_newline, 1) {
+            else if let Some(mat) = patterns.newline.find(self.remaining_source) {
+                // a line holding nothing but comments is skipped entirely, newline and all, so
+                // that it's as if the comments weren't there
+                if !self.line_has_comment || self.line_has_token {
+                    self.push_token(Newline, mat.as_str(), mat.end());
+                }
+                self.line_has_comment = false;
+                self.line_has_token = false;
                 // as this is specifically a newline, increment the current line and reset the current
-                // column count
+                // column count and indentation
                 self.current_line += 1;
                 self.current_column = 0;
+                self.current_indentation = 0;
                 // additionally, set the current token length to zero to stop columns on the next line
                 // getting thrown off in self.update_positions()
                 self.current_token_length = 0;
                 // because of the above adjustment, the current position has to be updated manually
-                self.current_position += 1;
+                self.current_position += mat.end();
+            }
+            // comments
+            else if self.remaining_source.starts_with("/*") {
+                error = self.skip_comment(patterns);
             }
             // brackets
-            else if self.try_token_variable_length(LParen, &re_lparen) {}
-            else if self.try_token_variable_length(RParen, &re_rparen) {}
-            else if self.try_token_variable_length(LBrack, &re_lbrack) {}
-            else if self.try_token_variable_length(RBrack, &re_rbrack) {}
+            else if self.try_token_variable_length(LParen, &patterns.lparen) {}
+            else if self.try_token_variable_length(RParen, &patterns.rparen) {}
+            else if self.try_token_variable_length(LBrack, &patterns.lbrack) {}
+            else if self.try_token_variable_length(RBrack, &patterns.rbrack) {}
             // misc. punctuation
-            else if self.try_token_fixed_length(Comma, &re_comma, 1) {}
-            else if self.try_token_fixed_length(FullStop, &re_full_stop, 1) {}
+            else if self.try_token_fixed_length(Comma, &patterns.comma, 1) {}
+            else if self.try_token_fixed_length(FullStop, &patterns.full_stop, 1) {}
             // arithmetic operators
-            else if self.try_token_fixed_length(Plus, &re_plus, 1) {}
-            else if self.try_token_fixed_length(Minus, &re_minus, 1) {}
-            else if self.try_token_fixed_length(Mul, &re_mul, 1) {}
-            else if self.try_token_fixed_length(Div, &re_div, 1) {}
-            else if self.try_token_fixed_length(Mod, &re_mod, 1) {}
+            else if self.try_token_fixed_length(Plus, &patterns.plus, 1) {}
+            else if self.try_token_fixed_length(Minus, &patterns.minus, 1) {}
+            else if self.try_token_fixed_length(Mul, &patterns.mul, 1) {}
+            else if self.try_token_fixed_length(Div, &patterns.div, 1) {}
+            else if self.try_token_fixed_length(Mod, &patterns.modulo, 1) {}
             // relational operators
-            else if self.try_token_fixed_length(Seq, &re_seq, 3) {}
-            else if self.try_token_fixed_length(Sne, &re_sne, 3) {}
-            else if self.try_token_fixed_length(Eq, &re_eq, 2) {}
-            else if self.try_token_fixed_length(Ne, &re_ne, 2) {}
-            else if self.try_token_fixed_length(Ge, &re_ge, 2) {}
-            else if self.try_token_fixed_length(Le, &re_le, 2) {}
-            else if self.try_token_fixed_length(Gt, &re_gt, 1) {}
-            else if self.try_token_fixed_length(Lt, &re_lt, 1) {}
+            else if self.try_token_fixed_length(Seq, &patterns.seq, 3) {}
+            else if self.try_token_fixed_length(Sne, &patterns.sne, 3) {}
+            else if self.try_token_fixed_length(Eq, &patterns.eq, 2) {}
+            else if self.try_token_fixed_length(Ne, &patterns.ne, 2) {}
+            else if self.try_token_fixed_length(Ge, &patterns.ge, 2) {}
+            else if self.try_token_fixed_length(Le, &patterns.le, 2) {}
+            else if self.try_token_fixed_length(Gt, &patterns.gt, 1) {}
+            else if self.try_token_fixed_length(Lt, &patterns.lt, 1) {}
             // misc. operators
-            else if self.try_token_fixed_length(Assign, &re_assign, 1) {}
-            // keywords - control flow
-            else if self.try_token_keyword(If, &re_if, "fi", 2) {}
-            else if self.try_token_keyword(Elif, &re_elif, "lefi", 4) {}
-            else if self.try_token_keyword(Else, &re_else, "sele", 4) {}
-            // keywords - loops
-            else if self.try_token_keyword(While, &re_while, "whitl", 5) {}
-            else if self.try_token_keyword(For, &re_for, "fir", 3) {}
-            else if self.try_token_keyword(In, &re_in, "ni", 2) {}
-            else if self.try_token_keyword(Break, &re_break, "brek", 4) {}
-            else if self.try_token_keyword(Continue, &re_continue, "cnotineu", 8) {}
-            // keywords - functions
-            else if self.try_token_keyword(Funcdef, &re_funcdef, "fnuc", 4) {}
-            else if self.try_token_keyword(Return, &re_return, "retrun", 6) {}
+            else if self.try_token_fixed_length(Assign, &patterns.assign, 1) {}
+            // keywords
+            else if patterns.keywords.iter()
+                .any(|(kind, text, regex)| self.try_token_keyword(*kind, regex, text, text.len())) {}
             // datatypes
-            else if self.try_token_keyword(BoolTrue, &re_bool_true, "rtue", 4) {}
-            else if self.try_token_keyword(BoolFalse, &re_bool_false, "flase", 5) {}
-            else if self.try_token_variable_length(Number, &re_number) {}
-            else if self.try_token_fixed_length(String, &re_empty_string_1, 2) {}
-            else if self.try_token_fixed_length(String, &re_empty_string_2, 2) {}
-            else if self.try_token_variable_length(String, &re_string) {}
+            else if self.try_token_keyword(BoolTrue, &patterns.bool_true, "rtue", 4) {}
+            else if self.try_token_keyword(BoolFalse, &patterns.bool_false, "flase", 5) {}
+            else if self.try_token_variable_length(Number, &patterns.number) {}
+            else if self.try_token_fixed_length(String, &patterns.empty_string_1, 2) {}
+            else if self.try_token_fixed_length(String, &patterns.empty_string_2, 2) {}
+            else if self.try_token_variable_length(String, &patterns.string) {}
             // name
-            else if self.try_token_variable_length(Name, &re_name) {}
+            else if self.try_token_variable_length(Name, &patterns.name) {}
             else {
-                return Err(Position {
-                    line: self.current_line,
-                    start: self.current_column,
-                    length: 1,
-                })
+                let symbol = self.remaining_source.chars().next().unwrap();
+                error = Some(Error::new(
+                    ErrorKind::UnexpectedSymbol(symbol),
+                    Some(Position::new(self.current_line, self.current_column, 1)),
+                ));
+                self.current_token_length = symbol.len_utf8();
             }
 
             self.update_positions();
+            if let Some(error) = error {
+                self.trivia_start = self.current_position;
+                return Some(Err(error));
+            }
+            if let Some(token) = self.token.take() {
+                self.trivia_start = self.current_position;
+                return Some(Ok(token));
+            }
         }
-
-        Ok(&self.token_vec)
+        None
     }
 
     fn try_token_fixed_length(&mut self, token: TokenKind, regex: &Regex, length: usize) -> bool {
@@ -223,11 +535,11 @@ impl<'a> Lexer<'a> {
             false
         }
     }
-    /// Since keywords only have special meanings when alone, a whitespace character is required to
-    /// follow them. Since this throws off the newline parsing by prematurely consuming newlines,
-    /// the length of this whitespace character is not included in the length of the token (i.e. the
-    /// If token 'fi\s' has length 2 still) to prevent the lexer advancing too far too quickly. The
-    /// extra character must be chopped off in the token text.
+    /// Since keywords only have special meanings when alone, a whitespace character or the end of
+    /// the source is required to follow them. Since this throws off the newline parsing by
+    /// prematurely consuming newlines, the length of this whitespace character is not included in
+    /// the length of the token (i.e. the If token 'fi\s' has length 2 still) to prevent the lexer
+    /// advancing too far too quickly. The extra character must be chopped off in the token text.
     /// To perform this, `try_token_keyword()` takes manual input of the text and length, rather
     /// than using the regex input to calculate it.
     fn try_token_keyword(&mut self,
@@ -245,23 +557,94 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Skips the comment at the start of the remaining source, like whitespace. Returns an error
+    /// if it's never closed, or if it's closed with as many stars as it was opened with.
+    fn skip_comment(&mut self, patterns: &Patterns) -> Option<Error> {
+        self.line_has_comment = true;
+        let Some(captures) = patterns.comment.captures(self.remaining_source) else {
+            let after_opener = self.remaining_source[1..].trim_start_matches('*');
+            let stars = self.remaining_source.len() - 1 - after_opener.len();
+            self.current_token_length = self.remaining_source.len();
+            return Some(Error::new(
+                ErrorKind::UnexpectedEOF,
+                Some(Position::new(self.current_line, self.current_column, 1 + stars)),
+            ));
+        };
+        let comment = captures.get(0).unwrap().as_str();
+        let (opener, closer) = (captures.get(1).unwrap().range(), captures.get(2).unwrap().range());
+        let error = (opener.len() == closer.len()).then(|| {
+            let opener_position = Position::new(self.current_line, self.current_column, opener.end);
+            let lines = comment[..closer.start].matches('\n').count();
+            let column = match comment[..closer.start].rfind('\n') {
+                Some(newline) => comment[newline + 1..closer.start].chars().count(),
+                None => self.current_column + comment[..closer.start].chars().count(),
+            };
+            Error::new(
+                ErrorKind::Balance {
+                    opener: comment[..opener.end].to_string(),
+                    closer: comment[closer.start..].to_string(),
+                    opener_position: Some(opener_position),
+                },
+                Some(Position::new(self.current_line + lines, column, closer.len() + 1)),
+            )
+        });
+
+        // comments can span several lines, in which case the lines before the last are moved
+        // past here, leaving the last to self.update_positions()
+        match comment.rfind('\n') {
+            Some(newline) => {
+                self.current_line += comment.matches('\n').count();
+                self.current_column = 0;
+                self.current_position += newline + 1;
+                self.remaining_source = &self.source[self.current_position..];
+                self.current_token_length = comment.len() - newline - 1;
+            },
+            None => self.current_token_length = comment.len(),
+        }
+        error
+    }
+
+    /// Pushes a token spanning the next `length` bytes of the source.
     fn push_token(&mut self, token: TokenKind, token_text: &'a str, length: usize) {
+        self.line_has_token = true;
         self.current_token_length = length;
-        self.token_vec.push(Token::new(
-            token,
-            token_text,
-            self.current_line,
-            self.current_column,
-            length,
-        ))
+        let characters = self.remaining_source[..length].chars().count();
+        let trivia = if self.keep_trivia {
+            &self.source[self.trivia_start..self.current_position]
+        } else {
+            ""
+        };
+        self.token = Some(Token {
+            indentation: self.current_indentation,
+            trivia,
+            ..Token::new(token, token_text, self.current_line, self.current_column, characters)
+        });
+    }
+    /// Returns how many columns `whitespace` at the start of a line spans, with each tab going up
+    /// to the next multiple of the tab width.
+    fn indentation_width(&self, whitespace: &str) -> usize {
+        whitespace.chars().fold(0, |width, character| match character {
+            '\t' => (width / self.tab_width + 1) * self.tab_width,
+            _ => width + 1,
+        })
     }
     fn update_positions(&mut self) {
+        // columns count characters, but positions in the source count bytes
+        self.current_column += self.remaining_source[..self.current_token_length].chars().count();
         self.current_position += self.current_token_length;
-        self.current_column += self.current_token_length;
         self.remaining_source = &self.source[self.current_position..];
     }
 }
 
+/// Tokens can be taken one at a time, as they're lexed, rather than all at once with
+/// [`Lexer::lex`]. Each unexpected symbol is yielded as an error, and lexing carries on after it.
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
 
 
 #[cfg(test)]
@@ -276,7 +659,7 @@ mod tests {
                 Token::new(Newline, "\n", 1, 0, 1),
                 Token::new(Newline, "\n", 2, 0, 1),
             ],
-            *Lexer::new("\n\n").lex().unwrap(),
+            Lexer::new("\n\n").lex().unwrap(),
         )
     }
 
@@ -287,7 +670,7 @@ mod tests {
                 Token::new(LParen, "((", 1, 0, 2),
                 Token::new(LParen, "(", 1, 3, 1),
             ],
-            *Lexer::new("(( (").lex().unwrap(),
+            Lexer::new("(( (").lex().unwrap(),
         )
     }
     #[test]
@@ -297,7 +680,7 @@ mod tests {
                 Token::new(RParen, "))", 1, 0, 2),
                 Token::new(RParen, ")", 1, 3, 1),
             ],
-            *Lexer::new(")) )").lex().unwrap(),
+            Lexer::new(")) )").lex().unwrap(),
         )
     }
     #[test]
@@ -307,7 +690,7 @@ mod tests {
                 Token::new(LBrack, "[[", 1, 0, 2),
                 Token::new(LBrack, "[", 1, 3, 1),
             ],
-            *Lexer::new("[[ [").lex().unwrap(),
+            Lexer::new("[[ [").lex().unwrap(),
         )
     }
     #[test]
@@ -317,7 +700,7 @@ mod tests {
                 Token::new(RBrack, "]]", 1, 0, 2),
                 Token::new(RBrack, "]", 1, 3, 1),
             ],
-            *Lexer::new("]] ]").lex().unwrap(),
+            Lexer::new("]] ]").lex().unwrap(),
         )
     }
 
@@ -328,7 +711,7 @@ mod tests {
                 Token::new(token, token_text, 1, length, length),
                 Token::new(token, token_text, 1, 2*length + 1, length),
             ],
-            *Lexer::new(&format!("{token_text}{token_text} {token_text} ")).lex().unwrap(),
+            Lexer::new(&format!("{token_text}{token_text} {token_text} ")).lex().unwrap(),
         )
     }
     /// Adapted symbol test, for when the standard symbol test doesn't work properly due to the
@@ -340,7 +723,7 @@ mod tests {
                 Token::new(token, token_text, 1, 0, length),
                 Token::new(token, token_text, 1, length + 1, length),
             ],
-            *Lexer::new(&format!("{token_text} {token_text} ")).lex().unwrap(),
+            Lexer::new(&format!("{token_text} {token_text} ")).lex().unwrap(),
         )
     }
 
@@ -416,9 +799,22 @@ mod tests {
                 Token::new(Name, "m0r_nIngton_rul3z", 1, 0, 17),
                 Token::new(Name, "_h3lloWorld", 1, 19, 11),
             ],
-            *Lexer::new("m0r_nIngton_rul3z  _h3lloWorld").lex().unwrap(),
+            Lexer::new("m0r_nIngton_rul3z  _h3lloWorld").lex().unwrap(),
         )
     }
+    #[test]
+    fn unicode_names_and_columns() {
+        assert_eq!(
+            Ok(vec![
+                Token::new(Name, "ñame", 1, 0, 4),
+                Token::new(Assign, "=", 1, 5, 1),
+                Token::new(String, "\"\"🦀\"", 1, 7, 4),
+                Token::new(Name, "φ2", 1, 12, 2),
+            ]),
+            Lexer::new("ñame = \"\"🦀\" φ2").lex(),
+        );
+    }
+
     #[test]
     fn bool_true() {
         adapted_symbol_test(BoolTrue, "rtue", 4);
@@ -433,6 +829,25 @@ mod tests {
         adapted_symbol_test(Number, "12", 2);
         adapted_symbol_test(Number, "1.0", 3);
         adapted_symbol_test(Number, "4.234", 5);
+        adapted_symbol_test(Number, "1e6", 3);
+        adapted_symbol_test(Number, "2.5E-3", 6);
+        adapted_symbol_test(Number, "1_000_000", 9);
+        adapted_symbol_test(Number, "0xFF_ff", 7);
+        adapted_symbol_test(Number, "0b1010", 6);
+        adapted_symbol_test(Number, "0o17", 4);
+    }
+    #[test]
+    fn number_separators_only_go_between_digits() {
+        assert_eq!(
+            Ok(vec![
+                Token::new(Number, "1", 1, 0, 1),
+                Token::new(Name, "__2", 1, 1, 3),
+                Token::new(Number, "3", 1, 5, 1),
+                Token::new(Name, "e", 1, 6, 1),
+                Token::new(Minus, "-", 1, 7, 1),
+            ]),
+            Lexer::new("1__2 3e-").lex(),
+        );
     }
     #[test]
     fn string() {
@@ -448,6 +863,18 @@ mod tests {
         adapted_symbol_test(String, "'\"", 2);
     }
 
+    #[test]
+    fn keywords_can_end_the_source() {
+        assert_eq!(
+            Ok(vec![Token::new(Break, "brek", 1, 0, 4)]),
+            Lexer::new("brek").lex(),
+        );
+        assert_eq!(
+            Ok(vec![Token::new(Return, "retrun", 1, 0, 6), Token::new(Name, "x", 1, 7, 1)]),
+            Lexer::new("retrun x").lex(),
+        );
+        assert_eq!(Ok(vec![Token::new(Name, "fix", 1, 0, 3)]), Lexer::new("fix").lex());
+    }
     #[test]
     fn if_keyword() {
         adapted_symbol_test(If, "fi", 2);
@@ -486,7 +913,179 @@ mod tests {
         adapted_symbol_test(Funcdef, "fnuc", 4);
     }
     #[test]
+    fn pass_keyword() {
+        adapted_symbol_test(Pass, "pas", 3);
+    }
+    #[test]
     fn return_keyword() {
         adapted_symbol_test(Return, "retrun", 6);
     }
-}
\ No newline at end of file
+    #[test]
+    fn and_keyword() {
+        adapted_symbol_test(And, "adn", 3);
+    }
+    #[test]
+    fn or_keyword() {
+        adapted_symbol_test(Or, "ro", 2);
+    }
+
+    #[test]
+    fn position_spans_single_line() {
+        let position = Position::new(2, 3, 4);
+        assert_eq!(Location::new(2, 3), position.start);
+        assert_eq!(Location::new(2, 7), position.end);
+        assert!(!position.is_multiline());
+        assert_eq!(Position::new(2, 7, 1), position.one_past());
+    }
+    #[test]
+    fn position_joins_across_lines() {
+        let position = Position::new(1, 4, 1).to(Position::new(3, 0, 2));
+        assert_eq!(Position::span(Location::new(1, 4), Location::new(3, 2)), position);
+        assert!(position.is_multiline());
+    }
+
+    #[test]
+    fn unexpected_symbols_are_all_reported() {
+        let error = Lexer::new("x = 3 $ 4\ny = @").lex().unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedSymbol('$'), error.kind);
+        assert_eq!(Some(Position::new(1, 6, 1)), error.pos);
+        assert_eq!(
+            vec![Error::new(ErrorKind::UnexpectedSymbol('@'), Some(Position::new(2, 4, 1)))],
+            error.related,
+        );
+    }
+
+    #[test]
+    fn line_index_finds_lines_and_offsets() {
+        let source = "x = 1\ny = \"\"é\"\n\nz";
+        let lines = LineIndex::new(source);
+        assert_eq!(Some("x = 1"), lines.line(1));
+        assert_eq!(Some("y = \"\"é\""), lines.line(2));
+        assert_eq!(Some(""), lines.line(3));
+        assert_eq!(Some("z"), lines.line(4));
+        assert_eq!(None, lines.line(5));
+        assert_eq!(None, lines.line(0));
+
+        let string = Lexer::new(source).lex().unwrap()[6];
+        assert_eq!(Some(10..15), lines.range(string.position));
+        assert_eq!("\"\"é\"", &source[lines.range(string.position).unwrap()]);
+        assert_eq!(Some(source.len()), lines.offset(Location::new(4, 1)));
+        assert_eq!(Some("a"), LineIndex::new("a\r\nb").line(1));
+    }
+
+    #[test]
+    fn windows_line_endings_are_newlines() {
+        assert_eq!(
+            Ok(vec![
+                Token::new(Name, "x", 1, 0, 1),
+                Token::new(Newline, "\r\n", 1, 1, 2),
+                Token::new(Name, "y", 2, 0, 1),
+            ]),
+            Lexer::new("x\r\ny").lex(),
+        );
+    }
+
+    #[test]
+    fn tabs_indent_to_the_next_tab_stop() {
+        let indentations = |lexer: Lexer| -> Vec<usize> {
+            lexer.filter_map(Result::ok)
+                .filter(|token| token.kind == Name)
+                .map(|token| token.indentation)
+                .collect()
+        };
+        assert_eq!(vec![0, 3, 3, 6, 7], indentations(Lexer::new("a\n\tb\n \tc\n\t\td\n\t\t e")));
+
+        let mut lexer = Lexer::new("a\n\tb\n \t c");
+        lexer.set_tab_width(4);
+        assert_eq!(vec![0, 4, 5], indentations(lexer));
+    }
+
+    #[test]
+    fn trivia_is_kept_with_the_token_after_it() {
+        let source = "fi  x\r\n   \ty =\t1  ";
+        let mut lexer = Lexer::new(source);
+        lexer.set_keep_trivia(true);
+        let tokens = lexer.lex().unwrap();
+        assert_eq!(
+            vec!["", "  ", "", "   \t", " ", "\t"],
+            tokens.iter().map(|token| token.trivia).collect::<Vec<_>>(),
+        );
+        let rebuilt: std::string::String = tokens.iter().flat_map(|token| [token.trivia, token.text]).collect();
+        assert_eq!(source, rebuilt + "  ");
+
+        assert!(Lexer::new(source).lex().unwrap().iter().all(|token| token.trivia.is_empty()));
+    }
+
+    #[test]
+    fn comments_are_trivia() {
+        let source = "/** a */\nx = 1 /* b\n c **/\n  /*** d */ /** e */\n y = /** f */ 2";
+        let mut lexer = Lexer::new(source);
+        lexer.set_keep_trivia(true);
+        let tokens = lexer.lex().unwrap();
+        assert_eq!(
+            vec![Name, Assign, Number, Newline, Name, Assign, Number],
+            tokens.iter().map(|token| token.kind).collect::<Vec<_>>(),
+        );
+        // the line holding only comments is skipped, newline and all
+        assert_eq!("  /*** d */ /** e */\n ", tokens[4].trivia);
+        assert_eq!(Position::new(5, 1, 1), tokens[4].position);
+        assert_eq!(Position::new(5, 14, 1), tokens[6].position);
+
+        assert_eq!(
+            vec![
+                (1, 0, "/** a */"),
+                (2, 6, "/* b\n c **/"),
+                (4, 2, "/*** d */"),
+                (4, 12, "/** e */"),
+                (5, 5, "/** f */"),
+            ],
+            comments(source, &tokens).iter()
+                .map(|comment| (comment.start.line, comment.start.column, comment.text))
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn comments_must_be_unbalanced_and_closed() {
+        let error = Lexer::new("x = 1\n /** a\nb **/").lex().unwrap_err();
+        assert_eq!(
+            ErrorKind::Balance {
+                opener: "/**".to_string(),
+                closer: "**/".to_string(),
+                opener_position: Some(Position::new(2, 1, 3)),
+            },
+            error.kind,
+        );
+        assert_eq!(Some(Position::new(3, 2, 3)), error.pos);
+
+        let error = Lexer::new("x = 1 /*** a").lex().unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEOF, error.kind);
+        assert_eq!(Some(Position::new(1, 6, 4)), error.pos);
+    }
+
+    #[test]
+    fn tokens_can_be_taken_one_at_a_time() {
+        let mut lexer = Lexer::new("x = $ 4\n");
+        assert_eq!(Some(Ok(Token::new(TokenKind::Name, "x", 1, 0, 1))), lexer.next());
+        assert_eq!(Some(Ok(Token::new(TokenKind::Assign, "=", 1, 2, 1))), lexer.next());
+        assert_eq!(
+            Some(Err(Error::new(ErrorKind::UnexpectedSymbol('$'), Some(Position::new(1, 4, 1))))),
+            lexer.next(),
+        );
+        assert_eq!(Some(Ok(Token::new(TokenKind::Number, "4", 1, 6, 1))), lexer.next());
+        assert_eq!(Some(Ok(Token::new(TokenKind::Newline, "\n", 1, 7, 1))), lexer.next());
+        assert_eq!(None, lexer.next());
+    }
+
+    #[test]
+    fn token_table() {
+        assert_eq!(
+            "POSITION      KIND        TEXT\n\
+             1:0-1:5       Name        \"pront\"\n\
+             1:5-1:7       LParen      \"((\"\n\
+             1:7-1:8       Number      \"1\"\n\
+             1:8-1:9       RParen      \")\"\n",
+            format_tokens(&Lexer::new("pront((1)").lex().unwrap()),
+        );
+    }
+}
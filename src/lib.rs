@@ -1,6 +1,184 @@
 pub mod lexer;
 pub mod parser;
 pub mod error;
-mod value;
+pub mod value;
 pub mod runtime;
-pub mod ast;
\ No newline at end of file
+pub mod ast;
+pub mod builtins;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod coverage;
+pub mod debugger;
+pub mod diagnostics;
+pub mod embed;
+pub mod fixtures;
+pub mod grammar;
+pub mod highlight;
+pub mod hooks;
+pub mod importer;
+pub mod json;
+pub mod lsp;
+pub mod modules;
+mod random;
+pub mod scramble;
+pub mod snapshot;
+mod stack;
+pub mod symbol;
+pub mod test_runner;
+pub mod warning;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use crate::ast::{Evaluable, Executable};
+use crate::builtins::BuiltinRegistry;
+use crate::error::Error;
+use crate::lexer::{Lexer, Token};
+use crate::parser::Parser;
+use crate::runtime::Runtime;
+use crate::value::Value;
+use crate::warning::Warning;
+
+
+/// Lexes, parses, and executes `source` as a Mornington program in a fresh [`Runtime`].
+pub fn run(source: &str) -> Result<(), Error> {
+    run_in(source, &mut Runtime::new())
+}
+
+/// Lexes, parses, and executes `source` as a Mornington program in `runtime`, so that any
+/// variables and functions it defines remain available afterwards. Any tasks spawned but not yet
+/// run are run once it's finished.
+pub fn run_in(source: &str, runtime: &mut Runtime) -> Result<(), Error> {
+    execute_in(source, runtime)?;
+    runtime.finish_tasks()
+}
+
+/// Lexes, parses, and executes `source` in `runtime`, leaving any tasks it spawns for whatever's
+/// running it to finish.
+pub(crate) fn execute_in(source: &str, runtime: &mut Runtime) -> Result<(), Error> {
+    let tokens = lex_in(source, runtime)?;
+    let mut ast = Parser::new(&tokens).parse()?.optimize();
+    ast.resolve();
+    builtins::preload_data(&ast, runtime)?;
+    match ast.execute(runtime)?.escaped_error() {
+        // the parser rejects control flow outside of loops and functions, so this is only a
+        // safeguard
+        Some(kind) => Err(Error::new(kind, None)),
+        None => Ok(()),
+    }
+}
+
+/// Lexes and parses `source` without running it, returning [`Warning`]s about anything in it that's
+/// probably a mistake.
+pub fn check(source: &str) -> Result<Vec<Warning>, Error> {
+    let tokens = Lexer::new(source).lex()?;
+    let ast = Parser::new(&tokens).parse()?;
+    Ok(ast.lint(&BuiltinRegistry::with_standard_library()))
+}
+
+/// Lexes and parses `source`, then prints it back out in a canonical style with valid wrapper
+/// imbalance and indentation, keeping its comments. See
+/// [`Block::to_source_with_comments`](ast::Block::to_source_with_comments).
+pub fn format(source: &str) -> Result<String, Error> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_keep_trivia(true);
+    let tokens = lexer.lex()?;
+    let comments = lexer::comments(source, &tokens);
+    Ok(Parser::new(&tokens).parse()?.to_source_with_comments(&comments))
+}
+
+/// Lexes, parses, and evaluates `source` as a single Mornington expression in a fresh
+/// [`Runtime`], e.g.
+/// ```
+/// # use mornington::value::Value;
+/// assert_eq!(Value::Number(7.0), mornington::eval_expression("1 + 2 * 3").unwrap());
+/// ```
+pub fn eval_expression(source: &str) -> Result<Value, Error> {
+    eval_expression_in(source, &mut Runtime::new())
+}
+
+/// Lexes, parses, and evaluates `source` as a single Mornington expression in `runtime`, so that
+/// it can refer to the variables and functions defined there.
+pub fn eval_expression_in(source: &str, runtime: &mut Runtime) -> Result<Value, Error> {
+    let tokens = lex_in(source, runtime)?;
+    let expression = Parser::new(&tokens).parse_single_expression()?;
+    expression.evaluate(runtime)
+}
+
+/// Lexes `source` as `runtime` is [configured](runtime::Config) to read it.
+fn lex_in<'a>(source: &'a str, runtime: &Runtime) -> Result<Vec<Token<'a>>, Error> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_tab_width(runtime.config().tab_width);
+    lexer.lex()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::ErrorKind;
+    use super::*;
+
+    #[test]
+    fn run_executes_program() {
+        let mut runtime = Runtime::new();
+        run_in("x = 3\n y = x * 2\n", &mut runtime).unwrap();
+        assert_eq!(Value::Number(6.0), *runtime.get_variable("y").unwrap());
+    }
+
+    #[test]
+    fn run_in_writes_to_runtime_streams() {
+        let stdout = runtime::SharedBuffer::new();
+        let mut runtime = Runtime::new();
+        runtime.set_stdout(stdout.clone());
+        runtime.set_stdin("Mornington\n".as_bytes());
+        run_in("name = inptu(()\n prointl((\"Hello, \"\" + name)", &mut runtime).unwrap();
+        assert_eq!("Hello, Mornington\n\n", stdout.contents());
+    }
+
+    #[test]
+    fn run_accepts_empty_source() {
+        assert_eq!(Ok(()), run(""));
+    }
+
+    #[test]
+    fn run_reports_unexpected_symbols() {
+        assert_eq!(ErrorKind::UnexpectedSymbol('$'), run("x = 3 $ 4").unwrap_err().kind);
+    }
+
+    #[test]
+    fn run_reports_parse_errors() {
+        assert_eq!(
+            ErrorKind::Balance {
+                opener: "(".to_string(),
+                closer: ")".to_string(),
+                opener_position: Some(lexer::Position::new(1, 4, 1)),
+            },
+            run("x = (3)").unwrap_err().kind,
+        );
+    }
+
+    #[test]
+    fn run_reports_runtime_errors() {
+        assert_eq!(ErrorKind::Name("y".to_string()), run("x = y").unwrap_err().kind);
+    }
+
+    #[test]
+    fn eval_expression_evaluates() {
+        assert_eq!(
+            Value::List(vec![Value::Number(0.0), Value::Number(1.0)]),
+            eval_expression("arnge((2)").unwrap(),
+        );
+    }
+
+    #[test]
+    fn eval_expression_rejects_trailing_tokens() {
+        assert_eq!(
+            ErrorKind::UnexpectedToken(lexer::TokenKind::Assign),
+            eval_expression("1 = 2").unwrap_err().kind,
+        );
+    }
+
+    #[test]
+    fn eval_expression_rejects_empty_source() {
+        assert_eq!(ErrorKind::MissingExpression, eval_expression("").unwrap_err().kind);
+    }
+}
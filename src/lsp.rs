@@ -0,0 +1,526 @@
+//! A language server, speaking the Language Server Protocol, so that editors can show Mornington's
+//! errors and warnings as you type, describe builtins on hover, jump to function definitions, and
+//! outline a file's functions and variables.
+//!
+//! Messages are JSON-RPC, framed with `Content-Length` headers; [`serve`] reads them from one
+//! stream and writes responses to another, as `mornington lsp` does with stdin and stdout.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use crate::builtins::{self, Arity, BuiltinRegistry};
+use crate::diagnostics::{describe_error_kind, describe_warning_kind};
+use crate::error::Error;
+use crate::json::Json;
+use crate::lexer::{Lexer, Position, Token, TokenKind};
+
+
+/// The longest message body the server reads, far longer than any document worth editing, so that
+/// a corrupt `Content-Length` can't make it allocate without bound.
+pub const MAX_CONTENT_LENGTH: usize = 64 * 1024 * 1024;
+
+/// Reads messages from `input` and answers them on `output` until the client sends `exit` or
+/// closes the stream. Returns whether the client asked the server to shut down first, as it
+/// should have. A message that isn't valid JSON is answered with a parse error, and the server
+/// carries on; only broken framing, after which no more messages can be found, is an error.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(body) = read_body(&mut input)? {
+        let responses = match parse_body(body) {
+            Ok(message) => server.handle(&message),
+            Err(reason) => vec![parse_error(reason)],
+        };
+        for response in responses {
+            write_message(&mut output, &response)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(server.shut_down)
+}
+
+/// Reads a message framed with a `Content-Length` header, returning `None` at the end of the
+/// stream.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let Some(body) = read_body(input)? else {
+        return Ok(None);
+    };
+    parse_body(body).map(Some).map_err(|reason| io::Error::new(io::ErrorKind::InvalidData, reason))
+}
+
+/// Reads the body of a message framed with a `Content-Length` header, returning `None` at the end
+/// of the stream.
+fn read_body(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length"));
+    };
+    if length > MAX_CONTENT_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {length} bytes is longer than the limit of {MAX_CONTENT_LENGTH}"),
+        ));
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Parses the body of a message, returning why if it isn't valid JSON.
+fn parse_body(body: Vec<u8>) -> Result<Json, String> {
+    let body = String::from_utf8(body).map_err(|error| error.to_string())?;
+    Json::parse(&body).map_err(|error| error.to_string())
+}
+
+/// Returns the response to a message that couldn't be parsed. Its id couldn't be read, so the
+/// response has none.
+fn parse_error(reason: String) -> Json {
+    Json::object([
+        ("jsonrpc", Json::String("2.0".to_string())),
+        ("id", Json::Null),
+        ("error", Json::object([
+            ("code", Json::Number(PARSE_ERROR)),
+            ("message", Json::String(reason)),
+        ])),
+    ])
+}
+
+/// Writes `message` framed with a `Content-Length` header.
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+
+/// The state of a language server: the documents the client has open, and how far through its
+/// lifecycle it is.
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: HashMap<String, String>,
+    builtins: BuiltinRegistry,
+    shut_down: bool,
+    exited: bool,
+}
+impl Server {
+    pub fn new() -> Server {
+        Server { builtins: BuiltinRegistry::with_standard_library(), ..Server::default() }
+    }
+
+    /// Handles a request or notification from the client, returning the messages to send back:
+    /// a response for requests, and diagnostics whenever a document changes.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let Ok(id) = message.get("id") else {
+            return self.notification(method, params);
+        };
+
+        let result = match method {
+            _ if self.shut_down => Err((INVALID_REQUEST, "the server has been shut down".to_string())),
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            },
+            "textDocument/hover" => Ok(self.hover(params)),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/documentSymbol" => Ok(self.symbols(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{method}`"))),
+        };
+        let outcome = match result {
+            Ok(result) => ("result", result),
+            Err((code, message)) => ("error", Json::object([
+                ("code", Json::Number(code)),
+                ("message", Json::String(message)),
+            ])),
+        };
+        vec![Json::object([("jsonrpc", Json::String("2.0".to_string())), ("id", id.clone()), outcome])]
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        if method == "exit" {
+            self.exited = true;
+        }
+        let Ok(uri) = params.get("textDocument").and_then(|document| document.get("uri")?.as_str()) else {
+            return Vec::new();
+        };
+        let uri = uri.to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let Ok(text) = params.get("textDocument").and_then(|document| document.get("text")?.as_str())
+                else {
+                    return Vec::new();
+                };
+                self.documents.insert(uri.clone(), text.to_string());
+            },
+            "textDocument/didChange" => {
+                // only whole-document changes are asked for, so the last has the latest text
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or_default();
+                let Some(Ok(text)) = changes.last().map(|change| change.get("text").and_then(Json::as_str))
+                else {
+                    return Vec::new();
+                };
+                self.documents.insert(uri.clone(), text.to_string());
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+            },
+            _ => return Vec::new(),
+        }
+        vec![self.publish_diagnostics(&uri)]
+    }
+
+    /// Checks the document `uri`, reporting its errors and warnings.
+    fn publish_diagnostics(&self, uri: &str) -> Json {
+        let mut diagnostics = Vec::new();
+        if let Some(text) = self.documents.get(uri) {
+            match crate::check(text) {
+                Ok(warnings) => for warning in warnings {
+                    diagnostics.push(diagnostic(
+                        text, Some(warning.pos), WARNING, warning.kind.code(), describe_warning_kind(&warning.kind),
+                    ));
+                },
+                Err(error) => for error in std::iter::once(&error).chain(&error.related) {
+                    diagnostics.push(error_diagnostic(text, error));
+                },
+            }
+        }
+        Json::object([
+            ("jsonrpc", Json::String("2.0".to_string())),
+            ("method", Json::String("textDocument/publishDiagnostics".to_string())),
+            ("params", Json::object([
+                ("uri", Json::String(uri.to_string())),
+                ("diagnostics", Json::Array(diagnostics)),
+            ])),
+        ])
+    }
+
+    /// Describes the builtin or function whose name is under the cursor.
+    fn hover(&self, params: &Json) -> Json {
+        let Some((text, token)) = self.token_at(params) else {
+            return Json::Null;
+        };
+        let contents = match builtins::describe(token.text) {
+            Some(description) => {
+                let arity = self.builtins.arity(token.text).map(describe_arity).unwrap_or_default();
+                format!("```mornington\n{}((...)\n```\nBuiltin{arity}. {description}", token.text)
+            },
+            None => match definitions(text).into_iter().find(|(name, _, _)| name == token.text) {
+                Some((_, _, position)) => {
+                    format!("```mornington\n{}\n```", &text.lines().nth(position.start.line - 1).unwrap().trim())
+                },
+                None => return Json::Null,
+            },
+        };
+        Json::object([
+            ("contents", Json::object([
+                ("kind", Json::String("markdown".to_string())),
+                ("value", Json::String(contents)),
+            ])),
+            ("range", range(text, token.position)),
+        ])
+    }
+
+    /// Finds where the function whose name is under the cursor is defined.
+    fn definition(&self, params: &Json) -> Json {
+        let Some((text, token)) = self.token_at(params) else {
+            return Json::Null;
+        };
+        definitions(text).into_iter()
+            .find(|(name, kind, _)| name == token.text && *kind == FUNCTION)
+            .map_or(Json::Null, |(_, _, position)| Json::object([
+                ("uri", params.get("textDocument").and_then(|document| document.get("uri")).unwrap().clone()),
+                ("range", range(text, position)),
+            ]))
+    }
+
+    /// Lists the functions and variables defined in the document.
+    fn symbols(&self, params: &Json) -> Json {
+        let Some(text) = self.document(params) else {
+            return Json::Null;
+        };
+        Json::Array(definitions(text).into_iter().map(|(name, kind, position)| Json::object([
+            ("name", Json::String(name)),
+            ("kind", Json::Number(kind)),
+            ("range", range(text, position)),
+            ("selectionRange", range(text, position)),
+        ])).collect())
+    }
+
+    fn document(&self, params: &Json) -> Option<&str> {
+        let uri = params.get("textDocument").ok()?.get("uri").ok()?.as_str().ok()?;
+        self.documents.get(uri).map(String::as_str)
+    }
+
+    /// Finds the name under the position in `params`, along with the document it's in.
+    fn token_at<'a>(&'a self, params: &Json) -> Option<(&'a str, Token<'a>)> {
+        let text = self.document(params)?;
+        let position = params.get("position").ok()?;
+        let line = position.get("line").ok()?.as_usize().ok()? + 1;
+        let character = position.get("character").ok()?.as_usize().ok()?;
+        let column = character_column(text.lines().nth(line - 1)?, character);
+        // lexing stops once it's past the line, and symbols it can't lex elsewhere don't matter
+        Lexer::new(text)
+            .filter_map(Result::ok)
+            .take_while(|token| token.position.start.line <= line)
+            .find(|token| {
+                token.kind == TokenKind::Name && token.position.start.line == line
+                    && token.position.start.column <= column && column <= token.position.end.column
+            })
+            .map(|token| (text, token))
+    }
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: f64 = -32700.0;
+const INVALID_REQUEST: f64 = -32600.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+// diagnostic severities
+const ERROR: f64 = 1.0;
+const WARNING: f64 = 2.0;
+// symbol kinds
+const FUNCTION: f64 = 12.0;
+const VARIABLE: f64 = 13.0;
+
+fn capabilities() -> Json {
+    Json::object([
+        ("capabilities", Json::object([
+            // whole documents are sent on every change
+            ("textDocumentSync", Json::Number(1.0)),
+            ("hoverProvider", Json::Bool(true)),
+            ("definitionProvider", Json::Bool(true)),
+            ("documentSymbolProvider", Json::Bool(true)),
+        ])),
+        ("serverInfo", Json::object([
+            ("name", Json::String("mornington".to_string())),
+            ("version", Json::String(env!("CARGO_PKG_VERSION").to_string())),
+        ])),
+    ])
+}
+
+fn describe_arity(arity: Arity) -> String {
+    match arity {
+        Arity::Exact(1) => ", taking 1 argument".to_string(),
+        Arity::Exact(count) => format!(", taking {count} arguments"),
+        Arity::Between(min, max) => format!(", taking {min} to {max} arguments"),
+        Arity::Variadic => ", taking any number of arguments".to_string(),
+    }
+}
+
+fn error_diagnostic(text: &str, error: &Error) -> Json {
+    diagnostic(text, error.pos, ERROR, error.kind.code(), describe_error_kind(&error.kind))
+}
+
+fn diagnostic(text: &str, position: Option<Position>, severity: f64, code: &str, message: String) -> Json {
+    Json::object([
+        ("range", position.map_or_else(|| range(text, Position::new(1, 0, 0)), |position| range(text, position))),
+        ("severity", Json::Number(severity)),
+        ("code", Json::String(code.to_string())),
+        ("source", Json::String("mornington".to_string())),
+        ("message", Json::String(message)),
+    ])
+}
+
+/// Every function and top-level variable defined in `text`, in order, with its symbol kind and
+/// the position of its name. Worked out from the tokens, so that definitions are still found in
+/// documents that don't parse.
+fn definitions(text: &str) -> Vec<(String, f64, Position)> {
+    let Ok(tokens) = Lexer::new(text).lex() else {
+        return Vec::new();
+    };
+    let mut definitions: Vec<(String, f64, Position)> = Vec::new();
+    let mut start_of_line = true;
+    for (index, token) in tokens.iter().enumerate() {
+        let next = tokens.get(index + 1);
+        let definition = match (token.kind, next) {
+            (TokenKind::Funcdef, Some(name)) if name.kind == TokenKind::Name => Some((name, FUNCTION)),
+            (TokenKind::Name, Some(Token { kind: TokenKind::Assign, .. }))
+                if start_of_line && token.indentation < 3 => Some((token, VARIABLE)),
+            _ => None,
+        };
+        if let Some((name, kind)) = definition {
+            if !definitions.iter().any(|(existing, _, _)| existing == name.text) {
+                definitions.push((name.text.to_string(), kind, name.position));
+            }
+        }
+        start_of_line = token.kind == TokenKind::Newline;
+    }
+    definitions
+}
+
+/// Converts a position to an LSP range, whose lines count from 0 and whose characters are UTF-16
+/// code units.
+fn range(text: &str, position: Position) -> Json {
+    let location = |line: usize, column: usize| {
+        let line_text = text.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+        let units: usize = line_text.chars().take(column).map(char::len_utf16).sum();
+        Json::object([
+            ("line", Json::Number(line.saturating_sub(1) as f64)),
+            ("character", Json::Number(units as f64)),
+        ])
+    };
+    Json::object([
+        ("start", location(position.start.line, position.start.column)),
+        ("end", location(position.end.line, position.end.column)),
+    ])
+}
+
+/// Converts an LSP character offset, in UTF-16 code units, to a column in `line`.
+fn character_column(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (column, character_here) in line.chars().enumerate() {
+        if units >= character {
+            return column;
+        }
+        units += character_here.len_utf16();
+    }
+    line.chars().count()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///prog.mron";
+
+    fn request(id: f64, method: &str, params: Json) -> Json {
+        Json::object([("id", Json::Number(id)), ("method", Json::String(method.to_string())), ("params", params)])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object([("method", Json::String(method.to_string())), ("params", params)])
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Json> {
+        server.handle(&notification("textDocument/didOpen", Json::object([
+            ("textDocument", Json::object([
+                ("uri", Json::String(URI.to_string())),
+                ("text", Json::String(text.to_string())),
+            ])),
+        ])))
+    }
+
+    fn at(line: f64, character: f64) -> Json {
+        Json::object([
+            ("textDocument", Json::object([("uri", Json::String(URI.to_string()))])),
+            ("position", Json::object([("line", Json::Number(line)), ("character", Json::Number(character))])),
+        ])
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::new();
+        let messages = open(&mut server, "x = 1\n y = ((2");
+        let diagnostics = messages[0].get("params").unwrap().get("diagnostics").unwrap().as_array().unwrap();
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("M0006", diagnostics[0].get("code").unwrap().as_str().unwrap());
+        assert_eq!(
+            r#"{"start":{"line":1,"character":5},"end":{"line":1,"character":8}}"#,
+            diagnostics[0].get("range").unwrap().to_string(),
+        );
+
+        let messages = open(&mut server, "x = 1");
+        let diagnostics = messages[0].get("params").unwrap().get("diagnostics").unwrap().as_array().unwrap();
+        assert_eq!("Unused Variable: `x`", diagnostics[0].get("message").unwrap().as_str().unwrap());
+    }
+
+    #[test]
+    fn hovers_over_builtins_and_functions() {
+        let mut server = Server::new();
+        open(&mut server, "fnuc dobule((x)\n   retrun x * 2\npront((dobule((2) )");
+        let hover = |server: &mut Server, character| {
+            let response = server.handle(&request(1.0, "textDocument/hover", at(2.0, character)));
+            response[0].get("result").unwrap().get("contents").unwrap().get("value").unwrap().as_str().unwrap().to_string()
+        };
+        assert!(hover(&mut server, 2.0).starts_with(
+            "```mornington\npront((...)\n```\nBuiltin, taking any number of arguments. Prints"
+        ));
+        assert_eq!("```mornington\nfnuc dobule((x)\n```", hover(&mut server, 9.0));
+    }
+
+    #[test]
+    fn goes_to_definitions() {
+        let mut server = Server::new();
+        open(&mut server, "fnuc dobule((x)\n   retrun x * 2\npront((dobule((2) )");
+        let response = server.handle(&request(2.0, "textDocument/definition", at(2.0, 9.0)));
+        assert_eq!(
+            format!(r#"{{"jsonrpc":"2.0","id":2,"result":{{"uri":"{URI}","range":{{"start":{{"line":0,"character":5}},"end":{{"line":0,"character":11}}}}}}}}"#),
+            response[0].to_string(),
+        );
+    }
+
+    #[test]
+    fn lists_symbols() {
+        let mut server = Server::new();
+        open(&mut server, "fnuc f((x)\n   y = x\ncoutn = f((1)\n coutn = 2");
+        let response = server.handle(&request(3.0, "textDocument/documentSymbol", at(0.0, 0.0)));
+        let symbols: Vec<(String, f64)> = response[0].get("result").unwrap().as_array().unwrap().iter()
+            .map(|symbol| (
+                symbol.get("name").unwrap().as_str().unwrap().to_string(),
+                symbol.get("kind").unwrap().as_f64().unwrap(),
+            ))
+            .collect();
+        assert_eq!(vec![("f".to_string(), FUNCTION), ("coutn".to_string(), VARIABLE)], symbols);
+    }
+
+    #[test]
+    fn frames_messages_and_shuts_down() {
+        let message = |json: Json| {
+            let mut framed = Vec::new();
+            write_message(&mut framed, &json).unwrap();
+            framed
+        };
+        let mut input = message(request(1.0, "initialize", Json::object([])));
+        input.extend(message(request(2.0, "shutdown", Json::Null)));
+        input.extend(message(notification("exit", Json::Null)));
+        let mut output = Vec::new();
+        assert!(serve(io::Cursor::new(input), &mut output).unwrap());
+
+        let mut output = io::Cursor::new(output);
+        let initialized = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(
+            &Json::Bool(true),
+            initialized.get("result").unwrap().get("capabilities").unwrap().get("hoverProvider").unwrap(),
+        );
+        assert_eq!(r#"{"jsonrpc":"2.0","id":2,"result":null}"#, read_message(&mut output).unwrap().unwrap().to_string());
+        assert_eq!(None, read_message(&mut output).unwrap());
+    }
+
+    #[test]
+    fn answers_malformed_messages_and_carries_on() {
+        let mut input = b"Content-Length: 5\r\n\r\n{oops".to_vec();
+        write_message(&mut input, &request(1.0, "shutdown", Json::Null)).unwrap();
+        let mut output = Vec::new();
+        assert!(serve(io::Cursor::new(input), &mut output).unwrap());
+
+        let mut output = io::Cursor::new(output);
+        let error = read_message(&mut output).unwrap().unwrap();
+        assert_eq!(&Json::Null, error.get("id").unwrap());
+        assert_eq!(&Json::Number(PARSE_ERROR), error.get("error").unwrap().get("code").unwrap());
+        assert_eq!(r#"{"jsonrpc":"2.0","id":1,"result":null}"#, read_message(&mut output).unwrap().unwrap().to_string());
+    }
+
+    #[test]
+    fn rejects_overlong_messages() {
+        let input = format!("Content-Length: {}\r\n\r\n", MAX_CONTENT_LENGTH + 1);
+        let error = serve(io::Cursor::new(input), Vec::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+}
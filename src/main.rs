@@ -1,161 +1,354 @@
-use std::{env, fs};
-use mornington::ast::Executable;
-use mornington::error::{Error, ErrorKind};
-use mornington::lexer::{Lexer, Position, TokenKind};
+mod cli;
+mod console_debugger;
+mod repl;
+
+use std::{env, fs, io, thread};
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use mornington::ast::Block;
+use mornington::coverage::Coverage;
+use mornington::diagnostics::Diagnostic;
+use mornington::error::Error;
+use mornington::highlight::highlight;
+use mornington::json::ToJson;
+use mornington::lexer::{self, Lexer, Token};
+use mornington::modules;
 use mornington::parser::Parser;
-use mornington::runtime::Runtime;
+use mornington::runtime::{RecordingReader, Runtime};
+use mornington::scramble::rescramble;
+use crate::cli::{Command, Mode, Options, Source};
+use crate::console_debugger::ConsoleDebugger;
 
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+/// How often `watch` checks whether the file has changed.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-    if args.len() == 1 {
-        eprintln!("Error: no file passed for execution. Supply one using\n\
-            \tmornington <filename>\n\
-            Terminating..."
-        );
-        return;
-    } else if args.len() > 2 {
-        println!("Warning: more than one file passed for execution. \
-            All but the first will be disregarded."
-        );
-    }
 
-    let source_filepath = &args[1];
-    let source = match fs::read_to_string(source_filepath) {
-        Ok(source) => source,
-        Err(_) => {
-            eprintln!("Error: unable to read file `{source_filepath}`.\nTerminating...");
-            return;
+fn main() -> ExitCode {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("Mornington {}\n\n{}", cli::VERSION, cli::USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Ok(Command::Version) => {
+            println!("mornington {}", cli::VERSION);
+            return ExitCode::SUCCESS;
+        },
+        Err(message) => {
+            eprintln!("Error: {message}.\n{}\nTerminating...", cli::USAGE);
+            return ExitCode::FAILURE;
         }
     };
-
-    let mut lexer = Lexer::new(&source);
-    let tokens = match lexer.lex() {
-        Ok(tokens) => tokens,
-        Err(position) => {
-            eprintln!("Error: Unexpected Symbol");
-            print_error_position(&source, position);
-            return;
-        }
+    let source = match &options.source {
+        Source::Inline(source) => source.clone(),
+        Source::File(source_filepath) => match fs::read_to_string(source_filepath) {
+            Ok(source) => source,
+            Err(_) => {
+                eprintln!("Error: unable to read file `{source_filepath}`.\nTerminating...");
+                return ExitCode::FAILURE;
+            }
+        },
     };
+    let reporter = Reporter { json: options.json_diagnostics, colour: options.colour.enabled() };
 
-    if tokens.is_empty() {
-        return;
+    if options.tokens {
+        return match lex(&source, options.config.tab_width) {
+            Ok(tokens) => {
+                print!("{}", lexer::format_tokens(&tokens));
+                ExitCode::SUCCESS
+            },
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                ExitCode::FAILURE
+            },
+        };
+    }
+    if options.highlight {
+        return match highlight(&source) {
+            Ok(spans) => {
+                println!("{}", spans.to_json());
+                ExitCode::SUCCESS
+            },
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                ExitCode::FAILURE
+            },
+        };
+    }
+    if options.ast {
+        return match parse(&source, options.config.tab_width) {
+            Ok(ast) => {
+                print!("{}", ast.dump());
+                ExitCode::SUCCESS
+            },
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                ExitCode::FAILURE
+            },
+        };
     }
 
-    let mut parser = Parser::new(tokens.clone());
-    let ast = match parser.parse() {
-        Ok(ast) => ast,
-        Err(Error { pos, kind}) => {
-            eprintln!("Error: {}", error_kind_to_print_name(kind));
-            if let Some(position) = pos {
-                print_error_position(&source, position);
-            }
-            return;
+    match (options.mode, &options.source) {
+        (Mode::Fmt, source_file) => match mornington::format(&source) {
+            Ok(formatted) => match source_file {
+                Source::Inline(_) => print!("{formatted}"),
+                Source::File(source_filepath) => if fs::write(source_filepath, formatted).is_err() {
+                    eprintln!("Error: unable to write file `{source_filepath}`.\nTerminating...");
+                    return ExitCode::FAILURE;
+                },
+            },
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                return ExitCode::FAILURE;
+            },
+        },
+        (Mode::Grammar, _) => print!("{}", mornington::grammar::grammar()),
+        (Mode::Lsp, _) => return match mornington::lsp::serve(io::stdin().lock(), io::stdout()) {
+            Ok(true) => ExitCode::SUCCESS,
+            // the protocol says to exit with an error if the client didn't ask to shut down first
+            Ok(false) => ExitCode::FAILURE,
+            Err(error) => {
+                eprintln!("Error: {error}.");
+                ExitCode::FAILURE
+            },
+        },
+        (Mode::Import, _) => match mornington::importer::import(&source) {
+            Ok(imported) => print!("{imported}"),
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                return ExitCode::FAILURE;
+            },
+        },
+        (Mode::Rescramble, _) => match rescramble(&source, options.seed.unwrap_or_else(random_seed)) {
+            Ok(rescrambled) => print!("{rescrambled}"),
+            Err(error) => {
+                reporter.report(&Diagnostic::from_error(&error), &source);
+                return ExitCode::FAILURE;
+            },
+        },
+        (Mode::Watch, Source::File(source_filepath)) => watch(source_filepath, &options, &reporter),
+        (Mode::Test, _) => return test(&source, &options, &reporter),
+        _ => return execute(&source, &options, &reporter),
+    }
+    ExitCode::SUCCESS
+}
+
+/// Warns about the program, then runs it, and then carries on in the REPL if that's the mode.
+/// Fails if the program, or any of the modules it needs, did.
+fn execute(source: &str, options: &Options, reporter: &Reporter) -> ExitCode {
+    if !options.quiet {
+        // any errors are reported when the program is run
+        for warning in mornington::check(source).unwrap_or_default() {
+            reporter.report(&Diagnostic::from_warning(&warning), source);
         }
+    }
+
+    let Some(mut runtime) = new_runtime(options, reporter) else {
+        return ExitCode::FAILURE;
     };
+    if let Err(message) = redirect_stdin(&mut runtime, options) {
+        eprintln!("Error: {message}.");
+        return ExitCode::FAILURE;
+    }
+    runtime.set_coverage(options.coverage.is_some());
+    if options.mode == Mode::Debug {
+        runtime.set_debugger(ConsoleDebugger::new());
+    }
+    let start = Instant::now();
+    let result = mornington::run_in(source, &mut runtime);
+    let elapsed = start.elapsed();
+    if let Err(error) = &result {
+        reporter.report(&Diagnostic::from_error(error), source);
+    }
 
-    match ast.execute(&mut Runtime::new()) {
-        Ok(_) => {},
-        Err(Error { pos, kind}) => {
-            eprintln!("Error: {}", error_kind_to_print_name(kind));
-            if let Some(position) = pos {
-                print_error_position(&source, position);
-            }
-        }
+    if options.time {
+        eprintln!("Time: {elapsed:.2?}");
+    }
+    if let (Some(report_filepath), Some(coverage)) = (&options.coverage, runtime.coverage()) {
+        write_coverage(report_filepath, coverage, source, &options.source, reporter);
+    }
+    if options.stats {
+        let stats = runtime.stats();
+        eprintln!("Statements executed: {}", stats.statements);
+        eprintln!("Peak scope depth: {}", stats.peak_scope_depth);
+        eprintln!("Allocations: {}", stats.allocations);
+        eprintln!("Peak memory: {} bytes", stats.peak_memory);
+    }
+    if options.mode == Mode::Repl {
+        repl::run(runtime, reporter);
+    }
+    if result.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Writes the coverage report to the file, in the `lcov` format if the file's extension is
+/// `.info` or `.lcov`, or as annotated source otherwise.
+fn write_coverage(
+    report_filepath: &str,
+    coverage: &Coverage,
+    source: &str,
+    source_file: &Source,
+    reporter: &Reporter,
+) {
+    let source_filepath = match source_file {
+        Source::File(source_filepath) => source_filepath.as_str(),
+        Source::Inline(_) => "<inline>",
+    };
+    let report = if report_filepath.ends_with(".info") || report_filepath.ends_with(".lcov") {
+        coverage.lcov_report(source, source_filepath)
+    } else {
+        coverage.text_report(source)
     };
+    match report {
+        Ok(report) => if fs::write(report_filepath, report).is_err() {
+            eprintln!("Error: unable to write file `{report_filepath}`.");
+        },
+        Err(error) => reporter.report(&Diagnostic::from_error(&error), source),
+    }
 }
 
+/// Runs each test function in the program in a fresh runtime, printing whether it passed, then
+/// any failures and a summary. Fails if any test did, or if the program couldn't be run.
+fn test(source: &str, options: &Options, reporter: &Reporter) -> ExitCode {
+    // check the modules load before running any tests, so that errors in them are only reported
+    // once
+    if new_runtime(options, reporter).is_none() {
+        return ExitCode::FAILURE;
+    }
+    let results = mornington::test_runner::run_tests(source, || {
+        new_runtime(options, reporter).unwrap_or_default()
+    });
+    let results = match results {
+        Ok(results) => results,
+        Err(error) => {
+            reporter.report(&Diagnostic::from_error(&error), source);
+            return ExitCode::FAILURE;
+        }
+    };
 
-fn print_error_position(source: &String, position: Position) {
-    let margin_width = (source.len().ilog10() + 2) as usize;
-    let source_line = source.lines().nth(position.line - 1).unwrap();
-    println!("{line_number:>margin_width$} | {source_line}", line_number=position.line);
-    println!("{0:>margin_width$} | {0:>start$}{indicator}",
-             "",
-             indicator="^".repeat(position.length),
-             start=position.start);
-    println!("{0:>margin_width$} | {0:>start$}here",
-             "",
-             start=position.start);
+    for result in &results {
+        println!("test {} ... {}", result.name, if result.passed() { "ok" } else { "FAILED" });
+    }
+    let failures: Vec<_> = results.iter()
+        .filter_map(|result| Some((&result.name, result.failure.as_ref()?)))
+        .collect();
+    for (name, error) in &failures {
+        eprintln!("\n`{name}` failed:");
+        reporter.report(&Diagnostic::from_error(error), source);
+    }
+    let passed = results.len() - failures.len();
+    let outcome = if failures.is_empty() { "ok" } else { "FAILED" };
+    println!("\ntest result: {outcome}. {passed} passed; {} failed", failures.len());
+    if failures.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
 }
 
-fn error_kind_to_print_name(kind: ErrorKind) -> String {
-    match kind {
-        ErrorKind::Balance { opener, closer } => {
-            format!("Wrapper Balance: closing `{closer}` balances opening `{opener}`")
-        }
-        ErrorKind::UnexpectedToken(kind) => {
-            format!("Unexpected Token: `{}`", token_kind_to_print_name(kind))
+/// Creates a runtime with the prelude and modules loaded into it, or returns `None` if any of
+/// them couldn't be, having reported why.
+fn new_runtime(options: &Options, reporter: &Reporter) -> Option<Runtime> {
+    let mut runtime = Runtime::with_config(options.config.clone());
+    // the prelude and modules are read afresh each time, so that `watch` picks up changes to
+    // them too
+    let prelude = modules::find_prelude(&modules::search_path());
+    let module_filepaths = prelude.iter().map(|path| path.display().to_string())
+        .chain(options.modules.iter().cloned());
+    for module_filepath in module_filepaths {
+        if !load_module(&module_filepath, &mut runtime, reporter) {
+            return None;
         }
-        ErrorKind::UnexpectedEOF => {"Unexpected End Of File".to_string()}
-        ErrorKind::MissingToken(kind) => {
-            format!("Missing Token: expected `{}`", token_kind_to_print_name(kind))
-        }
-        ErrorKind::MissingExpression => {"Missing Expression".to_string()}
-        ErrorKind::InvalidFormatFlag { flag, specifier_num } => {
-            format!("Invalid Sting Format Flag: `{flag}` (flag number {specifier_num})")
-        }
-        ErrorKind::IncorrectNumberOfFormatStringArguments { expected, received } => {
-            format!("Incorrect Number Of Format String Arguments: \
-                     expected {expected}, got {received}")
-        }
-        ErrorKind::Name(name) => {
-            format!("Name Not Found: `{name}`")
+    }
+    Some(runtime)
+}
+
+/// Runs a module in the runtime, returning whether it ran successfully.
+fn load_module(module_filepath: &str, runtime: &mut Runtime, reporter: &Reporter) -> bool {
+    let module = match fs::read_to_string(module_filepath) {
+        Ok(module) => module,
+        Err(_) => {
+            eprintln!("Error: unable to read module `{module_filepath}`.");
+            return false;
         }
-        ErrorKind::ConsistentIndentation { previous_indentation } => {
-            format!("Consistent Indentation: \
-                     indentation consistent with previous line at depth {previous_indentation}")
+    };
+    match mornington::run_in(&module, runtime) {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("Error in module `{module_filepath}`:");
+            reporter.report(&Diagnostic::from_error(&error), &module);
+            false
         }
-        ErrorKind::Signature { function_name, expected_args, passed_args } => {
-            format!("Function Signature: function `{function_name}` \
-                     takes {expected_args} arguments but {passed_args} were passed")
+    }
+}
+
+/// Points the runtime's stdin at the `--replay` file, or records it to the `--record` file.
+fn redirect_stdin(runtime: &mut Runtime, options: &Options) -> Result<(), String> {
+    if let Some(replay_filepath) = &options.replay {
+        let replay = File::open(replay_filepath)
+            .map_err(|_| format!("unable to read file `{replay_filepath}`"))?;
+        runtime.set_stdin(BufReader::new(replay));
+    }
+    if let Some(record_filepath) = &options.record {
+        let recording = File::create(record_filepath)
+            .map_err(|_| format!("unable to write file `{record_filepath}`"))?;
+        runtime.set_stdin(RecordingReader::new(BufReader::new(io::stdin()), recording));
+    }
+    Ok(())
+}
+
+/// Runs the file, then clears the screen and runs it again every time it's modified, until the
+/// interpreter is interrupted. The file is polled, since the standard library has no way of
+/// being notified of changes.
+fn watch(source_filepath: &str, options: &Options, reporter: &Reporter) {
+    let mut last_modified = None;
+    loop {
+        let modified = fs::metadata(source_filepath).and_then(|metadata| metadata.modified()).ok();
+        if modified != last_modified {
+            last_modified = modified;
+            // clear the screen and move the cursor back to the top
+            print!("\x1b[2J\x1b[H");
+            match fs::read_to_string(source_filepath) {
+                Ok(source) => {
+                    execute(&source, options, reporter);
+                },
+                Err(_) => eprintln!("Error: unable to read file `{source_filepath}`."),
+            }
+            println!("\nWatching `{source_filepath}` for changes...");
         }
-        ErrorKind::Input => {"Could Not Read Stdin".to_string()}
-        ErrorKind::Break | ErrorKind::Continue | ErrorKind::Return(_)
-            => panic!("Non-error propagated to interface")
+        thread::sleep(POLL_INTERVAL);
     }
 }
 
+/// Lexes the source, counting a tab in indentation as `tab_width` columns.
+fn lex(source: &str, tab_width: usize) -> Result<Vec<Token<'_>>, Error> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_tab_width(tab_width);
+    lexer.lex()
+}
+
+/// Lexes and parses the source without running it.
+fn parse(source: &str, tab_width: usize) -> Result<Block, Error> {
+    let tokens = lex(source, tab_width)?;
+    Parser::new(&tokens).parse()
+}
+
+/// Picks a seed to rescramble with when none was given, from the time.
+fn random_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+}
 
-fn token_kind_to_print_name(kind: TokenKind) -> String {
-    match kind {
-        TokenKind::Newline   => {"newline"}
-        TokenKind::LParen    => {"left parenthesis"}
-        TokenKind::RParen    => {"right parenthesis"}
-        TokenKind::LBrack    => {"left bracket"}
-        TokenKind::RBrack    => {"right bracket"}
-        TokenKind::Comma     => {"comma"}
-        TokenKind::FullStop  => {"full stop"}
-        TokenKind::Plus      => {"plus"}
-        TokenKind::Minus     => {"minus"}
-        TokenKind::Mul       => {"star"}
-        TokenKind::Div       => {"forward slash"}
-        TokenKind::Mod       => {"percent sign"}
-        TokenKind::Eq        => {"equal"}
-        TokenKind::Ne        => {"not equal"}
-        TokenKind::Seq       => {"strict equal"}
-        TokenKind::Sne       => {"strict not equal"}
-        TokenKind::Gt        => {"greater than"}
-        TokenKind::Lt        => {"less than"}
-        TokenKind::Ge        => {"greater than or equal to"}
-        TokenKind::Le        => {"less than or equal to"}
-        TokenKind::Assign    => {"assign"}
-        TokenKind::If        => {"fi"}
-        TokenKind::Elif      => {"lefi"}
-        TokenKind::Else      => {"sele"}
-        TokenKind::While     => {"whitl"}
-        TokenKind::For       => {"fir"}
-        TokenKind::In        => {"ni"}
-        TokenKind::Break     => {"brek"}
-        TokenKind::Continue  => {"cnotineu"}
-        TokenKind::Funcdef   => {"fnuc"}
-        TokenKind::Return    => {"retrun"}
-        TokenKind::BoolTrue  => {"rtue"}
-        TokenKind::BoolFalse => {"flase"}
-        TokenKind::Number    => {"nmu"}
-        TokenKind::String    => {"sting"}
-        TokenKind::Name      => {"name"}
-    }.to_string()
-}
\ No newline at end of file
+/// Prints diagnostics to stderr, either for people to read or as lines of JSON.
+struct Reporter {
+    json: bool,
+    colour: bool,
+}
+impl Reporter {
+    fn report(&self, diagnostic: &Diagnostic, source: &str) {
+        if self.json {
+            eprintln!("{}", diagnostic.to_json());
+        } else if self.colour {
+            eprint!("{}", diagnostic.render_in_colour(source));
+        } else {
+            eprint!("{}", diagnostic.render(source));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+
+/// The environment variable listing the directories that modules are looked for in, separated
+/// as `PATH` is on the platform (by `:` on Unix, and `;` on Windows).
+pub const PATH_VARIABLE: &str = "MORNINGTON_PATH";
+
+/// The name of the file that's run before every program if it's found in one of the directories
+/// in the search path.
+pub const PRELUDE: &str = "prelude.mron";
+
+
+/// Returns the directories listed in `MORNINGTON_PATH`, in order.
+pub fn search_path() -> Vec<PathBuf> {
+    env::var_os(PATH_VARIABLE)
+        .map(|paths| env::split_paths(&paths).collect())
+        .unwrap_or_default()
+}
+
+/// Works out which file `imoprt((name)` refers to: `name` itself if it exists, relative to the
+/// current directory, otherwise the first file called `name` in one of the `search_path`
+/// directories.
+pub fn resolve(name: &str, search_path: &[PathBuf]) -> Option<PathBuf> {
+    let path = Path::new(name);
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    if path.is_absolute() {
+        return None;
+    }
+    search_path.iter()
+        .map(|directory| directory.join(path))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Returns the first prelude found in the `search_path` directories, if there is one.
+pub fn find_prelude(search_path: &[PathBuf]) -> Option<PathBuf> {
+    search_path.iter()
+        .map(|directory| directory.join(PRELUDE))
+        .find(|candidate| candidate.is_file())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    /// Creates an empty directory for the test to put modules in.
+    fn module_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("mornington-modules-{name}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn resolves_through_search_path_in_order() {
+        let first = module_directory("first");
+        let second = module_directory("second");
+        fs::write(second.join("helpers.mron"), "").unwrap();
+        let search_path = vec![first.clone(), second.clone()];
+        assert_eq!(Some(second.join("helpers.mron")), resolve("helpers.mron", &search_path));
+
+        fs::write(first.join("helpers.mron"), "").unwrap();
+        assert_eq!(Some(first.join("helpers.mron")), resolve("helpers.mron", &search_path));
+        assert_eq!(None, resolve("missing.mron", &search_path));
+    }
+
+    #[test]
+    fn finds_prelude() {
+        let empty = module_directory("empty");
+        let with_prelude = module_directory("with-prelude");
+        let search_path = vec![empty, with_prelude.clone()];
+        assert_eq!(None, find_prelude(&search_path));
+        fs::write(with_prelude.join(PRELUDE), "").unwrap();
+        assert_eq!(Some(with_prelude.join(PRELUDE)), find_prelude(&search_path));
+    }
+}
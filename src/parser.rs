@@ -1,26 +1,39 @@
 use crate::ast::*;
 use crate::error::{Error, ErrorKind::*};
-use crate::lexer::{Position, Token, TokenKind};
+use crate::lexer::{scrambled_keyword, Location, Position, Token, TokenKind};
+use crate::runtime::Limit;
+use crate::stack;
+use crate::symbol::Symbol;
 use crate::value::Value;
 
+/// How deeply brackets, lists, function calls' arguments, and blocks can be nested inside each
+/// other. A chain of operators like `1 + 2 + 3` doesn't count as nesting, however long it is.
+pub const MAX_NESTING: usize = 128;
+
 #[derive(Debug)]
 pub struct Parser<'a> {
-    tokens: Vec<Token<'a>>,
+    tokens: &'a [Token<'a>],
+    /// The index of the next token to be consumed.
+    next: usize,
     current_token: Option<Token<'a>>,
     previous_token: Option<Token<'a>>,
+    /// The number of loops enclosing the statement being parsed, within the current function.
+    loop_depth: usize,
+    in_function: bool,
+    /// How many bracketed expressions, lists, and argument lists enclose the one being parsed.
+    nesting: usize,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(mut tokens: Vec<Token<'a>>) -> Parser<'a> {
-        if tokens.is_empty() {
-            panic!("No tokens passed, cannot initialise parser");
-        }
-        // reverse so that elements can easily and efficiently be popped off the end
-        tokens.reverse();
+    pub fn new(tokens: &'a [Token<'a>]) -> Parser<'a> {
         Parser {
             tokens,
+            next: 0,
             current_token: None,
             previous_token: None,
+            loop_depth: 0,
+            in_function: false,
+            nesting: 0,
         }
     }
 
@@ -28,23 +41,26 @@ impl<'a> Parser<'a> {
         match token.kind {
             TokenKind::Number => {
                 Ok(ConstantNode::new(
-                    Value::Number(token.text.parse::<f64>().unwrap()),
+                    Self::parse_number(token.text),
+                    token.position,
                 ))
             },
             TokenKind::BoolTrue => {
                 Ok(ConstantNode::new(
                     Value::Bool(true),
+                    token.position,
                 ))
             },
             TokenKind::BoolFalse => {
                 Ok(ConstantNode::new(
                     Value::Bool(false),
+                    token.position,
                 ))
             },
             TokenKind::String => {
                 // check for empty strings
                 if token.text == "\"'" || token.text == "'\"" {
-                    return Ok(ConstantNode::new( Value::String("".to_string()) ));
+                    return Ok(ConstantNode::new(Value::String("".to_string()), token.position));
                 }
 
                 let total_length = token.text.len();
@@ -55,16 +71,22 @@ impl<'a> Parser<'a> {
 
                 // check for quote imbalance, throw Balance error if quotes are balanced
                 if opener_length == closer_length {
+                    let Location { line, column } = token.position.start;
                     return Err(Error::new(
                         Balance {
                             opener: "\"".repeat(opener_length),
                             closer: "\"".repeat(closer_length),
+                            opener_position: Some(Position::new(line, column, opener_length)),
                         },
-                        Some(token.position)
+                        Some(Position::span(
+                            Location::new(line, token.position.end.column - closer_length),
+                            token.position.end,
+                        )),
                     ));
                 }
 
-                Ok(ConstantNode::new( Value::String(string_contents.to_string()) ))
+                Ok(ConstantNode::new(Value::String(string_contents.to_string()), token.position)
+                    .with_opener_length(opener_length))
             }
             _ => Err(Error::new(UnexpectedToken(token.kind), Some(token.position))),
         }
@@ -78,11 +100,23 @@ impl<'a> Parser<'a> {
         self.parse_comma_separated_expressions(opener, TokenKind::RParen)
     }
 
+    /// Parses the arguments of a call to `name`, whose opening parenthesis has just been consumed.
+    /// The call spans from the name to the closing parenthesis.
+    fn parse_function_call(&mut self, name: Symbol, name_position: Position, opener: &str)
+        -> Result<FunctionCallNode, Error>
+    {
+        let args = self.parse_function_arguments(opener)?;
+        let position = name_position.to(self.current_token.unwrap().position);
+        Ok(FunctionCallNode::new(name, args, position))
+    }
+
     fn parse_comma_separated_expressions(&mut self,
                                          opener: &str,
                                          closing_wrapper: TokenKind)
         -> Result<ListNode, Error>
     {
+        // the opening wrapper has just been consumed
+        let position = self.current_token.unwrap().position;
         let mut list: Vec<ExpressionNode> = Vec::new();
 
         // check for empty list eventuality
@@ -90,12 +124,12 @@ impl<'a> Parser<'a> {
             Some(token) => {
                 if token.kind == closing_wrapper {
                     self.advance();
-                    self.check_wrapper_balance(opener.to_string())?;
-                    return Ok(ListNode::new(list));
+                    self.check_wrapper_balance(opener.to_string(), Some(position))?;
+                    return Ok(ListNode::new(list, position));
                 }
             },
             None => return Err(Error::new(
-                UnexpectedEOF, Some(self.previous_token.unwrap().position.one_past())
+                UnexpectedEOF, Some(position.to(position.one_past()))
             )),
         }
 
@@ -103,16 +137,17 @@ impl<'a> Parser<'a> {
             list.push(self.parse_expr(0)?);
             self.advance();
             if self.current_token.is_none() {
+                // underline the whole unterminated list
                 return Err(Error::new(
                     UnexpectedEOF,
-                    Some(self.previous_token.unwrap().position.one_past()),
+                    Some(position.to(self.previous_token.unwrap().position.one_past())),
                 ))
             }
             match self.current_token.unwrap().kind {
                 TokenKind::Comma => continue,
                 other_token => {
                     if other_token == closing_wrapper {
-                        self.check_wrapper_balance(opener.to_string())?;
+                        self.check_wrapper_balance(opener.to_string(), Some(position))?;
                         break;
                     } else {
                         return Err(Error::new(
@@ -123,16 +158,18 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        Ok(ListNode::new(list))
+        Ok(ListNode::new(list, position))
     }
 
-    fn parse_function_parameter_names(&mut self, opener: &str) -> Result<Vec<String>, Error> {
+    fn parse_function_parameter_names(&mut self, opener: &str) -> Result<Vec<Symbol>, Error> {
+        // the opening parenthesis has just been consumed
+        let opener_position = self.current_token.map(|token| token.position);
         // empty parentheses
         match self.peek() {
             Some(token) => {
                 if token.kind == TokenKind::RParen {
                     self.advance();
-                    self.check_wrapper_balance(opener.to_string())?;
+                    self.check_wrapper_balance(opener.to_string(), opener_position)?;
                     return Ok(Vec::new());
                 }
             },
@@ -143,7 +180,7 @@ impl<'a> Parser<'a> {
 
         let mut params = Vec::new();
         loop {
-            params.push(self.eat_token(TokenKind::Name)?.text.to_string());
+            params.push(Symbol::intern(self.eat_token(TokenKind::Name)?.text));
             self.advance();
             if self.current_token.is_none() {
                 return Err(Error::new(
@@ -154,7 +191,7 @@ impl<'a> Parser<'a> {
             match self.current_token.unwrap().kind {
                 TokenKind::Comma => continue,
                 TokenKind::RParen => {
-                    self.check_wrapper_balance(opener.to_string())?;
+                    self.check_wrapper_balance(opener.to_string(), opener_position)?;
                     break;
                 },
                 other_token => return Err(Error::new(
@@ -167,10 +204,35 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_expr(&mut self, current_operator_precedence: u32) -> Result<ExpressionNode, Error> {
+        stack::guard(|| self.parse_nested_expr(current_operator_precedence))
+    }
+
+    /// Parses something that nests whatever's inside it a level deeper, throwing an error instead
+    /// if it would be nested too deeply.
+    fn nest<T>(&mut self, parse: impl FnOnce(&mut Self) -> Result<T, Error>) -> Result<T, Error> {
+        self.nesting += 1;
+        let result = self.check_nesting(self.nesting).and_then(|()| parse(self));
+        self.nesting -= 1;
+        result
+    }
+
+    /// Throws an error if `nesting` is too deep, positioned at the next token.
+    fn check_nesting(&self, nesting: usize) -> Result<(), Error> {
+        if nesting <= MAX_NESTING {
+            return Ok(());
+        }
+        Err(Error::new(
+            LimitExceeded(Limit::Depth(MAX_NESTING)),
+            self.peek().or(self.current_token.as_ref()).map(|token| token.position),
+        ))
+    }
+
+    fn parse_nested_expr(&mut self, current_operator_precedence: u32)
+        -> Result<ExpressionNode, Error>
+    {
         // going token by token:
-        // - if the token is an LParen, dig out the RParen, putting the intermediate tokens into
-        //   a secondary token stream, then call parse_expr on that to get its value. Then,
-        //   move on.
+        // - if the token is an LParen, parse the expression it encloses, then check that the
+        //   RParen after it doesn't balance it. Then, move on.
         //
         // - if the token is a value (a constant, variable, function call, or list), save its value
         //   as the lhs
@@ -188,39 +250,33 @@ impl<'a> Parser<'a> {
         while let Some(token) = self.peek() {
             match token.kind {
                 LParen => {
-                    // bracketed expression
+                    // bracketed expression, parsed in place so that the RParen closing it is
+                    // checked against this LParen, whatever wrappers there are in between
                     let lparen_text = token.text.to_string();
+                    let lparen_position = token.position;
                     self.advance();
-
-                    // dig out the RParen, then parse the enclosed tokens separately, and stick
-                    // the result in lhs
-                    let mut lparen_count = 1;
-                    let mut sub_expression = Vec::new();
-                    while lparen_count > 0 {
-                        self.advance();
-                        let token = match self.current_token {
-                            Some(token) => token,
-                            None => {
-                                // ran out of tokens before closing RParen
-                                return Err(Error::new(
-                                    MissingToken(RParen),
-                                    Some(self.previous_token.unwrap().position.one_past()),
-                                ))
-                            }
-                        };
-                        match token.kind {
-                            LParen => lparen_count += 1,
-                            RParen => lparen_count -= 1,
-                            _ => {},
-                        }
-                        sub_expression.push(token);
+                    if !self.is_closed(LParen, RParen) {
+                        // underline everything from the LParen onwards, rather than reporting
+                        // whatever the enclosed expression runs into first
+                        let last = self.tokens[self.tokens.len() - 1].position;
+                        return Err(Error::new(
+                            MissingToken(RParen), Some(lparen_position.to(last.one_past())),
+                        ));
                     }
-                    self.check_wrapper_balance(lparen_text)?;
-
-                    let mut sub_parser = Parser::new(sub_expression);
-                    lhs = Some(sub_parser.parse_expr(0)?);
+                    let expression = self.nest(|parser| parser.parse_expr(0))?;
+                    self.advance();
+                    match self.current_token {
+                        Some(Token { kind: RParen, .. }) => {
+                            self.check_wrapper_balance(lparen_text, Some(lparen_position))?;
+                        },
+                        token => return Err(Error::new(
+                            MissingToken(RParen),
+                            token.or(self.previous_token).map(|token| token.position),
+                        )),
+                    }
+                    lhs = Some(expression);
                 },
-                Plus | Minus | Mul | Div | Mod | Seq | Sne | Eq | Ne | Gt | Lt | Ge | Le => {
+                Plus | Minus | Mul | Div | Mod | Seq | Sne | Eq | Ne | Gt | Lt | Ge | Le | And | Or => {
                     // operator
 
                     // check that there is a value in lhs, and extract it if there is
@@ -237,6 +293,7 @@ impl<'a> Parser<'a> {
                     // compare precedence with the currently active operator (or 0 if there is
                     // none)
                     let operator = Operator::from_token(token);
+                    let position = token.position;
                     let precedence = operator.precedence();
                     if precedence > current_operator_precedence {
                         // this operator has a higher precedence, so should consume both the lhs and
@@ -249,6 +306,7 @@ impl<'a> Parser<'a> {
                             lhs_unwrapped,
                             rhs,
                             operator,
+                            position,
                         ).to_expression());
                         continue;
                     } else {
@@ -269,25 +327,23 @@ impl<'a> Parser<'a> {
                 LBrack => {
                     // list
                     self.advance();
-                    lhs = Some(self.parse_list(
-                        self.previous_token.unwrap().text
-                    )?.to_expression());
+                    let opener = self.current_token.unwrap().text;
+                    lhs = Some(self.nest(|parser| parser.parse_list(opener))?.to_expression());
                 },
                 Name => {
                     // variable or function
-                    let name = token.text.to_string();
+                    let name = Symbol::intern(token.text);
+                    let position = token.position;
                     self.advance();
-                    if let Some(token) = self.peek() {
-                        if token.kind == LParen {
+                    lhs = Some(match self.peek() {
+                        Some(token) if token.kind == LParen => {
                             let opener = token.text;
                             self.advance();
-                            return Ok(FunctionCallNode::new(
-                                name,
-                                self.parse_function_arguments(opener)?
-                            ).to_expression());
-                        }
-                    }
-                    lhs = Some(VariableNode::new(name).to_expression());
+                            self.nest(|parser| parser.parse_function_call(name, position, opener))?
+                                .to_expression()
+                        },
+                        _ => VariableNode::new(name, position).to_expression(),
+                    });
                 },
                 _other_token_type => break,
             }
@@ -297,56 +353,109 @@ impl<'a> Parser<'a> {
             Some(evaluable) => Ok(evaluable),
             None => Err(Error::new(
                 MissingExpression,
-                Some(self.current_token.unwrap().position.one_past())
+                self.current_token.map(|token| token.position.one_past())
             ))
         }
     }
 
+    /// Returns whether the wrapper that's just been opened is closed before the tokens run out,
+    /// counting each run of brackets as a single wrapper.
+    fn is_closed(&self, opener: TokenKind, closer: TokenKind) -> bool {
+        let mut depth = 1;
+        for token in &self.tokens[self.next..] {
+            if token.kind == opener {
+                depth += 1;
+            } else if token.kind == closer {
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     fn parse_expression(&mut self) -> Result<ExpressionNode, Error> {
         self.parse_expr(0)
     }
 
-    fn parse_expression_and_block(&mut self, current_indentation_level: usize)
+    /// Parses all the tokens as one expression, throwing an error if any tokens other than
+    /// newlines are left over afterwards.
+    pub fn parse_single_expression(&mut self) -> Result<ExpressionNode, Error> {
+        let expression = self.parse_expression()?;
+        while let Some(token) = self.peek() {
+            if token.kind != TokenKind::Newline {
+                return Err(Error::new(UnexpectedToken(token.kind), Some(token.position)));
+            }
+            self.advance();
+        }
+        Ok(expression)
+    }
+
+    fn parse_expression_and_block(&mut self, current_indentation_level: usize, header: Position)
         -> Result<(ExpressionNode, Block), Error>
     {
         let expression = self.parse_expression()?;
         self.eat_token(TokenKind::Newline)?;
-        let block = self.parse_block(current_indentation_level + 1)?;
+        let block = self.parse_body(current_indentation_level + 1, header)?;
         Ok((expression, block))
     }
 
-    fn parse_block(&mut self, indentation_level: usize) -> Result<Block, Error> {
+    /// Parses the lines at `indentation_level`, along with the blocks they start, until a line
+    /// that's indented less. `header` is the position of the keyword that starts the block, if
+    /// it isn't the whole program.
+    fn parse_block(&mut self, indentation_level: usize, header: Option<Position>)
+        -> Result<Block, Error>
+    {
         use TokenKind::*;
 
+        self.check_nesting(indentation_level)?;
+
         let mut block = Block::new();
         let mut start_of_line = true;
         let mut previous_indentation = usize::MAX;
 
         while let Some(token) = self.peek() {
             if start_of_line {
+                // blank lines, even ones with whitespace on them, don't count towards indentation
+                if token.kind == Newline {
+                    self.advance();
+                    continue;
+                }
                 // check indentation level is what this block requires
-                let indentation = token.position.start;
-                if Self::calculate_indentation_level(indentation) != indentation_level {
-                    break;
+                let level = Self::calculate_indentation_level(token.indentation);
+                if level > indentation_level {
+                    return Err(match header {
+                        Some(header) if block.statements().is_empty() => {
+                            Error::new(ExpectedBlock, Some(header))
+                        },
+                        _ => Error::new(
+                            UnexpectedIndentation,
+                            Some(Position::new(token.position.start.line, 0, token.position.start.column)),
+                        ),
+                    });
                 }
-                // check for indentation consistency
-                if indentation == previous_indentation {
-                    return Err(Error::new(
-                        ConsistentIndentation { previous_indentation },
-                        Some(Position::new(token.position.line, 0, token.position.start))
-                    ));
-                } else {
-                    previous_indentation = indentation;
+                if level < indentation_level {
+                    break;
                 }
+                Self::check_indentation_consistency(token, &mut previous_indentation)?;
                 start_of_line = false;
             }
             
             match token.kind {
                 Name => {
                     // function call or assignment
-                    let name = token.text.to_string();
+                    let name = Symbol::intern(token.text);
+                    let position = token.position;
                     self.advance();
                     self.advance();
+                    // a name that isn't being called or assigned to is most likely a keyword
+                    // that's been spelt correctly
+                    if !matches!(self.current_token, Some(Token { kind: LParen | Assign, .. })) {
+                        if let Some(error) = Self::unscrambled_keyword_error(&name, position) {
+                            return Err(error);
+                        }
+                    }
                     let current_token = match self.current_token {
                         Some(token) => token,
                         None => return Err(Error::new(
@@ -358,10 +467,7 @@ impl<'a> Parser<'a> {
                         LParen => {
                             // function call
                             let opener = current_token.text;
-                            let function_call = FunctionCallNode::new(
-                                name,
-                                self.parse_function_arguments(opener)?
-                            );
+                            let function_call = self.parse_function_call(name, position, opener)?;
                             block.add_statement(function_call.to_statement());
                         },
                         Assign => {
@@ -370,6 +476,7 @@ impl<'a> Parser<'a> {
                             block.add_statement(AssignNode::new(
                                 name,
                                 expression,
+                                position,
                             ).to_statement());
                         },
                         other_token_kind => return Err(Error::new(
@@ -380,9 +487,10 @@ impl<'a> Parser<'a> {
                 },
                 If => {
                     // conditional statement
+                    let position = token.position;
                     self.advance();
                     let (condition, block_if_condition) =
-                        self.parse_expression_and_block(indentation_level)?;
+                        self.parse_expression_and_block(indentation_level, position)?;
 
                     let mut conditional_paths = vec![ConditionalPath::new(
                         condition, block_if_condition
@@ -390,10 +498,18 @@ impl<'a> Parser<'a> {
                     let mut else_block = None;
 
                     while let Some(token) = self.peek() {
+                        // `lefi` and `sele` start lines of their own, which belong to this
+                        // conditional only if they're on this block's level
+                        let header = token.position;
+                        if !matches!(token.kind, Elif | Else)
+                            || Self::calculate_indentation_level(token.indentation) != indentation_level
+                        {
+                            break;
+                        }
                         if token.kind == Elif {
                             self.advance();
                             let (condition, block_if_condition) =
-                                self.parse_expression_and_block(indentation_level)?;
+                                self.parse_expression_and_block(indentation_level, header)?;
                             conditional_paths.push(ConditionalPath::new(
                                 condition, block_if_condition
                             ));
@@ -401,7 +517,7 @@ impl<'a> Parser<'a> {
                         else if token.kind == Else {
                             self.advance();
                             self.eat_token(Newline)?;
-                            else_block = Some(self.parse_block(indentation_level + 1)?);
+                            else_block = Some(self.parse_body(indentation_level + 1, header)?);
                             break;
                         }
                         else {
@@ -410,63 +526,84 @@ impl<'a> Parser<'a> {
                     }
 
                     block.add_statement(ConditionalNode::new(
-                        conditional_paths, else_block,
+                        conditional_paths, else_block, position,
                     ).to_statement());
                 },
                 For => {
                     // for loop
+                    let position = token.position;
                     self.advance();
-                    let loop_variable = self.eat_token(Name)?.text.to_string();
+                    let loop_variable = Symbol::intern(self.eat_token(Name)?.text);
                     self.eat_token(In)?;
                     let iterable = self.parse_expression()?;
                     self.eat_token(Newline)?;
-                    let for_block = self.parse_block(indentation_level + 1)?;
+                    let for_block = self.parse_loop_block(indentation_level + 1, position)?;
                     
                     block.add_statement(ForLoopNode::new(
-                        iterable, loop_variable, for_block,
+                        iterable, loop_variable, for_block, position,
                     ).to_statement());
                 },
                 While => {
                     // while loop
+                    let position = token.position;
                     self.advance();
                     let condition = self.parse_expression()?;
                     self.eat_token(Newline)?;
-                    let while_block = self.parse_block(indentation_level + 1)?;
+                    let while_block = self.parse_loop_block(indentation_level + 1, position)?;
                     
                     block.add_statement(WhileLoopNode::new(
-                        condition, while_block
+                        condition, while_block, position,
                     ).to_statement());
                 },
                 Break => {
                     // break
+                    let position = token.position;
+                    if self.loop_depth == 0 {
+                        return Err(Error::new(BreakOutsideLoop, Some(position)));
+                    }
                     self.advance();
-                    block.add_statement(BreakNode.to_statement());
+                    block.add_statement(BreakNode::new(position).to_statement());
                 },
                 Continue => {
-                    // break
+                    // continue
+                    let position = token.position;
+                    if self.loop_depth == 0 {
+                        return Err(Error::new(ContinueOutsideLoop, Some(position)));
+                    }
                     self.advance();
-                    block.add_statement(ContinueNode.to_statement());
+                    block.add_statement(ContinueNode::new(position).to_statement());
+                },
+                Pass => {
+                    // pass, which does nothing
+                    let position = token.position;
+                    self.advance();
+                    block.add_statement(PassNode::new(position).to_statement());
                 },
                 Return => {
                     // return
+                    let position = token.position;
+                    if !self.in_function {
+                        return Err(Error::new(ReturnOutsideFunction, Some(position)));
+                    }
                     self.advance();
                     let return_value = self.parse_expression()?;
                     block.add_statement(ReturnNode::new(
-                        return_value
+                        return_value, position,
                     ).to_statement());
                 },
                 Funcdef => {
                     // function definition
+                    let position = token.position;
                     self.advance();
                     // parse name, parameters, and block
-                    let name = self.eat_token(Name)?.text.to_string();
+                    let name = Symbol::intern(self.eat_token(Name)?.text);
                     let parentheses_opener = self.eat_token(LParen)?.text.to_string();
                     let parameters = self.parse_function_parameter_names(&parentheses_opener)?;
                     self.eat_token(Newline)?;
-                    let function_block = self.parse_block(indentation_level + 1)?;
+                    let function_block = self.parse_function_block(indentation_level + 1, position)?;
                     // wrap block into FunctionDefinitionNode and add to current block
                     block.add_statement(FunctionDefinitionNode::new(
-                        name, parameters, function_block
+                        name, parameters, function_block, position,
                     ).to_statement());
                 },
                 Newline => {
@@ -477,25 +614,65 @@ impl<'a> Parser<'a> {
                     UnexpectedToken(other_token_kind), Some(token.position),
                 )),
             }
+
+            // a statement with a block leaves off at the start of the line after the block, which
+            // isn't held to being indented differently from the statement's own line
+            if !start_of_line && self.current_token.is_some_and(|token| token.kind == Newline) {
+                start_of_line = true;
+                previous_indentation = usize::MAX;
+            }
         }
 
         Ok(block)
     }
 
+    /// Parses the block that the keyword at `header`, e.g. a `fi`, introduces. Blocks can't be
+    /// empty, so `pas` stands in for one that does nothing.
+    fn parse_body(&mut self, indentation_level: usize, header: Position) -> Result<Block, Error> {
+        let block = stack::guard(|| self.parse_block(indentation_level, Some(header)))?;
+        if block.statements().is_empty() {
+            return Err(Error::new(EmptyBlock, Some(header)));
+        }
+        Ok(block)
+    }
+
+    fn parse_loop_block(&mut self, indentation_level: usize, header: Position)
+        -> Result<Block, Error>
+    {
+        self.loop_depth += 1;
+        let block = self.parse_body(indentation_level, header);
+        self.loop_depth -= 1;
+        block
+    }
+
+    /// Parses a function body, in which `retrun` is allowed but the loops around the definition
+    /// can't be broken out of.
+    fn parse_function_block(&mut self, indentation_level: usize, header: Position)
+        -> Result<Block, Error>
+    {
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        let in_function = std::mem::replace(&mut self.in_function, true);
+        let block = self.parse_body(indentation_level, header);
+        self.loop_depth = loop_depth;
+        self.in_function = in_function;
+        block
+    }
+
     pub fn parse(&mut self) -> Result<Block, Error> {
-        self.parse_block(0)
+        self.parse_block(0, None)
     }
 
     fn advance(&mut self) {
         self.previous_token = self.current_token;
-        self.current_token = self.tokens.pop();
+        self.current_token = self.tokens.get(self.next).copied();
+        self.next = (self.next + 1).min(self.tokens.len());
     }
 
     fn peek(&self) -> Option<&Token<'a>>{
-        self.tokens.last()
+        self.tokens.get(self.next)
     }
 
-    fn eat_token(&mut self, kind: TokenKind) -> Result<Token, Error> {
+    fn eat_token(&mut self, kind: TokenKind) -> Result<Token<'a>, Error> {
         self.advance();
         let token = match self.current_token {
             Some(token) => token,
@@ -505,6 +682,11 @@ impl<'a> Parser<'a> {
             )),
         };
         if token.kind != kind {
+            if token.kind == TokenKind::Name {
+                if let Some(error) = Self::unscrambled_keyword_error(token.text, token.position) {
+                    return Err(error);
+                }
+            }
             return Err(Error::new(
                 UnexpectedToken(token.kind),
                 Some(token.position),
@@ -513,12 +695,23 @@ impl<'a> Parser<'a> {
         Ok(token)
     }
 
-    /// Throws an error if wrapper imbalance is invalidated, otherwise does nothing
-    fn check_wrapper_balance(&mut self, opener: String) -> Result<(), Error> {
+    /// Returns an error for `name` if it's the unscrambled spelling of a keyword.
+    fn unscrambled_keyword_error(name: &str, position: Position) -> Option<Error> {
+        scrambled_keyword(name).map(|keyword| Error::new(
+            UnscrambledKeyword { name: name.to_string(), keyword: keyword.to_string() },
+            Some(position),
+        ))
+    }
+
+    /// Throws an error if the closing wrapper just consumed balances `opener`, otherwise does
+    /// nothing
+    fn check_wrapper_balance(&mut self, opener: String, opener_position: Option<Position>)
+        -> Result<(), Error>
+    {
         let token = self.current_token.unwrap();
-        if opener.len() == token.position.length {
+        if opener.len() == token.text.len() {
             Err(Error::new(
-                Balance { opener, closer: token.text.to_string() },
+                Balance { opener, closer: token.text.to_string(), opener_position },
                 Some(token.position),
             ))
         } else {
@@ -526,8 +719,48 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn calculate_indentation_level(start: usize) -> usize {
-        start / 3
+    /// Converts the text of a number token to its value: an integer if it has no fraction or
+    /// exponent and fits in one, and a float otherwise. The lexer only lets underscores through
+    /// between digits, and only lets prefixed numbers through with digits of the right base.
+    fn parse_number(text: &str) -> Value {
+        let text = text.replace('_', "");
+        let radix = match text.get(..2) {
+            Some("0x" | "0X") => 16,
+            Some("0b" | "0B") => 2,
+            Some("0o" | "0O") => 8,
+            _ => return match text.parse() {
+                Ok(int) => Value::Int(int),
+                Err(_) => Value::Number(text.parse().unwrap()),
+            },
+        };
+        if let Ok(int) = i64::from_str_radix(&text[2..], radix) {
+            return Value::Int(int);
+        }
+        // folded into a float rather than parsed as an integer, so that long literals lose
+        // precision instead of overflowing
+        Value::Number(text[2..].chars().fold(0.0, |number, digit| {
+            number * radix as f64 + digit.to_digit(radix).unwrap() as f64
+        }))
+    }
+
+    /// Throws an error if the line `token` starts is indented by the same amount as the previous
+    /// line of the block, and otherwise records its indentation as the previous line's.
+    fn check_indentation_consistency(token: &Token, previous_indentation: &mut usize)
+        -> Result<(), Error>
+    {
+        if token.indentation == *previous_indentation {
+            return Err(Error::new(
+                ConsistentIndentation { previous_indentation: *previous_indentation },
+                Some(Position::new(token.position.start.line, 0, token.position.start.column)),
+            ));
+        }
+        *previous_indentation = token.indentation;
+        Ok(())
+    }
+
+    /// Every three columns of indentation, with tabs expanded by the lexer, is one level.
+    fn calculate_indentation_level(indentation: usize) -> usize {
+        indentation / 3
     }
 }
 
@@ -544,19 +777,45 @@ mod tests {
         #[allow(clippy::approx_constant)]
         fn number() {
             assert_eq!(
-                Ok(ConstantNode::new(Value::Number(3.14))),
+                Ok(ConstantNode::new(Value::Number(3.14), Position::new(1, 2, 4))),
                 Parser::parse_constant(&Token::new(Number, "3.14", 1, 2, 4)),
             );
         }
+        #[test]
+        fn prefixed_numbers() {
+            for (text, value) in [("0xff", 255), ("0B1010_0101", 165), ("0o777", 511)] {
+                let constant = Parser::parse_constant(&Token::new(Number, text, 1, 0, text.len())).unwrap();
+                assert!(matches!(constant.value(), Value::Int(int) if *int == value), "{text}");
+                assert_eq!(Position::new(1, 0, text.len()), constant.position());
+            }
+        }
+        #[test]
+        fn whole_literals_are_integers() {
+            let value = |text: &str| {
+                Parser::parse_constant(&Token::new(Number, text, 1, 0, text.len())).unwrap().value().clone()
+            };
+            assert!(matches!(value("42"), Value::Int(42)));
+            assert!(matches!(value("0xff"), Value::Int(255)));
+            assert!(matches!(value("42.0"), Value::Number(_)));
+            assert!(matches!(value("1e3"), Value::Number(_)));
+            assert!(matches!(value("99999999999999999999"), Value::Number(_)));
+        }
+        #[test]
+        fn number_with_exponent_and_separators() {
+            assert_eq!(
+                Ok(ConstantNode::new(Value::Number(1_250.0), Position::new(1, 0, 8))),
+                Parser::parse_constant(&Token::new(Number, "1_2.5e+2", 1, 0, 8)),
+            );
+        }
 
         #[test]
         fn bool() {
             assert_eq!(
-                Ok(ConstantNode::new(Value::Bool(true))),
+                Ok(ConstantNode::new(Value::Bool(true), Position::new(1, 0, 4))),
                 Parser::parse_constant(&Token::new(BoolTrue, "rtue", 1, 0, 4)),
             );
             assert_eq!(
-                Ok(ConstantNode::new(Value::Bool(false))),
+                Ok(ConstantNode::new(Value::Bool(false), Position::new(1, 0, 4))),
                 Parser::parse_constant(&Token::new(BoolFalse, "flase", 1, 0, 4)),
             );
         }
@@ -564,7 +823,8 @@ mod tests {
         #[test]
         fn string() {
             assert_eq!(
-                Ok(ConstantNode::new( Value::String("a sting".to_string()) )),
+                Ok(ConstantNode::new(Value::String("a sting".to_string()), Position::new(1, 0, 10))
+                    .with_opener_length(1)),
                 Parser::parse_constant(&Token::new(String, "\"a sting\"\"", 1, 0, 10)),
             );
         }
@@ -572,22 +832,37 @@ mod tests {
         #[test]
         fn empty_string() {
             assert_eq!(
-                Ok(ConstantNode::new( Value::String("".to_string()) )),
+                Ok(ConstantNode::new(Value::String("".to_string()), Position::new(1, 0, 2))),
                 Parser::parse_constant(&Token::new(String, "\"'", 1, 0, 2)),
             );
             assert_eq!(
-                Ok(ConstantNode::new(Value::String("".to_string()))),
+                Ok(ConstantNode::new(Value::String("".to_string()), Position::new(1, 0, 2))),
                 Parser::parse_constant(&Token::new(String, "'\"", 1, 0, 2)),
             )
         }
 
+        #[test]
+        fn balanced_string_error_points_at_both_quotes() {
+            let error = Parser::parse_constant(&Token::new(String, "\"\"hi\"\"", 2, 4, 6)).unwrap_err();
+            assert_eq!(
+                Balance {
+                    opener: "\"\"".to_string(),
+                    closer: "\"\"".to_string(),
+                    opener_position: Some(Position::new(2, 4, 2)),
+                },
+                error.kind,
+            );
+            assert_eq!(Some(Position::new(2, 8, 2)), error.pos);
+        }
+
         #[test]
         fn string_throw_balance_error_on_balanced_strings() {
             match Parser::parse_constant(&Token::new(String, "\"balanced\"", 1, 0, 10)) {
                 Ok(_) => panic!("Expected BalanceError due to balanced double quotes. No error indicated"),
                 Err(Error {
                         pos: _,
-                        kind: Balance { opener, closer }
+                        kind: Balance { opener, closer, .. },
+                        ..
                     }
                 ) => {
                     assert_eq!("\"", opener);
@@ -607,7 +882,7 @@ mod tests {
 
         fn parse_list_test(expected: Vec<Value>, source: Vec<Token>) {
             let opener = source[0].text;
-            let mut parser = Parser::new(source);
+            let mut parser = Parser::new(&source);
             // the parser must be advanced one to keep with how parse_list is called from
             // parse_expr, since this will have consumed the left bracket before calling parse_list
             parser.advance();
@@ -630,16 +905,18 @@ mod tests {
 
         #[test]
         fn empty_list_bracket_balance_throws_error() {
-            let mut parser = Parser::new(vec![
+            let tokens = [
                 Token::new(LBrack, "[", 1, 0, 1),
                 Token::new(RBrack, "]", 1, 2, 1),
-            ]);
+            ];
+            let mut parser = Parser::new(&tokens);
             parser.advance();
             match parser.parse_list("[") {
                 Ok(_) => panic!("Expected Balance error, none thrown"),
                 Err(Error {
-                    kind: Balance { opener, closer },
-                    pos: _
+                    kind: Balance { opener, closer, .. },
+                    pos: _,
+                    ..
                 }) => {
                     assert_eq!(opener, "[".to_string());
                     assert_eq!(closer, "]".to_string());
@@ -676,19 +953,21 @@ mod tests {
 
         #[test]
         fn two_element_list_bracket_balance_throws_error() {
-            let mut parser = Parser::new(vec![
+            let tokens = [
                 Token::new(LBrack, "[", 1, 0, 1),
                 Token::new(Number, "1", 1, 2, 1),
                 Token::new(Comma, ",", 1, 3, 1),
                 Token::new(Number, "2", 1, 4, 1),
                 Token::new(RBrack, "]", 1, 5, 1),
-            ]);
+            ];
+            let mut parser = Parser::new(&tokens);
             parser.advance();
             match parser.parse_list("[") {
                 Ok(_) => panic!("Expected Balance error, none thrown"),
                 Err(Error {
-                        kind: Balance { opener, closer },
-                        pos: _
+                        kind: Balance { opener, closer, .. },
+                        pos: _,
+                        ..
                     }) => {
                     assert_eq!(opener, "[".to_string());
                     assert_eq!(closer, "]".to_string());
@@ -772,8 +1051,8 @@ mod tests {
                 Token::new(RParen, "))", 1, 0, 2),
             ];
             assert_eq!(
-                Parser::new(tokens).parse_function_parameter_names("(").unwrap(),
-                Vec::<std::string::String>::new(),
+                Parser::new(&tokens).parse_function_parameter_names("(").unwrap(),
+                Vec::<Symbol>::new(),
             )
         }
 
@@ -782,7 +1061,7 @@ mod tests {
             let tokens = vec![
                 Token::new(RParen, ")", 1, 0, 1),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if let Balance {..} = error.kind
             {} else {
                 panic!("Expected Balance error (got: {:?})", error.kind);
@@ -794,7 +1073,7 @@ mod tests {
             let tokens = vec![
                 Token::new(Seq, "===", 1, 0, 3),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedToken(Seq) {
             } else {
                 panic!("Expected UnexpectedToken error (got: {:?})", error.kind);
@@ -808,8 +1087,8 @@ mod tests {
                 Token::new(TokenKind::RParen, "))", 1, 7, 2),
             ];
             assert_eq!(
-                Parser::new(tokens).parse_function_parameter_names("(").unwrap(),
-                vec!["param1".to_string()],
+                Parser::new(&tokens).parse_function_parameter_names("(").unwrap(),
+                vec![Symbol::intern("param1")],
             )
         }
 
@@ -819,7 +1098,7 @@ mod tests {
                 Token::new(TokenKind::Name, "param1", 1, 0, 6),
                 Token::new(TokenKind::RParen, ")", 1, 7, 1),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if let Balance {..} = error.kind
             {} else {
                 panic!("Expected Balance error (got: {:?})", error.kind);
@@ -831,7 +1110,7 @@ mod tests {
             let tokens = vec![
                 Token::new(TokenKind::Name, "param1", 1, 0, 6),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedEOF {
             } else {
                 panic!("Expected UnexpectedEOF error (got: {:?})", error.kind);
@@ -844,7 +1123,7 @@ mod tests {
                 Token::new(TokenKind::Name, "param1", 1, 0, 6),
                 Token::new(Seq, "===", 1, 7, 3),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedToken(Seq) {
             } else {
                 panic!("Expected UnexpectedToken error (got: {:?})", error.kind);
@@ -862,8 +1141,8 @@ mod tests {
                 Token::new(TokenKind::RParen, "))", 1, 22, 2),
             ];
             assert_eq!(
-                Parser::new(tokens).parse_function_parameter_names("(").unwrap(),
-                vec!["param1".to_string(), "param2".to_string(), "param3".to_string()],
+                Parser::new(&tokens).parse_function_parameter_names("(").unwrap(),
+                vec![Symbol::intern("param1"), Symbol::intern("param2"), Symbol::intern("param3")],
             )
         }
 
@@ -877,7 +1156,7 @@ mod tests {
                 Token::new(TokenKind::Name, "param3", 1, 16, 6),
                 Token::new(TokenKind::RParen, ")", 1, 22, 1),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if let Balance {..} = error.kind
             {} else {
                 panic!("Expected Balance error (got: {:?})", error.kind);
@@ -893,7 +1172,7 @@ mod tests {
                 Token::new(TokenKind::Comma, ",", 1, 14, 1),
                 Token::new(TokenKind::Name, "param3", 1, 16, 6),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedEOF {
             } else {
                 panic!("Expected UnexpectedEOF error (got: {:?})", error.kind);
@@ -910,7 +1189,7 @@ mod tests {
                 Token::new(TokenKind::Name, "param3", 1, 16, 6),
                 Token::new(Seq, "===", 1, 22, 3),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedToken(Seq) {
             } else {
                 panic!("Expected UnexpectedToken error (got: {:?})", error.kind);
@@ -925,7 +1204,7 @@ mod tests {
                 Token::new(TokenKind::Name, "param2", 1, 8, 6),
                 Token::new(TokenKind::Comma, ",", 1, 14, 1),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedEOF {
             } else {
                 panic!("Expected UnexpectedEOF error (got: {:?})", error.kind);
@@ -941,7 +1220,7 @@ mod tests {
                 Token::new(TokenKind::Comma, ",", 1, 14, 1),
                 Token::new(Seq, "===", 1, 15, 3),
             ];
-            let error = Parser::new(tokens).parse_function_parameter_names("(").unwrap_err();
+            let error = Parser::new(&tokens).parse_function_parameter_names("(").unwrap_err();
             if error.kind == UnexpectedToken(Seq) {
             } else {
                 panic!("Expected UnexpectedToken error (got: {:?})", error.kind);
@@ -949,8 +1228,182 @@ mod tests {
         }
     }
 
+    mod nesting_tests {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        fn parse(source: &str) -> Result<Block, Error> {
+            Parser::new(&Lexer::new(source).lex().unwrap()).parse()
+        }
+
+        #[test]
+        fn long_operator_chains_are_not_nesting() {
+            let source = format!("x = {}", vec!["1"; MAX_NESTING * 100].join(" + "));
+            assert!(parse(&source).is_ok());
+        }
+
+        #[test]
+        fn deeply_nested_lists_are_rejected() {
+            let depth = MAX_NESTING + 1;
+            let source = format!("x = {}{}", "[ ".repeat(depth), "]] ".repeat(depth));
+            assert_eq!(
+                LimitExceeded(Limit::Depth(MAX_NESTING)),
+                parse(&source).unwrap_err().kind,
+            );
+        }
+    }
+
+    mod empty_input_tests {
+        use super::*;
+        use crate::error::ErrorKind;
+
+        #[test]
+        fn no_tokens_is_an_empty_program() {
+            assert!(Parser::new(&[]).parse().unwrap().statements().is_empty());
+            assert_eq!(Ok(""), crate::format("  \n\n").as_deref());
+            assert_eq!(Ok(()), crate::run(""));
+        }
+
+        #[test]
+        fn no_tokens_is_a_missing_expression() {
+            assert_eq!(
+                ErrorKind::MissingExpression,
+                Parser::new(&[]).parse_single_expression().unwrap_err().kind,
+            );
+        }
+    }
+
+    mod indentation_tests {
+        use super::*;
+        use crate::lexer::Lexer;
+
+        fn parse(source: &str) -> Result<Block, Error> {
+            Parser::new(&Lexer::new(source).lex().unwrap()).parse()
+        }
+
+        #[test]
+        fn blank_lines_between_statements_are_ignored() {
+            let block = parse("x = 1\n\n      \n y = 2\n \n").unwrap();
+            assert_eq!(2, block.statements().len());
+        }
+
+        #[test]
+        fn blank_lines_inside_blocks_are_ignored() {
+            let block = parse("fi rtue\n\n   x = 1\n\n \n    y = 2\n\nz = 3").unwrap();
+            let [StatementNode::Conditional(conditional), StatementNode::Assign(_)] = block.statements() else {
+                panic!("unexpected statements {:?}", block.statements());
+            };
+            assert_eq!(2, conditional.conditional_paths()[0].block().statements().len());
+        }
+
+        #[test]
+        fn blank_lines_do_not_separate_consistent_indentation() {
+            assert_eq!(
+                ConsistentIndentation { previous_indentation: 0 },
+                parse("x = 1\n   \ny = 2").unwrap_err().kind,
+            );
+        }
+
+        #[test]
+        fn lines_dedenting_several_levels_leave_every_block() {
+            let block = parse("fnuc f(()\n   fi rtue\n      x = 1\n y = 2").unwrap();
+            let [StatementNode::FunctionDefinition(definition), StatementNode::Assign(_)] = block.statements() else {
+                panic!("unexpected statements {:?}", block.statements());
+            };
+            assert_eq!(1, definition.block().statements().len());
+        }
+
+        #[test]
+        fn lefi_and_sele_must_be_on_the_level_of_their_fi() {
+            assert_eq!(
+                UnexpectedToken(Else),
+                parse("fnuc f(()\n   fi rtue\n      x = 1\nsele\n      x = 2").unwrap_err().kind,
+            );
+        }
+
+        #[test]
+        fn empty_blocks_are_rejected() {
+            let error = parse("x = 1\n fi x\ny = 2").unwrap_err();
+            assert_eq!(EmptyBlock, error.kind);
+            assert_eq!(Some(Position::new(2, 1, 2)), error.pos);
+            assert_eq!(EmptyBlock, parse("fnuc f(()\n").unwrap_err().kind);
+            assert_eq!(EmptyBlock, parse("fi rtue\n   x = 1\n sele\n").unwrap_err().kind);
+        }
+
+        #[test]
+        fn blocks_indented_too_far_are_reported_at_their_header() {
+            let error = parse("x = 1\n whitl x < 3\n         x = x + 1").unwrap_err();
+            assert_eq!(ExpectedBlock, error.kind);
+            assert_eq!(Some(Position::new(2, 1, 5)), error.pos);
+        }
+
+        #[test]
+        fn lines_indented_too_far_are_rejected() {
+            let error = parse("fi rtue\n   x = 1\n         pront((x)\ny = 2").unwrap_err();
+            assert_eq!(UnexpectedIndentation, error.kind);
+            assert_eq!(Some(Position::new(3, 0, 9)), error.pos);
+            assert_eq!(UnexpectedIndentation, parse("x = 1\n    y = 2").unwrap_err().kind);
+        }
+
+        #[test]
+        fn pas_fills_a_block_that_does_nothing() {
+            let block = parse("fnuc f(()\n   pas\nwhitl flase\n    pas").unwrap();
+            assert_eq!(2, block.statements().len());
+            assert_eq!(Ok(()), crate::run("fnuc f(()\n   pas\n f(()"));
+        }
+
+        #[test]
+        fn tabs_and_windows_line_endings_are_understood() {
+            let tokens = Lexer::new("fi rtue\r\n\tx = 1\r\n\t y = 2\r\nz = 3\r\n").lex().unwrap();
+            let block = Parser::new(&tokens).parse().unwrap();
+            let [StatementNode::Conditional(conditional), StatementNode::Assign(_)] = block.statements() else {
+                panic!("unexpected statements {:?}", block.statements());
+            };
+            assert_eq!(2, conditional.conditional_paths()[0].block().statements().len());
+        }
+    }
+
+    mod unscrambled_keyword_tests {
+        use super::*;
+        use crate::error::ErrorKind;
+        use crate::lexer::Lexer;
+
+        fn parse_error(source: &str) -> Error {
+            Parser::new(&Lexer::new(source).lex().unwrap()).parse().unwrap_err()
+        }
+
+        fn unscrambled(name: &str, keyword: &str) -> ErrorKind {
+            UnscrambledKeyword { name: name.to_string(), keyword: keyword.to_string() }
+        }
+
+        #[test]
+        fn statement_keyword() {
+            let error = parse_error("while x\n   y = 1");
+            assert_eq!(unscrambled("while", "whitl"), error.kind);
+            assert_eq!(Some(Position::new(1, 0, 5)), error.pos);
+        }
+
+        #[test]
+        fn keyword_at_end_of_file() {
+            assert_eq!(unscrambled("else", "sele"), parse_error("else").kind);
+        }
+
+        #[test]
+        fn keyword_inside_statement() {
+            let error = parse_error("fir i in arnge((3)\n   y = i");
+            assert_eq!(unscrambled("in", "ni"), error.kind);
+            assert_eq!(Some(Position::new(1, 6, 2)), error.pos);
+        }
+
+        #[test]
+        fn ordinary_names_are_unaffected() {
+            assert_eq!(UnexpectedToken(TokenKind::Name), parse_error("foo bar").kind);
+        }
+    }
+
     mod parse_expr_tests {
         use super::*;
+        use crate::lexer::Location;
 
         #[test]
         fn brackets_take_priority() {
@@ -965,17 +1418,19 @@ mod tests {
                 Token::new(RParen, "))", 1, 10, 2),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(2.0)).to_expression(),
-                    ConstantNode::new(Value::Number(4.0)).to_expression(),
+                    ConstantNode::new(Value::Number(2.0), Position::new(1, 5, 1)).to_expression(),
+                    ConstantNode::new(Value::Number(4.0), Position::new(1, 9, 1)).to_expression(),
                     Operator::Add,
+                    Position::new(1, 7, 1),
                 ).to_expression(),
                 Operator::Mul,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap()
+                Parser::new(&tokens).parse_expression().unwrap()
             );
         }
 
@@ -990,17 +1445,19 @@ mod tests {
                 Token::new(Number, "5", 1, 8, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(4.0)).to_expression(),
-                    ConstantNode::new(Value::Number(5.0)).to_expression(),
+                    ConstantNode::new(Value::Number(4.0), Position::new(1, 4, 1)).to_expression(),
+                    ConstantNode::new(Value::Number(5.0), Position::new(1, 8, 1)).to_expression(),
                     Operator::Mul,
+                    Position::new(1, 6, 1),
                 ).to_expression(),
                 Operator::Add,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1015,17 +1472,19 @@ mod tests {
                 Token::new(Number, "5", 1, 8, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(4.0)).to_expression(),
-                    ConstantNode::new(Value::Number(5.0)).to_expression(),
+                    ConstantNode::new(Value::Number(4.0), Position::new(1, 4, 1)).to_expression(),
+                    ConstantNode::new(Value::Number(5.0), Position::new(1, 8, 1)).to_expression(),
                     Operator::Mul,
+                    Position::new(1, 6, 1),
                 ).to_expression(),
                 Operator::Sub,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1040,17 +1499,19 @@ mod tests {
                 Token::new(Number, "4", 1, 9, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(12.0)).to_expression(),
-                    ConstantNode::new(Value::Number(4.0)).to_expression(),
+                    ConstantNode::new(Value::Number(12.0), Position::new(1, 4, 2)).to_expression(),
+                    ConstantNode::new(Value::Number(4.0), Position::new(1, 9, 1)).to_expression(),
                     Operator::Div,
+                    Position::new(1, 7, 1),
                 ).to_expression(),
                 Operator::Add,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1065,17 +1526,19 @@ mod tests {
                 Token::new(Number, "4", 1, 9, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(12.0)).to_expression(),
-                    ConstantNode::new(Value::Number(4.0)).to_expression(),
+                    ConstantNode::new(Value::Number(12.0), Position::new(1, 4, 2)).to_expression(),
+                    ConstantNode::new(Value::Number(4.0), Position::new(1, 9, 1)).to_expression(),
                     Operator::Div,
+                    Position::new(1, 7, 1),
                 ).to_expression(),
                 Operator::Sub,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1090,17 +1553,19 @@ mod tests {
                 Token::new(Number, "5", 1, 9, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(12.0)).to_expression(),
-                    ConstantNode::new(Value::Number(5.0)).to_expression(),
+                    ConstantNode::new(Value::Number(12.0), Position::new(1, 4, 2)).to_expression(),
+                    ConstantNode::new(Value::Number(5.0), Position::new(1, 9, 1)).to_expression(),
                     Operator::Mod,
+                    Position::new(1, 7, 1),
                 ).to_expression(),
                 Operator::Add,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1115,17 +1580,19 @@ mod tests {
                 Token::new(Number, "5", 1, 9, 1),
             ];
             let expected_ast = OperatorNode::new(
-                ConstantNode::new(Value::Number(3.0)).to_expression(),
+                ConstantNode::new(Value::Number(3.0), Position::new(1, 0, 1)).to_expression(),
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(12.0)).to_expression(),
-                    ConstantNode::new(Value::Number(5.0)).to_expression(),
+                    ConstantNode::new(Value::Number(12.0), Position::new(1, 4, 2)).to_expression(),
+                    ConstantNode::new(Value::Number(5.0), Position::new(1, 9, 1)).to_expression(),
                     Operator::Mod,
+                    Position::new(1, 7, 1),
                 ).to_expression(),
                 Operator::Sub,
+                Position::new(1, 2, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1157,32 +1624,38 @@ mod tests {
             //     5   2   3   4    2   4
             let expected_ast = OperatorNode::new(
                 OperatorNode::new(
-                    ConstantNode::new(Value::Number(7.0)).to_expression(),
+                    ConstantNode::new(Value::Number(7.0), Position::new(1, 0, 1)).to_expression(),
                     OperatorNode::new(
-                        ConstantNode::new(Value::Number(5.0)).to_expression(),
-                        ConstantNode::new(Value::Number(2.0)).to_expression(),
+                        ConstantNode::new(Value::Number(5.0), Position::new(1, 4, 1)).to_expression(),
+                        ConstantNode::new(Value::Number(2.0), Position::new(1, 8, 1)).to_expression(),
                         Operator::Mod,
+                        Position::new(1, 6, 1),
                     ).to_expression(),
                     Operator::Sub,
+                    Position::new(1, 2, 1),
                 ).to_expression(),
                 OperatorNode::new(
                     OperatorNode::new(
-                        ConstantNode::new(Value::Number(3.0)).to_expression(),
-                        ConstantNode::new(Value::Number(4.0)).to_expression(),
+                        ConstantNode::new(Value::Number(3.0), Position::new(1, 12, 1)).to_expression(),
+                        ConstantNode::new(Value::Number(4.0), Position::new(1, 16, 1)).to_expression(),
                         Operator::Mul,
+                        Position::new(1, 14, 1),
                     ).to_expression(),
                     OperatorNode::new(
-                        ConstantNode::new(Value::Number(2.0)).to_expression(),
-                        ConstantNode::new(Value::Number(4.0)).to_expression(),
+                        ConstantNode::new(Value::Number(2.0), Position::new(1, 21, 1)).to_expression(),
+                        ConstantNode::new(Value::Number(4.0), Position::new(1, 25, 1)).to_expression(),
                         Operator::Add,
+                        Position::new(1, 23, 1),
                     ).to_expression(),
                     Operator::Div,
+                    Position::new(1, 18, 1),
                 ).to_expression(),
                 Operator::Add,
+                Position::new(1, 10, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             )
         }
 
@@ -1193,10 +1666,10 @@ mod tests {
                 Token::new(Number, "1", 1, 1, 1),
                 Token::new(RParen, ")", 1, 2, 1),
             ];
-            let result = Parser::new(tokens).parse_expression();
+            let result = Parser::new(&tokens).parse_expression();
             match result {
                 Ok(_) => panic!("Expected Balance error, got Ok()"),
-                Err(Error { kind: Balance { opener, closer }, ..}) => {
+                Err(Error { kind: Balance { opener, closer, .. }, ..}) => {
                     assert_eq!("(", opener);
                     assert_eq!(")", closer);
                 },
@@ -1204,15 +1677,75 @@ mod tests {
             }
         }
 
+        #[test]
+        fn unterminated_parentheses_error_spans_to_end() {
+            // (1 +
+            // 2
+            let tokens = vec![
+                Token::new(LParen, "(", 1, 0, 1),
+                Token::new(Number, "1", 1, 1, 1),
+                Token::new(Plus, "+", 1, 3, 1),
+                Token::new(Newline, "\n", 1, 4, 1),
+                Token::new(Number, "2", 2, 0, 1),
+            ];
+            let error = Parser::new(&tokens).parse_expression().unwrap_err();
+            assert_eq!(MissingToken(RParen), error.kind);
+            assert_eq!(
+                Some(Position::span(Location::new(1, 0), Location::new(2, 2))),
+                error.pos,
+            );
+        }
+
+        fn parse_source(source: &str) -> Result<ExpressionNode, Error> {
+            Parser::new(&crate::lexer::Lexer::new(source).lex().unwrap()).parse_single_expression()
+        }
+
+        #[test]
+        fn calls_can_be_operands() {
+            let ExpressionNode::Operator(operator) = parse_source("f((1) + g(2)) * 3").unwrap() else {
+                panic!("expected an operation");
+            };
+            assert_eq!(&Operator::Add, operator.operator());
+            assert!(matches!(operator.lhs(), ExpressionNode::FunctionCall(_)));
+            assert!(matches!(operator.rhs(), ExpressionNode::Operator(_)));
+        }
+
+        #[test]
+        fn balance_is_checked_against_the_matching_opener() {
+            // the parenthesis around the call is the one balanced, not the call's own
+            let error = parse_source("(f((1) ) + 2").unwrap_err();
+            assert_eq!(
+                Balance {
+                    opener: "(".to_string(),
+                    closer: ")".to_string(),
+                    opener_position: Some(Position::new(1, 0, 1)),
+                },
+                error.kind,
+            );
+            assert_eq!(Some(Position::new(1, 7, 1)), error.pos);
+
+            // and nested calls are checked against their own openers
+            let error = parse_source("f((g((x)) )").unwrap_err();
+            assert_eq!(
+                Balance {
+                    opener: "((".to_string(),
+                    closer: "))".to_string(),
+                    opener_position: Some(Position::new(1, 4, 2)),
+                },
+                error.kind,
+            );
+            assert_eq!(Some(Position::new(1, 7, 2)), error.pos);
+        }
+
         #[test]
         fn single_variable_parsing() {
             let tokens = vec![
                 Token::new(TokenKind::Name, "a", 1, 0, 1),
             ];
-            let expected_ast = VariableNode::new("a".to_string()).to_expression();
+            let expected_ast = VariableNode::new("a".to_string(), Position::new(1, 0, 1)).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             );
         }
 
@@ -1244,32 +1777,38 @@ mod tests {
             //       five  2   three  four  2  four
             let expected_ast = OperatorNode::new(
                 OperatorNode::new(
-                    VariableNode::new("seven".to_string()).to_expression(),
+                    VariableNode::new("seven".to_string(), Position::new(1, 0, 5)).to_expression(),
                     OperatorNode::new(
-                        VariableNode::new("five".to_string()).to_expression(),
-                        ConstantNode::new(Value::Number(2.0)).to_expression(),
+                        VariableNode::new("five".to_string(), Position::new(1, 8, 4)).to_expression(),
+                        ConstantNode::new(Value::Number(2.0), Position::new(1, 15, 1)).to_expression(),
                         Operator::Mod,
+                        Position::new(1, 13, 1),
                     ).to_expression(),
                     Operator::Sub,
+                    Position::new(1, 6, 1),
                 ).to_expression(),
                 OperatorNode::new(
                     OperatorNode::new(
-                        VariableNode::new("three".to_string()).to_expression(),
-                        VariableNode::new("four".to_string()).to_expression(),
+                        VariableNode::new("three".to_string(), Position::new(1, 19, 5)).to_expression(),
+                        VariableNode::new("four".to_string(), Position::new(1, 27, 4)).to_expression(),
                         Operator::Mul,
+                        Position::new(1, 25, 1),
                     ).to_expression(),
                     OperatorNode::new(
-                        ConstantNode::new(Value::Number(2.0)).to_expression(),
-                        VariableNode::new("four".to_string()).to_expression(),
+                        ConstantNode::new(Value::Number(2.0), Position::new(1, 35, 1)).to_expression(),
+                        VariableNode::new("four".to_string(), Position::new(39, 25, 4)).to_expression(),
                         Operator::Add,
+                        Position::new(1, 37, 1),
                     ).to_expression(),
                     Operator::Div,
+                    Position::new(1, 32, 1),
                 ).to_expression(),
                 Operator::Add,
+                Position::new(1, 17, 1),
             ).to_expression();
             assert_eq!(
                 expected_ast,
-                Parser::new(tokens).parse_expression().unwrap(),
+                Parser::new(&tokens).parse_expression().unwrap(),
             )
         }
     }
@@ -0,0 +1,19 @@
+/// A small xorshift generator. It's nowhere near good enough for cryptography, but it's plenty for
+/// choosing lengths and for programs that want some variety, and the same seed always gives the
+/// same numbers.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Random(u64);
+impl Random {
+    pub(crate) fn new(seed: u64) -> Random {
+        // xorshift gets stuck at zero, so mix the seed into a non-zero state
+        Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// Returns a number from 0 up to but not including `bound`.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
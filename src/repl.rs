@@ -0,0 +1,398 @@
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use mornington::diagnostics::Diagnostic;
+use mornington::runtime::Runtime;
+use mornington::snapshot::Snapshot;
+use mornington::value::{PRETTY_MAX_DEPTH, PRETTY_MAX_ITEMS};
+use crate::Reporter;
+
+
+const PROMPT: &str = "mron> ";
+const CONTINUATION_PROMPT: &str = "....  ";
+/// The most lines kept in the history file.
+const MAX_HISTORY: usize = 1000;
+/// Keywords that begin a line followed by an indented block.
+const BLOCK_KEYWORDS: [&str; 4] = ["fi", "whitl", "fir", "fnuc"];
+const KEYWORDS: [&str; 15] = [
+    "fi", "lefi", "sele", "whitl", "fir", "ni", "brek", "cnotineu", "pas", "fnuc", "retrun", "adn",
+    "ro", "rtue", "flase",
+];
+
+
+/// Reads entries from the terminal and runs each one in `runtime` until the input ends. Entries
+/// that aren't valid programs but are valid expressions have their values printed. A line
+/// starting a block is followed by further lines until a blank one, and one starting with `:` is
+/// a command for the session itself, such as `:save` or `:load`.
+pub fn run(mut runtime: Runtime, reporter: &Reporter) {
+    let mut editor = LineEditor::new(history_file());
+    eprintln!("Mornington {} (press Ctrl-D to exit)", crate::cli::VERSION);
+    while let Some(line) = editor.read_line(PROMPT, &runtime) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(command) = line.trim().strip_prefix(':') {
+            session_command(command, &mut runtime, reporter);
+            continue;
+        }
+        let mut entry = line;
+        if starts_block(&entry) {
+            while let Some(line) = editor.read_line(CONTINUATION_PROMPT, &runtime) {
+                if line.trim().is_empty() {
+                    break;
+                }
+                entry = format!("{entry}\n{line}");
+            }
+        }
+        evaluate(&entry, &mut runtime, reporter);
+    }
+}
+
+fn evaluate(entry: &str, runtime: &mut Runtime, reporter: &Reporter) {
+    // only try the entry as an expression if it isn't a valid program, so that statements such as
+    // calls aren't run twice
+    if let Err(error) = mornington::check(entry) {
+        match mornington::eval_expression_in(entry, runtime) {
+            Ok(value) => println!("{}", value.display_pretty(PRETTY_MAX_DEPTH, PRETTY_MAX_ITEMS)),
+            Err(_) => reporter.report(&Diagnostic::from_error(&error), entry),
+        }
+        return;
+    }
+    if let Err(error) = mornington::run_in(entry, runtime) {
+        reporter.report(&Diagnostic::from_error(&error), entry);
+    }
+    // leave the prompt on a line of its own, even if the program didn't end with a newline
+    println!();
+}
+
+/// Runs `:save <file>`, which snapshots the session's variables and functions to a file, or
+/// `:load <file>`, which restores them.
+fn session_command(command: &str, runtime: &mut Runtime, reporter: &Reporter) {
+    let (name, path) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+    let path = path.trim();
+    let result = match name {
+        "save" if !path.is_empty() => runtime.snapshot().save(path),
+        "load" if !path.is_empty() => Snapshot::load(path).and_then(|snapshot| runtime.restore(&snapshot)),
+        "save" | "load" => return eprintln!("Usage: :{name} <file>"),
+        _ => return eprintln!("Unknown command `:{name}`, expected `:save <file>` or `:load <file>`"),
+    };
+    if let Err(error) = result {
+        reporter.report(&Diagnostic::from_error(&error), "");
+    }
+}
+
+fn starts_block(line: &str) -> bool {
+    line.split_whitespace().next().is_some_and(|word| BLOCK_KEYWORDS.contains(&word))
+}
+
+/// Returns the history file: `MORNINGTON_HISTORY` if it's set, otherwise `.mornington_history` in
+/// the home directory.
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("MORNINGTON_HISTORY").map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".mornington_history")))
+}
+
+/// Returns every keyword, variable, function, and builtin name starting with `prefix`, sorted
+/// and without duplicates.
+fn completions(prefix: &str, runtime: &Runtime) -> Vec<String> {
+    let mut names: Vec<String> = KEYWORDS.iter().map(|keyword| keyword.to_string())
+        .chain(runtime.visible_variables().into_iter().map(|(name, _)| name))
+        .chain(runtime.visible_functions())
+        .chain(runtime.builtin_names())
+        .filter(|name| name.starts_with(prefix))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Returns the longest prefix shared by all of `names`.
+fn common_prefix(names: &[String]) -> &str {
+    let Some(first) = names.first() else {
+        return "";
+    };
+    // names can hold any letters, so compare characters rather than bytes, which would stop
+    // partway through one that's several bytes long
+    let mut length = first.len();
+    for name in names {
+        length = first.char_indices().zip(name.chars())
+            .take_while(|&((index, a), b)| index < length && a == b)
+            .last()
+            .map_or(0, |((index, a), _)| index + a.len_utf8());
+    }
+    &first[..length]
+}
+
+
+/// Reads lines from the terminal with basic editing: moving the cursor with the arrow keys,
+/// Home, and End, recalling earlier lines with up and down, and completing names with Tab. When
+/// stdin isn't a terminal, lines are read as they are.
+struct LineEditor {
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+}
+impl LineEditor {
+    fn new(history_file: Option<PathBuf>) -> LineEditor {
+        let history = history_file.as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|history| history.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        LineEditor { history, history_file }
+    }
+
+    /// Reads a line, returning `None` once the input has ended.
+    fn read_line(&mut self, prompt: &str, runtime: &Runtime) -> Option<String> {
+        let line = if io::stdin().is_terminal() {
+            let _raw_mode = RawMode::enable();
+            let mut input = io::stdin().lock().bytes().map_while(Result::ok);
+            self.edit(prompt, &mut input, &mut io::stdout(), |prefix| completions(prefix, runtime))
+        } else {
+            print!("{prompt}");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+            }
+        }?;
+        self.remember(&line);
+        Some(line)
+    }
+
+    /// Adds the line to the history, saving it to the history file if there is one.
+    fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.history_file {
+            let _ = fs::write(path, self.history.join("\n") + "\n");
+        }
+    }
+
+    /// Edits a line from the keys in `input`, echoing it to `output`. Returns `None` if the input
+    /// ends, or Ctrl-D is pressed on an empty line.
+    fn edit(&self,
+            prompt: &str,
+            input: &mut impl Iterator<Item = u8>,
+            output: &mut impl Write,
+            complete: impl Fn(&str) -> Vec<String>)
+        -> Option<String>
+    {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // how far back through the history the line is, with 0 being a new line
+        let mut recalled = 0;
+        let mut pending = Vec::new();
+        redraw(output, prompt, &line, cursor);
+
+        loop {
+            let byte = input.next()?;
+            match byte {
+                b'\r' | b'\n' => {
+                    let _ = write!(output, "\r\n");
+                    let _ = output.flush();
+                    return Some(line.into_iter().collect());
+                },
+                // Ctrl-D
+                4 if line.is_empty() => {
+                    let _ = write!(output, "\r\n");
+                    return None;
+                },
+                // Ctrl-C abandons the line
+                3 => {
+                    line.clear();
+                    cursor = 0;
+                    let _ = write!(output, "^C\r\n");
+                },
+                // Ctrl-A and Ctrl-E
+                1 => cursor = 0,
+                5 => cursor = line.len(),
+                // backspace
+                8 | 127 => if cursor > 0 {
+                    cursor -= 1;
+                    line.remove(cursor);
+                },
+                b'\t' => {
+                    let start = line[..cursor].iter()
+                        .rposition(|character| !is_name_character(*character))
+                        .map_or(0, |index| index + 1);
+                    let prefix: String = line[start..cursor].iter().collect();
+                    let candidates = complete(&prefix);
+                    let completion = common_prefix(&candidates);
+                    if completion.len() > prefix.len() {
+                        for character in completion[prefix.len()..].chars() {
+                            line.insert(cursor, character);
+                            cursor += 1;
+                        }
+                    } else if candidates.len() > 1 {
+                        let _ = write!(output, "\r\n{}\r\n", candidates.join("  "));
+                    }
+                },
+                // escape sequences for the arrow, Home, and End keys
+                27 => {
+                    if input.next() != Some(b'[') {
+                        continue;
+                    }
+                    match input.next()? {
+                        b'A' if recalled < self.history.len() => {
+                            recalled += 1;
+                            line = self.history[self.history.len() - recalled].chars().collect();
+                            cursor = line.len();
+                        },
+                        b'B' if recalled > 0 => {
+                            recalled -= 1;
+                            line = match recalled {
+                                0 => Vec::new(),
+                                _ => self.history[self.history.len() - recalled].chars().collect(),
+                            };
+                            cursor = line.len();
+                        },
+                        b'C' => cursor = (cursor + 1).min(line.len()),
+                        b'D' => cursor = cursor.saturating_sub(1),
+                        b'H' => cursor = 0,
+                        b'F' => cursor = line.len(),
+                        _ => {},
+                    }
+                },
+                control if control < 32 => {},
+                _ => {
+                    // collect the bytes of multi-byte characters before inserting them
+                    pending.push(byte);
+                    match std::str::from_utf8(&pending) {
+                        Ok(text) => {
+                            for character in text.chars() {
+                                line.insert(cursor, character);
+                                cursor += 1;
+                            }
+                            pending.clear();
+                        },
+                        Err(error) if error.error_len().is_some() => pending.clear(),
+                        Err(_) => continue,
+                    }
+                },
+            }
+            redraw(output, prompt, &line, cursor);
+        }
+    }
+}
+
+fn is_name_character(character: char) -> bool {
+    character.is_alphanumeric() || character == '_'
+}
+
+/// Clears the current terminal line and redraws the prompt and line, with the cursor in place.
+fn redraw(output: &mut impl Write, prompt: &str, line: &[char], cursor: usize) {
+    let line: String = line.iter().collect();
+    let _ = write!(output, "\r\x1b[K{prompt}{line}\r");
+    let column = prompt.chars().count() + cursor;
+    if column > 0 {
+        let _ = write!(output, "\x1b[{column}C");
+    }
+    let _ = output.flush();
+}
+
+
+/// Puts the terminal into a mode where keys are read as they're pressed, without being echoed,
+/// until dropped. There's no way to do this with the standard library alone, so it's done with
+/// `stty`; if that isn't available, the terminal is left as it is.
+struct RawMode {
+    saved_settings: Option<String>,
+}
+impl RawMode {
+    fn enable() -> RawMode {
+        let saved_settings = stty(&["-g"]).filter(|_| {
+            stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"]).is_some()
+        });
+        RawMode { saved_settings }
+    }
+}
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(settings) = &self.saved_settings {
+            stty(&[settings.trim()]);
+        }
+    }
+}
+
+/// Runs `stty` on the terminal, returning what it printed if it succeeded.
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()
+        .ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(history: &[&str], keys: &str) -> Option<String> {
+        let editor = LineEditor {
+            history: history.iter().map(|line| line.to_string()).collect(),
+            history_file: None,
+        };
+        let names = ["prointl".to_string(), "pront".to_string(), "x_total".to_string()];
+        let complete = |prefix: &str| {
+            names.iter().filter(|name| name.starts_with(prefix)).cloned().collect()
+        };
+        editor.edit("> ", &mut keys.bytes(), &mut Vec::new(), complete)
+    }
+
+    #[test]
+    fn editing_keys() {
+        assert_eq!(Some("x = 12".to_string()), edit(&[], "x = 2\x1b[D1\r"));
+        assert_eq!(Some("y = 1".to_string()), edit(&[], "x = 1\x01\x1b[C\x08y\r"));
+        assert_eq!(None, edit(&[], "\x04"));
+        assert_eq!(None, edit(&[], "x = "));
+    }
+
+    #[test]
+    fn history_is_recalled() {
+        assert_eq!(Some("b".to_string()), edit(&["a", "b"], "\x1b[A\r"));
+        assert_eq!(Some("a".to_string()), edit(&["a", "b"], "\x1b[A\x1b[A\x1b[A\r"));
+        assert_eq!(Some("".to_string()), edit(&["a", "b"], "\x1b[A\x1b[B\r"));
+    }
+
+    #[test]
+    fn tab_completes_common_prefix() {
+        assert_eq!(Some("x_total = pro".to_string()), edit(&[], "x\t = p\t\r"));
+        assert_eq!(Some("prointl((".to_string()), edit(&[], "proi\t((\r"));
+    }
+
+    #[test]
+    fn common_prefixes_end_between_characters() {
+        assert_eq!("x", common_prefix(&["xè".to_string(), "xé".to_string()]));
+        assert_eq!("ñam", common_prefix(&["ñame".to_string(), "ñamé".to_string()]));
+        assert_eq!("", common_prefix(&["é".to_string(), "è".to_string()]));
+    }
+
+    #[test]
+    fn completions_include_definitions_and_keywords() {
+        let mut runtime = Runtime::new();
+        mornington::run_in("fnuc frob(()\n   retrun 1\nfinal = 2", &mut runtime).unwrap();
+        assert_eq!(
+            vec!["fi".to_string(), "final".to_string(), "fir".to_string()],
+            completions("fi", &runtime),
+        );
+        assert_eq!(vec!["frob".to_string()], completions("fr", &runtime));
+    }
+
+    #[test]
+    fn history_persists() {
+        let path = std::env::temp_dir().join("mornington-repl-history");
+        let _ = fs::remove_file(&path);
+        let mut editor = LineEditor::new(Some(path.clone()));
+        editor.remember("x = 1");
+        editor.remember("x = 1");
+        editor.remember("pront((x)");
+        assert_eq!(vec!["x = 1", "pront((x)"], LineEditor::new(Some(path)).history);
+    }
+}
@@ -1,58 +1,609 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::io::{self, BufRead, Read, Write};
 use std::rc::Rc;
-use crate::ast::FunctionDefinitionNode;
-use crate::error::{Error, ErrorKind::Name};
-use crate::value::Value;
+use std::time::{Duration, Instant};
+use crate::ast::{FunctionDefinitionNode, ListNode, StatementNode};
+use crate::builtins::{Arity, Builtin, BuiltinContext, BuiltinRegistry, Elements};
+use crate::coverage::Coverage;
+use crate::debugger::{DebugContext, Debugger};
+use crate::error::{Error, ErrorKind::{Data, Frozen, LimitExceeded, MemoryLimit, ModuleCycle, Name, Output}};
+use crate::hooks::RuntimeHooks;
+use crate::lexer::Position;
+use crate::random::Random;
+use crate::snapshot::Snapshot;
+use crate::symbol::Symbol;
+use crate::value::{Coercion, Comparison, Value};
+use tasks::Tasks;
+
+pub use config::Config;
+
+mod config;
+mod tasks;
 
 
-#[derive(Debug, Default, PartialEq)]
 pub struct Runtime {
     stack: Vec<Scope>,
+    /// Scopes the host has provided that the program can't change, searched after the stack, the
+    /// last pushed first.
+    frozen: Vec<Scope>,
+    /// The index in the stack of the first scope of each function call in progress, innermost
+    /// last.
+    frames: Vec<usize>,
+    builtins: BuiltinRegistry,
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+    stdin: Box<dyn BufRead>,
+    module_loader: ModuleLoader,
+    /// The names of the modules being imported, outermost first.
+    importing: Vec<String>,
+    data_loader: DataLoader,
+    /// The values of the data files loaded so far for `dtaa`, by path.
+    data: HashMap<String, Value>,
+    random: Random,
+    config: Config,
+    limits: Limits,
+    debugger: Option<Box<dyn Debugger>>,
+    hooks: Option<Box<dyn RuntimeHooks>>,
+    stats: Stats,
+    coverage: Option<Coverage>,
+    tasks: Tasks,
+}
+
+/// Counts of what a runtime has done, for profiling programs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats {
+    /// The number of statements executed.
+    pub statements: u64,
+    /// The largest number of scopes that were on the stack at once.
+    pub peak_scope_depth: usize,
+    /// The number of values holding heap memory, i.e. strings and lists, stored in variables.
+    pub allocations: u64,
+    /// The most heap memory, in bytes, held by variables at once.
+    pub peak_memory: usize,
+}
+
+/// An execution limit that a program can be stopped for exceeding.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Limit {
+    /// The maximum number of statements executed.
+    Steps(u64),
+    /// The maximum wall-clock time spent executing.
+    Duration(Duration),
+    /// The maximum depth that function calls can be nested to.
+    Depth(usize),
+}
+
+/// The maximum depth that a new runtime lets function calls be nested to. The stack grows onto
+/// the heap as calls need it, so this is only there to stop runaway recursion before it uses up
+/// memory.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// How far a program has got towards the limits in the runtime's [`Config`].
+#[derive(Debug)]
+struct Limits {
+    steps: u64,
+    start: Instant,
+    memory_used: usize,
+    depth: usize,
 }
 
-#[derive(Debug, Default, PartialEq)]
+/// Variables are kept in the order they were created, so that they can be found by their slot in
+/// that order as well as by name, and functions in order of name, so that anything listing them
+/// (or printing the scope) always does so in the same order. Only the index of slots by name is
+/// unordered, since it's never listed.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Scope {
-    variables: HashMap<String, Value>,
-    functions: HashMap<String, Rc<RefCell<FunctionDefinitionNode>>>,
+    variables: Vec<(Symbol, Value)>,
+    slots: HashMap<Symbol, usize>,
+    functions: BTreeMap<Symbol, Rc<FunctionDefinitionNode>>,
+}
+
+/// Where a variable is expected to be found: in the scope `depth` scopes below the uppermost, at
+/// position `index` among the variables created there. Slots are worked out before a program
+/// runs (see [`Block::resolve`](crate::ast::Block::resolve)), and are only ever hints, since
+/// variables can also be created where they couldn't be predicted, e.g. by a caller or the REPL.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
 }
 
 
 impl Runtime {
     pub fn new() -> Runtime {
+        Runtime::with_config(Config::default())
+    }
+
+    /// Creates a runtime that runs programs as `config` says.
+    pub fn with_config(config: Config) -> Runtime {
         Runtime {
             stack: vec![Scope::new()],
+            frozen: Vec::new(),
+            frames: Vec::new(),
+            builtins: BuiltinRegistry::with_standard_library(),
+            stdout: default_stdout(),
+            stderr: default_stderr(),
+            stdin: default_stdin(),
+            module_loader: Box::new(default_module_loader),
+            importing: Vec::new(),
+            data_loader: Box::new(default_data_loader),
+            data: HashMap::new(),
+            random: Random::new(config.seed),
+            config,
+            limits: Limits {
+                steps: 0,
+                start: Instant::now(),
+                memory_used: 0,
+                depth: 0,
+            },
+            debugger: None,
+            hooks: None,
+            stats: Stats { peak_scope_depth: 1, ..Stats::default() },
+            coverage: None,
+            tasks: Tasks::default(),
+        }
+    }
+
+    /// Creates a runtime that stops programs with a `LimitExceeded` error once they have executed
+    /// more than `max_steps` statements, or run for longer than `max_duration` since the runtime
+    /// was created. `None` leaves that limit unbounded.
+    pub fn with_limits(max_steps: Option<u64>, max_duration: Option<Duration>) -> Runtime {
+        Runtime::with_config(Config { max_steps, max_duration, ..Config::default() })
+    }
+
+    /// Returns how the runtime runs programs, including any changes made since it was created.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Records that a statement is about to be executed, throwing an error if doing so would
+    /// exceed the runtime's limits.
+    pub(crate) fn step(&mut self) -> Result<(), Error> {
+        self.limits.steps += 1;
+        self.stats.statements += 1;
+        if let Some(max_steps) = self.config.max_steps {
+            if self.limits.steps > max_steps {
+                return Err(Error::new(LimitExceeded(Limit::Steps(max_steps)), None));
+            }
+        }
+        if let Some(max_duration) = self.config.max_duration {
+            if self.limits.start.elapsed() > max_duration {
+                return Err(Error::new(LimitExceeded(Limit::Duration(max_duration)), None));
+            }
+        }
+        Ok(())
+    }
+
+    /// Caps how deeply function calls, including calls to builtins, can be nested before a
+    /// `LimitExceeded` error is thrown. The stack is grown as deeper calls need it, so the only
+    /// cost of raising the limit is the memory that runaway recursion can use up before it's
+    /// stopped.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.config.max_depth = max_depth;
+    }
+
+    /// Records that a function call is starting, throwing an error if that would nest calls too
+    /// deeply. Must be paired with a call to [`Runtime::ascend`].
+    pub(crate) fn descend(&mut self) -> Result<(), Error> {
+        if self.limits.depth >= self.config.max_depth {
+            return Err(Error::new(LimitExceeded(Limit::Depth(self.config.max_depth)), None));
+        }
+        self.limits.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn ascend(&mut self) {
+        self.limits.depth -= 1;
+    }
+
+    /// Caps the approximate heap memory held by variables at `max_bytes`. Assignments or
+    /// operations that would exceed the cap throw a `MemoryLimit` error. `None` removes the cap.
+    pub fn set_memory_limit(&mut self, max_bytes: Option<usize>) {
+        self.config.max_memory = max_bytes;
+    }
+
+    /// Returns the approximate number of bytes of heap memory held by live variables.
+    pub fn memory_used(&self) -> usize {
+        self.limits.memory_used
+    }
+
+    /// Throws an error if allocating a further `bytes` bytes would exceed the memory limit.
+    pub(crate) fn check_allocation(&self, bytes: usize) -> Result<(), Error> {
+        self.check_memory(self.limits.memory_used.saturating_add(bytes))
+    }
+
+    fn check_memory(&self, used: usize) -> Result<(), Error> {
+        match self.config.max_memory {
+            Some(max_bytes) if used > max_bytes => {
+                Err(Error::new(MemoryLimit { max_bytes }, None))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Sets whether each statement and assignment is logged to the runtime's stderr stream as it
+    /// executes.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.config.trace = trace;
+    }
+
+    pub(crate) fn is_tracing(&self) -> bool {
+        self.config.trace
+    }
+
+    /// Sets how operators treat operands of different types. Runtimes start out with
+    /// [`Coercion::LhsDriven`].
+    pub fn set_coercion(&mut self, coercion: Coercion) {
+        self.config.coercion = coercion;
+    }
+
+    pub(crate) fn coercion(&self) -> Coercion {
+        self.config.coercion
+    }
+
+    /// Sets how the relational operators compare their operands. Runtimes start out with
+    /// [`Comparison::Numeric`].
+    pub fn set_comparison(&mut self, comparison: Comparison) {
+        self.config.comparison = comparison;
+    }
+
+    pub(crate) fn comparison(&self) -> Comparison {
+        self.config.comparison
+    }
+
+    /// Sets whether operators fail with a `Type` error when applied to values of types they would
+    /// otherwise coerce, rather than coercing them.
+    pub fn set_strict_types(&mut self, strict_types: bool) {
+        self.config.strict_types = strict_types;
+    }
+
+    pub(crate) fn has_strict_types(&self) -> bool {
+        self.config.strict_types
+    }
+
+    /// Sets whether function calls can see and assign to the variables of whatever called them,
+    /// as they could in earlier versions, rather than only their own variables and the global
+    /// scope's.
+    pub fn set_dynamic_scoping(&mut self, dynamic_scoping: bool) {
+        self.config.dynamic_scoping = dynamic_scoping;
+    }
+
+    pub(crate) fn has_dynamic_scoping(&self) -> bool {
+        self.config.dynamic_scoping
+    }
+
+    /// Sets whether `arnge` takes its three-argument form as `arnge((start, step, stop)`, as it
+    /// did in earlier versions, rather than `arnge((start, stop, step)`.
+    pub fn set_legacy_range(&mut self, legacy_range: bool) {
+        self.config.legacy_range = legacy_range;
+    }
+
+    pub(crate) fn has_legacy_range(&self) -> bool {
+        self.config.legacy_range
+    }
+
+    /// Sets whether each `whitl` and `fir` loop runs all its iterations in the one scope, as in
+    /// earlier versions, so that variables created by one iteration are still there in the next,
+    /// rather than giving each iteration a scope of its own.
+    pub fn set_shared_loop_scopes(&mut self, shared_loop_scopes: bool) {
+        self.config.shared_loop_scopes = shared_loop_scopes;
+    }
+
+    /// Logs the message to the runtime's stderr stream as part of the execution trace, along with
+    /// the line it comes from.
+    pub(crate) fn write_trace(&mut self, position: Position, message: &str) -> Result<(), Error> {
+        writeln!(self.stderr, "[trace] line {}: {message}", position.start.line)
+            .map_err(|_| Error::new(Output, None))
+    }
+
+    /// Logs the assignment to the execution trace, if tracing is enabled, and passes it to the
+    /// hooks, if there are any.
+    pub(crate) fn record_assignment(&mut self, position: Position, name: &str, value: &Value)
+        -> Result<(), Error>
+    {
+        if let Some(hooks) = self.hooks() {
+            hooks.assign(name, value, position);
+        }
+        if self.config.trace {
+            self.write_trace(position, &format!("{name} = {value}"))?;
+        }
+        Ok(())
+    }
+
+    pub fn set_debugger(&mut self, debugger: impl Debugger + 'static) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    /// Passes the statement about to be executed to the debugger, if there is one.
+    pub(crate) fn debug_statement(&mut self, statement: &StatementNode) {
+        // the debugger is taken out for the duration of the call so that it can be handed the
+        // runtime mutably
+        if let Some(mut debugger) = self.debugger.take() {
+            debugger.before_statement(&mut DebugContext::new(self, statement));
+            self.debugger = Some(debugger);
         }
     }
 
+    /// Tells the debugger, if there is one, that the named function is about to be called.
+    pub(crate) fn debug_call(&mut self, name: &str) {
+        if let Some(debugger) = &mut self.debugger {
+            debugger.before_call(name);
+        }
+    }
+
+    /// Attaches `hooks` to the runtime, replacing any already attached, so that they're told about
+    /// everything the runtime executes from now on.
+    pub fn set_hooks(&mut self, hooks: impl RuntimeHooks + 'static) {
+        self.hooks = Some(Box::new(hooks));
+    }
+
+    pub(crate) fn hooks(&mut self) -> Option<&mut (dyn RuntimeHooks + 'static)> {
+        self.hooks.as_deref_mut()
+    }
+
+    /// Starts counting how many times each statement is executed, discarding any counts so far,
+    /// or stops counting.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = enabled.then(Coverage::new);
+    }
+
+    /// Returns the statements executed since coverage was turned on, if it is.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Records the statement at `position` as executed, if coverage is turned on.
+    pub(crate) fn cover(&mut self, position: Position) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(position);
+        }
+    }
+
+    /// Returns what the runtime has done so far.
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Returns the number of scopes on the stack.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns every variable visible from the uppermost scope, including frozen ones, sorted by
+    /// name.
+    pub fn visible_variables(&self) -> Vec<(String, Value)> {
+        Self::variables_in(self.frozen.iter().chain(self.visible_scopes().map(|scope| &self.stack[scope])))
+    }
+
+    /// Returns the variables in `scopes`, sorted by name, with those in later scopes shadowing
+    /// those of the same name in earlier ones.
+    fn variables_in<'a>(scopes: impl Iterator<Item = &'a Scope>) -> Vec<(String, Value)> {
+        let mut variables: BTreeMap<Symbol, &Value> = BTreeMap::new();
+        for scope in scopes {
+            variables.extend(scope.variables.iter().map(|(name, value)| (*name, value)));
+        }
+        variables.into_iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect()
+    }
+
+    /// Returns the names of every function visible from the uppermost scope, including frozen
+    /// ones, sorted and without duplicates.
+    pub fn visible_functions(&self) -> Vec<String> {
+        let functions: BTreeSet<Symbol> = self.frozen.iter().chain(&self.stack)
+            .flat_map(|scope| scope.functions.keys().copied())
+            .collect();
+        functions.iter().map(Symbol::to_string).collect()
+    }
+
+    /// Captures every variable and function visible from the uppermost scope, so that they can be
+    /// saved and later put back with [`Runtime::restore`]. Frozen variables and functions are left
+    /// out, since they're the host's to provide.
+    pub fn snapshot(&self) -> Snapshot {
+        let mut functions: BTreeMap<Symbol, &Rc<FunctionDefinitionNode>> = BTreeMap::new();
+        for scope in &self.stack {
+            functions.extend(scope.functions.iter().map(|(name, definition)| (*name, definition)));
+        }
+        let functions = functions.into_iter()
+            .map(|(name, definition)| (name.to_string(), definition.to_source()))
+            .collect();
+        let variables = Self::variables_in(self.visible_scopes().map(|scope| &self.stack[scope]));
+        Snapshot::new(variables, functions)
+    }
+
+    /// Defines the variables and functions in `snapshot` in the uppermost scope, replacing any of
+    /// the same names.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), Error> {
+        for (name, value) in snapshot.variables() {
+            self.set_variable(name, value.clone())?;
+        }
+        for (_, source) in snapshot.functions() {
+            crate::run_in(source, self)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the names of every builtin registered with the runtime, sorted.
+    pub fn builtin_names(&self) -> Vec<String> {
+        self.builtins.names()
+    }
+
+    /// Makes the variables and functions in `scope` visible everywhere in the program, including
+    /// inside functions, but unchangeable by it: assigning to one of its variables, defining a
+    /// function with the name of one of its functions, or deleting one of them fails with a
+    /// `Frozen` error. The program's own variables and function parameters still shadow frozen
+    /// variables of the same name. Frozen scopes are searched after every scope on the stack, the
+    /// last pushed first.
+    pub fn push_frozen_scope(&mut self, scope: Scope) {
+        self.frozen.push(scope);
+    }
+
     pub fn begin_scope(&mut self) {
         self.stack.push(Scope::new());
+        self.stats.peak_scope_depth = self.stats.peak_scope_depth.max(self.stack.len());
     }
     pub fn end_scope(&mut self) {
-        self.stack.pop();
+        if let Some(scope) = self.stack.pop() {
+            self.limits.memory_used -= scope.heap_size();
+        }
+    }
+
+    /// Starts the next iteration of the loop whose scope is uppermost, clearing out the variables
+    /// the last iteration created unless loop scopes are
+    /// [shared](Runtime::set_shared_loop_scopes).
+    pub(crate) fn begin_iteration(&mut self) {
+        if !self.config.shared_loop_scopes {
+            self.end_scope();
+            self.begin_scope();
+        }
+    }
+
+    /// Opens the first scope of a function call. Unless scoping is
+    /// [dynamic](Runtime::set_dynamic_scoping), the call can only see its own variables and the
+    /// global scope's until it's closed with [`Runtime::end_call`].
+    pub(crate) fn begin_call(&mut self) {
+        self.frames.push(self.stack.len());
+        self.begin_scope();
+    }
+
+    /// Closes the function call opened last, along with any of its scopes still open.
+    pub(crate) fn end_call(&mut self) {
+        let start = self.frames.pop().expect("`end_call()` called without a call in progress");
+        while self.stack.len() > start {
+            self.end_scope();
+        }
+    }
+
+    /// Returns the indices of the scopes whose variables are visible, from the global scope up:
+    /// the global scope and those of the innermost call, or every scope if scoping is dynamic.
+    fn visible_scopes(&self) -> impl DoubleEndedIterator<Item = usize> {
+        std::iter::once(0).chain(self.call_start()..self.stack.len())
+    }
+
+    /// Returns the index of the lowest scope above the global scope whose variables are visible.
+    fn call_start(&self) -> usize {
+        match self.frames.last() {
+            Some(&start) if !self.config.dynamic_scoping => start,
+            _ => 1,
+        }
+    }
+
+    pub fn get_variable(&self, name: impl Into<Symbol>) -> Result<&Value, Error> {
+        self.get_variable_at(name.into(), None)
     }
 
-    pub fn get_variable(&self, name: &str) -> Result<&Value, Error> {
-        for scope in self.stack.iter().rev() {
-            if let Some(value) = scope.get_variable(name) {
+    /// Gets the variable `name`, looking in `slot` before searching the stack for it.
+    pub(crate) fn get_variable_at(&self, name: Symbol, slot: Option<Slot>) -> Result<&Value, Error> {
+        if let Some((scope, index)) = self.check_slot(name, slot) {
+            return Ok(&self.stack[scope].variables[index].1);
+        }
+        for scope in self.visible_scopes().rev() {
+            if let Some(value) = self.stack[scope].get_variable(name) {
                 return Ok(value);
             }
         }
-        Err(Error::new(Name(name.to_string()), None))
+        self.frozen.iter().rev()
+            .find_map(|scope| scope.get_variable(name))
+            .ok_or_else(|| Error::new(Name(name.to_string()), None))
     }
 
-    pub fn set_variable(&mut self, name: &str, value: Value) {
-        for scope in self.stack.iter_mut().rev() {
-            if scope.get_variable(name).is_some() {
-                scope.set_variable(name, value);
-                return;
-            }
+    pub fn set_variable(&mut self, name: impl Into<Symbol>, value: Value) -> Result<(), Error> {
+        self.set_variable_at(name.into(), None, value)
+    }
+
+    /// Sets the variable `name`, looking in `slot` before searching the stack for it.
+    pub(crate) fn set_variable_at(&mut self, name: Symbol, slot: Option<Slot>, value: Value)
+        -> Result<(), Error>
+    {
+        // set the variable in the uppermost scope it exists in, or create it in the highest scope
+        // unless it's frozen
+        let (scope, index) = match self.check_slot(name, slot) {
+            Some((scope, index)) => (scope, Some(index)),
+            None => match self.visible_scopes().rev()
+                .find_map(|scope| Some((scope, *self.stack[scope].slots.get(&name)?)))
+            {
+                Some((scope, index)) => (scope, Some(index)),
+                None => {
+                    self.check_variable_not_frozen(name)?;
+                    (self.stack.len() - 1, None)
+                },
+            },
+        };
+        self.store_variable(scope, index, name, value)
+    }
+
+    /// Creates the variable `name` in the uppermost scope, or sets it if it's already there,
+    /// whether or not there's a variable of the same name further down the stack.
+    pub(crate) fn define_variable(&mut self, name: Symbol, value: Value) -> Result<(), Error> {
+        let scope = self.stack.len() - 1;
+        let index = self.stack[scope].slots.get(&name).copied();
+        self.store_variable(scope, index, name, value)
+    }
+
+    /// Stores `value` as the variable `name` in the scope at `scope`, where it's at `index` if it
+    /// already exists, keeping track of the memory held.
+    fn store_variable(&mut self, scope: usize, index: Option<usize>, name: Symbol, value: Value)
+        -> Result<(), Error>
+    {
+        let previous_size = index.map_or(0, |index| self.stack[scope].variables[index].1.heap_size());
+        let memory_used = self.limits.memory_used - previous_size + value.heap_size();
+        self.check_memory(memory_used)?;
+        self.limits.memory_used = memory_used;
+        self.stats.peak_memory = self.stats.peak_memory.max(memory_used);
+        if value.heap_size() > 0 {
+            self.stats.allocations += 1;
+        }
+
+        match index {
+            Some(index) => self.stack[scope].variables[index].1 = value,
+            None => self.stack[scope].set_variable(name, value),
+        }
+        Ok(())
+    }
+
+    /// Returns whether the variable `name` is visible from the uppermost scope, including in a
+    /// frozen scope.
+    pub fn has_variable(&self, name: impl Into<Symbol>) -> bool {
+        let name = name.into();
+        self.visible_scopes().any(|scope| self.stack[scope].get_variable(name).is_some())
+            || self.frozen.iter().any(|scope| scope.get_variable(name).is_some())
+    }
+
+    /// Removes the variable `name` from the uppermost scope it's visible in, returning its value,
+    /// or `None` if it isn't visible. Any variable of the same name further down the stack is
+    /// left in place, and becomes visible again. Frozen variables are never removed.
+    pub fn remove_variable(&mut self, name: impl Into<Symbol>) -> Option<Value> {
+        let name = name.into();
+        let scope = self.visible_scopes().rev()
+            .find(|&scope| self.stack[scope].get_variable(name).is_some())?;
+        let value = self.stack[scope].remove_variable(name)?;
+        self.limits.memory_used -= value.heap_size();
+        Some(value)
+    }
+
+    /// Returns the indices of the scope and variable `slot` points to, if it's visible and holds
+    /// `name`. Slots are only worked out within a function or outside all of them, and a variable
+    /// is only ever created when it isn't visible already, so if the slot holds the name, it's
+    /// the right one.
+    fn check_slot(&self, name: Symbol, slot: Option<Slot>) -> Option<(usize, usize)> {
+        let Slot { depth, index } = slot?;
+        let scope = self.stack.len().checked_sub(depth + 1)?;
+        if scope != 0 && scope < self.call_start() {
+            return None;
+        }
+        match self.stack[scope].variables.get(index) {
+            Some((held, _)) if *held == name => Some((scope, index)),
+            _ => None,
         }
-        self.stack.last_mut().unwrap().set_variable(name, value);
     }
 
-    pub fn get_function_definition(&self, name: &str) -> Result<Rc<RefCell<FunctionDefinitionNode>>, Error> {
-        for scope in self.stack.iter().rev() {
+    pub fn get_function_definition(&self, name: impl Into<Symbol>)
+        -> Result<Rc<FunctionDefinitionNode>, Error>
+    {
+        let name = name.into();
+        for scope in self.stack.iter().rev().chain(self.frozen.iter().rev()) {
             if let Some(definition) = scope.get_function_definition(name) {
                 return Ok(definition)
             }
@@ -60,43 +611,372 @@ impl Runtime {
         Err(Error::new(Name(name.to_string()), None))
     }
 
-    pub fn set_function_definition(&mut self, name: &str, definition: RefCell<FunctionDefinitionNode>) {
+    /// Fails with a `Frozen` error if `name` is a frozen variable, which the program can't
+    /// assign to or delete.
+    pub(crate) fn check_variable_not_frozen(&self, name: Symbol) -> Result<(), Error> {
+        match self.frozen.iter().any(|scope| scope.get_variable(name).is_some()) {
+            true => Err(Error::new(Frozen(name.to_string()), None)),
+            false => Ok(()),
+        }
+    }
+
+    /// Fails with a `Frozen` error if `name` is a frozen function, which the program can't define.
+    pub(crate) fn check_function_not_frozen(&self, name: Symbol) -> Result<(), Error> {
+        match self.frozen.iter().any(|scope| scope.get_function_definition(name).is_some()) {
+            true => Err(Error::new(Frozen(name.to_string()), None)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn set_function_definition(&mut self, name: impl Into<Symbol>, definition: Rc<FunctionDefinitionNode>) {
         let top_scope = self.stack.last_mut().expect("`set_function_definition()` called after last scope closed");
         top_scope.set_function_definition(name, definition);
     }
+
+    /// Makes `builtin` callable from Mornington code as `name`, replacing any existing builtin of
+    /// the same name.
+    pub fn register_builtin(&mut self, name: &str, arity: Arity, builtin: impl Builtin + 'static) {
+        self.builtins.register(name, arity, builtin);
+    }
+
+    /// Calls the builtin `name` with `args`, or returns `None` if there is no such builtin.
+    pub fn call_builtin(&mut self, name: &str, args: &ListNode) -> Option<Result<Value, Error>> {
+        let builtin = self.builtins.get(name)?;
+        Some(builtin.call(name, &mut BuiltinContext::new(self, args)))
+    }
+
+    /// Calls the builtin `name` with `args` for a `fir` loop to iterate over, or returns `None` if
+    /// there is no such builtin.
+    pub fn iterate_builtin(&mut self, name: &str, args: &ListNode) -> Option<Result<Elements, Error>> {
+        let builtin = self.builtins.get(name)?;
+        Some(builtin.iterate(name, &mut BuiltinContext::new(self, args)))
+    }
+
+    /// Redirects everything the program writes to stdout (e.g. with `pront`) into `stdout`.
+    pub fn set_stdout(&mut self, stdout: impl Write + 'static) {
+        self.stdout = Box::new(stdout);
+    }
+    /// Redirects everything the program writes to stderr (e.g. with `pritner`) into `stderr`.
+    pub fn set_stderr(&mut self, stderr: impl Write + 'static) {
+        self.stderr = Box::new(stderr);
+    }
+    /// Makes the program read its input (e.g. with `inptu`) from `stdin`.
+    pub fn set_stdin(&mut self, stdin: impl BufRead + 'static) {
+        self.stdin = Box::new(stdin);
+    }
+
+    /// Makes `imoprt` get the source of modules from `loader`, which is given the name passed to
+    /// `imoprt` and returns `None` if there's no such module. By default, modules are read from
+    /// files found with [`modules::resolve`](crate::modules::resolve).
+    pub fn set_module_loader(&mut self, loader: impl Fn(&str) -> Option<String> + 'static) {
+        self.module_loader = Box::new(loader);
+    }
+
+    /// Returns the source of the module `name`, if it can be found.
+    pub(crate) fn load_module(&self, name: &str) -> Option<String> {
+        (self.module_loader)(name)
+    }
+
+    /// Makes `dtaa` get the contents of data files from `loader`, which is given the path passed
+    /// to `dtaa` and returns `None` if there's no such file. By default, files are read relative
+    /// to the current directory.
+    pub fn set_data_loader(&mut self, loader: impl Fn(&str) -> Option<String> + 'static) {
+        self.data_loader = Box::new(loader);
+    }
+
+    /// Returns the value of the data file at `path`, loading it the first time it's asked for.
+    pub(crate) fn data(&mut self, path: &str) -> Result<Value, Error> {
+        if let Some(value) = self.data.get(path) {
+            return Ok(value.clone());
+        }
+        let text = (self.data_loader)(path)
+            .ok_or_else(|| Error::new(Data(format!("couldn't load `{path}`")), None))?;
+        let value = crate::builtins::data_value(path, &text)?;
+        self.data.insert(path.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Seeds the random numbers builtins are given (see
+    /// [`BuiltinContext::random_below`](crate::builtins::BuiltinContext::random_below)), so that a
+    /// program that uses them can be made to do the same thing every time it's run. Runtimes are
+    /// seeded with their [`Config::seed`] until this is called.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.config.seed = seed;
+        self.random = Random::new(seed);
+    }
+
+    /// Returns a random number from 0 up to but not including `bound`.
+    pub(crate) fn random_below(&mut self, bound: usize) -> usize {
+        self.random.below(bound)
+    }
+
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        &mut self.stdout
+    }
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        &mut self.stderr
+    }
+    pub fn stdin(&mut self) -> &mut dyn BufRead {
+        &mut self.stdin
+    }
+
+    /// Records that the module `name` is being imported, throwing a `ModuleCycle` error if it
+    /// already is, i.e. it imports itself, directly or through other modules. Must be paired with
+    /// a call to [`Runtime::end_import`].
+    pub(crate) fn begin_import(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(start) = self.importing.iter().position(|importing| importing == name) {
+            let mut cycle = self.importing[start..].to_vec();
+            cycle.push(name.to_string());
+            return Err(Error::new(ModuleCycle(cycle), None));
+        }
+        self.importing.push(name.to_string());
+        Ok(())
+    }
+
+    pub(crate) fn end_import(&mut self) {
+        self.importing.pop();
+    }
+}
+impl Default for Runtime {
+    fn default() -> Runtime {
+        Runtime::new()
+    }
+}
+impl Debug for Runtime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Runtime")
+            .field("stack", &self.stack)
+            .field("builtins", &self.builtins)
+            .finish_non_exhaustive()
+    }
+}
+/// Runtimes are equal if their scopes and builtins are, regardless of where their streams point.
+impl PartialEq for Runtime {
+    fn eq(&self, other: &Self) -> bool {
+        self.stack == other.stack && self.builtins == other.builtins
+    }
+}
+
+
+/// Finds the source of a module given its name. See [`Runtime::set_module_loader`].
+type ModuleLoader = Box<dyn Fn(&str) -> Option<String>>;
+/// Finds the contents of a data file given its path. See [`Runtime::set_data_loader`].
+type DataLoader = Box<dyn Fn(&str) -> Option<String>>;
+
+// with the `no-io` feature, runtimes can't touch the terminal or the filesystem unless they're
+// given streams and loaders that do
+
+#[cfg(not(feature = "no-io"))]
+fn default_stdout() -> Box<dyn Write> {
+    Box::new(io::stdout())
+}
+#[cfg(not(feature = "no-io"))]
+fn default_stderr() -> Box<dyn Write> {
+    Box::new(io::stderr())
+}
+#[cfg(not(feature = "no-io"))]
+fn default_stdin() -> Box<dyn BufRead> {
+    Box::new(io::BufReader::new(io::stdin()))
+}
+#[cfg(not(feature = "no-io"))]
+fn default_module_loader(name: &str) -> Option<String> {
+    crate::modules::resolve(name, &crate::modules::search_path())
+        .and_then(|path| std::fs::read_to_string(path).ok())
+}
+
+#[cfg(not(feature = "no-io"))]
+fn default_data_loader(path: &str) -> Option<String> {
+    std::fs::read_to_string(path).ok()
+}
+
+#[cfg(feature = "no-io")]
+fn default_stdout() -> Box<dyn Write> {
+    Box::new(io::sink())
+}
+#[cfg(feature = "no-io")]
+fn default_stderr() -> Box<dyn Write> {
+    Box::new(io::sink())
+}
+#[cfg(feature = "no-io")]
+fn default_stdin() -> Box<dyn BufRead> {
+    Box::new(io::empty())
+}
+#[cfg(feature = "no-io")]
+fn default_module_loader(_name: &str) -> Option<String> {
+    None
+}
+#[cfg(feature = "no-io")]
+fn default_data_loader(_path: &str) -> Option<String> {
+    None
+}
+
+
+/// An in-memory output stream whose clones all write to the same buffer, so that a program's
+/// output can be captured by passing a clone to [`Runtime::set_stdout`] or
+/// [`Runtime::set_stderr`] and reading it back afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer {
+    buffer: Rc<RefCell<Vec<u8>>>,
+}
+impl SharedBuffer {
+    pub fn new() -> SharedBuffer {
+        SharedBuffer::default()
+    }
+
+    /// Everything written to the buffer so far, lossily decoded as UTF-8.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buffer.borrow()).into_owned()
+    }
+}
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An output stream that passes everything written to it to a callback, e.g. to forward a
+/// program's output to a host that isn't a terminal. Text is decoded as UTF-8 before being passed
+/// on, lossily if it isn't valid.
+pub struct CallbackWriter<F: FnMut(&str)> {
+    callback: F,
+}
+impl<F: FnMut(&str)> CallbackWriter<F> {
+    pub fn new(callback: F) -> CallbackWriter<F> {
+        CallbackWriter { callback }
+    }
+}
+impl<F: FnMut(&str)> Write for CallbackWriter<F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (self.callback)(&String::from_utf8_lossy(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An input stream that asks a callback for each line when it needs more input, e.g. to prompt
+/// the user of a host that isn't a terminal. The callback returns `None` at the end of the input.
+pub struct CallbackReader<F: FnMut() -> Option<String>> {
+    callback: F,
+    buffer: Vec<u8>,
+    position: usize,
+}
+impl<F: FnMut() -> Option<String>> CallbackReader<F> {
+    pub fn new(callback: F) -> CallbackReader<F> {
+        CallbackReader { callback, buffer: Vec::new(), position: 0 }
+    }
+}
+impl<F: FnMut() -> Option<String>> Read for CallbackReader<F> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let length = available.len().min(buf.len());
+        buf[..length].copy_from_slice(&available[..length]);
+        self.consume(length);
+        Ok(length)
+    }
+}
+impl<F: FnMut() -> Option<String>> BufRead for CallbackReader<F> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position == self.buffer.len() {
+            self.buffer = (self.callback)().unwrap_or_default().into_bytes();
+            self.position = 0;
+        }
+        Ok(&self.buffer[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position = (self.position + amount).min(self.buffer.len());
+    }
+}
+
+/// An input stream that copies everything read from `input` to `recording` as it goes, so that
+/// passing one to [`Runtime::set_stdin`] records a program's input for replaying later. The
+/// recording can be replayed by passing it to [`Runtime::set_stdin`] in place of the real input.
+///
+/// The recording is flushed after every read, so it's complete even if the program is
+/// interrupted. Failing to write to it doesn't stop the program from reading its input.
+pub struct RecordingReader<R, W> {
+    input: R,
+    recording: W,
+}
+impl<R: BufRead, W: Write> RecordingReader<R, W> {
+    pub fn new(input: R, recording: W) -> RecordingReader<R, W> {
+        RecordingReader { input, recording }
+    }
+}
+impl<R: BufRead, W: Write> Read for RecordingReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.fill_buf()?.read(buf)?;
+        self.consume(read);
+        Ok(read)
+    }
+}
+impl<R: BufRead, W: Write> BufRead for RecordingReader<R, W> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.input.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        if let Ok(buffer) = self.input.fill_buf() {
+            let _ = self.recording.write_all(&buffer[..amount.min(buffer.len())]);
+            let _ = self.recording.flush();
+        }
+        self.input.consume(amount);
+    }
 }
 
 
 impl Scope {
     pub fn new() -> Scope {
         Scope {
-            variables: HashMap::new(),
-            functions: HashMap::new(),
+            variables: Vec::new(),
+            slots: HashMap::new(),
+            functions: BTreeMap::new(),
         }
     }
 
-    pub fn get_variable(&self, name: &str) -> Option<&Value> {
-        self.variables.get(name)
+    pub fn get_variable(&self, name: impl Into<Symbol>) -> Option<&Value> {
+        Some(&self.variables[*self.slots.get(&name.into())?].1)
     }
 
-    pub fn set_variable(&mut self, name: &str, value: Value) {
-        if let Some(stored_value) = self.variables.get_mut(name) {
-            *stored_value = value;
-        } else {
-            self.variables.insert(name.to_string(), value);
+    pub fn set_variable(&mut self, name: impl Into<Symbol>, value: Value) {
+        let name = name.into();
+        match self.slots.get(&name) {
+            Some(&index) => self.variables[index].1 = value,
+            None => {
+                self.slots.insert(name, self.variables.len());
+                self.variables.push((name, value));
+            },
         }
     }
 
-    pub fn get_function_definition(&self, name: &str) -> Option<Rc<RefCell<FunctionDefinitionNode>>> {
-        Some(Rc::clone(self.functions.get(name)?))
+    /// Removes the variable `name`, returning its value, or `None` if it doesn't exist in this
+    /// scope. The variables created after it move down a place, so slots pointing at them are no
+    /// longer hits, and are searched for instead.
+    pub fn remove_variable(&mut self, name: impl Into<Symbol>) -> Option<Value> {
+        let index = self.slots.remove(&name.into())?;
+        for later in self.slots.values_mut().filter(|later| **later > index) {
+            *later -= 1;
+        }
+        Some(self.variables.remove(index).1)
     }
 
-    pub fn set_function_definition(&mut self, name: &str, definition: RefCell<FunctionDefinitionNode>) {
-        if let Some(existing_definition) = self.functions.get_mut(name) {
-            *existing_definition = Rc::new(definition);
-        } else {
-            self.functions.insert(name.to_string(), Rc::new(definition));
-        }
+    fn heap_size(&self) -> usize {
+        self.variables.iter().map(|(_, value)| value.heap_size()).sum()
+    }
+
+    pub fn get_function_definition(&self, name: impl Into<Symbol>) -> Option<Rc<FunctionDefinitionNode>> {
+        Some(Rc::clone(self.functions.get(&name.into())?))
+    }
+
+    pub fn set_function_definition(&mut self, name: impl Into<Symbol>, definition: Rc<FunctionDefinitionNode>) {
+        self.functions.insert(name.into(), definition);
     }
 }
 
@@ -122,7 +1002,8 @@ mod tests {
                         scope.set_variable("a", Value::Bool(true));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
             assert_eq!(
                 Value::Bool(true),
@@ -143,7 +1024,8 @@ mod tests {
                         scope.set_variable("a", Value::Bool(true));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
             assert_eq!(
                 Value::Bool(false),
@@ -173,9 +1055,10 @@ mod tests {
                         scope.set_variable("a", Value::Bool(true));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
-            runtime.set_variable("a", Value::Number(3.0));
+            runtime.set_variable("a", Value::Number(3.0)).unwrap();
             assert_eq!(
                 Runtime {
                     stack: vec![
@@ -188,7 +1071,8 @@ mod tests {
                             scope.set_variable("a", Value::Number(3.0));
                             scope
                         }
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
@@ -207,9 +1091,10 @@ mod tests {
                         scope.set_variable("b", Value::Bool(true));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
-            runtime.set_variable("a", Value::Number(3.0));
+            runtime.set_variable("a", Value::Number(3.0)).unwrap();
             assert_eq!(
                 Runtime {
                     stack: vec![
@@ -222,7 +1107,8 @@ mod tests {
                             scope.set_variable("b", Value::Bool(true));
                             scope
                         }
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
@@ -241,9 +1127,10 @@ mod tests {
                         scope.set_variable("b", Value::Bool(true));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
-            runtime.set_variable("c", Value::Number(3.0));
+            runtime.set_variable("c", Value::Number(3.0)).unwrap();
             assert_eq!(
                 Runtime {
                     stack: vec![
@@ -257,7 +1144,8 @@ mod tests {
                             scope.set_variable("c", Value::Number(3.0));
                             scope
                         },
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
@@ -272,18 +1160,19 @@ mod tests {
                 stack: vec![
                     {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("a", RefCell::new(lower_definition));
+                        scope.set_function_definition("a", Rc::new(lower_definition));
                         scope
                     }, {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("a", RefCell::new(upper_definition.clone()));
+                        scope.set_function_definition("a", Rc::new(upper_definition.clone()));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
             assert_eq!(
                 upper_definition,
-                *runtime.get_function_definition("a").unwrap().borrow()
+                *runtime.get_function_definition("a").unwrap()
             );
         }
 
@@ -296,18 +1185,19 @@ mod tests {
                 stack: vec![
                     {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("b", RefCell::new(b_definition.clone()));
+                        scope.set_function_definition("b", Rc::new(b_definition.clone()));
                         scope
                     }, {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("a", RefCell::new(a_definition));
+                        scope.set_function_definition("a", Rc::new(a_definition));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
             assert_eq!(
                 b_definition,
-                *runtime.get_function_definition("b").unwrap().borrow(),
+                *runtime.get_function_definition("b").unwrap(),
             );
         }
 
@@ -324,16 +1214,17 @@ mod tests {
         fn set_function_defines_new_function_in_highest_scope_if_no_existing_definition() {
             let definition = generic_function_definition_returning(Value::Bool(false));
             let mut runtime = Runtime::new();
-            runtime.set_function_definition("test", RefCell::new(definition.clone()));
+            runtime.set_function_definition("test", Rc::new(definition.clone()));
             assert_eq!(
                 Runtime {
                     stack: vec![
                         {
                             let mut scope = Scope::new();
-                            scope.set_function_definition("test", RefCell::new(definition));
+                            scope.set_function_definition("test", Rc::new(definition));
                             scope
                         },
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
@@ -347,27 +1238,29 @@ mod tests {
                 stack: vec![
                     {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("test", RefCell::new(lower_definition.clone()));
+                        scope.set_function_definition("test", Rc::new(lower_definition.clone()));
                         scope
                     },
                     Scope::new(),
-                ]
+                ],
+                ..Runtime::new()
             };
-            runtime.set_function_definition("test", RefCell::new(upper_definition.clone()));
+            runtime.set_function_definition("test", Rc::new(upper_definition.clone()));
             assert_eq!(
                 Runtime {
                     stack: vec![
                         {
                             let mut scope = Scope::new();
-                            scope.set_function_definition("test", RefCell::new(lower_definition));
+                            scope.set_function_definition("test", Rc::new(lower_definition));
                             scope
                         },
                         {
                             let mut scope = Scope::new();
-                            scope.set_function_definition("test", RefCell::new(upper_definition));
+                            scope.set_function_definition("test", Rc::new(upper_definition));
                             scope
                         },
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
@@ -381,48 +1274,158 @@ mod tests {
                 stack: vec![
                     {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("a", RefCell::new(lower_definition.clone()));
+                        scope.set_function_definition("a", Rc::new(lower_definition.clone()));
                         scope
                     }, {
                         let mut scope = Scope::new();
-                        scope.set_function_definition("a", RefCell::new(upper_definition.clone()));
+                        scope.set_function_definition("a", Rc::new(upper_definition.clone()));
                         scope
                     }
-                ]
+                ],
+                ..Runtime::new()
             };
             let replacement_definition = generic_function_definition_returning(Value::Number(3.0));
-            runtime.set_function_definition("a", RefCell::new(replacement_definition.clone()));
+            runtime.set_function_definition("a", Rc::new(replacement_definition.clone()));
             assert_eq!(
                 Runtime {
                     stack: vec![
                         {
                             let mut scope = Scope::new();
-                            scope.set_function_definition("a", RefCell::new(lower_definition));
+                            scope.set_function_definition("a", Rc::new(lower_definition));
                             scope
                         }, {
                             let mut scope = Scope::new();
-                            scope.set_function_definition("a", RefCell::new(replacement_definition));
+                            scope.set_function_definition("a", Rc::new(replacement_definition));
                             scope
                         }
-                    ]
+                    ],
+                    ..Runtime::new()
                 },
                 runtime,
             );
         }
     }
 
+    mod removal_tests {
+        use super::*;
+
+        #[test]
+        fn remove_variable_uncovers_outer_variable() {
+            let mut runtime = Runtime::new();
+            runtime.set_variable("a", Value::Int(1)).unwrap();
+            runtime.begin_scope();
+            runtime.stack.last_mut().unwrap().set_variable("a", Value::Int(2));
+            assert!(runtime.has_variable("a"));
+            assert_eq!(Some(Value::Int(2)), runtime.remove_variable("a"));
+            assert_eq!(Value::Int(1), *runtime.get_variable("a").unwrap());
+            assert_eq!(Some(Value::Int(1)), runtime.remove_variable("a"));
+            assert!(!runtime.has_variable("a"));
+            assert_eq!(None, runtime.remove_variable("a"));
+        }
+
+        #[test]
+        fn remove_variable_frees_its_memory() {
+            let mut runtime = Runtime::new();
+            runtime.set_variable("a", Value::from("abc")).unwrap();
+            runtime.remove_variable("a");
+            assert_eq!(0, runtime.memory_used());
+        }
+
+        #[test]
+        fn resolved_slots_survive_removal() {
+            let mut runtime = Runtime::new();
+            crate::run_in("a = 1\n b = 2\nc = 3", &mut runtime).unwrap();
+            runtime.remove_variable("a");
+            crate::run_in("c = c + b", &mut runtime).unwrap();
+            assert_eq!(Value::Int(5), *runtime.get_variable("c").unwrap());
+        }
+    }
+
+    mod frozen_scope_tests {
+        use crate::error::ErrorKind;
+        use super::*;
+
+        fn frozen_runtime() -> Runtime {
+            let mut host = Runtime::new();
+            crate::run_in("fnuc dobule((n)\n   retrun n * 2", &mut host).unwrap();
+            let mut scope = Scope::new();
+            scope.set_variable("limit", Value::Int(10));
+            scope.set_function_definition("dobule", host.get_function_definition("dobule").unwrap());
+            let mut runtime = Runtime::new();
+            runtime.push_frozen_scope(scope);
+            runtime
+        }
+
+        fn frozen_error(source: &str) -> Error {
+            crate::run_in(source, &mut frozen_runtime()).unwrap_err()
+        }
+
+        #[test]
+        fn frozen_bindings_are_visible_everywhere() {
+            let mut runtime = frozen_runtime();
+            crate::run_in("fnuc f(()\n   retrun dobule((limit)\nx = f(()", &mut runtime).unwrap();
+            assert_eq!(Value::Int(20), *runtime.get_variable("x").unwrap());
+            assert!(runtime.has_variable("limit"));
+            assert!(runtime.visible_functions().contains(&"dobule".to_string()));
+        }
+
+        #[test]
+        fn frozen_variables_cannot_be_assigned_to() {
+            let error = frozen_error("limit = 5");
+            assert_eq!(ErrorKind::Frozen("limit".to_string()), error.kind);
+            assert_eq!(Some(Position::new(1, 0, 5)), error.pos);
+            let error = frozen_error("fnuc f(()\n   limit = 5\nf(()");
+            assert_eq!(ErrorKind::Frozen("limit".to_string()), error.kind);
+        }
+
+        #[test]
+        fn frozen_variables_cannot_be_deleted() {
+            assert_eq!(ErrorKind::Frozen("limit".to_string()), frozen_error("dlete((\"\"limit\")").kind);
+        }
+
+        #[test]
+        fn frozen_functions_cannot_be_redefined() {
+            let error = frozen_error("x = 1\n fnuc dobule((n)\n    retrun n");
+            assert_eq!(ErrorKind::Frozen("dobule".to_string()), error.kind);
+            assert_eq!(Some(Position::new(2, 1, 4)), error.pos);
+        }
+
+        #[test]
+        fn parameters_shadow_frozen_variables() {
+            let mut runtime = frozen_runtime();
+            crate::run_in("fnuc f((limit)\n   limit = limit + 1\n    retrun limit\nx = f((1)", &mut runtime).unwrap();
+            assert_eq!(Value::Int(2), *runtime.get_variable("x").unwrap());
+            assert_eq!(Value::Int(10), *runtime.get_variable("limit").unwrap());
+        }
+
+        #[test]
+        fn snapshots_leave_frozen_bindings_to_the_host() {
+            let mut runtime = frozen_runtime();
+            crate::run_in("x = limit", &mut runtime).unwrap();
+            let snapshot = runtime.snapshot();
+            assert_eq!(&[("x".to_string(), Value::Int(10))], snapshot.variables());
+            assert!(snapshot.functions().is_empty());
+            frozen_runtime().restore(&snapshot).unwrap();
+        }
+    }
+
     mod scope_tests {
         use super::*;
 
         #[test]
         fn defined_variable_get_yields_value() {
             let scope = Scope {
-                variables: HashMap::from([
-                    ("a".to_string(), Value::Number(1.0)),
-                    ("b".to_string(), Value::Bool(false)),
-                    ("c".to_string(), Value::String("test".to_string())),
+                variables: vec![
+                    (Symbol::intern("a"), Value::Number(1.0)),
+                    (Symbol::intern("b"), Value::Bool(false)),
+                    (Symbol::intern("c"), Value::String("test".to_string())),
+                ],
+                slots: HashMap::from([
+                    (Symbol::intern("a"), 0),
+                    (Symbol::intern("b"), 1),
+                    (Symbol::intern("c"), 2),
                 ]),
-                functions: HashMap::new(),
+                functions: BTreeMap::new(),
             };
             assert_eq!(
                 Value::Number(1.0),
@@ -441,10 +1444,9 @@ mod tests {
         #[test]
         fn undefined_variable_get_yields_none() {
             let scope = Scope {
-                variables: HashMap::from([
-                    ("a".to_string(), Value::Number(1.0)),
-                ]),
-                functions: HashMap::new(),
+                variables: vec![(Symbol::intern("a"), Value::Number(1.0))],
+                slots: HashMap::from([(Symbol::intern("a"), 0)]),
+                functions: BTreeMap::new(),
             };
             assert_eq!(
                 None,
@@ -473,26 +1475,42 @@ mod tests {
             );
         }
 
+        #[test]
+        fn remove_variable_keeps_later_variables_reachable() {
+            let mut scope = Scope::new();
+            scope.set_variable("a", Value::Int(1));
+            scope.set_variable("b", Value::Int(2));
+            scope.set_variable("c", Value::Int(3));
+            assert_eq!(Some(Value::Int(2)), scope.remove_variable("b"));
+            assert_eq!(None, scope.remove_variable("b"));
+            assert_eq!(None, scope.get_variable("b"));
+            assert_eq!(Value::Int(3), *scope.get_variable("c").unwrap());
+            scope.set_variable("c", Value::Int(4));
+            assert_eq!(Value::Int(4), *scope.get_variable("c").unwrap());
+        }
+
         #[test]
         fn defined_function_get_yields_definition() {
             let definition = generic_function_definition_returning(Value::Bool(true));
             let scope = Scope {
-                variables: HashMap::new(),
-                functions: HashMap::from([
-                    ("test".to_string(), Rc::new(RefCell::new(definition.clone())))
+                variables: Vec::new(),
+                slots: HashMap::new(),
+                functions: BTreeMap::from([
+                    (Symbol::intern("test"), Rc::new(definition.clone()))
                 ]),
             };
             assert_eq!(
                 definition,
-                *scope.get_function_definition("test").unwrap().borrow(),
+                *scope.get_function_definition("test").unwrap(),
             );
         }
 
         #[test]
         fn undefined_function_get_yields_none() {
             let scope = Scope {
-                variables: HashMap::new(),
-                functions: HashMap::new(),
+                variables: Vec::new(),
+                slots: HashMap::new(),
+                functions: BTreeMap::new(),
             };
             assert_eq!(
                 None,
@@ -505,11 +1523,11 @@ mod tests {
             let definition = generic_function_definition_returning(Value::Bool(true));
 
             let mut scope = Scope::new();
-            scope.set_function_definition("test", RefCell::new(definition.clone()));
+            scope.set_function_definition("test", Rc::new(definition.clone()));
 
             assert_eq!(
                 definition,
-                *scope.get_function_definition("test").unwrap().borrow(),
+                *scope.get_function_definition("test").unwrap(),
             );
         }
 
@@ -519,11 +1537,315 @@ mod tests {
             let definition_new = generic_function_definition_returning(Value::Bool(false));
 
             let mut scope = Scope::new();
-            scope.set_function_definition("a", RefCell::new(definition_old));
-            scope.set_function_definition("a", RefCell::new(definition_new.clone()));
+            scope.set_function_definition("a", Rc::new(definition_old));
+            scope.set_function_definition("a", Rc::new(definition_new.clone()));
             assert_eq!(
                 definition_new,
-                *scope.get_function_definition("a").unwrap().borrow(),
+                *scope.get_function_definition("a").unwrap(),
+            );
+        }
+    }
+
+    mod limit_tests {
+        use super::*;
+
+        #[test]
+        fn unlimited_runtime_does_not_stop_program() {
+            let mut runtime = Runtime::new();
+            crate::run_in("fir i ni arnge((1000)\n   x = i", &mut runtime).unwrap();
+        }
+
+        #[test]
+        fn step_limit_stops_infinite_loop() {
+            let mut runtime = Runtime::with_limits(Some(100), None);
+            assert_eq!(
+                LimitExceeded(Limit::Steps(100)),
+                crate::run_in("whitl rtue\n   x = 1", &mut runtime).unwrap_err().kind,
+            );
+        }
+
+        #[test]
+        fn step_limit_counts_statements() {
+            let mut runtime = Runtime::with_limits(Some(3), None);
+            crate::run_in("x = 1\n y = 2\n  z = 3", &mut runtime).unwrap();
+            let mut runtime = Runtime::with_limits(Some(2), None);
+            assert!(crate::run_in("x = 1\n y = 2\n  z = 3", &mut runtime).is_err());
+        }
+
+        #[test]
+        fn depth_limit_stops_infinite_recursion() {
+            let mut runtime = Runtime::new();
+            assert_eq!(
+                LimitExceeded(Limit::Depth(DEFAULT_MAX_DEPTH)),
+                crate::run_in("fnuc f((n)\n   retrun f((n + 1)\nf((0)", &mut runtime)
+                    .unwrap_err().kind,
+            );
+            // the levels descended into are all climbed back out of, and only calls count
+            runtime.set_max_depth(3);
+            crate::run_in("x = 1 + 2 + 3 + 4\n fnuc g((n)\n   retrun n\ny = g((g((g((1) ) )", &mut runtime).unwrap();
+            assert!(crate::run_in("y = g((g((g((g((1) ) ) )", &mut runtime).is_err());
+        }
+
+        #[test]
+        fn deep_recursion_grows_the_stack() {
+            let source = "fnuc f((n)\n   fi n == 0\n      retrun 0\n   retrun 1 + f((n - 1)\nprointl((f((5000) )";
+            let stdout = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(stdout.clone());
+            crate::run_in(source, &mut runtime).unwrap();
+            assert_eq!("5000\n", stdout.contents());
+        }
+
+        #[test]
+        fn lists_visible_functions_and_builtins() {
+            let mut runtime = Runtime::new();
+            crate::run_in("fnuc b(()\n   retrun 1\nfnuc a(()\n   retrun 2", &mut runtime).unwrap();
+            runtime.begin_scope();
+            crate::run_in("fnuc b(()\n   retrun 3", &mut runtime).unwrap();
+            assert_eq!(vec!["a".to_string(), "b".to_string()], runtime.visible_functions());
+            assert!(runtime.builtin_names().contains(&"prointl".to_string()));
+        }
+
+        #[test]
+        fn lists_and_prints_names_in_order() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "fnuc c(()\n   pas\nfnuc a(()\n   pas\nfnuc b(()\n   pas\nz = 1\n y = 2",
+                &mut runtime,
+            ).unwrap();
+            let names = runtime.builtin_names();
+            assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+            let debug = format!("{runtime:?}");
+            let position = |name: &str| debug.find(name).unwrap();
+            assert!(position("\"a\": ") < position("\"b\": ") && position("\"b\": ") < position("\"c\": "));
+            assert!(position("\"arnge\"") < position("\"bulitin\"") && position("\"bulitin\"") < position("\"srot\""));
+            assert_eq!(
+                vec![("y".to_string(), Value::Int(2)), ("z".to_string(), Value::Int(1))],
+                runtime.visible_variables(),
+            );
+        }
+
+        #[test]
+        fn recorded_input_replays() {
+            let recording = SharedBuffer::new();
+            let program = "a = inptu(()\n b = inptu(()\nprointl((a + b)";
+            let mut runtime = Runtime::new();
+            runtime.set_stdin(RecordingReader::new("1\n2\nunread\n".as_bytes(), recording.clone()));
+            crate::run_in(program, &mut runtime).unwrap();
+            assert_eq!("1\n2\n", recording.contents());
+
+            let stdout = SharedBuffer::new();
+            let mut replay = Runtime::new();
+            replay.set_stdin(io::Cursor::new(recording.contents()));
+            replay.set_stdout(stdout.clone());
+            crate::run_in(program, &mut replay).unwrap();
+            assert_eq!("1\n2\n\n", stdout.contents());
+        }
+
+        #[test]
+        fn snapshots_restore_into_fresh_runtime() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "fnuc f((a)\n   retrun a * 2\nx = [[1, \"\"b\"]\n y = f((4)",
+                &mut runtime,
+            ).unwrap();
+            let text = runtime.snapshot().to_string();
+
+            let mut restored = Runtime::new();
+            restored.restore(&Snapshot::parse(&text).unwrap()).unwrap();
+            assert_eq!(runtime.snapshot(), restored.snapshot());
+            crate::run_in("z = f((y)", &mut restored).unwrap();
+            assert_eq!(Value::Number(16.0), *restored.get_variable("z").unwrap());
+        }
+
+        #[test]
+        fn stats_are_collected() {
+            let mut runtime = Runtime::new();
+            crate::run_in(
+                "fnuc f((s)\n   retrun s * 2\nx = f((\"ab\"\")\n y = 1",
+                &mut runtime,
+            ).unwrap();
+            assert_eq!(
+                &Stats { statements: 4, peak_scope_depth: 2, allocations: 2, peak_memory: 4 },
+                runtime.stats(),
+            );
+        }
+
+        #[test]
+        fn duration_limit_stops_infinite_loop() {
+            let max_duration = Duration::from_millis(20);
+            let mut runtime = Runtime::with_limits(None, Some(max_duration));
+            assert_eq!(
+                LimitExceeded(Limit::Duration(max_duration)),
+                crate::run_in("whitl rtue\n   x = 1", &mut runtime).unwrap_err().kind,
+            );
+        }
+    }
+
+    mod config_tests {
+        use super::*;
+        use crate::error::ErrorKind::ExpectedBlock;
+
+        #[test]
+        fn runtime_starts_out_as_configured() {
+            let config = Config {
+                strict_types: true,
+                legacy_range: true,
+                max_steps: Some(5),
+                max_depth: 10,
+                seed: 7,
+                ..Config::default()
+            };
+            let runtime = Runtime::with_config(config.clone());
+            assert_eq!(&config, runtime.config());
+            assert!(runtime.has_strict_types() && runtime.has_legacy_range());
+        }
+
+        #[test]
+        fn setters_change_config() {
+            let mut runtime = Runtime::new();
+            runtime.set_dynamic_scoping(true);
+            runtime.set_memory_limit(Some(64));
+            runtime.set_seed(3);
+            assert_eq!(
+                &Config { dynamic_scoping: true, max_memory: Some(64), seed: 3, ..Config::default() },
+                runtime.config(),
+            );
+        }
+
+        #[test]
+        fn source_is_read_with_configured_tab_width() {
+            crate::run_in("fi rtue\n\tx = 1", &mut Runtime::new()).unwrap();
+            let mut runtime = Runtime::with_config(Config { tab_width: 6, ..Config::default() });
+            assert_eq!(ExpectedBlock, crate::run_in("fi rtue\n\tx = 1", &mut runtime).unwrap_err().kind);
+        }
+    }
+
+    mod coercion_tests {
+        use super::*;
+
+        #[test]
+        fn coercion_mode_decides_mixed_operators() {
+            let mut runtime = Runtime::new();
+            crate::run_in("x = 5 + \"\"a\"", &mut runtime).unwrap();
+            assert_eq!(Value::Number(102.0), *runtime.get_variable("x").unwrap());
+            // constant folding mustn't settle the result ahead of the runtime's mode
+            runtime.set_coercion(Coercion::Symmetric);
+            crate::run_in("x = 5 + \"\"a\"\n y = 3 * \"\"ab\"", &mut runtime).unwrap();
+            assert_eq!(Value::from("5a"), *runtime.get_variable("x").unwrap());
+            assert_eq!(Value::from("ababab"), *runtime.get_variable("y").unwrap());
+        }
+
+        #[test]
+        fn comparison_mode_decides_relational_operators() {
+            let mut runtime = Runtime::new();
+            crate::run_in("x = \"\"b\" < 99", &mut runtime).unwrap();
+            assert_eq!(Value::Bool(true), *runtime.get_variable("x").unwrap());
+            runtime.set_comparison(Comparison::Total);
+            crate::run_in("x = \"\"b\" < 99\n y = [[1, \"\"a\"] < [[1, \"\"b\"]", &mut runtime).unwrap();
+            assert_eq!(Value::Bool(false), *runtime.get_variable("x").unwrap());
+            assert_eq!(Value::Bool(true), *runtime.get_variable("y").unwrap());
+        }
+    }
+
+    mod trace_tests {
+        use super::*;
+
+        fn trace_of(source: &str) -> String {
+            let stderr = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stdout(SharedBuffer::new());
+            runtime.set_stderr(stderr.clone());
+            runtime.set_trace(true);
+            crate::run_in(source, &mut runtime).unwrap();
+            stderr.contents()
+        }
+
+        #[test]
+        fn no_trace_by_default() {
+            let stderr = SharedBuffer::new();
+            let mut runtime = Runtime::new();
+            runtime.set_stderr(stderr.clone());
+            crate::run_in("x = 1", &mut runtime).unwrap();
+            assert_eq!("", stderr.contents());
+        }
+
+        #[test]
+        fn traces_assignments_with_values() {
+            assert_eq!(
+                "[trace] line 1: x = 1\n[trace] line 2: y = \"a\"\"\n",
+                trace_of("x = 1\n y = \"a\"\""),
+            );
+        }
+
+        #[test]
+        fn traces_control_flow_and_calls() {
+            assert_eq!(
+                "[trace] line 1: fir i\n[trace] line 1: i = 0\n[trace] line 2: call pront\n",
+                trace_of("fir i ni arnge((1)\n   pront((i)"),
+            );
+        }
+
+        #[test]
+        fn traces_function_parameters() {
+            assert_eq!(
+                "[trace] line 1: fnuc f\n[trace] line 3: call f\n[trace] line 3: a = 2\n\
+                 [trace] line 2: retrun\n",
+                trace_of("fnuc f((a)\n   retrun a\n f((2)"),
+            );
+        }
+    }
+
+    mod memory_tests {
+        use super::*;
+
+        #[test]
+        fn assignments_are_accounted_for() {
+            let mut runtime = Runtime::new();
+            runtime.set_variable("a", Value::String("abcd".to_string())).unwrap();
+            assert_eq!(4, runtime.memory_used());
+            runtime.set_variable("a", Value::String("ab".to_string())).unwrap();
+            assert_eq!(2, runtime.memory_used());
+        }
+
+        #[test]
+        fn ending_scope_releases_memory() {
+            let mut runtime = Runtime::new();
+            runtime.begin_scope();
+            runtime.set_variable("a", Value::String("abcd".to_string())).unwrap();
+            runtime.end_scope();
+            assert_eq!(0, runtime.memory_used());
+        }
+
+        #[test]
+        fn assignment_over_limit_throws_memory_limit_error() {
+            let mut runtime = Runtime::new();
+            runtime.set_memory_limit(Some(3));
+            assert_eq!(
+                MemoryLimit { max_bytes: 3 },
+                runtime.set_variable("a", Value::String("abcd".to_string())).unwrap_err().kind,
+            );
+            assert_eq!(0, runtime.memory_used());
+        }
+
+        #[test]
+        fn memory_limit_stops_runaway_append() {
+            let mut runtime = Runtime::new();
+            runtime.set_memory_limit(Some(1024));
+            assert_eq!(
+                MemoryLimit { max_bytes: 1024 },
+                crate::run_in("x = []]\n whitl rtue\n    x = x + [1]]", &mut runtime)
+                    .unwrap_err().kind,
+            );
+        }
+
+        #[test]
+        fn memory_limit_stops_large_repetition_before_allocating() {
+            let mut runtime = Runtime::new();
+            runtime.set_memory_limit(Some(1024));
+            assert_eq!(
+                MemoryLimit { max_bytes: 1024 },
+                crate::run_in("x = \"a\"\" * 1000000000000", &mut runtime).unwrap_err().kind,
             );
         }
     }
@@ -531,12 +1853,14 @@ mod tests {
     fn generic_function_definition_returning(return_value: Value) -> FunctionDefinitionNode {
         use crate::ast::{ConstantNode, ExpressionNode, ReturnNode, StatementNode};
 
+        let position = Position::new(1, 0, 1);
         let mut function_block = Block::new();
         function_block.add_statement(StatementNode::Return(ReturnNode::new(
             ExpressionNode::Constant(ConstantNode::new(
-                return_value
-            ))
+                return_value, position,
+            )),
+            position,
         )));
-        FunctionDefinitionNode::new("test".to_string(), vec![], function_block)
+        FunctionDefinitionNode::new("test".to_string(), vec![], function_block, position)
     }
 }
\ No newline at end of file
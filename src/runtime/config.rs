@@ -0,0 +1,73 @@
+//! Everything about how a runtime runs programs that can be chosen when it's created.
+
+use std::time::Duration;
+use crate::lexer::DEFAULT_TAB_WIDTH;
+use crate::value::{Coercion, Comparison};
+use super::DEFAULT_MAX_DEPTH;
+
+
+/// How a [`Runtime`](super::Runtime) runs programs: the semantics it gives them, the limits it
+/// holds them to, and how their source is read. Pass one to
+/// [`Runtime::with_config`](super::Runtime::with_config), starting from the defaults for anything
+/// that doesn't matter, e.g.
+/// ```
+/// # use mornington::runtime::{Config, Runtime};
+/// let runtime = Runtime::with_config(Config {
+///     strict_types: true,
+///     max_steps: Some(10_000),
+///     ..Config::default()
+/// });
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// How operators treat operands of different types.
+    pub coercion: Coercion,
+    /// How the relational operators compare their operands.
+    pub comparison: Comparison,
+    /// Whether operators fail with a `Type` error when applied to values of types they would
+    /// otherwise coerce, rather than coercing them.
+    pub strict_types: bool,
+    /// Whether function calls can see and assign to the variables of whatever called them, as
+    /// they could in earlier versions.
+    pub dynamic_scoping: bool,
+    /// Whether `arnge` takes its three-argument form as `arnge((start, step, stop)`, as it did in
+    /// earlier versions.
+    pub legacy_range: bool,
+    /// Whether each loop runs all its iterations in the one scope, as in earlier versions.
+    pub shared_loop_scopes: bool,
+    /// Whether each statement and assignment is logged to the runtime's stderr stream as it
+    /// executes.
+    pub trace: bool,
+    /// The number of statements a program can execute before it's stopped, if it's limited.
+    pub max_steps: Option<u64>,
+    /// How long a program can run for, counted from when the runtime was created, if it's limited.
+    pub max_duration: Option<Duration>,
+    /// The approximate heap memory, in bytes, variables can hold at once, if it's limited.
+    pub max_memory: Option<usize>,
+    /// How deeply function calls can be nested. See
+    /// [`Runtime::set_max_depth`](super::Runtime::set_max_depth).
+    pub max_depth: usize,
+    /// How many columns a tab in a line's indentation counts for, in the source the runtime runs.
+    pub tab_width: usize,
+    /// The seed of the random numbers builtins are given.
+    pub seed: u64,
+}
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            coercion: Coercion::default(),
+            comparison: Comparison::default(),
+            strict_types: false,
+            dynamic_scoping: false,
+            legacy_range: false,
+            shared_loop_scopes: false,
+            trace: false,
+            max_steps: None,
+            max_duration: None,
+            max_memory: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            tab_width: DEFAULT_TAB_WIDTH,
+            seed: 0,
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Cooperative tasks, the channels they communicate through, and timers.
+//!
+//! Tasks don't run in parallel, or even interleave: each runs to completion once it's started,
+//! inside whatever started it. A task is started when something needs it to have run, i.e. when
+//! it's joined, when a channel is received from while empty (in which case tasks are started in
+//! the order they were spawned until one sends to it), or when the program ends.
+//!
+//! Timers make a call once a number of seconds has passed, or every time it does, but only while
+//! the program is waiting for them in [`Runtime::run_timers`].
+
+use std::collections::VecDeque;
+use std::mem;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ast::{Evaluable, FunctionCallNode};
+use crate::error::{Error, ErrorKind::Task};
+use crate::value::Value;
+use super::{Runtime, Scope};
+
+
+/// Every task, channel, and timer a runtime has created, identified by their indices.
+#[derive(Debug, Default)]
+pub(crate) struct Tasks {
+    tasks: Vec<TaskState>,
+    channels: Vec<VecDeque<Value>>,
+    /// Timers that haven't yet fired for the last time or been cancelled.
+    timers: Vec<Option<Timer>>,
+}
+
+#[derive(Debug)]
+enum TaskState {
+    /// A call waiting to be made, with arguments that have already been evaluated, and the scope
+    /// it'll be made in.
+    Pending { call: FunctionCallNode, scope: Scope },
+    Running,
+    Finished(Result<Value, Error>),
+}
+
+#[derive(Debug)]
+struct Timer {
+    due: Instant,
+    /// How long to wait before firing again, for timers that repeat.
+    interval: Option<Duration>,
+    call: FunctionCallNode,
+    scope: Scope,
+}
+
+impl Runtime {
+    /// Creates a task that will make `call`, whose arguments must already have been evaluated,
+    /// with its own scope stack that starts with every function currently visible. Returns the
+    /// task's handle.
+    pub(crate) fn spawn_task(&mut self, call: FunctionCallNode) -> Value {
+        let scope = self.function_scope();
+        self.tasks.tasks.push(TaskState::Pending { call, scope });
+        Value::Int((self.tasks.tasks.len() - 1) as i64)
+    }
+
+    /// Runs the task with `handle` if it hasn't been already, then returns its result.
+    pub(crate) fn join_task(&mut self, handle: &Value) -> Result<Value, Error> {
+        let index = handle_index(handle, self.tasks.tasks.len(), "task")?;
+        if let TaskState::Pending { .. } = self.tasks.tasks[index] {
+            self.run_task(index)?;
+        }
+        match &self.tasks.tasks[index] {
+            TaskState::Finished(result) => result.clone(),
+            _ => Err(Error::new(Task(format!("task {index} is waiting for itself to finish")), None)),
+        }
+    }
+
+    /// Creates an empty channel and returns its handle.
+    pub(crate) fn new_channel(&mut self) -> Value {
+        self.tasks.channels.push(VecDeque::new());
+        Value::Int((self.tasks.channels.len() - 1) as i64)
+    }
+
+    /// Queues `value` on the channel with `handle`.
+    pub(crate) fn send(&mut self, handle: &Value, value: Value) -> Result<(), Error> {
+        let index = handle_index(handle, self.tasks.channels.len(), "channel")?;
+        self.tasks.channels[index].push_back(value);
+        Ok(())
+    }
+
+    /// Takes the oldest value from the channel with `handle`, running tasks until one is sent if
+    /// it's empty.
+    pub(crate) fn receive(&mut self, handle: &Value) -> Result<Value, Error> {
+        let index = handle_index(handle, self.tasks.channels.len(), "channel")?;
+        loop {
+            if let Some(value) = self.tasks.channels[index].pop_front() {
+                return Ok(value);
+            }
+            let Some(task) = self.next_pending_task() else {
+                return Err(Error::new(
+                    Task(format!("channel {index} is empty, and there are no tasks left to send to it")),
+                    None,
+                ));
+            };
+            self.run_task(task)?;
+        }
+    }
+
+    /// Runs every task that hasn't been run yet, including any they spawn.
+    pub(crate) fn finish_tasks(&mut self) -> Result<(), Error> {
+        while let Some(task) = self.next_pending_task() {
+            self.run_task(task)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a timer that will make `call`, whose arguments must already have been evaluated,
+    /// once `seconds` have passed, then every `seconds` after that if it `repeats`. Like a task, it
+    /// gets its own scope stack every time it fires. Returns the timer's handle.
+    pub(crate) fn start_timer(&mut self, seconds: &Value, repeats: bool, call: FunctionCallNode)
+        -> Result<Value, Error>
+    {
+        let interval = Duration::try_from_secs_f64(seconds.coerce_to_number().max(0.0))
+            .map_err(|_| Error::new(Task(format!("`{seconds}` isn't a number of seconds")), None))?;
+        let scope = self.function_scope();
+        self.tasks.timers.push(Some(Timer {
+            due: Instant::now() + interval,
+            interval: repeats.then_some(interval),
+            call,
+            scope,
+        }));
+        Ok(Value::Int((self.tasks.timers.len() - 1) as i64))
+    }
+
+    /// Stops the timer with `handle` from firing again. Cancelling a timer that's already finished
+    /// does nothing.
+    pub(crate) fn cancel_timer(&mut self, handle: &Value) -> Result<(), Error> {
+        let index = handle_index(handle, self.tasks.timers.len(), "timer")?;
+        self.tasks.timers[index] = None;
+        Ok(())
+    }
+
+    /// Waits for each timer to be due in turn and fires it, until none are left.
+    pub(crate) fn run_timers(&mut self) -> Result<(), Error> {
+        loop {
+            // the earliest timer to be due, or the first started if several are due at once
+            let Some((index, due)) = self.tasks.timers.iter()
+                .enumerate()
+                .filter_map(|(index, timer)| Some((index, timer.as_ref()?.due)))
+                .min_by_key(|(_, due)| *due)
+                else { return Ok(()) };
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+
+            // reschedule or remove the timer before firing it, so that it can cancel itself
+            let timer = self.tasks.timers[index].as_mut().unwrap();
+            let (call, scope) = match timer.interval {
+                Some(interval) => {
+                    timer.due += interval;
+                    (timer.call.clone(), timer.scope.clone())
+                },
+                None => {
+                    let timer = self.tasks.timers[index].take().unwrap();
+                    (timer.call, timer.scope)
+                },
+            };
+            self.call_on_own_stack(&call, scope)?;
+        }
+    }
+
+    /// Returns a scope holding every function currently visible, for calls made on their own
+    /// scope stack.
+    fn function_scope(&self) -> Scope {
+        let mut scope = Scope::new();
+        for outer in &self.stack {
+            scope.functions.extend(outer.functions.iter().map(|(name, definition)| {
+                (*name, Rc::clone(definition))
+            }));
+        }
+        scope
+    }
+
+    /// Makes `call` on a scope stack that starts with just `scope`, then puts the current stack
+    /// back.
+    fn call_on_own_stack(&mut self, call: &FunctionCallNode, scope: Scope) -> Result<Value, Error> {
+        let stack = mem::replace(&mut self.stack, vec![scope]);
+        let frames = mem::take(&mut self.frames);
+        let result = call.evaluate(self);
+        while !self.stack.is_empty() {
+            self.end_scope();
+        }
+        self.stack = stack;
+        self.frames = frames;
+        result
+    }
+
+    fn next_pending_task(&self) -> Option<usize> {
+        self.tasks.tasks.iter().position(|task| matches!(task, TaskState::Pending { .. }))
+    }
+
+    /// Runs the pending task at `index` on its own scope stack, recording its result. If it fails,
+    /// the error is also returned, so that it isn't lost if the task is never joined.
+    fn run_task(&mut self, index: usize) -> Result<(), Error> {
+        let TaskState::Pending { call, scope } = mem::replace(&mut self.tasks.tasks[index], TaskState::Running)
+            else { unreachable!("only pending tasks are run") };
+        let result = self.call_on_own_stack(&call, scope);
+        self.tasks.tasks[index] = TaskState::Finished(result.clone());
+        result.map(|_| ())
+    }
+}
+
+/// Returns the index `handle` refers to, if it's a valid handle for one of `count` tasks,
+/// channels, or timers.
+fn handle_index(handle: &Value, count: usize, kind: &str) -> Result<usize, Error> {
+    let number = handle.coerce_to_number();
+    if number >= 0.0 && number.fract() == 0.0 && number < count as f64 {
+        Ok(number as usize)
+    } else {
+        Err(Error::new(Task(format!("`{handle}` isn't a {kind}")), None))
+    }
+}
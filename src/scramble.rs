@@ -0,0 +1,177 @@
+use crate::error::{Error, ErrorKind::Unconvertible};
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::parser::Parser;
+use crate::random::Random;
+
+
+/// Rewrites `source` with every wrapper, string quote, and indentation re-randomised, choosing
+/// new lengths with `seed`, so that the same program can be shown in many different shapes. The
+/// same seed always gives the same result.
+///
+/// Only the lengths of bracket runs and quote runs and the spaces past each indentation level
+/// change; everything else is left as it was. The result is parsed again and checked to give
+/// the same syntax tree as `source`, and an error is returned if it somehow doesn't.
+pub fn rescramble(source: &str, seed: u64) -> Result<String, Error> {
+    let mut lexer = Lexer::new(source);
+    lexer.set_keep_trivia(true);
+    let tokens = lexer.lex()?;
+    let original = Parser::new(&tokens).parse()?.to_source();
+
+    let mut scrambler = Scrambler {
+        random: Random::new(seed),
+        output: String::new(),
+        wrappers: Vec::new(),
+        indentations: Vec::new(),
+    };
+    let mut cursor = 0;
+    let mut start_of_line = true;
+    for token in &tokens {
+        if start_of_line && scrambler.wrappers.is_empty() {
+            // lines holding only comments are part of the trivia, and kept as they are; only the
+            // line the token's on is reindented, after anything before it on that line
+            let line_start = token.trivia.rfind('\n').map_or(0, |newline| newline + 1);
+            let (comment_lines, line) = token.trivia.split_at(line_start);
+            scrambler.output += comment_lines;
+            scrambler.indent(token.indentation, token.kind);
+            scrambler.output += line.trim_start_matches([' ', '\t']);
+        } else {
+            scrambler.output += token.trivia;
+        }
+        scrambler.token(token);
+        start_of_line = token.kind == TokenKind::Newline;
+        cursor += token.trivia.len() + token.text.len();
+    }
+    scrambler.output += &source[cursor..];
+
+    let rescrambled = Parser::new(&Lexer::new(&scrambler.output).lex()?).parse()?.to_source();
+    if rescrambled != original {
+        return Err(Error::new(
+            Unconvertible("the program couldn't be rescrambled without changing its meaning".to_string()),
+            None,
+        ));
+    }
+    Ok(scrambler.output)
+}
+
+struct Scrambler {
+    random: Random,
+    output: String,
+    /// The length chosen for each wrapper that's been opened but not yet closed.
+    wrappers: Vec<usize>,
+    /// The indentation of the last statement at each level of the current line's enclosing
+    /// blocks, which the next statement at that level mustn't match.
+    indentations: Vec<Option<usize>>,
+}
+impl Scrambler {
+    /// The longest run of brackets or quotes to write.
+    const MAX_RUN: usize = 4;
+
+    /// Indents a line that was indented by `indentation` columns, and starts with a token of
+    /// `kind`, by a random amount at the same level.
+    fn indent(&mut self, indentation: usize, kind: TokenKind) {
+        let level = indentation / 3;
+        self.indentations.resize(level + 1, None);
+        let previous = self.indentations[level];
+        let chosen = match (kind, previous) {
+            // `lefi` and `sele` line up with the `fi` they belong to, so that they can't clash
+            // with whatever follows the conditional
+            (TokenKind::Elif | TokenKind::Else, Some(previous)) => previous,
+            _ => loop {
+                let candidate = level * 3 + self.random.below(3);
+                if Some(candidate) != previous {
+                    self.indentations[level] = Some(candidate);
+                    break candidate;
+                }
+            },
+        };
+        self.output += &" ".repeat(chosen);
+    }
+
+    fn token(&mut self, token: &Token) {
+        let text = token.text;
+        match token.kind {
+            TokenKind::LParen | TokenKind::LBrack => {
+                let length = 1 + self.random.below(Self::MAX_RUN);
+                self.wrappers.push(length);
+                self.run(&text[..1], length);
+            },
+            TokenKind::RParen | TokenKind::RBrack => {
+                let opener = self.wrappers.pop().unwrap_or_default();
+                let length = self.length_other_than(opener);
+                self.run(&text[..1], length);
+            },
+            TokenKind::String if text == "\"'" || text == "'\"" => {
+                self.output += if self.random.below(2) == 0 { "\"'" } else { "'\"" };
+            },
+            TokenKind::String => {
+                let contents = text.trim_matches('"');
+                // a lone quote followed by an apostrophe would be read as an empty string
+                let opener = if contents.starts_with('\'') {
+                    2 + self.random.below(Self::MAX_RUN - 1)
+                } else {
+                    1 + self.random.below(Self::MAX_RUN)
+                };
+                let closer = self.length_other_than(opener);
+                self.output += &format!("{}{contents}{}", "\"".repeat(opener), "\"".repeat(closer));
+            },
+            _ => self.output += text,
+        }
+    }
+
+    /// Chooses a random run length that doesn't balance one of length `other`.
+    fn length_other_than(&mut self, other: usize) -> usize {
+        let length = 1 + self.random.below(Self::MAX_RUN - 1);
+        if length >= other { length + 1 } else { length }
+    }
+
+    /// Writes a run of `length` copies of `wrapper`, separating it from a run of the same wrapper
+    /// before it so that the two aren't read as one.
+    fn run(&mut self, wrapper: &str, length: usize) {
+        if self.output.ends_with(wrapper) {
+            self.output.push(' ');
+        }
+        self.output += &wrapper.repeat(length);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+fnuc f((a, b)
+   fi a > b
+      retrun [[a, \"\"x\", \"', [[b] ]
+    sele
+      retrun ((a + b) * 2
+x = f((1, ((2) )
+ pront((x)
+";
+
+    #[test]
+    fn preserves_the_program() {
+        for seed in 0..50 {
+            let rescrambled = rescramble(SOURCE, seed).unwrap();
+            assert_eq!(crate::format(SOURCE).unwrap(), crate::format(&rescrambled).unwrap());
+        }
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "/** a */\nx = [[1, 2] /* b **/\n  /** c */\n pront((x)\n/** d */";
+        for seed in 0..20 {
+            let rescrambled = rescramble(source, seed).unwrap();
+            assert!(rescrambled.starts_with("/** a */\n"));
+            assert!(rescrambled.ends_with("\n/** d */"));
+            assert!(rescrambled.contains("] /* b **/\n  /** c */\n"));
+            assert_eq!(crate::format(source).unwrap(), crate::format(&rescrambled).unwrap());
+        }
+    }
+
+    #[test]
+    fn varies_with_the_seed() {
+        assert_eq!(rescramble(SOURCE, 7).unwrap(), rescramble(SOURCE, 7).unwrap());
+        assert_ne!(rescramble(SOURCE, 7).unwrap(), rescramble(SOURCE, 8).unwrap());
+    }
+}
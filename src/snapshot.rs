@@ -0,0 +1,233 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use crate::error::{Error, ErrorKind::{InvalidSnapshot, Output}};
+use crate::value::Value;
+
+
+/// The first line of every snapshot, so that other files aren't mistaken for one.
+const HEADER: &str = "mornington snapshot 1";
+
+
+/// The variables and functions visible in a [`Runtime`](crate::runtime::Runtime) at some point,
+/// taken with [`Runtime::snapshot`](crate::runtime::Runtime::snapshot) and put back with
+/// [`Runtime::restore`](crate::runtime::Runtime::restore).
+///
+/// Snapshots are written out as text: a header line, then a line for each variable and each
+/// function. Values are written as a type letter followed by their contents, e.g. `n 3` or
+/// `l 2 b 1 s 5:hello`, and strings and function source are prefixed with their length in bytes
+/// so that they can hold anything, newlines included.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    variables: Vec<(String, Value)>,
+    /// Each function's name and source.
+    functions: Vec<(String, String)>,
+}
+impl Snapshot {
+    pub fn new(variables: Vec<(String, Value)>, functions: Vec<(String, String)>) -> Snapshot {
+        Snapshot { variables, functions }
+    }
+
+    pub fn variables(&self) -> &[(String, Value)] {
+        &self.variables
+    }
+
+    /// Returns each function's name alongside the source defining it.
+    pub fn functions(&self) -> &[(String, String)] {
+        &self.functions
+    }
+
+    /// Reads a snapshot back from the text written by its [`Display`] implementation.
+    pub fn parse(text: &str) -> Result<Snapshot, Error> {
+        let body = text.strip_prefix(HEADER).ok_or_else(|| invalid("missing header"))?;
+        let mut reader = Reader { rest: body };
+        let mut snapshot = Snapshot::default();
+        while let Some(kind) = reader.word() {
+            match kind {
+                "variable" => {
+                    let name = reader.expect_word()?.to_string();
+                    let value = reader.value()?;
+                    snapshot.variables.push((name, value));
+                },
+                "function" => {
+                    let name = reader.expect_word()?.to_string();
+                    let source = reader.string()?.to_string();
+                    snapshot.functions.push((name, source));
+                },
+                other => return Err(invalid(&format!("unknown entry `{other}`"))),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Writes the snapshot to the file at `path`, replacing anything already there.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.to_string()).map_err(|_| Error::new(Output, None))
+    }
+
+    /// Reads a snapshot from the file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Snapshot, Error> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|_| invalid(&format!("couldn't read `{}`", path.display())))?;
+        Snapshot::parse(&text)
+    }
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        for (name, value) in &self.variables {
+            write!(f, "variable {name}")?;
+            write_value(f, value)?;
+            writeln!(f)?;
+        }
+        for (name, source) in &self.functions {
+            writeln!(f, "function {name} {}:{source}", source.len())?;
+        }
+        Ok(())
+    }
+}
+
+fn write_value(f: &mut Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::Int(int) => write!(f, " i {int}"),
+        Value::Number(number) => write!(f, " n {number}"),
+        Value::Bool(bool) => write!(f, " b {}", u8::from(*bool)),
+        Value::String(string) => write!(f, " s {}:{string}", string.len()),
+        Value::Bytes(bytes) => {
+            write!(f, " x {}:", bytes.len() * 2)?;
+            bytes.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+        },
+        Value::List(list) => {
+            write!(f, " l {}", list.len())?;
+            list.iter().try_for_each(|element| write_value(f, element))
+        },
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::new(InvalidSnapshot(reason.to_string()), None)
+}
+
+
+/// Picks apart the text of a snapshot, a piece at a time.
+struct Reader<'a> {
+    rest: &'a str,
+}
+impl<'a> Reader<'a> {
+    /// Returns the next run of non-whitespace characters, or `None` at the end of the text.
+    fn word(&mut self) -> Option<&'a str> {
+        let trimmed = self.rest.trim_start();
+        if trimmed.is_empty() {
+            return None;
+        }
+        let end = trimmed.find(char::is_whitespace).unwrap_or(trimmed.len());
+        let (word, rest) = trimmed.split_at(end);
+        self.rest = rest;
+        Some(word)
+    }
+
+    fn expect_word(&mut self) -> Result<&'a str, Error> {
+        self.word().ok_or_else(|| invalid("unexpected end of snapshot"))
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, Error> {
+        let word = self.expect_word()?;
+        word.parse().map_err(|_| invalid(&format!("expected a number, found `{word}`")))
+    }
+
+    /// Reads a string written as its length in bytes, a colon, then the string itself.
+    fn string(&mut self) -> Result<&'a str, Error> {
+        let trimmed = self.rest.trim_start();
+        let (length, rest) = trimmed.split_once(':')
+            .ok_or_else(|| invalid("expected a string length"))?;
+        let length: usize = length.parse()
+            .map_err(|_| invalid(&format!("expected a string length, found `{length}`")))?;
+        let string = rest.get(..length)
+            .ok_or_else(|| invalid("string runs past the end of the snapshot"))?;
+        self.rest = &rest[length..];
+        Ok(string)
+    }
+
+    fn value(&mut self) -> Result<Value, Error> {
+        Ok(match self.expect_word()? {
+            "i" => Value::Int(self.number()?),
+            "n" => Value::Number(self.number()?),
+            "b" => Value::Bool(self.number::<u8>()? != 0),
+            "s" => Value::String(self.string()?.to_string()),
+            "x" => {
+                let hex = self.string()?;
+                let bytes = (0..hex.len()).step_by(2)
+                    .map(|index| hex.get(index..index + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid(&format!("expected hexadecimal bytes, found `{hex}`")))?;
+                Value::Bytes(bytes)
+            },
+            "l" => {
+                let length: usize = self.number()?;
+                let mut list = Vec::new();
+                for _ in 0..length {
+                    list.push(self.value()?);
+                }
+                Value::List(list)
+            },
+            other => return Err(invalid(&format!("unknown value type `{other}`"))),
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let snapshot = Snapshot::new(
+            vec![
+                ("x".to_string(), Value::Number(-0.1)),
+                ("y".to_string(), Value::List(vec![
+                    Value::Bool(true),
+                    Value::String("two words\nand a line".to_string()),
+                    Value::List(Vec::new()),
+                ])),
+            ],
+            vec![("f".to_string(), "fnuc f((a)\n   retrun a\n".to_string())],
+        );
+        let text = snapshot.to_string();
+        assert_eq!(
+            "mornington snapshot 1\n\
+             variable x n -0.1\n\
+             variable y l 3 b 1 s 20:two words\nand a line l 0\n\
+             function f 23:fnuc f((a)\n   retrun a\n\n",
+            text,
+        );
+        assert_eq!(snapshot, Snapshot::parse(&text).unwrap());
+    }
+
+    #[test]
+    fn round_trips_integers_and_bytes() {
+        let snapshot = Snapshot::new(
+            vec![("x".to_string(), Value::List(vec![Value::Int(7), Value::Bytes(vec![0, 171])]))],
+            Vec::new(),
+        );
+        let text = snapshot.to_string();
+        assert_eq!("mornington snapshot 1\nvariable x l 2 i 7 x 4:00ab\n", text);
+        assert_eq!(snapshot, Snapshot::parse(&text).unwrap());
+        assert!(Snapshot::parse("mornington snapshot 1\nvariable x x 2:zz").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_snapshots() {
+        let reason = |text: &str| match Snapshot::parse(text).unwrap_err().kind {
+            InvalidSnapshot(reason) => reason,
+            kind => panic!("unexpected error {kind:?}"),
+        };
+        assert_eq!("missing header", reason("variable x n 1"));
+        assert_eq!("unknown value type `q`", reason("mornington snapshot 1\nvariable x q 1"));
+        assert_eq!("expected a number, found `one`", reason("mornington snapshot 1\nvariable x n one"));
+        assert_eq!("string runs past the end of the snapshot", reason("mornington snapshot 1\nvariable x s 9:ab"));
+        assert_eq!("unexpected end of snapshot", reason("mornington snapshot 1\nvariable x l 2 n 1"));
+    }
+}
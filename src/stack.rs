@@ -0,0 +1,24 @@
+//! Keeps code that recurses as deeply as a program is nested (parsing, walking the tree, and
+//! running it) from overflowing the stack, by carrying on in a new stack allocated on the heap
+//! whenever the current one is nearly used up.
+
+/// How much of the stack has to be left for a guarded function to run without switching to a
+/// new one. Comfortably more than the recursion between two guarded calls uses.
+#[cfg(not(target_family = "wasm"))]
+const RED_ZONE: usize = 128 * 1024;
+/// The size of each new stack.
+#[cfg(not(target_family = "wasm"))]
+const STACK_SIZE: usize = 4 * 1024 * 1024;
+
+/// Calls `f`, on a new stack if the current one is nearly used up. WebAssembly can't switch
+/// stacks, so there `f` is just called, and [`Config::max_depth`](crate::runtime::Config) is what
+/// stops programs running out of stack.
+#[cfg(not(target_family = "wasm"))]
+pub(crate) fn guard<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(RED_ZONE, STACK_SIZE, f)
+}
+
+#[cfg(target_family = "wasm")]
+pub(crate) fn guard<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
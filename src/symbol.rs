@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+
+
+/// An interned variable or function name.
+///
+/// Every symbol with the same text shares one copy of it, so symbols are compared and hashed by
+/// address rather than by their text, which makes looking names up in scopes cheap. Names are
+/// interned once, when they're parsed, and the table of names is never freed, which is fine for
+/// the handful of names any program uses.
+#[derive(Copy, Clone)]
+pub struct Symbol(&'static str);
+impl Symbol {
+    /// Returns the symbol for `name`, adding it to the table if it isn't there already.
+    pub fn intern(name: &str) -> Symbol {
+        static TABLE: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut table = TABLE.get_or_init(Default::default).lock().unwrap_or_else(|error| error.into_inner());
+        match table.get(name) {
+            Some(interned) => Symbol(interned),
+            None => {
+                let interned: &'static str = Box::leak(name.into());
+                table.insert(interned);
+                Symbol(interned)
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        ptr::eq(self.0, other.0)
+    }
+}
+impl Eq for Symbol {}
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.as_ptr().hash(state);
+    }
+}
+/// Symbols are ordered by their text, so that anything sorted by name is in alphabetical order.
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(other.0)
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.0, f)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+/// Symbols are serialized as their text, and interned again when they're deserialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        String::deserialize(deserializer).map(Symbol::from)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_one_copy_of_each_name() {
+        let name = String::from("shared");
+        assert!(ptr::eq(Symbol::intern("shared").as_str(), Symbol::from(name).as_str()));
+        assert_eq!(Symbol::intern("shared"), Symbol::intern("shared"));
+        assert_ne!(Symbol::intern("shared"), Symbol::intern("shraed"));
+    }
+
+    #[test]
+    fn orders_by_name() {
+        let mut symbols = [Symbol::intern("b"), Symbol::intern("c"), Symbol::intern("a")];
+        symbols.sort();
+        assert_eq!(vec!["a", "b", "c"], symbols.iter().map(Symbol::as_str).collect::<Vec<_>>());
+    }
+}
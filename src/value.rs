@@ -1,16 +1,103 @@
+use std::cmp::Ordering;
 use std::fmt::{Display, Formatter};
-use crate::error::{Error, ErrorKind::{InvalidFormatFlag, IncorrectNumberOfFormatStringArguments}};
-
-#[derive(Clone, Debug, PartialEq)]
+use std::iter::Peekable;
+use std::str::CharIndices;
+use crate::ast::Operator;
+use crate::error::{Error, ErrorKind::{
+    FormatArgumentIndex, InvalidFormatFlag, IncorrectNumberOfFormatStringArguments,
+}};
+
+#[cfg(feature = "serde")]
+mod serde;
+
+/// A Mornington value. `nmu`s are held as integers for as long as they can be exactly, so that
+/// counters and indices stay whole, and are promoted to floats once an operation on them
+/// overflows or has a fractional result; the two are otherwise interchangeable, and compare equal
+/// whenever they hold the same number. With the `serde` feature, values can be serialized and
+/// deserialized as the plain data closest to them, e.g. `[[1, ""a"]` as `[1,"a"]` in JSON.
+#[derive(Clone, Debug)]
 pub enum Value {
+    Int(i64),
     Number(f64),
     Bool(bool),
     String(String),
+    /// Binary data, which unlike a string needn't be valid UTF-8.
+    Bytes(Vec<u8>),
     List(Vec<Value>),
 }
+
+/// How deeply `pprnit` and the REPL show nested `lsit`s, unless told otherwise. See
+/// [`Value::display_pretty`].
+pub const PRETTY_MAX_DEPTH: usize = 8;
+/// How many elements of each `lsit` `pprnit` and the REPL show, unless told otherwise.
+pub const PRETTY_MAX_ITEMS: usize = 100;
+
 impl Value {
+    /// Returns the name of the value's type, as Mornington calls it, e.g. `nmu` for a number.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) | Value::Number(_) => "nmu",
+            Value::Bool(_) => "obol",
+            Value::String(_) => "sting",
+            Value::Bytes(_) => "bytse",
+            Value::List(_) => "lsit",
+        }
+    }
+
+    /// Returns the approximate number of bytes of heap memory held by the value.
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Value::Int(_) | Value::Number(_) | Value::Bool(_) => 0,
+            Value::String(string) => string.len(),
+            Value::Bytes(bytes) => bytes.len(),
+            Value::List(list) => {
+                list.len() * std::mem::size_of::<Value>()
+                    + list.iter().map(Value::heap_size).sum::<usize>()
+            },
+        }
+    }
+
+    /// Returns the number of elements in a `lsit`, characters in a `sting`, or bytes in a
+    /// `bytse`, or `None` if the value isn't any of them.
+    pub fn len(&self) -> Option<usize> {
+        match self {
+            Value::Int(_) | Value::Number(_) | Value::Bool(_) => None,
+            Value::String(string) => Some(string.chars().count()),
+            Value::Bytes(bytes) => Some(bytes.len()),
+            Value::List(list) => Some(list.len()),
+        }
+    }
+
+    /// Returns whether the value is an empty `lsit`, `sting`, or `bytse`, or `None` if it isn't
+    /// any of them.
+    pub fn is_empty(&self) -> Option<bool> {
+        self.len().map(|len| len == 0)
+    }
+
+    /// Iterates over the elements of a `lsit`, the characters of a `sting` as one-character
+    /// `sting`s, or the bytes of a `bytse` as `nmu`s. Any other value has nothing to iterate over.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(match self {
+            Value::Int(_) | Value::Number(_) | Value::Bool(_) => IterInner::Empty,
+            Value::String(string) => IterInner::Chars(string.chars()),
+            Value::Bytes(bytes) => IterInner::Bytes(bytes.iter()),
+            Value::List(list) => IterInner::Elements(list.iter()),
+        })
+    }
+
+    /// Returns the element, character, or byte at `index`, as [`Value::iter`] would give it, or
+    /// `None` if the index is out of range or the value isn't a `lsit`, `sting`, or `bytse`.
+    pub fn get(&self, index: usize) -> Option<Value> {
+        match self {
+            Value::List(list) => list.get(index).cloned(),
+            Value::Bytes(bytes) => bytes.get(index).map(|&byte| Value::Int(byte as i64)),
+            _ => self.iter().nth(index),
+        }
+    }
+
     pub(crate) fn coerce_to_number(&self) -> f64 {
         match self {
+            Value::Int(value) => *value as f64,
             Value::Number(value) => *value,
             Value::Bool(value) => if *value { 1.0 } else { 0.0 },
             Value::String(value) => {
@@ -21,6 +108,7 @@ impl Value {
                 }
                 total as f64
             },
+            Value::Bytes(bytes) => bytes.iter().map(|&byte| byte as f64).sum(),
             Value::List(list) => {
                 let mut total = 0.0;
                 for val in list {
@@ -31,13 +119,26 @@ impl Value {
         }
     }
 
+    /// Coerces the value to a number like [`Value::coerce_to_number`], but keeps it an integer
+    /// wherever that can be done exactly.
+    pub(crate) fn coerce_to_numeric(&self) -> Value {
+        match self {
+            Value::Int(_) | Value::Number(_) => self.clone(),
+            Value::Bool(value) => Value::Int(*value as i64),
+            Value::String(_) | Value::Bytes(_) => Value::Int(self.coerce_to_number() as i64),
+            Value::List(list) => list.iter().fold(Value::Int(0), |total, val| total.add(val)),
+        }
+    }
+
     pub(crate) fn coerce_to_bool(&self) -> bool {
         match self {
+            Value::Int(num) => *num != 0,
             Value::Number(num) => *num != 0.0,
             Value::Bool(val) => *val,
             Value::String(string) => {
                 Value::Number(Value::String(string.clone()).coerce_to_number()).coerce_to_bool()
             }
+            Value::Bytes(bytes) => bytes.iter().any(|&byte| byte != 0),
             Value::List(list) => {
                 for val in list {
                     if val.coerce_to_bool() {
@@ -52,21 +153,86 @@ impl Value {
     pub(crate) fn coerce_to_string(&self) -> String {
         match self {
             Value::String(string) => string.clone(),
+            Value::Bytes(bytes) => String::from_utf8_lossy(bytes).into_owned(),
             value => format!("{value}"),
         }
     }
 
     pub(crate) fn coerce_to_list(&self) -> Vec<Value> {
         match self {
+            Value::Int(num) => vec![Value::Int(*num)],
             Value::Number(num) => vec![Value::Number(*num)],
             Value::Bool(val) => vec![Value::Bool(*val)],
             Value::String(string) => vec![Value::String(string.clone())],
-            Value::List(list) => list.clone(),
+            Value::Bytes(_) | Value::List(_) => self.iter().collect(),
+        }
+    }
+
+    /// Returns the elements a `fir` loop over the value iterates over: the characters of a `sting`
+    /// as one-character `sting`s, as with [`Value::iter`], or otherwise the value coerced to a
+    /// `lsit`.
+    pub(crate) fn loop_elements(&self) -> Vec<Value> {
+        match self {
+            Value::String(_) => self.iter().collect(),
+            _ => self.coerce_to_list(),
+        }
+    }
+
+    /// Coerces the value to bytes: strings are encoded as UTF-8, lists have each element coerced
+    /// to a number and truncated to a byte, and anything else becomes a single byte in the same way.
+    pub(crate) fn coerce_to_bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Bytes(bytes) => bytes.clone(),
+            Value::String(string) => string.as_bytes().to_vec(),
+            Value::List(list) => list.iter().map(|val| val.coerce_to_number() as u8).collect(),
+            value => vec![value.coerce_to_number() as u8],
+        }
+    }
+
+    /// Coerces the value to the type of `other`.
+    fn coerce_to_type_of(&self, other: &Value) -> Value {
+        match other {
+            Value::Int(_) => self.coerce_to_numeric(),
+            Value::Number(_) => Value::Number(self.coerce_to_number()),
+            Value::Bool(_) => Value::Bool(self.coerce_to_bool()),
+            Value::String(_) => Value::String(self.coerce_to_string()),
+            Value::Bytes(_) => Value::Bytes(self.coerce_to_bytes()),
+            Value::List(_) => Value::List(self.coerce_to_list()),
+        }
+    }
+
+    /// Where the value's type ranks when [`Coercion::Symmetric`] picks which operand's type counts.
+    fn rank(&self) -> u8 {
+        match self {
+            Value::Bool(_) => 0,
+            Value::Int(_) | Value::Number(_) => 1,
+            Value::String(_) => 2,
+            Value::Bytes(_) => 3,
+            Value::List(_) => 4,
+        }
+    }
+
+    /// Applies an arithmetic operator to the integer `lhs` and `rhs` coerced to a number, using
+    /// `integer` if `rhs` is an integer too, and falling back to `float` if it isn't or `integer`
+    /// can't give an exact result.
+    fn integer_arithmetic(
+        lhs: i64,
+        rhs: &Value,
+        integer: impl Fn(i64, i64) -> Option<i64>,
+        float: impl Fn(f64, f64) -> f64,
+    ) -> Value {
+        match rhs.coerce_to_numeric() {
+            Value::Int(rhs) => match integer(lhs, rhs) {
+                Some(result) => Value::Int(result),
+                None => Value::Number(float(lhs as f64, rhs as f64)),
+            },
+            rhs => Value::Number(float(lhs as f64, rhs.coerce_to_number())),
         }
     }
 
     pub(crate) fn add(&self, rhs: &Value) -> Value {
         match self {
+            Value::Int(lhs) => Self::integer_arithmetic(*lhs, rhs, i64::checked_add, |a, b| a + b),
             Value::Number(lhs) => {
                 let rhs = rhs.coerce_to_number();
                 Value::Number(lhs + rhs)
@@ -81,6 +247,11 @@ impl Value {
                 lhs += &*rhs;
                 Value::String(lhs)
             },
+            Value::Bytes(lhs) => {
+                let mut lhs = lhs.clone();
+                lhs.extend(rhs.coerce_to_bytes());
+                Value::Bytes(lhs)
+            },
             Value::List(lhs) => {
                 let mut lhs = lhs.clone();
                 let mut rhs = rhs.coerce_to_list();
@@ -92,6 +263,7 @@ impl Value {
 
     pub(crate) fn sub(&self, rhs: &Value) -> Value {
         match self {
+            Value::Int(lhs) => Self::integer_arithmetic(*lhs, rhs, i64::checked_sub, |a, b| a - b),
             Value::Number(lhs) => {
                 let rhs = rhs.coerce_to_number();
                 Value::Number(lhs - rhs)
@@ -104,6 +276,7 @@ impl Value {
                 let rhs = rhs.coerce_to_string();
                 Value::String(lhs.replacen(&rhs, "", 1))
             },
+            Value::Bytes(lhs) => Value::Bytes(remove_subslices(lhs, &rhs.coerce_to_bytes(), 1)),
             Value::List(lhs) => {
                 let mut lhs = lhs.clone();
                 let mut location = None;
@@ -126,6 +299,7 @@ impl Value {
 
     pub(crate) fn mul(&self, rhs: &Value) -> Value {
         match self {
+            Value::Int(lhs) => Self::integer_arithmetic(*lhs, rhs, i64::checked_mul, |a, b| a * b),
             Value::Number(lhs) => {
                 let rhs = rhs.coerce_to_number();
                 Value::Number(lhs * rhs)
@@ -138,6 +312,10 @@ impl Value {
                 let rhs = rhs.coerce_to_number().abs() as usize;
                 Value::String(lhs.repeat(rhs))
             },
+            Value::Bytes(lhs) => {
+                let rhs = rhs.coerce_to_number().abs() as usize;
+                Value::Bytes(lhs.repeat(rhs))
+            },
             Value::List(lhs) => {
                 let rhs = rhs.coerce_to_number().abs() as usize;
                 let mut result = Vec::new();
@@ -152,6 +330,13 @@ impl Value {
 
     pub(crate) fn div(&self, rhs: &Value) -> Value {
         match self {
+            Value::Int(lhs) => Self::integer_arithmetic(
+                *lhs,
+                rhs,
+                // only exact quotients stay integers
+                |a, b| if b != 0 && a.checked_rem(b) == Some(0) { a.checked_div(b) } else { None },
+                |a, b| a / b,
+            ),
             Value::Number(lhs) => Value::Number(lhs / rhs.coerce_to_number()),
             Value::Bool(lhs) => {
                 let rhs = rhs.coerce_to_bool();
@@ -161,6 +346,9 @@ impl Value {
                 let rhs = rhs.coerce_to_string();
                 Value::String(lhs.replace(&*rhs, ""))
             },
+            Value::Bytes(lhs) => {
+                Value::Bytes(remove_subslices(lhs, &rhs.coerce_to_bytes(), usize::MAX))
+            },
             Value::List(lhs) => {
                 let mut result = Vec::new();
                 for elem in lhs {
@@ -175,6 +363,7 @@ impl Value {
 
     pub(crate) fn modulus(&self, rhs: &Value) -> Result<Value, Error> {
         match self {
+            Value::Int(lhs) => Ok(Self::integer_arithmetic(*lhs, rhs, i64::checked_rem, |a, b| a % b)),
             Value::Number(lhs) => {
                 let lhs = *lhs;
                 let rhs = rhs.coerce_to_number();
@@ -190,6 +379,10 @@ impl Value {
                     &rhs.coerce_to_list(),
                 )?))
             },
+            Value::Bytes(lhs) => {
+                let rhs = rhs.coerce_to_number() as u8;
+                Ok(Value::Int(lhs.iter().filter(|&&byte| byte != rhs).count() as i64))
+            },
             Value::List(lhs) => {
                 let mut result = lhs.len();
                 for elem in lhs {
@@ -197,7 +390,7 @@ impl Value {
                         result -= 1;
                     }
                 }
-                Ok(Value::Number(result as f64))
+                Ok(Value::Int(result as i64))
             },
         }
     }
@@ -211,9 +404,14 @@ impl Value {
     pub fn eq(&self, rhs: &Value) -> Value {
         Value::Bool(
             match self {
-                Value::Number(lhs) => *lhs == rhs.coerce_to_number(),
+                Value::Int(lhs) => match rhs.coerce_to_numeric() {
+                    Value::Int(rhs) => *lhs == rhs,
+                    rhs => compare_floats(*lhs as f64, rhs.coerce_to_number()).is_eq(),
+                },
+                Value::Number(lhs) => compare_floats(*lhs, rhs.coerce_to_number()).is_eq(),
                 Value::Bool(lhs) => *lhs == rhs.coerce_to_bool(),
                 Value::String(lhs) => *lhs == rhs.coerce_to_string(),
+                Value::Bytes(lhs) => *lhs == rhs.coerce_to_bytes(),
                 Value::List(lhs) => *lhs == rhs.coerce_to_list(),
             }
         )
@@ -222,11 +420,66 @@ impl Value {
         Value::Bool(!self.eq(rhs).coerce_to_bool())
     }
 
+    /// Compares the values in a total order that doesn't coerce them: values of different types
+    /// are ordered by type, `nmu`s before `obol`s before `sting`s before `bytse`s before `lsit`s,
+    /// and values of the same type by their contents. `nmu`s are compared exactly, with `NaN`
+    /// last (see [`compare_floats`]), `sting`s by their characters' code points, and `bytse`s and
+    /// `lsit`s lexicographically, so this is the order that `srot` sorts into.
+    pub fn compare(&self, rhs: &Value) -> Ordering {
+        match (self, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs.cmp(rhs),
+            (Value::Number(lhs), Value::Number(rhs)) => compare_floats(*lhs, *rhs),
+            (Value::Int(lhs), Value::Number(rhs)) => compare_int_to_float(*lhs, *rhs),
+            (Value::Number(lhs), Value::Int(rhs)) => compare_int_to_float(*rhs, *lhs).reverse(),
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs.cmp(rhs),
+            (Value::String(lhs), Value::String(rhs)) => lhs.cmp(rhs),
+            (Value::Bytes(lhs), Value::Bytes(rhs)) => lhs.cmp(rhs),
+            (Value::List(lhs), Value::List(rhs)) => {
+                lhs.iter().zip(rhs)
+                    .map(|(lhs, rhs)| lhs.compare(rhs))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or_else(|| lhs.len().cmp(&rhs.len()))
+            },
+            _ => self.type_order().cmp(&rhs.type_order()),
+        }
+    }
+
+    /// Returns where the value's type comes in [`Value::compare`]'s order.
+    fn type_order(&self) -> u8 {
+        match self {
+            Value::Int(_) | Value::Number(_) => 0,
+            Value::Bool(_) => 1,
+            Value::String(_) => 2,
+            Value::Bytes(_) => 3,
+            Value::List(_) => 4,
+        }
+    }
+
+    /// Compares the values as the relational operators do by default: two lists
+    /// lexicographically, element by element, and anything else as numbers (see
+    /// [`compare_floats`]), exactly if they are both integers. Unlike [`Value::compare`], this
+    /// isn't transitive across types.
+    pub(crate) fn compare_numerically(&self, rhs: &Value) -> Ordering {
+        if let (Value::List(lhs), Value::List(rhs)) = (self, rhs) {
+            for (lhs, rhs) in lhs.iter().zip(rhs) {
+                match lhs.compare_numerically(rhs) {
+                    Ordering::Equal => continue,
+                    ordering => return ordering,
+                }
+            }
+            return lhs.len().cmp(&rhs.len());
+        }
+        match (self.coerce_to_numeric(), rhs.coerce_to_numeric()) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs.cmp(&rhs),
+            (lhs, rhs) => compare_floats(lhs.coerce_to_number(), rhs.coerce_to_number()),
+        }
+    }
+
     pub fn gt(&self, rhs: &Value) -> Value {
-        Value::Bool(self.coerce_to_number() > rhs.coerce_to_number())
+        Value::Bool(self.compare_numerically(rhs) == Ordering::Greater)
     }
     pub fn lt(&self, rhs: &Value) -> Value {
-        Value::Bool(self.coerce_to_number() < rhs.coerce_to_number())
+        Value::Bool(self.compare_numerically(rhs) == Ordering::Less)
     }
     pub fn ge(&self, rhs: &Value) -> Value {
         Value::Bool(!self.lt(rhs).coerce_to_bool())
@@ -235,94 +488,386 @@ impl Value {
         Value::Bool(!self.gt(rhs).coerce_to_bool())
     }
 
-    fn string_format(format_string: &String, values_to_insert: &Vec<Value>) -> Result<String, Error>
-    {
-        let mut result = String::new();
-        let result_parts: Vec<&str> = format_string.split('%').collect();
-        // avoid doing any unnecessary processing if there's none to be done
-        if result_parts.len() == 1 {
-            return Ok(result_parts.first().unwrap().to_string())
-        }
-        let num_non_escaped_percentage_signs = {
-            let mut count = 0;
-            for double_char in (0..format_string.len()-1)
-                .map(|i| &format_string[i..i+2])
-            {
-                if double_char == r#"\%"# {
-                    count += 1;
-                }
+    fn string_format(format_string: &str, values_to_insert: &[Value]) -> Result<String, Error> {
+        let pieces = FormatPiece::parse(format_string);
+        let specifiers: Vec<&FormatSpecifier> = pieces.iter()
+            .filter_map(|piece| match piece {
+                FormatPiece::Specifier(specifier) => Some(specifier),
+                FormatPiece::Text(_) => None,
+            })
+            .collect();
+        for (specifier_num, specifier) in (1..).zip(&specifiers) {
+            if let Some(index) = specifier.index.filter(|&index| index >= values_to_insert.len()) {
+                return Err(Error::new(
+                    FormatArgumentIndex { index, specifier_num, offset: specifier.offset },
+                    None
+                ));
             }
-            result_parts.len() - 1 - count
-        };
-        if num_non_escaped_percentage_signs != values_to_insert.len() {
+        }
+        // every argument must be used, by an indexed specifier or by one taking the next in turn
+        let num_unindexed = specifiers.iter().filter(|specifier| specifier.index.is_none()).count();
+        let expected = specifiers.iter()
+            .filter_map(|specifier| specifier.index.map(|index| index + 1))
+            .fold(num_unindexed, usize::max);
+        if expected != values_to_insert.len() {
             return Err(Error::new(
                 IncorrectNumberOfFormatStringArguments {
-                    expected: num_non_escaped_percentage_signs,
+                    expected,
                     received: values_to_insert.len(),
                 },
                 None
             ));
         }
-        let mut last_was_not_escape = false;
+        let mut result = String::new();
+        let mut values = values_to_insert.iter();
         let mut num_inserted_so_far = 0;
-        for (i, j) in (1..result_parts.len()).enumerate() {
-            // if the last `%` wasn't escaped, its type character will still be at the start of
-            // `first` this time around
-            let first = if last_was_not_escape {
-                &result_parts[i][1..]
-            } else {
-                result_parts[i]
-            };
-            let second = result_parts[j];
-            // process escaped `%`s
-            if first.ends_with('\\') {
-                last_was_not_escape = false;
-                result += &first[0..first.len()-1];
-                result += "%";
-                continue;
+        for piece in &pieces {
+            match piece {
+                FormatPiece::Text(text) => result += text,
+                FormatPiece::Specifier(specifier) => {
+                    num_inserted_so_far += 1;
+                    let value = match specifier.index {
+                        Some(index) => &values_to_insert[index],
+                        None => values.next().unwrap(),
+                    };
+                    result += &specifier.format(value, num_inserted_so_far)?;
+                },
             }
-            last_was_not_escape = true;
-            result += first;
-            match &second[0..1] {
-                "n" => result += &format!(
-                    "{}",
-                    Value::Number(values_to_insert[num_inserted_so_far].coerce_to_number())
-                ),
-                "o" => result += &format!(
-                    "{}",
-                    Value::Bool(values_to_insert[num_inserted_so_far].coerce_to_bool())
-                ),
-                "s" => result += &values_to_insert[num_inserted_so_far].coerce_to_string(),
-                "l" => result += &format!(
-                    "{}",
-                    Value::List(values_to_insert[num_inserted_so_far].coerce_to_list())
-                ),
-                other => return Err(Error::new(
-                    InvalidFormatFlag {
-                        flag: other.to_string(),
-                        specifier_num: num_inserted_so_far + 1,
-                    },
-                    None
-                ))
+        }
+        Ok(result)
+    }
+
+    /// Returns the value as it's displayed, except that `lsit`s nested more than `max_depth`
+    /// deep are shown as `[...]]`, and only the first `max_items` elements of each `lsit` (or
+    /// bytes of `bytse`) are shown, followed by how many more there are, e.g. `[1, 2, ... 98 more]]`.
+    /// However big the value, what's shown stays readable, and showing it never nests deeper
+    /// than `max_depth`.
+    pub fn display_pretty(&self, max_depth: usize, max_items: usize) -> String {
+        let mut output = String::new();
+        self.write_pretty(&mut output, max_depth, max_items);
+        output
+    }
+
+    fn write_pretty(&self, output: &mut String, max_depth: usize, max_items: usize) {
+        match self {
+            Value::List(list) if list.is_empty() => output.push_str("[]]"),
+            Value::List(_) if max_depth == 0 => output.push_str("[...]]"),
+            Value::List(list) => {
+                output.push('[');
+                for (index, element) in list.iter().take(max_items).enumerate() {
+                    if index > 0 {
+                        output.push_str(", ");
+                    }
+                    element.write_pretty(output, max_depth - 1, max_items);
+                }
+                if list.len() > max_items {
+                    if max_items > 0 {
+                        output.push_str(", ");
+                    }
+                    output.push_str(&format!("... {} more", list.len() - max_items));
+                } else if let Some(Value::List(_)) = list.last() {
+                    // as in `Display`, so that the closing brackets aren't read as one
+                    output.push(' ');
+                }
+                output.push_str("]]");
+            },
+            Value::Bytes(bytes) if bytes.len() > max_items => {
+                output.push_str("<bytse");
+                for byte in &bytes[..max_items] {
+                    output.push_str(&format!(" {byte:02x}"));
+                }
+                output.push_str(&format!(" ... {} more>", bytes.len() - max_items));
+            },
+            value => output.push_str(&value.to_string()),
+        }
+    }
+}
+/// A piece of a format string: either text to copy into the result as it is, or a specifier to
+/// replace with a value.
+#[derive(Debug, PartialEq)]
+enum FormatPiece<'a> {
+    Text(&'a str),
+    Specifier(FormatSpecifier),
+}
+impl<'a> FormatPiece<'a> {
+    /// Splits a format string into its pieces. A `%` always starts a specifier, unless it's escaped
+    /// by a backslash, in which case the pair is replaced by a `%`.
+    fn parse(format_string: &'a str) -> Vec<FormatPiece<'a>> {
+        let mut pieces = Vec::new();
+        let mut text_start = 0;
+        // the number of characters before `text_start`, so that no prefix is counted twice
+        let mut text_offset = 0;
+        let mut chars = format_string.char_indices().peekable();
+        while let Some((index, char)) = chars.next() {
+            match char {
+                '\\' if chars.next_if(|&(_, next)| next == '%').is_some() => {
+                    pieces.push(FormatPiece::Text(&format_string[text_start..index]));
+                    pieces.push(FormatPiece::Text("%"));
+                },
+                '%' => {
+                    pieces.push(FormatPiece::Text(&format_string[text_start..index]));
+                    let offset = text_offset + format_string[text_start..index].chars().count();
+                    pieces.push(FormatPiece::Specifier(FormatSpecifier::parse(&mut chars, offset)));
+                },
+                _ => continue,
             }
-            num_inserted_so_far += 1;
+            let next_start = chars.peek().map_or(format_string.len(), |&(index, _)| index);
+            text_offset += format_string[text_start..next_start].chars().count();
+            text_start = next_start;
         }
-        // cut off the format flag if necessary
-        result += if last_was_not_escape {
-            &result_parts.last().unwrap()[1..]
-        } else {
-            result_parts.last().unwrap()
+        pieces.push(FormatPiece::Text(&format_string[text_start..]));
+        pieces.retain(|piece| *piece != FormatPiece::Text(""));
+        pieces
+    }
+}
+
+/// A format specifier, written `%[index$][-][0][width][.precision]flag`, e.g. `%-8.2n` or `%1$s`.
+#[derive(Debug, Default, PartialEq)]
+struct FormatSpecifier {
+    /// The number of characters before the specifier's `%` in the format string.
+    offset: usize,
+    /// The index of the argument to insert, or `None` to take the next one not yet taken by an
+    /// unindexed specifier.
+    index: Option<usize>,
+    /// Whether to pad on the right rather than the left.
+    left_align: bool,
+    /// Whether to pad numbers with zeros after their sign rather than spaces before it.
+    zero_pad: bool,
+    /// The least number of characters to pad the value to.
+    width: usize,
+    /// The number of decimal places to give a number, or the most characters to keep of anything
+    /// else.
+    precision: Option<usize>,
+    /// The type to coerce the value to, or `None` if the format string ended first.
+    flag: Option<char>,
+}
+impl FormatSpecifier {
+    /// Reads the specifier that follows a `%`, which is `offset` characters into the format
+    /// string.
+    fn parse(chars: &mut Peekable<CharIndices>, offset: usize) -> FormatSpecifier {
+        let mut specifier = FormatSpecifier { offset, ..FormatSpecifier::default() };
+        // digits are only an index if they're followed by a `$`, and are the width otherwise
+        let mut lookahead = chars.clone();
+        if lookahead.peek().is_some_and(|(_, char)| char.is_ascii_digit()) {
+            let index = Self::parse_count(&mut lookahead);
+            if lookahead.next_if(|&(_, char)| char == '$').is_some() {
+                specifier.index = Some(index);
+                *chars = lookahead;
+            }
+        }
+        while let Some((_, flag)) = chars.next_if(|&(_, char)| char == '-' || char == '0') {
+            match flag {
+                '-' => specifier.left_align = true,
+                _ => specifier.zero_pad = true,
+            }
+        }
+        specifier.width = Self::parse_count(chars);
+        if chars.next_if(|&(_, char)| char == '.').is_some() {
+            specifier.precision = Some(Self::parse_count(chars));
+        }
+        specifier.flag = chars.next().map(|(_, flag)| flag);
+        specifier
+    }
+
+    /// Reads a run of digits as a count, which is 0 if there aren't any.
+    fn parse_count(chars: &mut Peekable<CharIndices>) -> usize {
+        let mut count: usize = 0;
+        while let Some((_, digit)) = chars.next_if(|(_, char)| char.is_ascii_digit()) {
+            count = count.saturating_mul(10).saturating_add(digit as usize - '0' as usize);
+        }
+        count
+    }
+
+    /// Formats `value` as the specifier says. `specifier_num` counts the specifiers from 1, for
+    /// errors.
+    fn format(&self, value: &Value, specifier_num: usize) -> Result<String, Error> {
+        let text = match (self.flag, self.precision) {
+            (Some('n'), Some(precision)) => format!("{:.*}", precision, value.coerce_to_number()),
+            (Some('n'), None) => value.coerce_to_numeric().to_string(),
+            (Some('o'), _) => Value::Bool(value.coerce_to_bool()).to_string(),
+            (Some('s'), _) => value.coerce_to_string(),
+            (Some('l'), _) => Value::List(value.coerce_to_list()).to_string(),
+            (flag, _) => return Err(Error::new(
+                InvalidFormatFlag {
+                    flag: flag.map(String::from).unwrap_or_default(),
+                    specifier_num,
+                    offset: self.offset,
+                },
+                None
+            )),
+        };
+        let text = match self.precision {
+            Some(precision) if self.flag != Some('n') => text.chars().take(precision).collect(),
+            _ => text,
         };
+        let padding = self.width.saturating_sub(text.chars().count());
+        Ok(if self.left_align {
+            text + &" ".repeat(padding)
+        } else if self.zero_pad && self.flag == Some('n') && value.coerce_to_number().is_finite() {
+            let digits_start = if text.starts_with('-') { 1 } else { 0 };
+            format!("{}{}{}", &text[..digits_start], "0".repeat(padding), &text[digits_start..])
+        } else {
+            " ".repeat(padding) + &text
+        })
+    }
+}
 
-        Ok(result)
+/// How an arithmetic or equality operator treats operands of different types.
+///
+/// Every operator is defined in terms of the type of its left operand, with the right operand
+/// coerced to suit (see [`Coercion::dispatch`]). The coercion mode decides whether the left
+/// operand is always the one whose type counts, or whether the operand of the *higher* type does,
+/// where types rank, from lowest to highest, `Bool`, `Number`, `String`, `Bytes`, `List`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Coercion {
+    /// The left operand's type always decides, so `5 + ""a"` is `5 + 97` but `""a" + 5` is `"a5"`.
+    #[default]
+    LhsDriven,
+    /// The higher-ranked operand's type decides, so `5 + ""a"` is `"5a"` and `""a" + 5` is `"a5"`.
+    Symmetric,
+}
+impl Coercion {
+    /// Returns which operand's type decides what `operator` does with `lhs` and `rhs`:
+    ///
+    /// | Operators | `LhsDriven` | `Symmetric`, `rhs` ranked lower or equal | `Symmetric`, `rhs` ranked higher |
+    /// |---|---|---|---|
+    /// | `+ - / == !=` | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) | [`Rhs`](Dispatch::Rhs) |
+    /// | `%` | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) if `lhs` is a string, otherwise [`Rhs`](Dispatch::Rhs) |
+    /// | `*` | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) | [`Swapped`](Dispatch::Swapped) if `rhs` is a string, bytes, or list, otherwise [`Rhs`](Dispatch::Rhs) |
+    /// | `=== !== > < >= <=` | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) | [`Lhs`](Dispatch::Lhs) |
+    ///
+    /// Strict equality and the relational operators don't depend on their operands' types, so
+    /// they are never redirected, and nor is formatting a string, which takes its arguments as a
+    /// list.
+    pub fn dispatch(self, operator: &Operator, lhs: &Value, rhs: &Value) -> Dispatch {
+        use Operator::*;
+        if self == Coercion::LhsDriven || rhs.rank() <= lhs.rank() {
+            return Dispatch::Lhs;
+        }
+        match operator {
+            Seq | Sne | Gt | Lt | Ge | Le | Adn | Ro => Dispatch::Lhs,
+            Mod if matches!(lhs, Value::String(_)) => Dispatch::Lhs,
+            Mul if matches!(rhs, Value::String(_) | Value::Bytes(_) | Value::List(_)) => Dispatch::Swapped,
+            Add | Sub | Mul | Div | Mod | Eq | Ne => Dispatch::Rhs,
+        }
+    }
+
+    /// Returns the operands `operator` should be applied to, left-hand side first, once they have
+    /// been arranged according to [`Coercion::dispatch`].
+    pub fn operands(self, operator: &Operator, lhs: Value, rhs: Value) -> (Value, Value) {
+        match self.dispatch(operator, &lhs, &rhs) {
+            Dispatch::Lhs => (lhs, rhs),
+            Dispatch::Rhs => (lhs.coerce_to_type_of(&rhs), rhs),
+            Dispatch::Swapped => (rhs, lhs),
+        }
     }
 }
+
+/// Which operand's type decides what an operator does; see [`Coercion::dispatch`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dispatch {
+    /// The left operand's type decides, and the right operand is coerced to it.
+    Lhs,
+    /// The right operand's type decides: the left operand is coerced to it first, and then the
+    /// operator applies as usual.
+    Rhs,
+    /// The right operand's type decides, and the operands swap places. This is used for
+    /// repetition, where `3 * ""ab"` means the same as `""ab" * 3`.
+    Swapped,
+}
+
+/// How the relational operators compare their operands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Comparison {
+    /// Operands are coerced to numbers, except that two `lsit`s are compared element by element,
+    /// so `""b" < 99` is true.
+    #[default]
+    Numeric,
+    /// Operands are compared in [`Value::compare`]'s total order, so `""b" < 99` is false, since
+    /// every `nmu` comes before every `sting`.
+    Total,
+}
+impl Comparison {
+    /// Compares `lhs` and `rhs` as the relational operators do in this mode.
+    pub fn compare(self, lhs: &Value, rhs: &Value) -> Ordering {
+        match self {
+            Comparison::Numeric => lhs.compare_numerically(rhs),
+            Comparison::Total => lhs.compare(rhs),
+        }
+    }
+}
+
+/// Compares two floats in a total order, which is the usual numeric order except that `NaN`
+/// equals itself and comes after every other number, including infinity. `NaN`s therefore sort
+/// last, and every value equals itself, even under `===`.
+pub(crate) fn compare_floats(lhs: f64, rhs: f64) -> Ordering {
+    match (lhs.is_nan(), rhs.is_nan()) {
+        (false, false) => lhs.partial_cmp(&rhs).unwrap(),
+        (lhs_is_nan, rhs_is_nan) => lhs_is_nan.cmp(&rhs_is_nan),
+    }
+}
+
+/// Compares an integer with a float exactly, even where the integer has no exact float
+/// equivalent. `NaN` comes after every integer, as in [`compare_floats`].
+fn compare_int_to_float(int: i64, float: f64) -> Ordering {
+    // 2^63, the first float too large to be an `i64`
+    const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+    if float.is_nan() || float >= LIMIT {
+        Ordering::Less
+    } else if float < -LIMIT {
+        Ordering::Greater
+    } else {
+        int.cmp(&(float.trunc() as i64)).then_with(|| compare_floats(0.0, float.fract()))
+    }
+}
+
+/// Returns `bytes` with up to `count` non-overlapping occurrences of `pattern` removed, from the
+/// start. An empty pattern removes nothing.
+fn remove_subslices(bytes: &[u8], pattern: &[u8], count: usize) -> Vec<u8> {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut removed = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        if !pattern.is_empty() && removed < count && bytes[index..].starts_with(pattern) {
+            index += pattern.len();
+            removed += 1;
+        } else {
+            result.push(bytes[index]);
+            index += 1;
+        }
+    }
+    result
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(lhs), Value::Int(rhs)) => lhs == rhs,
+            (Value::Number(lhs), Value::Number(rhs)) => compare_floats(*lhs, *rhs).is_eq(),
+            (Value::Int(int), Value::Number(number)) | (Value::Number(number), Value::Int(int)) => {
+                compare_int_to_float(*int, *number).is_eq()
+            },
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+            (Value::String(lhs), Value::String(rhs)) => lhs == rhs,
+            (Value::Bytes(lhs), Value::Bytes(rhs)) => lhs == rhs,
+            (Value::List(lhs), Value::List(rhs)) => lhs == rhs,
+            _ => false,
+        }
+    }
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
+            Value::Int(num) => write!(f, "{num}"),
             Value::Number(num) => write!(f, "{num}"),
             Value::Bool(val) => write!(f, "{}", if *val { "rtue" } else { "flase" }),
             Value::String(string) => write!(f, "\"{}\"\"", *string),
+            Value::Bytes(bytes) => {
+                write!(f, "<bytse")?;
+                bytes.iter().try_for_each(|byte| write!(f, " {byte:02x}"))?;
+                write!(f, ">")
+            },
             Value::List(vec) => {
                 if vec.is_empty() {
                     return write!(f, "[]]");
@@ -346,6 +891,110 @@ impl Display for Value {
     }
 }
 
+/// An iterator over the elements of a value, created by [`Value::iter`].
+#[derive(Clone, Debug)]
+pub struct Iter<'a>(IterInner<'a>);
+#[derive(Clone, Debug)]
+enum IterInner<'a> {
+    Empty,
+    Chars(std::str::Chars<'a>),
+    Bytes(std::slice::Iter<'a, u8>),
+    Elements(std::slice::Iter<'a, Value>),
+}
+impl Iterator for Iter<'_> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        match &mut self.0 {
+            IterInner::Empty => None,
+            IterInner::Chars(chars) => chars.next().map(|char| Value::String(char.to_string())),
+            IterInner::Bytes(bytes) => bytes.next().map(|&byte| Value::Int(byte as i64)),
+            IterInner::Elements(elements) => elements.next().cloned(),
+        }
+    }
+}
+
+
+impl From<i64> for Value {
+    fn from(int: i64) -> Value {
+        Value::Int(int)
+    }
+}
+impl From<f64> for Value {
+    fn from(number: f64) -> Value {
+        Value::Number(number)
+    }
+}
+impl From<bool> for Value {
+    fn from(bool: bool) -> Value {
+        Value::Bool(bool)
+    }
+}
+impl From<&str> for Value {
+    fn from(string: &str) -> Value {
+        Value::String(string.to_string())
+    }
+}
+impl From<String> for Value {
+    fn from(string: String) -> Value {
+        Value::String(string)
+    }
+}
+impl From<Vec<Value>> for Value {
+    fn from(list: Vec<Value>) -> Value {
+        Value::List(list)
+    }
+}
+
+/// Converts values back into Rust types without coercion, failing unless the value is already of
+/// the right type.
+macro_rules! try_from_value {
+    ($type:ty, $variant:ident, $name:literal) => {
+        impl TryFrom<Value> for $type {
+            type Error = ConversionError;
+
+            fn try_from(value: Value) -> Result<$type, ConversionError> {
+                match value {
+                    Value::$variant(inner) => Ok(inner),
+                    value => Err(ConversionError { expected: $name, value }),
+                }
+            }
+        }
+    };
+}
+try_from_value!(i64, Int, "integer");
+impl TryFrom<Value> for f64 {
+    type Error = ConversionError;
+
+    fn try_from(value: Value) -> Result<f64, ConversionError> {
+        match value {
+            Value::Int(int) => Ok(int as f64),
+            Value::Number(number) => Ok(number),
+            value => Err(ConversionError { expected: "number", value }),
+        }
+    }
+}
+try_from_value!(bool, Bool, "bool");
+try_from_value!(String, String, "string");
+try_from_value!(Vec<u8>, Bytes, "bytes");
+try_from_value!(Vec<Value>, List, "list");
+
+/// A value that couldn't be converted into a Rust type because it was of a different type. The
+/// value is handed back, so it isn't lost.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConversionError {
+    /// The type of value that was needed: `integer`, `number`, `bool`, `string`, `bytes`, or
+    /// `list`.
+    pub expected: &'static str,
+    pub value: Value,
+}
+impl Display for ConversionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "expected a {}, found `{}`", self.expected, self.value)
+    }
+}
+impl std::error::Error for ConversionError {}
+
 
 #[cfg(test)]
 #[allow(clippy::approx_constant)]
@@ -810,6 +1459,123 @@ mod tests {
             );
         }
 
+        #[test]
+        fn string_mod_pads_and_truncates() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values)).unwrap().to_string()
+            };
+            assert_eq!(
+                "\"[    3.14]\"\"",
+                format("[%8.2n]", vec![Number(3.14159)]),
+            );
+            assert_eq!("\"[-003]\"\"", format("[%04n]", vec![Int(-3)]));
+            assert_eq!("\"[ab  |  ab]\"\"", format("[%-4s|%4s]", vec!["ab".into(), "ab".into()]));
+            assert_eq!("\"[Mor]\"\"", format("[%.3s]", vec!["Mornington".into()]));
+            assert_eq!("\"[rtue |3    ]\"\"", format("[%-5o|%-05n]", vec![Bool(true), Int(3)]));
+            assert_eq!("\"[é  ]\"\"", format("[%-3s]", vec!["é".into()]));
+            assert_eq!("\"[  NaN]\"\"", format("[%05n]", vec![Number(f64::NAN)]));
+        }
+
+        #[test]
+        fn string_mod_takes_arguments_by_index() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values))
+            };
+            assert_eq!(
+                Ok(String("b a b".to_string())),
+                format("%1$s %0$s %1$s", vec!["a".into(), "b".into()]),
+            );
+            // unindexed specifiers take the arguments in turn, whatever the indexed ones take
+            assert_eq!(
+                Ok(String("a b a [  b]".to_string())),
+                format("%s %s %0$s [%1$3s]", vec!["a".into(), "b".into()]),
+            );
+            assert_eq!(
+                Ok(String("[7]".to_string())),
+                format("[%0$n]", vec![Int(7)]),
+            );
+            assert_eq!(
+                Ok(String("[  7]".to_string())),
+                format("[%3n]", vec![Int(7)]),
+            );
+        }
+
+        #[test]
+        fn string_mod_rejects_bad_indexes() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values)).unwrap_err().kind
+            };
+            assert_eq!(
+                FormatArgumentIndex { index: 2, specifier_num: 2, offset: 7 },
+                format("é %0$s %2$s", vec![Int(1), Int(2)]),
+            );
+            assert_eq!(
+                IncorrectNumberOfFormatStringArguments { expected: 1, received: 2 },
+                format("%0$s %0$s", vec![Int(1), Int(2)]),
+            );
+        }
+
+        #[test]
+        fn string_mod_rejects_bad_flags() {
+            assert_eq!(
+                Err(Error::new(InvalidFormatFlag { flag: "q".to_string(), specifier_num: 2, offset: 3 }, None)),
+                String("%s %8q".to_string()).modulus(&List(vec![Int(1), Int(2)])),
+            );
+            assert_eq!(
+                Err(Error::new(InvalidFormatFlag { flag: "".to_string(), specifier_num: 1, offset: 3 }, None)),
+                String("100%".to_string()).modulus(&List(vec![Int(1)])),
+            );
+        }
+
+        #[test]
+        fn string_mod_handles_escapes_next_to_specifiers() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values))
+            };
+            assert_eq!(Ok(String("%".to_string())), format("\\%", vec![]));
+            assert_eq!(Ok(String("%a".to_string())), format("\\%%s", vec!["a".into()]));
+            assert_eq!(Ok(String("a%".to_string())), format("%s\\%", vec!["a".into()]));
+            assert_eq!(Ok(String("%a%".to_string())), format("\\%%s\\%", vec!["a".into()]));
+            assert_eq!(Ok(String("%%".to_string())), format("\\%\\%", vec![]));
+            assert_eq!(Ok(String("\\%s".to_string())), format("\\\\%s", vec![]));
+            assert_eq!(Ok(String("a\\".to_string())), format("%s\\", vec!["a".into()]));
+            assert_eq!(Ok(String("\\ a".to_string())), format("\\ %s", vec!["a".into()]));
+        }
+
+        #[test]
+        fn string_mod_handles_escapes_next_to_multibyte_characters() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values))
+            };
+            assert_eq!(Ok(String("é%ü".to_string())), format("é\\%ü", vec![]));
+            assert_eq!(Ok(String("%éa".to_string())), format("\\%é%s", vec!["a".into()]));
+            assert_eq!(Ok(String("日本%a語".to_string())), format("日本\\%%s語", vec!["a".into()]));
+            assert_eq!(
+                Err(Error::new(InvalidFormatFlag { flag: "é".to_string(), specifier_num: 1, offset: 3 }, None)),
+                format("ü\\%%é", vec![Int(1)]),
+            );
+        }
+
+        #[test]
+        fn string_mod_does_not_count_escapes_as_specifiers() {
+            let format = |format_string: &str, values: Vec<Value>| {
+                String(format_string.to_string()).modulus(&List(values)).unwrap_err().kind
+            };
+            assert_eq!(
+                IncorrectNumberOfFormatStringArguments { expected: 1, received: 2 },
+                format("\\%s %s \\%", vec![Int(1), Int(2)]),
+            );
+            assert_eq!(
+                IncorrectNumberOfFormatStringArguments { expected: 0, received: 1 },
+                format("\\%\\%\\%", vec![Int(1)]),
+            );
+            // a backslash straight after a `%` is the specifier's flag, not an escape
+            assert_eq!(
+                InvalidFormatFlag { flag: "\\".to_string(), specifier_num: 1, offset: 0 },
+                format("%\\%s", vec![Int(1), Int(2)]),
+            );
+        }
+
         #[test]
         fn list_mod_works() {
             assert_eq!(
@@ -1015,5 +1781,357 @@ mod tests {
                 String("d".to_string()).le(&Bool(true))
             )
         }
+        #[test]
+        fn lists_compare_lexicographically() {
+            let list = |numbers: &[i64]| List(numbers.iter().map(|&number| Int(number)).collect());
+            assert_eq!(Bool(true), list(&[1, 2]).lt(&list(&[9])));
+            assert_eq!(Bool(true), list(&[2]).gt(&list(&[1, 9, 9])));
+            assert_eq!(Bool(true), list(&[1, 2]).lt(&list(&[1, 2, 0])));
+            assert_eq!(Bool(true), list(&[1, 2]).ge(&list(&[1, 2])));
+            assert_eq!(Bool(false), list(&[1, 2]).gt(&list(&[1, 2])));
+            assert_eq!(Bool(true), List(vec![list(&[1, 3])]).gt(&List(vec![list(&[1, 2, 5])])));
+        }
+        #[test]
+        fn lists_compare_against_other_types_as_numbers() {
+            assert_eq!(Bool(true), List(vec![Int(1), Int(2)]).lt(&Int(9)));
+            assert_eq!(Ordering::Equal, List(vec![Int(1), Int(2)]).compare_numerically(&Int(3)));
+        }
+        #[test]
+        fn nan_comes_after_every_other_number() {
+            let nan = Number(f64::NAN);
+            assert_eq!(Bool(true), nan.gt(&Number(f64::INFINITY)));
+            assert_eq!(Bool(true), Int(i64::MAX).lt(&nan));
+            assert_eq!(Bool(false), nan.lt(&nan));
+            assert_eq!(Bool(true), nan.ge(&nan));
+            assert_eq!(Bool(true), List(vec![nan.clone()]).gt(&List(vec![Int(1)])));
+        }
+        #[test]
+        fn nan_equals_itself() {
+            let nan = Number(f64::NAN);
+            assert_eq!(nan, nan);
+            assert_eq!(Bool(true), nan.seq(&nan));
+            assert_eq!(Bool(true), nan.eq(&nan));
+            assert_eq!(Bool(false), nan.eq(&Int(0)));
+            assert_eq!(Bool(true), List(vec![nan.clone()]).seq(&List(vec![nan.clone()])));
+            assert_eq!(Bool(true), Number(0.0).eq(&Number(-0.0)));
+        }
+    }
+
+    mod ordering_tests {
+        use super::*;
+
+        /// Values covering each type and the edge cases between them, for checking properties of
+        /// the order over every pair and triple.
+        fn samples() -> Vec<Value> {
+            let mut samples = vec![
+                Int(i64::MIN), Int(-3), Int(0), Int(2), Int(1 << 53), Int((1 << 53) + 1), Int(i64::MAX),
+                Number(f64::NEG_INFINITY), Number(-2.5), Number(-0.0), Number(2.0), Number(2.5),
+                Number((1u64 << 53) as f64), Number(9.3e18), Number(f64::INFINITY), Number(f64::NAN),
+                Bool(false), Bool(true),
+                "".into(), "a".into(), "ab".into(), "b".into(), "é".into(),
+                Bytes(vec![]), Bytes(vec![0]), Bytes(vec![0, 255]),
+                List(vec![]),
+            ];
+            let scalars = samples.clone();
+            samples.extend(scalars.iter().step_by(3).map(|value| List(vec![value.clone()])));
+            samples.push(List(vec![Int(1), Int(5)]));
+            samples.push(List(vec![Int(2)]));
+            samples.push(List(vec![List(vec![Bool(true)]), Int(0)]));
+            samples
+        }
+
+        #[test]
+        fn orders_types_before_contents() {
+            assert_eq!(Ordering::Less, Number(f64::NAN).compare(&Bool(false)));
+            assert_eq!(Ordering::Less, Bool(true).compare(&"".into()));
+            assert_eq!(Ordering::Less, Value::from("zzz").compare(&Bytes(vec![])));
+            assert_eq!(Ordering::Less, Bytes(vec![255]).compare(&List(vec![])));
+            assert_eq!(Ordering::Greater, Value::from("b").compare(&"ab".into()));
+            assert_eq!(Ordering::Less, List(vec![Int(1), Int(5)]).compare(&List(vec![Int(2)])));
+        }
+
+        #[test]
+        fn compares_integers_and_floats_exactly() {
+            assert_eq!(Ordering::Greater, Int((1 << 53) + 1).compare(&Number((1u64 << 53) as f64)));
+            assert_eq!(Ordering::Less, Int(i64::MAX).compare(&Number(9.3e18)));
+            assert_eq!(Ordering::Greater, Int(-2).compare(&Number(-2.5)));
+            assert_eq!(Ordering::Equal, Number(2.0).compare(&Int(2)));
+            assert_ne!(Int((1 << 53) + 1), Number((1u64 << 53) as f64));
+        }
+
+        #[test]
+        fn is_antisymmetric_and_agrees_with_equality() {
+            let samples = samples();
+            for lhs in &samples {
+                for rhs in &samples {
+                    assert_eq!(lhs.compare(rhs), rhs.compare(lhs).reverse(), "{lhs} and {rhs}");
+                    assert_eq!(lhs == rhs, lhs.compare(rhs).is_eq(), "{lhs} and {rhs}");
+                }
+            }
+        }
+
+        #[test]
+        fn is_transitive() {
+            let samples = samples();
+            for a in &samples {
+                for b in samples.iter().filter(|b| a.compare(b).is_le()) {
+                    for c in samples.iter().filter(|c| b.compare(c).is_le()) {
+                        assert!(a.compare(c).is_le(), "{a} <= {b} <= {c}");
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn relational_operators_use_the_comparison_mode() {
+            assert_eq!(Ordering::Less, Comparison::Numeric.compare(&"b".into(), &Int(99)));
+            assert_eq!(Ordering::Greater, Comparison::Total.compare(&"b".into(), &Int(99)));
+        }
+    }
+
+    mod integer_tests {
+        use super::*;
+
+        #[test]
+        fn integer_arithmetic_stays_integral() {
+            assert!(matches!(Int(2).add(&Int(3)), Int(5)));
+            assert!(matches!(Int(2).sub(&Bool(true)), Int(1)));
+            assert!(matches!(Int(6).mul(&Int(7)), Int(42)));
+            assert!(matches!(Int(6).div(&Int(3)), Int(2)));
+            assert!(matches!(Int(-7).modulus(&Int(3)), Ok(Int(-1))));
+            assert!(matches!(List(vec![Int(1), Int(2)]).modulus(&Int(3)), Ok(Int(2))));
+        }
+
+        #[test]
+        fn integers_are_promoted_to_floats() {
+            assert!(matches!(Int(1).add(&Number(0.5)), Number(n) if n == 1.5));
+            assert!(matches!(Int(7).div(&Int(2)), Number(n) if n == 3.5));
+            assert!(matches!(Int(1).div(&Int(0)), Number(n) if n == f64::INFINITY));
+            assert!(matches!(Int(1).modulus(&Int(0)), Ok(Number(n)) if n.is_nan()));
+            assert!(matches!(Int(i64::MAX).add(&Int(1)), Number(n) if n == 2f64.powi(63)));
+            assert!(matches!(Int(i64::MIN).mul(&Int(-1)), Number(_)));
+        }
+
+        #[test]
+        fn integers_equal_the_same_floats() {
+            assert_eq!(Int(3), Number(3.0));
+            assert_eq!(Bool(true), Int(3).seq(&Number(3.0)));
+            assert_eq!(Bool(true), Int(3).eq(&String("\u{3}".to_string())));
+            assert_ne!(Int(3), Number(3.5));
+            assert_eq!(Bool(true), Int(i64::MAX).gt(&Int(i64::MAX - 1)));
+        }
+
+        #[test]
+        fn integers_display_without_a_fraction() {
+            assert_eq!("3", Int(3).to_string());
+            assert_eq!("-12", Int(-12).to_string());
+        }
+    }
+
+    mod bytes_tests {
+        use super::*;
+
+        #[test]
+        fn bytes_coerce() {
+            let bytes = Bytes(vec![104, 105]);
+            assert_eq!(209.0, bytes.coerce_to_number());
+            assert!(bytes.coerce_to_bool());
+            assert!(!Bytes(vec![0, 0]).coerce_to_bool());
+            assert_eq!("hi", bytes.coerce_to_string());
+            assert_eq!("\u{fffd}", Bytes(vec![0xff]).coerce_to_string());
+            assert_eq!(vec![Int(104), Int(105)], bytes.coerce_to_list());
+            assert_eq!(vec![104, 105], String("hi".to_string()).coerce_to_bytes());
+            assert_eq!(vec![1, 2], List(vec![Int(1), Number(2.0)]).coerce_to_bytes());
+        }
+
+        #[test]
+        fn bytes_operators() {
+            let bytes = |bytes: &[u8]| Bytes(bytes.to_vec());
+            assert_eq!(bytes(&[1, 2, 104, 105]), bytes(&[1, 2]).add(&String("hi".to_string())));
+            assert_eq!(bytes(&[3, 1, 2]), bytes(&[1, 2, 3, 1, 2]).sub(&bytes(&[1, 2])));
+            assert_eq!(bytes(&[3]), bytes(&[1, 2, 3, 1, 2]).div(&bytes(&[1, 2])));
+            assert_eq!(bytes(&[1, 2, 1, 2]), bytes(&[1, 2]).mul(&Int(2)));
+            assert_eq!(Ok(Int(1)), bytes(&[1, 2, 1]).modulus(&Int(1)));
+            assert_eq!(Bool(true), bytes(&[104, 105]).eq(&String("hi".to_string())));
+            assert_ne!(bytes(&[104, 105]), String("hi".to_string()));
+        }
+
+        #[test]
+        fn bytes_display_in_hexadecimal() {
+            assert_eq!("<bytse 00 ff>", Bytes(vec![0, 255]).to_string());
+            assert_eq!("<bytse>", Bytes(Vec::new()).to_string());
+        }
     }
-}
\ No newline at end of file
+
+    mod coercion_mode_tests {
+        use super::*;
+
+        fn apply(coercion: Coercion, operator: Operator, lhs: Value, rhs: Value) -> Value {
+            let (lhs, rhs) = coercion.operands(&operator, lhs, rhs);
+            operator.apply(&lhs, &rhs).unwrap()
+        }
+
+        #[test]
+        fn lhs_driven_mode_always_dispatches_on_the_lhs() {
+            for operator in Operator::ALL {
+                assert_eq!(
+                    Dispatch::Lhs,
+                    Coercion::LhsDriven.dispatch(&operator, &Number(5.0), &String("a".to_string())),
+                );
+            }
+            assert_eq!(
+                Number(102.0),
+                apply(Coercion::LhsDriven, Operator::Add, Number(5.0), String("a".to_string())),
+            );
+        }
+
+        #[test]
+        fn symmetric_mode_dispatches_on_the_higher_ranked_type() {
+            let dispatch = |operator, lhs: &Value, rhs: &Value| {
+                Coercion::Symmetric.dispatch(&operator, lhs, rhs)
+            };
+            assert_eq!(Dispatch::Lhs, dispatch(Operator::Add, &List(vec![]), &Number(1.0)));
+            assert_eq!(Dispatch::Lhs, dispatch(Operator::Add, &Number(1.0), &Number(2.0)));
+            assert_eq!(Dispatch::Rhs, dispatch(Operator::Add, &Bool(true), &Number(1.0)));
+            assert_eq!(Dispatch::Rhs, dispatch(Operator::Eq, &Number(1.0), &List(vec![])));
+            assert_eq!(Dispatch::Swapped, dispatch(Operator::Mul, &Number(3.0), &String("a".to_string())));
+            assert_eq!(Dispatch::Rhs, dispatch(Operator::Mul, &Bool(true), &Number(3.0)));
+            assert_eq!(Dispatch::Lhs, dispatch(Operator::Gt, &Number(1.0), &String("a".to_string())));
+            assert_eq!(Dispatch::Lhs, dispatch(Operator::Seq, &Number(1.0), &String("1".to_string())));
+            assert_eq!(Dispatch::Lhs, dispatch(Operator::Mod, &String("%n".to_string()), &List(vec![])));
+            assert_eq!(Dispatch::Rhs, dispatch(Operator::Mod, &Number(1.0), &List(vec![])));
+        }
+
+        #[test]
+        fn symmetric_mode_agrees_on_the_result_type() {
+            assert_eq!(
+                String("5a".to_string()),
+                apply(Coercion::Symmetric, Operator::Add, Number(5.0), String("a".to_string())),
+            );
+            assert_eq!(
+                String("a5".to_string()),
+                apply(Coercion::Symmetric, Operator::Add, String("a".to_string()), Number(5.0)),
+            );
+            assert_eq!(
+                String("ababab".to_string()),
+                apply(Coercion::Symmetric, Operator::Mul, Number(3.0), String("ab".to_string())),
+            );
+            assert_eq!(
+                List(vec![Number(1.0), Number(2.0)]),
+                apply(Coercion::Symmetric, Operator::Add, Number(1.0), List(vec![Number(2.0)])),
+            );
+            assert_eq!(
+                Number(3.0),
+                apply(Coercion::Symmetric, Operator::Add, Bool(true), Number(2.0)),
+            );
+            assert_eq!(
+                Bool(true),
+                apply(Coercion::Symmetric, Operator::Eq, Number(5.0), String("5".to_string())),
+            );
+        }
+    }
+
+    mod conversion_tests {
+        use super::*;
+
+        #[test]
+        fn converts_from_rust_types() {
+            assert_eq!(Number(1.5), Value::from(1.5));
+            assert_eq!(Bool(true), Value::from(true));
+            assert_eq!(String("hi".to_string()), Value::from("hi"));
+            assert_eq!(
+                List(vec![Number(1.0), String("a".to_string())]),
+                Value::from(vec![1.0.into(), "a".into()]),
+            );
+        }
+
+        #[test]
+        fn converts_into_rust_types() {
+            assert_eq!(Ok(2.0), f64::try_from(Number(2.0)));
+            assert_eq!(Ok("hi".to_string()), std::string::String::try_from(String("hi".to_string())));
+            assert_eq!(Ok(vec![Bool(false)]), Vec::<Value>::try_from(List(vec![Bool(false)])));
+        }
+
+        #[test]
+        fn conversions_do_not_coerce() {
+            let error = bool::try_from(Number(1.0)).unwrap_err();
+            assert_eq!(ConversionError { expected: "bool", value: Number(1.0) }, error);
+            assert_eq!("expected a bool, found `1`", error.to_string());
+        }
+    }
+
+    mod pretty_display_tests {
+        use super::*;
+
+        fn nested(depth: usize) -> Value {
+            (0..depth).fold(Value::Int(1), |value, _| Value::List(vec![value]))
+        }
+
+        #[test]
+        fn small_values_display_as_usual() {
+            let value = Value::List(vec![Value::Int(1), "a".into(), Value::List(vec![Value::Bool(true)])]);
+            assert_eq!(value.to_string(), value.display_pretty(8, 100));
+            assert_eq!("[]]", Value::List(vec![]).display_pretty(0, 0));
+        }
+
+        #[test]
+        fn long_lists_are_cut_short() {
+            let value = Value::List((0..100).map(Value::Int).collect());
+            assert_eq!("[0, 1, 2, ... 97 more]]", value.display_pretty(8, 3));
+            assert_eq!("[... 100 more]]", value.display_pretty(8, 0));
+            assert_eq!("<bytse 00 01 ... 2 more>", Value::Bytes(vec![0, 1, 2, 3]).display_pretty(8, 2));
+        }
+
+        #[test]
+        fn deep_lists_are_cut_short() {
+            assert_eq!("[[[...]] ]] ]]", nested(5).display_pretty(2, 100));
+            assert_eq!(nested(2).to_string(), nested(2).display_pretty(2, 100));
+        }
+
+        #[test]
+        fn very_deep_lists_do_not_overflow_the_stack() {
+            let value = nested(100_000);
+            assert_eq!(16 * "[ ]]".len() + "[...]]".len(), value.display_pretty(16, 100).len());
+            // dropping a value this deep would itself overflow the stack
+            std::mem::forget(value);
+        }
+    }
+
+    mod iteration_tests {
+        use super::*;
+
+        #[test]
+        fn len_counts_elements_characters_and_bytes() {
+            assert_eq!(Some(2), List(vec![Int(1), Int(2)]).len());
+            assert_eq!(Some(3), String("héj".to_string()).len());
+            assert_eq!(Some(1), Bytes(vec![0xff]).len());
+            assert_eq!(Some(true), List(vec![]).is_empty());
+            assert_eq!(None, Int(3).len());
+            assert_eq!(None, Bool(true).is_empty());
+        }
+
+        #[test]
+        fn iter_yields_elements_characters_and_bytes() {
+            assert_eq!(vec![Int(1), Bool(true)], List(vec![Int(1), Bool(true)]).iter().collect::<Vec<_>>());
+            assert_eq!(vec![Value::from("h"), Value::from("é")], String("hé".to_string()).iter().collect::<Vec<_>>());
+            assert_eq!(vec![Int(0), Int(255)], Bytes(vec![0, 255]).iter().collect::<Vec<_>>());
+            assert_eq!(0, Number(1.5).iter().count());
+        }
+
+        #[test]
+        fn loops_iterate_over_characters_but_coerce_anything_else() {
+            assert_eq!(vec![Value::from("h"), Value::from("é")], String("hé".to_string()).loop_elements());
+            assert_eq!(vec![Int(0), Int(255)], Bytes(vec![0, 255]).loop_elements());
+            assert_eq!(vec![Bool(true)], Bool(true).loop_elements());
+        }
+
+        #[test]
+        fn get_indexes_like_iter() {
+            assert_eq!(Some(Int(2)), List(vec![Int(1), Int(2)]).get(1));
+            assert_eq!(Some(Value::from("é")), String("hé".to_string()).get(1));
+            assert_eq!(Some(Int(255)), Bytes(vec![0, 255]).get(1));
+            assert_eq!(None, List(vec![Int(1)]).get(1));
+            assert_eq!(None, Bool(false).get(0));
+        }
+    }
+}
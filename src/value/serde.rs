@@ -0,0 +1,143 @@
+//! `Serialize` and `Deserialize` for values, with the `serde` feature, so that hosts can pass them
+//! in and out in any format serde supports. Values are written as the plain data closest to them
+//! rather than tagged with their type, e.g. `[[1, ""a"]` as `[1,"a"]` in JSON, and read back the
+//! same way: nothing (`null`, or `None`) becomes `[]`, and maps become `lsit`s of `[key, value]`
+//! pairs. Formats without a way of writing binary data or non-finite floats, like JSON, write
+//! them as they can, so they may not be read back as the same value.
+
+use std::fmt::{self, Formatter};
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, Serializer};
+use super::Value;
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Int(int) => serializer.serialize_i64(*int),
+            Value::Number(number) => serializer.serialize_f64(*number),
+            Value::Bool(bool) => serializer.serialize_bool(*bool),
+            Value::String(string) => serializer.serialize_str(string),
+            Value::Bytes(bytes) => serializer.serialize_bytes(bytes),
+            Value::List(list) => serializer.collect_seq(list),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("a Mornington value")
+    }
+
+    fn visit_bool<E: de::Error>(self, bool: bool) -> Result<Value, E> {
+        Ok(Value::Bool(bool))
+    }
+
+    fn visit_i64<E: de::Error>(self, int: i64) -> Result<Value, E> {
+        Ok(Value::Int(int))
+    }
+
+    fn visit_u64<E: de::Error>(self, int: u64) -> Result<Value, E> {
+        // too big to be held as an integer, so held as closely as a float can
+        Ok(i64::try_from(int).map_or(Value::Number(int as f64), Value::Int))
+    }
+
+    fn visit_f64<E: de::Error>(self, number: f64) -> Result<Value, E> {
+        Ok(Value::Number(number))
+    }
+
+    fn visit_str<E: de::Error>(self, string: &str) -> Result<Value, E> {
+        Ok(Value::String(string.to_string()))
+    }
+
+    fn visit_string<E: de::Error>(self, string: String) -> Result<Value, E> {
+        Ok(Value::String(string))
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Value, E> {
+        Ok(Value::Bytes(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Value, E> {
+        Ok(Value::Bytes(bytes))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::List(vec![]))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::List(vec![]))
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut list = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            list.push(element);
+        }
+        Ok(Value::List(list))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut pairs = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry::<Value, Value>()? {
+            pairs.push(Value::List(vec![key, value]));
+        }
+        Ok(Value::List(pairs))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_plain_data() {
+        let value = Value::List(vec![Value::Int(1), Value::Number(2.5), "a".into(), Value::Bool(true)]);
+        assert_eq!(r#"[1,2.5,"a",true]"#, serde_json::to_string(&value).unwrap());
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = Value::List(vec![
+            Value::Int(-3),
+            Value::Int(i64::MAX),
+            Value::Number(1.0),
+            Value::String("é".to_string()),
+            Value::List(vec![Value::List(vec![])]),
+        ]);
+        let text = serde_json::to_string(&value).unwrap();
+        let read: Value = serde_json::from_str(&text).unwrap();
+        assert!(matches!(read, Value::List(ref list) if matches!(list[..3], [Value::Int(-3), Value::Int(i64::MAX), Value::Number(_)])));
+        assert_eq!(value, read);
+    }
+
+    #[test]
+    fn reads_nothing_and_maps_as_lists() {
+        let value: Value = serde_json::from_str(r#"{"a": null, "b": 18446744073709551615}"#).unwrap();
+        assert_eq!(
+            Value::List(vec![
+                Value::List(vec!["a".into(), Value::List(vec![])]),
+                Value::List(vec!["b".into(), Value::Number(u64::MAX as f64)]),
+            ]),
+            value,
+        );
+    }
+}
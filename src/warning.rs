@@ -0,0 +1,34 @@
+use crate::lexer::Position;
+
+
+/// Something in a program that's probably a mistake, but which doesn't stop it from running.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub pos: Position,
+}
+impl Warning {
+    pub fn new(kind: WarningKind, position: Position) -> Warning {
+        Warning { kind, pos: position }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WarningKind {
+    UnusedVariable(String),
+    /// A statement following a `retrun`, `brek`, or `cnotineu` in the same block.
+    UnreachableCode,
+    /// A function defined with the same name as a builtin (`previous` is `None`) or an earlier
+    /// definition.
+    ShadowedFunction { name: String, previous: Option<Position> },
+}
+impl WarningKind {
+    /// Returns the warning's stable code, e.g. `W0001` for an unused variable.
+    pub fn code(&self) -> &'static str {
+        match self {
+            WarningKind::UnusedVariable(_) => "W0001",
+            WarningKind::UnreachableCode => "W0002",
+            WarningKind::ShadowedFunction { .. } => "W0003",
+        }
+    }
+}
@@ -0,0 +1,18 @@
+//! A WebAssembly interface to the interpreter, for in-browser playgrounds. It's only compiled with
+//! the `wasm` feature, which also turns on `no-io`; build it with:
+//! ```shell
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/mornington.wasm --out-dir pkg
+//! ```
+//! From JavaScript, `run(source)` returns an object with `stdout`, `stderr`, and `error` (which is
+//! `undefined` if the program succeeded), as [`embed::run`] does.
+
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::embed::{self, Output};
+
+
+/// Runs `source` with no input, capturing its output and any error.
+#[wasm_bindgen]
+pub fn run(source: &str) -> Output {
+    embed::run(source, "")
+}
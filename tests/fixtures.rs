@@ -0,0 +1,14 @@
+use mornington::fixtures;
+
+/// Runs every program in `tests/fixtures` and checks it against its expected output.
+#[test]
+fn fixtures_produce_expected_output() {
+    let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let mut failures = String::new();
+    for (path, mismatches) in fixtures::check_directory(directory).unwrap() {
+        for mismatch in mismatches {
+            failures += &format!("{}: {mismatch}\n", path.display());
+        }
+    }
+    assert!(failures.is_empty(), "{failures}");
+}